@@ -0,0 +1,499 @@
+//! Declaring which components systems borrow, and checking them.
+
+use std::{any::Any,
+          any::TypeId,
+          collections::HashMap,
+          fmt,
+          marker::PhantomData,
+          ops::AddAssign};
+
+use num::{Num, Bounded, ToPrimitive};
+
+use super::{Component, ComponentStore, GenIndexError, Query, SecondaryMap};
+use super::sync::{Mutex, lock};
+
+/// Access
+///
+/// The component types a system reads, and those it writes. Two
+/// systems conflict when one writes a type the other reads or
+/// writes; systems which do not conflict can run at the same time,
+/// on a thread pool say, and `batches` sorts a list of systems into
+/// groups that can.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// struct Position(f32);
+/// struct Velocity(f32);
+///
+/// let physics = Access::new().read::<Velocity>().write::<Position>();
+/// let render = Access::of_query::<&Position, u32, u32>();
+/// let steer = Access::new().write::<Velocity>();
+///
+/// assert!(physics.conflicts_with(&render));
+/// assert!(!render.conflicts_with(&steer));
+/// assert_eq!(vec![vec![0], vec![1, 2]], Access::batches(&[physics, render, steer]));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Access {
+    reads: Vec<TypeId>,
+    writes: Vec<TypeId>,
+}
+
+impl Access {
+
+    /// Create a new declaration, borrowing nothing.
+    pub fn new() -> Access {
+        Access{reads: Vec::new(), writes: Vec::new()}
+    }
+
+    /// Declare the components of type `T` read.
+    pub fn read<T: Component>(self) -> Self { self.read_id(TypeId::of::<T>()) }
+
+    /// Declare the components of type `T` written, which includes
+    /// reading them.
+    pub fn write<T: Component>(self) -> Self { self.write_id(TypeId::of::<T>()) }
+
+    /// Declare the components of the type with the given id read.
+    pub fn read_id(mut self, id: TypeId) -> Self {
+        if !self.reads.contains(&id) && !self.writes.contains(&id) {
+            self.reads.push(id);
+        }
+        self
+    }
+
+    /// Declare the components of the type with the given id
+    /// written.
+    pub fn write_id(mut self, id: TypeId) -> Self {
+        self.reads.retain(|&read| read != id);
+        if !self.writes.contains(&id) {
+            self.writes.push(id);
+        }
+        self
+    }
+
+    /// return the access of running the query `Q`: reading the
+    /// types it asks for as `&T`, and writing those it asks for as
+    /// `&mut T`.
+    pub fn of_query<Q, I, G>() -> Access
+    where Q: Query<'static, I, G>,
+          I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+          G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static {
+        let mut access = Vec::new();
+        Q::access(&mut access);
+        access.into_iter().fold(Access::new(), |declared, (id, mutable)| {
+            if mutable { declared.write_id(id) } else { declared.read_id(id) }
+        })
+    }
+
+    /// Declare everything `other` borrows borrowed too, for a
+    /// system made of several.
+    pub fn merge(self, other: &Access) -> Self {
+        let read = other.reads.iter().fold(self, |declared, &id| declared.read_id(id));
+        other.writes.iter().fold(read, |declared, &id| declared.write_id(id))
+    }
+
+    /// return the types only read, in the order declared.
+    pub fn reads(&self) -> &[TypeId] { &self.reads }
+
+    /// return the types written, in the order declared.
+    pub fn writes(&self) -> &[TypeId] { &self.writes }
+
+    /// Check whether one of the two writes a type the other
+    /// borrows in any way.
+    pub fn conflicts_with(&self, other: &Access) -> bool {
+        self.writes.iter().any(|id| other.reads.contains(id) || other.writes.contains(id))
+            || other.writes.iter().any(|id| self.reads.contains(id))
+    }
+
+    /// Sort the systems into batches, by their position in
+    /// `accesses`, such that no two systems in a batch conflict.
+    /// Every system goes in the first batch it can, so running the
+    /// batches one after the other runs the systems declared
+    /// earlier first whenever two conflict.
+    pub fn batches(accesses: &[Access]) -> Vec<Vec<usize>> {
+        let mut batches: Vec<Vec<usize>> = Vec::new();
+        for (n, access) in accesses.iter().enumerate() {
+            // a system may not go before an earlier one it conflicts
+            // with, so only batches after the last such are open
+            let open = batches.iter()
+                .rposition(|batch| batch.iter().any(|&m| accesses[m].conflicts_with(access)))
+                .map_or(0, |last| last + 1);
+            match batches.get_mut(open) {
+                Some(batch) => batch.push(n),
+                None => batches.push(vec![n]),
+            }
+        }
+        batches
+    }
+}
+
+/// how a component type is borrowed at the moment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Borrow {
+    /// by this many readers.
+    Shared(usize),
+    /// by a writer.
+    Exclusive,
+}
+
+/// AccessChecker
+///
+/// Keeps track of the accesses of the systems running at the
+/// moment, and refuses to let a system start while it conflicts
+/// with one that is running. A system takes an AccessGuard with
+/// `try_acquire` before it touches the components, and drops it
+/// once done; the checker is shared between threads by reference.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// struct Position(f32);
+///
+/// let checker = AccessChecker::new();
+/// let reader = checker.try_acquire(&Access::new().read::<Position>()).unwrap();
+/// let other_reader = checker.try_acquire(&Access::new().read::<Position>()).unwrap();
+/// assert_eq!(Err(GenIndexError::AccessConflict),
+///            checker.try_acquire(&Access::new().write::<Position>()).map(|_| ()));
+///
+/// drop((reader, other_reader));
+/// assert!(checker.try_acquire(&Access::new().write::<Position>()).is_ok());
+/// ```
+#[derive(Debug, Default)]
+pub struct AccessChecker {
+    borrows: Mutex<HashMap<TypeId, Borrow>>,
+}
+
+/// AccessGuard
+///
+/// The access of a running system, as granted by an AccessChecker,
+/// given back when the guard is dropped.
+#[derive(Debug)]
+pub struct AccessGuard<'a> {
+    checker: &'a AccessChecker,
+    access: Access,
+}
+
+impl AccessChecker {
+
+    /// Create a new checker, with nothing borrowed.
+    pub fn new() -> AccessChecker {
+        AccessChecker{borrows: Mutex::new(HashMap::new())}
+    }
+
+    /// Borrow everything the access declares, all or nothing, and
+    /// return a guard giving it back when dropped. Fails with
+    /// `AccessConflict`, borrowing nothing, if the access conflicts
+    /// with that of a guard still around.
+    pub fn try_acquire(&self, access: &Access) -> Result<AccessGuard<'_>, GenIndexError> {
+        let mut borrows = lock(&self.borrows);
+        let readable = access.reads.iter().all(|id| borrows.get(id).is_none_or(|&b| b != Borrow::Exclusive));
+        let writable = access.writes.iter().all(|id| !borrows.contains_key(id));
+        if !readable || !writable {
+            return Err(GenIndexError::AccessConflict);
+        }
+        for &id in &access.reads {
+            match borrows.entry(id).or_insert(Borrow::Shared(0)) {
+                Borrow::Shared(readers) => *readers += 1,
+                // checked above
+                Borrow::Exclusive => unreachable!(),
+            }
+        }
+        for &id in &access.writes {
+            borrows.insert(id, Borrow::Exclusive);
+        }
+        Ok(AccessGuard{checker: self, access: access.clone()})
+    }
+
+    /// Like `try_acquire`, but panic instead of reporting an error.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `try_acquire` fails.
+    pub fn acquire(&self, access: &Access) -> AccessGuard<'_> {
+        match self.try_acquire(access) {
+            Ok(guard) => guard,
+            Err(e) => panic!("acquire: {}", e),
+        }
+    }
+
+    /// Check whether any component type is borrowed at the moment.
+    pub fn is_borrowed(&self) -> bool { !lock(&self.borrows).is_empty() }
+
+    fn release(&self, access: &Access) {
+        let mut borrows = lock(&self.borrows);
+        for id in &access.reads {
+            if let Some(Borrow::Shared(readers)) = borrows.get_mut(id) {
+                *readers -= 1;
+                if *readers == 0 {
+                    borrows.remove(id);
+                }
+            }
+        }
+        for id in &access.writes {
+            borrows.remove(id);
+        }
+    }
+}
+
+impl AccessGuard<'_> {
+    /// return the access granted.
+    pub fn access(&self) -> &Access { &self.access }
+}
+
+impl Drop for AccessGuard<'_> {
+    fn drop(&mut self) { self.checker.release(&self.access); }
+}
+
+/// SharedStore
+///
+/// A ComponentStore lent to any number of systems at once, on
+/// threads of their own say. Every system borrows the maps of the
+/// component types its Access declares with `borrow_for`, which
+/// checks it with an AccessChecker of the store's own, so that no
+/// two systems ever borrow the same map where one of them writes
+/// it.
+///
+/// Only the maps of the types stored before the store was shared
+/// can be borrowed, and none if the components are kept by
+/// archetype.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// struct Position(f32);
+/// struct Velocity(f32);
+///
+/// let mut entities = GenIndexEntitySet::<u32, u32>::new_unsync();
+/// let mut components = ComponentStore::new();
+/// let ball = entities.next_index();
+/// components.insert(ball, Position(0.0)).unwrap();
+/// components.insert(ball, Velocity(2.0)).unwrap();
+///
+/// let shared = SharedStore::new(&mut components);
+/// let mut physics = shared.borrow_for(&Access::new().read::<Velocity>().write::<Position>()).unwrap();
+/// assert!(shared.borrow_for(&Access::new().read::<Position>()).is_err());
+///
+/// let velocity = physics.storage::<Velocity>().unwrap().get(ball).unwrap().0;
+/// physics.storage_mut::<Position>().unwrap().get_mut(ball).unwrap().0 += velocity;
+/// drop(physics);
+/// assert_eq!(2.0, components.get::<Position>(ball).unwrap().0);
+/// ```
+pub struct SharedStore<'a, I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static = u32,
+                       G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static = u32> {
+    /// per component type, its map in the store.
+    maps: HashMap<TypeId, *mut dyn Any>,
+    checker: AccessChecker,
+    store: PhantomData<&'a mut ComponentStore<I, G>>,
+}
+
+// the maps are only ever borrowed as the checker allows, and the
+// components in them are Send and Sync
+unsafe impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+            G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> Send for SharedStore<'_, I, G> {}
+unsafe impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+            G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> Sync for SharedStore<'_, I, G> {}
+
+/// StoreBorrow
+///
+/// The maps a system borrowed from a SharedStore, those of the
+/// types its Access declares, given back when dropped.
+pub struct StoreBorrow<'s, 'a, I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static = u32,
+                       G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static = u32> {
+    shared: &'s SharedStore<'a, I, G>,
+    guard: AccessGuard<'s>,
+}
+
+impl<'a, I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> SharedStore<'a, I, G> {
+
+    /// Lend the store to systems, until the SharedStore is dropped.
+    pub fn new(store: &'a mut ComponentStore<I, G>) -> SharedStore<'a, I, G> {
+        SharedStore{maps: store.map_pointers(), checker: AccessChecker::new(), store: PhantomData}
+    }
+
+    /// Borrow the maps of the types the access declares, as
+    /// `AccessChecker::try_acquire`, and fail as it does if the
+    /// access conflicts with that of a borrow still around.
+    pub fn borrow_for(&self, access: &Access) -> Result<StoreBorrow<'_, 'a, I, G>, GenIndexError> {
+        Ok(StoreBorrow{shared: self, guard: self.checker.try_acquire(access)?})
+    }
+
+    /// Check whether any map is borrowed at the moment.
+    pub fn is_borrowed(&self) -> bool { self.checker.is_borrowed() }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> StoreBorrow<'_, '_, I, G> {
+
+    /// return the access granted.
+    pub fn access(&self) -> &Access { self.guard.access() }
+
+    /// return the map holding the components of type `T`, if any
+    /// were stored before the store was shared.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the access does not declare `T` read or written.
+    pub fn storage<T: Component>(&self) -> Option<&SecondaryMap<T, I, G>> {
+        let id = TypeId::of::<T>();
+        assert!(self.access().reads.contains(&id) || self.access().writes.contains(&id),
+                "storage: the type is not declared in the access");
+        let map = *self.shared.maps.get(&id)?;
+        // no borrow writes the map while this one reads it
+        unsafe { (*map).downcast_ref() }
+    }
+
+    /// return the map holding the components of type `T`, for
+    /// modification, if any were stored before the store was
+    /// shared.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the access does not declare `T` written.
+    pub fn storage_mut<T: Component>(&mut self) -> Option<&mut SecondaryMap<T, I, G>> {
+        let id = TypeId::of::<T>();
+        assert!(self.access().writes.contains(&id), "storage_mut: the type is not declared written in the access");
+        let map = *self.shared.maps.get(&id)?;
+        // no other borrow has the map at all while this one writes
+        // it, and this one only hands it out once at a time
+        unsafe { (*map).downcast_mut() }
+    }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> fmt::Debug for SharedStore<'_, I, G> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SharedStore").field("types", &self.maps.len()).field("checker", &self.checker).finish()
+    }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> fmt::Debug for StoreBorrow<'_, '_, I, G> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StoreBorrow").field("access", self.access()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::GenIndexEntitySet;
+    use super::super::test_fixtures::{Health, Armor};
+
+    #[test]
+    fn test_access() {
+        let both = Access::new().read::<Health>().write::<Health>().read::<Health>();
+        assert!(both.reads().is_empty());
+        assert_eq!(&[TypeId::of::<Health>()], both.writes());
+        assert_eq!(Access::new().write::<Health>(), Access::of_query::<(&Health, &mut Health), u32, u32>());
+
+        let read = Access::new().read::<Health>();
+        assert!(!read.conflicts_with(&read));
+        assert!(read.conflicts_with(&both) && both.conflicts_with(&read));
+        assert!(!both.conflicts_with(&Access::new().write::<Armor>()));
+        let merged = read.clone().merge(&Access::new().write::<Armor>());
+        assert_eq!((1, 1), (merged.reads().len(), merged.writes().len()));
+
+        // a system never goes before an earlier one it conflicts with
+        let armor = Access::new().read::<Armor>();
+        assert_eq!(vec![vec![0, 1], vec![2], vec![3]],
+                   Access::batches(&[read.clone(), armor.clone(), both, armor.merge(&read).write::<Health>()]));
+        assert!(Access::batches(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_access_checker() {
+        let checker = AccessChecker::new();
+        let write = Access::new().write::<Health>().read::<Armor>();
+        {
+            let _guard = checker.acquire(&write);
+            assert!(checker.is_borrowed());
+            assert!(checker.try_acquire(&Access::new().read::<Health>()).is_err());
+            assert!(checker.try_acquire(&Access::new().write::<Armor>()).is_err());
+            let _reader = checker.acquire(&Access::new().read::<Armor>());
+        }
+        assert!(!checker.is_borrowed());
+
+        // a refused access borrows nothing
+        let _reader = checker.acquire(&Access::new().read::<Armor>());
+        assert!(checker.try_acquire(&Access::new().write::<Health>().write::<Armor>()).is_err());
+        assert!(checker.try_acquire(&Access::new().write::<Health>()).is_ok());
+
+        // systems on several threads
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| drop(checker.acquire(&Access::new().read::<Armor>())));
+            }
+        });
+        drop(_reader);
+        assert!(!checker.is_borrowed());
+    }
+
+    #[test]
+    fn test_shared_store() {
+        let mut entities = GenIndexEntitySet::<u32, u32>::new_unsync();
+        let mut components = ComponentStore::new();
+        let idxs = entities.next_indices(100);
+        for (n, &gi) in idxs.iter().enumerate() {
+            components.insert(gi, Health(n as u32)).unwrap();
+            components.insert(gi, Armor(0)).unwrap();
+        }
+
+        // two systems not conflicting, at the same time
+        let shared = SharedStore::new(&mut components);
+        let heal = Access::new().write::<Health>();
+        let harden = Access::new().write::<Armor>();
+        let (mut healing, mut hardening) = (shared.borrow_for(&heal).unwrap(), shared.borrow_for(&harden).unwrap());
+        assert!(shared.borrow_for(&Access::new().read::<Health>()).is_err());
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                for (_, health) in healing.storage_mut::<Health>().unwrap().iter_mut() {
+                    health.0 += 1;
+                }
+            });
+            scope.spawn(|| {
+                for (_, armor) in hardening.storage_mut::<Armor>().unwrap().iter_mut() {
+                    armor.0 += 10;
+                }
+            });
+        });
+        drop((healing, hardening));
+        assert!(!shared.is_borrowed());
+
+        // systems borrowing on threads of their own
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    let reading = shared.borrow_for(&Access::new().read::<Health>().read::<String>()).unwrap();
+                    assert_eq!(100, reading.storage::<Health>().unwrap().len());
+                    assert!(reading.storage::<String>().is_none());
+                });
+            }
+        });
+        drop(shared);
+        assert_eq!(Some(&Health(43)), components.get(idxs[42]));
+        assert_eq!(Some(&Armor(10)), components.get(idxs[42]));
+    }
+
+    #[test]
+    #[should_panic(expected = "not declared written")]
+    fn test_shared_store_undeclared() {
+        let mut components = ComponentStore::<u32, u32>::new();
+        let shared = SharedStore::new(&mut components);
+        let mut reading = shared.borrow_for(&Access::new().read::<Health>()).unwrap();
+        reading.storage_mut::<Health>();
+    }
+}
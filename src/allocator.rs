@@ -0,0 +1,220 @@
+//! The allocator a GenIndexEntitySet keeps its internal vectors in.
+//! Only with the `nightly` feature can it be another than the
+//! global one; without, `Allocator` and `Global` stand in for those
+//! of `std::alloc`, which are unstable.
+
+#[cfg(feature = "nightly")]
+pub use std::alloc::{Allocator, Global};
+
+use std::collections::VecDeque;
+
+/// Allocator
+///
+/// Without the `nightly` feature, a stand-in for
+/// `std::alloc::Allocator`, implemented by `Global` alone.
+#[cfg(not(feature = "nightly"))]
+pub trait Allocator: sealed::Sealed {}
+
+/// Global
+///
+/// Without the `nightly` feature, a stand-in for
+/// `std::alloc::Global`, the global allocator.
+#[cfg(not(feature = "nightly"))]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Global;
+
+#[cfg(not(feature = "nightly"))]
+impl Allocator for Global {}
+
+#[cfg(not(feature = "nightly"))]
+mod sealed {
+    pub trait Sealed {}
+
+    impl Sealed for super::Global {}
+}
+
+/// an empty vector in the allocator.
+#[cfg(feature = "nightly")]
+pub(crate) fn vec_in<T, A: Allocator + Clone>(alloc: &A) -> Vec<T, A> { Vec::new_in(alloc.clone()) }
+
+/// an empty vector; there is but the global allocator.
+#[cfg(not(feature = "nightly"))]
+pub(crate) fn vec_in<T, A>(_alloc: &A) -> Vec<T> { Vec::new() }
+
+/// an empty double-ended queue in the allocator.
+#[cfg(feature = "nightly")]
+pub(crate) fn deque_in<T, A: Allocator + Clone>(alloc: &A) -> VecDeque<T, A> { VecDeque::new_in(alloc.clone()) }
+
+/// an empty double-ended queue; there is but the global allocator.
+#[cfg(not(feature = "nightly"))]
+pub(crate) fn deque_in<T, A>(_alloc: &A) -> VecDeque<T> { VecDeque::new() }
+
+/// a copy of the items, in a vector in the allocator.
+#[cfg(feature = "nightly")]
+pub(crate) fn vec_from_in<T: Clone, A: Allocator + Clone>(items: &[T], alloc: &A) -> Vec<T, A> {
+    let mut copy = Vec::with_capacity_in(items.len(), alloc.clone());
+    copy.extend_from_slice(items);
+    copy
+}
+
+/// a copy of the items; there is but the global allocator.
+#[cfg(not(feature = "nightly"))]
+pub(crate) fn vec_from_in<T: Clone, A>(items: &[T], _alloc: &A) -> Vec<T> { items.to_vec() }
+
+/// a copy of the items, in a double-ended queue in the allocator.
+#[cfg(feature = "nightly")]
+pub(crate) fn deque_from_in<T: Clone, A: Allocator + Clone>(items: &VecDeque<T>, alloc: &A) -> VecDeque<T, A> {
+    let mut copy = VecDeque::with_capacity_in(items.len(), alloc.clone());
+    copy.extend(items.iter().cloned());
+    copy
+}
+
+/// a copy of the items; there is but the global allocator.
+#[cfg(not(feature = "nightly"))]
+pub(crate) fn deque_from_in<T: Clone, A>(items: &VecDeque<T>, _alloc: &A) -> VecDeque<T> { items.clone() }
+
+/// Serializing the vectors of any allocator as sequences, and
+/// deserializing them into a default constructed allocator, for
+/// `#[serde(with = "allocator::in_alloc")]`: serde only knows those
+/// of the global allocator.
+#[cfg(all(feature = "nightly", feature = "serde"))]
+pub(crate) mod in_alloc {
+    use std::collections::VecDeque;
+
+    use serde::{Serialize, Serializer, Deserialize, Deserializer};
+
+    use super::Allocator;
+
+    /// a vector of items, in any allocator.
+    pub(crate) trait Items {
+        type Item;
+    }
+
+    impl<T, A: Allocator> Items for Vec<T, A> {
+        type Item = T;
+    }
+
+    impl<T, A: Allocator> Items for VecDeque<T, A> {
+        type Item = T;
+    }
+
+    /// a vector which can be built from a global one.
+    pub(crate) trait FromVec: Items {
+        fn from_vec(items: Vec<Self::Item>) -> Self;
+    }
+
+    impl<T, A: Allocator + Default> FromVec for Vec<T, A> {
+        fn from_vec(items: Vec<T>) -> Self {
+            let mut copy = Vec::with_capacity_in(items.len(), A::default());
+            copy.extend(items);
+            copy
+        }
+    }
+
+    impl<T, A: Allocator + Default> FromVec for VecDeque<T, A> {
+        fn from_vec(items: Vec<T>) -> Self {
+            let mut copy = VecDeque::with_capacity_in(items.len(), A::default());
+            copy.extend(items);
+            copy
+        }
+    }
+
+    pub(crate) fn serialize<C, S>(items: &C, serializer: S) -> Result<S::Ok, S::Error>
+    where C: Items,
+          C::Item: Serialize,
+          for<'a> &'a C: IntoIterator<Item = &'a C::Item>,
+          S: Serializer {
+        serializer.collect_seq(items)
+    }
+
+    pub(crate) fn deserialize<'de, C, D>(deserializer: D) -> Result<C, D::Error>
+    where C: FromVec,
+          C::Item: Deserialize<'de>,
+          D: Deserializer<'de> {
+        Vec::deserialize(deserializer).map(C::from_vec)
+    }
+}
+
+/// Archiving the vectors of any allocator as an ArchivedVec, and
+/// deserializing them into a default constructed allocator, for
+/// `#[rkyv(with = allocator::InAlloc)]`: rkyv only knows those of
+/// the global allocator.
+#[cfg(all(feature = "nightly", feature = "rkyv"))]
+pub struct InAlloc;
+
+#[cfg(all(feature = "nightly", feature = "rkyv"))]
+mod archive_in_alloc {
+    use std::collections::VecDeque;
+
+    use rkyv::{Archive, Deserialize, Place, Serialize,
+               rancor::Fallible,
+               ser::Writer,
+               vec::{ArchivedVec, VecResolver},
+               with::{ArchiveWith, DeserializeWith, SerializeWith}};
+
+    use super::{Allocator, InAlloc};
+
+    impl<T: Archive, A: Allocator> ArchiveWith<Vec<T, A>> for InAlloc {
+        type Archived = ArchivedVec<T::Archived>;
+        type Resolver = VecResolver;
+
+        fn resolve_with(field: &Vec<T, A>, resolver: VecResolver, out: Place<Self::Archived>) {
+            ArchivedVec::resolve_from_slice(field.as_slice(), resolver, out);
+        }
+    }
+
+    impl<T, A, S> SerializeWith<Vec<T, A>, S> for InAlloc
+    where T: Serialize<S>,
+          A: Allocator,
+          S: Fallible + rkyv::ser::Allocator + Writer + ?Sized {
+        fn serialize_with(field: &Vec<T, A>, serializer: &mut S) -> Result<VecResolver, S::Error> {
+            ArchivedVec::<T::Archived>::serialize_from_slice(field.as_slice(), serializer)
+        }
+    }
+
+    impl<T, A, D> DeserializeWith<ArchivedVec<T::Archived>, Vec<T, A>, D> for InAlloc
+    where T: Archive,
+          T::Archived: Deserialize<T, D>,
+          A: Allocator + Default,
+          D: Fallible + ?Sized {
+        fn deserialize_with(field: &ArchivedVec<T::Archived>, deserializer: &mut D) -> Result<Vec<T, A>, D::Error> {
+            let mut items = Vec::with_capacity_in(field.len(), A::default());
+            for item in field.iter() {
+                items.push(item.deserialize(deserializer)?);
+            }
+            Ok(items)
+        }
+    }
+
+    impl<T: Archive, A: Allocator> ArchiveWith<VecDeque<T, A>> for InAlloc {
+        type Archived = ArchivedVec<T::Archived>;
+        type Resolver = VecResolver;
+
+        fn resolve_with(field: &VecDeque<T, A>, resolver: VecResolver, out: Place<Self::Archived>) {
+            ArchivedVec::resolve_from_len(field.len(), resolver, out);
+        }
+    }
+
+    impl<T, A, S> SerializeWith<VecDeque<T, A>, S> for InAlloc
+    where T: Serialize<S>,
+          A: Allocator,
+          S: Fallible + rkyv::ser::Allocator + Writer + ?Sized {
+        fn serialize_with(field: &VecDeque<T, A>, serializer: &mut S) -> Result<VecResolver, S::Error> {
+            ArchivedVec::<T::Archived>::serialize_from_iter::<T, _, _>(field.iter(), serializer)
+        }
+    }
+
+    impl<T, A, D> DeserializeWith<ArchivedVec<T::Archived>, VecDeque<T, A>, D> for InAlloc
+    where T: Archive,
+          T::Archived: Deserialize<T, D>,
+          A: Allocator + Default,
+          D: Fallible + ?Sized {
+        fn deserialize_with(field: &ArchivedVec<T::Archived>, deserializer: &mut D) -> Result<VecDeque<T, A>, D::Error> {
+            let mut items = VecDeque::with_capacity_in(field.len(), A::default());
+            for item in field.iter() {
+                items.push_back(item.deserialize(deserializer)?);
+            }
+            Ok(items)
+        }
+    }
+}
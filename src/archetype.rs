@@ -0,0 +1,379 @@
+//! Storing components grouped by the set of types entities have.
+
+use std::{any::Any,
+          any::TypeId,
+          collections::HashMap,
+          collections::HashSet,
+          fmt,
+          ops::AddAssign};
+
+use num::{Num, Bounded, ToPrimitive};
+
+use super::{Component, GenIndex, GenIndexError};
+
+/// StorageBackend
+///
+/// How a ComponentStore lays its components out in memory, chosen
+/// when it is created.
+#[derive(Hash, Debug, PartialEq, Eq, Copy, Clone, Default)]
+pub enum StorageBackend {
+    /// One SecondaryMap per component type, indexed by slot: adding
+    /// and removing components is cheap, and every component stays
+    /// where it is.
+    #[default]
+    SparseSets,
+    /// One table per archetype, the set of component types some
+    /// entities have, with a column per type: the components of the
+    /// entities found by a query lie next to each other, which
+    /// makes going through them as fast as it gets, while adding
+    /// or removing a component moves the entity's other components
+    /// to another table.
+    Archetypes,
+}
+
+/// a column of an archetype's table, with the type erased.
+trait Column: Send + Sync {
+    /// return a new, empty column of the same type.
+    fn empty(&self) -> Box<dyn Column>;
+    /// move the component in `row` to the end of `into`, a column
+    /// of the same type, the last component taking its place.
+    fn swap_remove_into(&mut self, row: usize, into: &mut dyn Column);
+    /// drop the component in `row`, the last one taking its place.
+    fn swap_remove_drop(&mut self, row: usize);
+    /// return the component in `row`, with its type erased.
+    fn row(&self, row: usize) -> Option<&dyn Any>;
+    fn row_mut(&mut self, row: usize) -> Option<&mut dyn Any>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Component> Column for Vec<T> {
+    fn empty(&self) -> Box<dyn Column> { Box::new(Vec::<T>::new()) }
+
+    fn swap_remove_into(&mut self, row: usize, into: &mut dyn Column) {
+        let value = self.swap_remove(row);
+        // only ever called with columns of the same type id
+        into.as_any_mut().downcast_mut::<Vec<T>>().unwrap().push(value);
+    }
+
+    fn swap_remove_drop(&mut self, row: usize) { self.swap_remove(row); }
+
+    fn row(&self, row: usize) -> Option<&dyn Any> { self.get(row).map(|value| value as &dyn Any) }
+    fn row_mut(&mut self, row: usize) -> Option<&mut dyn Any> { self.get_mut(row).map(|value| value as &mut dyn Any) }
+
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+}
+
+/// Archetype
+///
+/// The table of a ComponentStore created with
+/// `StorageBackend::Archetypes` holding the entities having exactly
+/// a certain set of component types: a row per entity, and a column
+/// per type, in which the entities' components follow each other.
+pub struct Archetype<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+                     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> {
+    /// the component types, sorted...
+    ids: Vec<TypeId>,
+    /// ...and a column for each.
+    columns: Vec<Box<dyn Column>>,
+    /// per row, the entity.
+    entities: Vec<GenIndex<I, G>>,
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> Archetype<I, G> {
+
+    /// return the component types of the entities, sorted by id.
+    pub fn types(&self) -> &[TypeId] { &self.ids }
+
+    /// return the entities, in the order of their rows.
+    pub fn entities(&self) -> &[GenIndex<I, G>] { &self.entities }
+
+    /// Check whether the entities have a component of type `id`.
+    pub fn has(&self, id: TypeId) -> bool { self.position(id).is_some() }
+
+    /// return the number of entities.
+    pub fn len(&self) -> usize { self.entities.len() }
+
+    /// return true if there are no entities.
+    pub fn is_empty(&self) -> bool { self.entities.is_empty() }
+
+    /// return the column of the components of type `T`, if the
+    /// entities have them.
+    pub(crate) fn column<T: Component>(&self) -> Option<&Vec<T>> {
+        self.columns[self.position(TypeId::of::<T>())?].as_any().downcast_ref()
+    }
+
+    /// return the column of the components of type `T` for
+    /// modification, if the entities have them.
+    pub(crate) fn column_mut<T: Component>(&mut self) -> Option<&mut Vec<T>> {
+        let position = self.position(TypeId::of::<T>())?;
+        self.columns[position].as_any_mut().downcast_mut()
+    }
+
+    fn position(&self, id: TypeId) -> Option<usize> { self.ids.binary_search(&id).ok() }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> fmt::Debug for Archetype<I, G> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Archetype")
+            .field("types", &self.ids.len())
+            .field("entities", &self.entities.len())
+            .finish()
+    }
+}
+
+/// The components of a ComponentStore created with
+/// `StorageBackend::Archetypes`. Entities without components are in
+/// no archetype at all.
+pub(crate) struct Archetypes<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+                             G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> {
+    archetypes: Vec<Archetype<I, G>>,
+    /// the archetype of every set of types, sorted.
+    by_types: HashMap<Vec<TypeId>, usize>,
+    /// per slot, the archetype and row of the entity last stored
+    /// there, which may be of an older generation.
+    locations: Vec<Option<(usize, usize)>>,
+    /// every type ever stored.
+    types: HashSet<TypeId>,
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> Archetypes<I, G> {
+    pub(crate) fn new() -> Archetypes<I, G> {
+        Archetypes{archetypes: Vec::new(), by_types: HashMap::new(), locations: Vec::new(), types: HashSet::new()}
+    }
+
+    /// as `ComponentStore::insert`.
+    pub(crate) fn insert<T: Component>(&mut self, gi: GenIndex<I, G>, value: T) -> Result<Option<T>, GenIndexError> {
+        let slot = gi.get_index().to_usize().ok_or(GenIndexError::UnknownIndex)?;
+        let mut from = None;
+        if let Some(Some((archetype, row))) = self.locations.get(slot).copied() {
+            let stored = self.archetypes[archetype].entities[row];
+            if stored.get_generation() > gi.get_generation() {
+                return Err(GenIndexError::StaleGeneration);
+            } else if stored == gi {
+                if let Some(column) = self.archetypes[archetype].column_mut::<T>() {
+                    return Ok(Some(std::mem::replace(&mut column[row], value)));
+                }
+                from = Some((archetype, row));
+            } else {
+                // an older generation, deleted without telling us
+                self.relocate(archetype, row, None, None);
+            }
+        }
+        let mut ids: Vec<_> = from.map_or(&[][..], |(archetype, _)| &self.archetypes[archetype].ids[..]).to_vec();
+        ids.push(TypeId::of::<T>());
+        ids.sort();
+        let to = self.archetype_of(ids, from.map(|(archetype, _)| archetype), || Box::new(Vec::<T>::new()));
+        match from {
+            Some((archetype, row)) => self.relocate(archetype, row, Some(to), None),
+            None => {
+                if slot >= self.locations.len() {
+                    self.locations.resize(slot + 1, None);
+                }
+                self.archetypes[to].entities.push(gi);
+                self.locations[slot] = Some((to, self.archetypes[to].entities.len() - 1));
+            },
+        }
+        // the archetype was just made to have the type
+        self.archetypes[to].column_mut::<T>().unwrap().push(value);
+        self.types.insert(TypeId::of::<T>());
+        Ok(None)
+    }
+
+    /// as `ComponentStore::get`.
+    pub(crate) fn get<T: Component>(&self, gi: GenIndex<I, G>) -> Option<&T> {
+        let (archetype, row) = self.locate(gi)?;
+        self.archetypes[archetype].column::<T>()?.get(row)
+    }
+
+    /// as `ComponentStore::get_mut`.
+    pub(crate) fn get_mut<T: Component>(&mut self, gi: GenIndex<I, G>) -> Option<&mut T> {
+        let (archetype, row) = self.locate(gi)?;
+        self.archetypes[archetype].column_mut::<T>()?.get_mut(row)
+    }
+
+    /// as `ComponentStore::get_dyn`.
+    pub(crate) fn get_dyn(&self, gi: GenIndex<I, G>, id: TypeId) -> Option<&dyn Any> {
+        let (archetype, row) = self.locate(gi)?;
+        let archetype = &self.archetypes[archetype];
+        archetype.columns[archetype.position(id)?].row(row)
+    }
+
+    /// as `ComponentStore::get_dyn_mut`.
+    pub(crate) fn get_dyn_mut(&mut self, gi: GenIndex<I, G>, id: TypeId) -> Option<&mut dyn Any> {
+        let (archetype, row) = self.locate(gi)?;
+        let archetype = &mut self.archetypes[archetype];
+        let position = archetype.position(id)?;
+        archetype.columns[position].row_mut(row)
+    }
+
+    /// as `ComponentStore::remove`.
+    pub(crate) fn remove<T: Component>(&mut self, gi: GenIndex<I, G>) -> Option<T> {
+        let (from, row) = self.locate(gi)?;
+        let value = self.archetypes[from].column_mut::<T>()?.swap_remove(row);
+        let ids: Vec<_> = self.archetypes[from].ids.iter().copied().filter(|&id| id != TypeId::of::<T>()).collect();
+        // every other type has a column in the archetype moved from
+        let to = if ids.is_empty() { None } else { Some(self.archetype_of(ids, Some(from), || unreachable!())) };
+        self.relocate(from, row, to, Some(TypeId::of::<T>()));
+        Some(value)
+    }
+
+    /// as `ComponentStore::remove_all`.
+    pub(crate) fn remove_all(&mut self, gi: GenIndex<I, G>) -> usize {
+        match self.locate(gi) {
+            Some((archetype, row)) => {
+                self.relocate(archetype, row, None, None);
+                self.archetypes[archetype].ids.len()
+            },
+            None => 0,
+        }
+    }
+
+    /// return the number of component types ever stored.
+    pub(crate) fn type_count(&self) -> usize { self.types.len() }
+
+    pub(crate) fn archetypes(&self) -> &[Archetype<I, G>] { &self.archetypes }
+
+    pub(crate) fn archetypes_mut(&mut self) -> &mut [Archetype<I, G>] { &mut self.archetypes }
+
+    /// the archetype and row of the handle, if it has components.
+    fn locate(&self, gi: GenIndex<I, G>) -> Option<(usize, usize)> {
+        let (archetype, row) = (*self.locations.get(gi.get_index().to_usize()?)?)?;
+        if self.archetypes[archetype].entities[row] == gi { Some((archetype, row)) } else { None }
+    }
+
+    /// return the archetype of the sorted types, creating it if need
+    /// be, with its columns made like those of the archetype `like`,
+    /// where it has them, and by `column` otherwise.
+    fn archetype_of<F: Fn() -> Box<dyn Column>>(&mut self, ids: Vec<TypeId>, like: Option<usize>, column: F) -> usize {
+        if let Some(&archetype) = self.by_types.get(&ids) {
+            return archetype;
+        }
+        let columns = ids.iter().map(|&id| {
+            let like = like.map(|like| &self.archetypes[like]);
+            match like.and_then(|like| like.position(id).map(|position| &like.columns[position])) {
+                Some(similar) => similar.empty(),
+                None => column(),
+            }
+        }).collect();
+        self.archetypes.push(Archetype{ids: ids.clone(), columns, entities: Vec::new()});
+        self.by_types.insert(ids, self.archetypes.len() - 1);
+        self.archetypes.len() - 1
+    }
+
+    /// move the entity in `row` of the archetype `from` to the end
+    /// of the archetype `to`, or none, carrying along the components
+    /// of the types both have, dropping the others, and leaving the
+    /// column of type `taken` alone, its component already taken
+    /// out.
+    fn relocate(&mut self, from: usize, row: usize, to: Option<usize>, taken: Option<TypeId>) {
+        let (source, mut target) = match to {
+            Some(to) if to < from => {
+                let (left, right) = self.archetypes.split_at_mut(from);
+                (&mut right[0], Some(&mut left[to]))
+            },
+            Some(to) => {
+                let (left, right) = self.archetypes.split_at_mut(to);
+                (&mut left[from], Some(&mut right[0]))
+            },
+            None => (&mut self.archetypes[from], None),
+        };
+        for (&id, column) in source.ids.iter().zip(&mut source.columns) {
+            if Some(id) == taken {
+                continue;
+            }
+            let into = target.as_mut().and_then(|target| {
+                let position = target.position(id)?;
+                Some(&mut target.columns[position])
+            });
+            match into {
+                Some(into) => column.swap_remove_into(row, &mut **into),
+                None => column.swap_remove_drop(row),
+            }
+        }
+        let gi = source.entities.swap_remove(row);
+        let moved = source.entities.get(row).copied();
+        let moved_to = target.map(|target| {
+            target.entities.push(gi);
+            target.entities.len() - 1
+        });
+        if let Some(moved) = moved {
+            self.locations[slot(moved)] = Some((from, row));
+        }
+        self.locations[slot(gi)] = to.zip(moved_to);
+    }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> fmt::Debug for Archetypes<I, G> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Archetypes").field("archetypes", &self.archetypes.len()).finish()
+    }
+}
+
+/// the slot of a handle with a location.
+fn slot<I: Num + AddAssign + Copy + ToPrimitive, G: Num + AddAssign + Copy>(gi: GenIndex<I, G>) -> usize {
+    gi.get_index().to_usize().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::ComponentStore;
+    use super::super::test_fixtures::{Health, Armor};
+
+    #[test]
+    fn test_archetype_store() {
+        let mut store = ComponentStore::<u32, u32>::with_backend(StorageBackend::Archetypes);
+        assert_eq!(StorageBackend::Archetypes, store.backend());
+        let a = GenIndex::new(0, 0);
+        let b = GenIndex::new(1, 0);
+        let c = GenIndex::new(2, 0);
+
+        assert_eq!(Ok(None), store.insert(a, Health(10)));
+        assert_eq!(Ok(Some(Health(10))), store.insert(a, Health(20)));
+        store.insert(b, Health(30)).unwrap();
+        store.insert(c, Health(40)).unwrap();
+        // a moves to the table of Health and Armor, c takes its row
+        store.insert(a, Armor(5)).unwrap();
+        assert_eq!(2, store.type_count());
+        assert_eq!(2, store.archetypes().len());
+        assert_eq!(&[c, b], store.archetypes()[0].entities());
+        assert_eq!(&[a], store.archetypes()[1].entities());
+        assert_eq!(Some(&Health(20)), store.get(a));
+        assert_eq!(Some(&Health(40)), store.get(c));
+        store.get_mut::<Health>(c).unwrap().0 += 1;
+
+        // and back, to the table it came from
+        assert_eq!(Some(Armor(5)), store.remove(a));
+        assert_eq!(None, store.remove::<Armor>(a));
+        assert_eq!(&[c, b, a], store.archetypes()[0].entities());
+        assert!(store.archetypes()[1].is_empty());
+        assert_eq!(Some(&Health(41)), store.get(c));
+
+        // a later generation of the index does not see the old components
+        let a2 = GenIndex::new(0, 1);
+        assert_eq!(None, store.get::<Health>(a2));
+        store.insert(a2, Armor(2)).unwrap();
+        assert_eq!(None, store.get::<Health>(a));
+        assert_eq!(Err(GenIndexError::StaleGeneration), store.insert(a, Armor(3)));
+
+        assert_eq!(1, store.remove_all(b));
+        assert_eq!(0, store.remove_all(b));
+        assert_eq!(Some(&Health(41)), store.get(c));
+        assert_eq!(Some(Health(41)), store.remove(c));
+        assert_eq!(None, store.get::<Health>(c));
+        assert!(store.storage::<Health>().is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "kept by archetype")]
+    fn test_archetype_store_no_maps() {
+        let mut store = ComponentStore::<u32, u32>::with_backend(StorageBackend::Archetypes);
+        store.storage_mut::<Health>();
+    }
+}
@@ -0,0 +1,71 @@
+//! Reading archived handles and sets in place, behind the `rkyv`
+//! feature.
+
+use std::ops::AddAssign;
+
+use num::{Num, Bounded, ToPrimitive};
+use rkyv::{Archive, Archived};
+
+use super::{ArchivedGenIndex, ArchivedGenIndexEntitySet, GenIndex};
+
+impl<I: Num + AddAssign + Copy + Archive,
+     G: Num + AddAssign + Copy + Archive,
+     Tag> ArchivedGenIndex<I, G, Tag> {
+    /// return the archived index number.
+    pub fn get_index(&self) -> &Archived<I> { &self.index }
+
+    /// return the archived generation number.
+    pub fn get_generation(&self) -> &Archived<G> { &self.generation }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + Archive,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Archive> ArchivedGenIndexEntitySet<I, G>
+    where Archived<G>: PartialEq<G> {
+
+    /// Check whether the given index was live when the set was
+    /// archived.
+    pub fn is_live(&self, gi: GenIndex<I, G>) -> bool {
+        gi.index.to_usize()
+            .and_then(|i| self.slots.get(i))
+            .is_some_and(|slot| slot.live && slot.generation == gi.generation)
+    }
+
+    /// return the number of indices live when the set was archived.
+    pub fn live_count(&self) -> usize { self.live.to_native() as usize }
+}
+
+#[cfg(test)]
+mod tests {
+    use rkyv::rancor::Error;
+    use super::*;
+    use super::super::GenIndexEntitySet;
+
+    #[test]
+    fn test_archive_in_place() {
+        let mut set = GenIndexEntitySet::<u32, u32>::new_unsync();
+        let idxs = set.next_indices(3);
+        set.delete_index(idxs[1]).unwrap();
+        let recycled = set.next_index();
+
+        let bytes = rkyv::to_bytes::<Error>(&set).unwrap();
+        let archived = rkyv::access::<ArchivedGenIndexEntitySet<u32, u32>, Error>(&bytes).unwrap();
+        assert_eq!(3, archived.live_count());
+        assert!(archived.is_live(idxs[0]));
+        assert!(!archived.is_live(idxs[1]));
+        assert!(archived.is_live(recycled));
+        assert!(!archived.is_live(GenIndex::new(7, 0)));
+
+        let back: GenIndexEntitySet<u32, u32> = rkyv::deserialize::<_, Error>(archived).unwrap();
+        assert_eq!(set, back);
+    }
+
+    #[test]
+    fn test_archive_handle() {
+        let gi = GenIndex::<u32, u16>::new(42, 3);
+        let bytes = rkyv::to_bytes::<Error>(&gi).unwrap();
+        let archived = rkyv::access::<ArchivedGenIndex<u32, u16>, Error>(&bytes).unwrap();
+        assert_eq!(42, archived.get_index().to_native());
+        assert_eq!(3, archived.get_generation().to_native());
+        assert_eq!(gi, rkyv::deserialize::<GenIndex<u32, u16>, Error>(archived).unwrap());
+    }
+}
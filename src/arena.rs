@@ -0,0 +1,974 @@
+//! Storing values under generational handles.
+
+use std::{fmt, ops::AddAssign, ops::Index, ops::IndexMut, marker::PhantomData, mem};
+
+use num::{Num, Bounded, ToPrimitive, zero, one};
+#[cfg(feature = "rayon")]
+use num::{NumCast, cast};
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+use super::{GenIndex, GenIndexConfig, GenIndexEntitySet, GenIndexError, MemoryUsage};
+use super::chunked::ChunkedVec;
+
+/// GenIndexArena
+///
+/// A generational arena: stores values, and hands out a GenIndex
+/// for each, by which it can be found again. Indices and their
+/// generations are handed out by a GenIndexEntitySet of the
+/// arena's own, so a handle to a removed value never finds the value
+/// stored in its slot later on, and all the settings of
+/// GenIndexConfig apply.
+///
+/// The index and generation types default to `u32`. The optional
+/// `Tag` marks the handles as those of this arena, see
+/// TaggedGenIndexEntitySet: give each arena a tag of its own, and
+/// looking up a mesh with a texture handle no longer compiles.
+///
+/// Values are stored in chunks of 4096 slots, allocated a whole
+/// chunk at a time. Growing the arena never moves the values stored
+/// already, so it never copies the whole world over, and a value
+/// stays at the same address until it is removed or the arena is
+/// compacted.
+///
+/// With the `serde` feature, an arena serializes as a struct of
+/// `set`, the GenIndexEntitySet with its slot generations and free
+/// list, and `values`, per slot the value stored or null. A
+/// reloaded arena accepts, and refuses, exactly the handles the
+/// saved one did.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// let mut arena = GenIndexArena::<&str>::new();
+/// let player = arena.insert("player");
+/// assert_eq!(Some(&"player"), arena.get(player));
+///
+/// arena.remove(player).unwrap();
+/// let camera = arena.insert("camera");
+/// // the slot is reused, but the old handle does not see its new value
+/// assert_eq!(player.get_index(), camera.get_index());
+/// assert_eq!(None, arena.get(player));
+/// ```
+///
+/// Handles of tagged arenas only fit the arena they came from:
+///
+/// ```compile_fail
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// struct Mesh;
+/// struct Texture;
+///
+/// let mut meshes = GenIndexArena::<&str, u32, u32, Mesh>::new();
+/// let mut textures = GenIndexArena::<&str, u32, u32, Texture>::new();
+/// let brick = textures.insert("brick.png");
+/// meshes.get(brick);
+/// ```
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GenIndexArena<T,
+                         I: Num + AddAssign + Copy + ToPrimitive + Bounded = u32,
+                         G: Num + AddAssign + Copy + Bounded + PartialOrd = u32,
+                         Tag = ()> {
+    set: GenIndexEntitySet<I, G>,
+    values: ChunkedVec<Option<T>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    tag: PhantomData<fn() -> Tag>,
+}
+
+impl<T,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd,
+     Tag> GenIndexArena<T, I, G, Tag> {
+
+    /// Create a new, empty arena.
+    pub fn new() -> GenIndexArena<T, I, G, Tag> {
+        Self::with_config(GenIndexConfig::default())
+    }
+
+    /// Create a new, empty arena, whose handles are handed out
+    /// according to the given settings.
+    pub fn with_config(config: GenIndexConfig) -> GenIndexArena<T, I, G, Tag> {
+        GenIndexArena{set: GenIndexEntitySet::from_config(config), values: ChunkedVec::new(), tag: PhantomData}
+    }
+
+    /// Create a new, empty arena with room for `capacity` values
+    /// before its storage has to grow.
+    pub fn with_capacity(capacity: usize) -> GenIndexArena<T, I, G, Tag> {
+        let mut arena = Self::new();
+        arena.reserve(capacity);
+        arena
+    }
+
+    /// Make room for at least `additional` more values without the
+    /// storage growing.
+    pub fn reserve(&mut self, additional: usize) {
+        self.set.reserve(additional);
+        self.values.reserve(additional);
+    }
+
+    /// return the number of values the arena can hold without its
+    /// storage growing.
+    pub fn capacity(&self) -> usize { self.values.capacity() }
+
+    /// return the set handing out this arena's handles, for its
+    /// counters and settings.
+    pub fn set(&self) -> &GenIndexEntitySet<I, G> { &self.set }
+
+    /// return the memory this arena holds, its set's included, broken
+    /// down by what it is used for.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.set.memory_usage() + MemoryUsage {
+            slots: self.values.capacity() * mem::size_of::<Option<T>>(),
+            free_list: 0,
+            metadata: mem::size_of::<Self>() - mem::size_of::<GenIndexEntitySet<I, G>>()
+                + self.values.table_bytes(),
+        }
+    }
+
+    /// Store a value, and return its handle.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as
+    /// `GenIndexEntitySet::next_index`.
+    pub fn insert(&mut self, value: T) -> GenIndex<I, G, Tag> {
+        match self.try_insert(value) {
+            Ok(gi) => gi,
+            Err(e) => panic!("insert: {}", e),
+        }
+    }
+
+    /// Store a value, and return its handle, reporting an error
+    /// instead of panicking if no handle can be handed out. The
+    /// value is dropped in that case.
+    pub fn try_insert(&mut self, value: T) -> Result<GenIndex<I, G, Tag>, GenIndexError> {
+        let gi = self.set.try_next_index()?.retag();
+        let slot = Self::slot(gi).ok_or(GenIndexError::UnknownIndex)?;
+        self.put(slot, value);
+        Ok(gi)
+    }
+
+    /// Look up the given handle for inserting or updating its value
+    /// in place. If the handle is not live, the entry is vacant, and
+    /// inserting into it brings the handle to life as
+    /// `GenIndexEntitySet::reserve_index` does, which is checked
+    /// here already; a handle `reserve_index` would refuse yields
+    /// its error instead of an entry.
+    ///
+    /// ```
+    /// # use gen_indices::*;
+    /// let mut arena = GenIndexArena::<u32>::new();
+    /// let gi = arena.insert(1);
+    /// *arena.entry(gi).unwrap().or_insert(0) += 1;
+    /// assert_eq!(Some(&2), arena.get(gi));
+    /// ```
+    pub fn entry(&mut self, gi: GenIndex<I, G, Tag>) -> Result<ArenaEntry<'_, T, I, G, Tag>, GenIndexError> {
+        if self.set.is_live(gi.untagged()) {
+            return Ok(ArenaEntry::Occupied(OccupiedArenaEntry{arena: self, gi}));
+        }
+        let slot = self.set.check_reserve(gi.untagged())?;
+        Ok(ArenaEntry::Vacant(VacantArenaEntry{arena: self, gi, slot}))
+    }
+
+    /// return the value stored under the given handle, or None if
+    /// it has been removed.
+    pub fn get(&self, gi: GenIndex<I, G, Tag>) -> Option<&T> {
+        if !self.set.is_live(gi.untagged()) {
+            return None;
+        }
+        self.values.get(Self::slot(gi)?)?.as_ref()
+    }
+
+    /// return the value stored under the given handle for
+    /// modification, or None if it has been removed.
+    pub fn get_mut(&mut self, gi: GenIndex<I, G, Tag>) -> Option<&mut T> {
+        if !self.set.is_live(gi.untagged()) {
+            return None;
+        }
+        self.values.get_mut(Self::slot(gi)?)?.as_mut()
+    }
+
+    /// return the values stored under several handles at once, all
+    /// for modification, or None if any handle is not live, or the
+    /// same handle is given twice.
+    ///
+    /// ```
+    /// # use gen_indices::*;
+    /// let mut arena = GenIndexArena::<i32>::new();
+    /// let a = arena.insert(1);
+    /// let b = arena.insert(2);
+    /// if let Some([x, y]) = arena.get_disjoint_mut([a, b]) {
+    ///     std::mem::swap(x, y);
+    /// }
+    /// assert_eq!(Some(&2), arena.get(a));
+    /// assert!(arena.get_disjoint_mut([a, a]).is_none());
+    /// ```
+    pub fn get_disjoint_mut<const N: usize>(&mut self, gis: [GenIndex<I, G, Tag>; N]) -> Option<[&mut T; N]> {
+        if !gis.iter().all(|&gi| self.set.is_live(gi.untagged())) {
+            return None;
+        }
+        let mut slots = [0; N];
+        for (slot, gi) in slots.iter_mut().zip(gis.iter()) {
+            *slot = Self::slot(*gi)?;
+        }
+        let values = self.values.get_disjoint_mut(slots)?.map(Option::as_mut);
+        if values.iter().any(Option::is_none) {
+            return None;
+        }
+        Some(values.map(Option::unwrap))
+    }
+
+    /// return the values stored under two different handles, both
+    /// for modification, as `get_disjoint_mut`.
+    pub fn get2_mut(&mut self, a: GenIndex<I, G, Tag>, b: GenIndex<I, G, Tag>) -> Option<(&mut T, &mut T)> {
+        let [a, b] = self.get_disjoint_mut([a, b])?;
+        Some((a, b))
+    }
+
+    /// Remove the value stored under the given handle, and return
+    /// it. The handle is validated as in
+    /// `GenIndexEntitySet::delete_index`.
+    pub fn remove(&mut self, gi: GenIndex<I, G, Tag>) -> Result<T, GenIndexError> {
+        self.set.delete_index(gi.untagged())?;
+        Self::slot(gi)
+            .and_then(|slot| self.values.get_mut(slot))
+            .and_then(Option::take)
+            .ok_or(GenIndexError::UnknownIndex)
+    }
+
+    /// Check whether a value is stored under the given handle.
+    pub fn contains(&self, gi: GenIndex<I, G, Tag>) -> bool { self.set.is_live(gi.untagged()) }
+
+    /// return the number of values stored.
+    pub fn len(&self) -> usize { self.set.live_count() }
+
+    /// return true if no values are stored.
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// iterate over every value, with its handle, in index order.
+    pub fn iter(&self) -> impl Iterator<Item = (GenIndex<I, G, Tag>, &T)> + '_ {
+        self.set.slot_handles().map(GenIndex::retag)
+            .zip(self.values.iter())
+            .filter_map(|(gi, value)| Some((gi, value.as_ref()?)))
+    }
+
+    /// iterate over every value for modification, with its handle,
+    /// in index order.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (GenIndex<I, G, Tag>, &mut T)> + '_ {
+        self.set.slot_handles().map(GenIndex::retag)
+            .zip(self.values.iter_mut())
+            .filter_map(|(gi, value)| Some((gi, value.as_mut()?)))
+    }
+
+    /// iterate over the handle of every value, in index order.
+    pub fn keys(&self) -> impl Iterator<Item = GenIndex<I, G, Tag>> + '_ {
+        self.iter().map(|(gi, _)| gi)
+    }
+
+    /// iterate over every value, without the handles, in index
+    /// order.
+    pub fn values(&self) -> impl Iterator<Item = &T> + '_ {
+        self.values.iter().filter_map(Option::as_ref)
+    }
+
+    /// iterate over every value for modification, without the
+    /// handles, in index order.
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut T> + '_ {
+        self.values.iter_mut().filter_map(Option::as_mut)
+    }
+
+    /// Remove every value, returning them with their handles, in
+    /// index order. All outstanding handles become stale, as with
+    /// `GenIndexEntitySet::clear`, and the slots are recycled at a
+    /// higher generation later. Everything is removed at once, even
+    /// if the iterator is dropped before the end.
+    pub fn drain(&mut self) -> std::vec::IntoIter<(GenIndex<I, G, Tag>, T)> {
+        let drained: Vec<_> = self.set.slot_handles().map(GenIndex::retag)
+            .zip(self.values.iter_mut())
+            .filter_map(|(gi, value)| Some((gi, value.take()?)))
+            .collect();
+        self.set.clear();
+        drained.into_iter()
+    }
+
+    /// Remove every value at once, dropping them. Every handle
+    /// handed out before becomes stale, as with
+    /// `GenIndexEntitySet::clear`, and stays so for good: each slot
+    /// is only handed out again at a higher generation, so handles
+    /// kept by scripts or UI from before the clear never find the
+    /// values stored after it.
+    ///
+    /// ```
+    /// # use gen_indices::*;
+    /// let mut level = GenIndexArena::<&str>::new();
+    /// let door = level.insert("door");
+    /// level.clear();
+    /// let lever = level.insert("lever");
+    /// assert_eq!(door.get_index(), lever.get_index());
+    /// assert_eq!(None, level.get(door));
+    /// ```
+    pub fn clear(&mut self) {
+        for value in self.values.iter_mut() {
+            *value = None;
+        }
+        self.set.clear();
+    }
+
+    /// Keep only the values for which `f` returns true, removing
+    /// the rest in a single pass, in index order, as
+    /// `remove` would. The handles of removed values become stale.
+    ///
+    /// ```
+    /// # use gen_indices::*;
+    /// let mut enemies = GenIndexArena::<i32>::new();
+    /// let alive = enemies.insert(10);
+    /// let dead = enemies.insert(0);
+    /// enemies.retain(|_, health| *health > 0);
+    /// assert!(enemies.contains(alive));
+    /// assert!(!enemies.contains(dead));
+    /// ```
+    pub fn retain<F: FnMut(GenIndex<I, G, Tag>, &mut T) -> bool>(&mut self, mut f: F) {
+        let mut index = zero();
+        for value in self.values.iter_mut() {
+            if let (Some(v), Some(gi)) = (value.as_mut(), self.set.slot_handle(index).map(GenIndex::retag)) {
+                if !f(gi, v) {
+                    *value = None;
+                    // live, since it has a value
+                    let _ = self.set.delete_index(gi.untagged());
+                }
+            }
+            index += one();
+        }
+    }
+
+    /// Move the values into the lowest slots, closing up the holes
+    /// left by removed ones, and return how many were moved. Each
+    /// value that moves gets a new handle, as described at
+    /// `GenIndexEntitySet::compact`; `f` is called with every old
+    /// handle and its replacement, so references kept elsewhere can
+    /// be updated. The handles of values that stay put stay valid.
+    ///
+    /// ```
+    /// # use gen_indices::*;
+    /// let mut arena = GenIndexArena::<&str>::new();
+    /// let a = arena.insert("a");
+    /// let mut b = arena.insert("b");
+    /// arena.remove(a).unwrap();
+    ///
+    /// arena.compact(|old, new| if old == b { b = new });
+    /// assert_eq!(0, b.get_index());
+    /// assert_eq!(Some(&"b"), arena.get(b));
+    /// ```
+    pub fn compact<F: FnMut(GenIndex<I, G, Tag>, GenIndex<I, G, Tag>)>(&mut self, mut f: F) -> usize {
+        let mut moved = Vec::new();
+        self.set.compact(|old, new| moved.push((old.retag(), new.retag())));
+        for &(old, new) in &moved {
+            if let (Some(from), Some(to)) = (Self::slot(old), Self::slot(new)) {
+                self.values.swap(from, to);
+            }
+        }
+        // nothing is stored above the live values any more
+        while let Some(None) = self.values.last() {
+            self.values.pop();
+        }
+        // only now the arena is consistent again, should `f` panic
+        for &(old, new) in &moved {
+            f(old, new);
+        }
+        moved.len()
+    }
+
+    fn slot(gi: GenIndex<I, G, Tag>) -> Option<usize> { gi.get_index().to_usize() }
+
+    fn put(&mut self, slot: usize, value: T) -> &mut T {
+        self.values.resize_with(slot + 1, || None);
+        // just made sure it is there
+        self.values.get_mut(slot).unwrap().insert(value)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<T: Send + Sync,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded + NumCast + Send + Sync,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync,
+     Tag> GenIndexArena<T, I, G, Tag> {
+
+    /// iterate over every value, with its handle, on rayon's
+    /// worker threads.
+    pub fn par_iter(&self) -> impl ParallelIterator<Item = (GenIndex<I, G, Tag>, &T)> + '_ {
+        let set = &self.set;
+        self.values.par_iter().filter_map(move |(slot, value)| {
+            Some((set.slot_handle(cast(slot)?)?.retag(), value.as_ref()?))
+        })
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<T: Send,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded + NumCast + Send + Sync,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync,
+     Tag> GenIndexArena<T, I, G, Tag> {
+
+    /// iterate over every value for modification, with its handle,
+    /// on rayon's worker threads, for running a system over all
+    /// entities at once.
+    pub fn par_iter_mut(&mut self) -> impl ParallelIterator<Item = (GenIndex<I, G, Tag>, &mut T)> + '_ {
+        let set = &self.set;
+        self.values.par_iter_mut().filter_map(move |(slot, value)| {
+            Some((set.slot_handle(cast(slot)?)?.retag(), value.as_mut()?))
+        })
+    }
+}
+
+/// ArenaEntry
+///
+/// The value stored under one handle in a GenIndexArena, or the
+/// lack of it, as returned by `GenIndexArena::entry`.
+pub enum ArenaEntry<'a, T,
+                    I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+                    G: Num + AddAssign + Copy + Bounded + PartialOrd,
+                    Tag> {
+    /// the handle is live, and has a value.
+    Occupied(OccupiedArenaEntry<'a, T, I, G, Tag>),
+    /// the handle is not live, but can be brought to life.
+    Vacant(VacantArenaEntry<'a, T, I, G, Tag>),
+}
+
+/// OccupiedArenaEntry
+///
+/// A live handle in a GenIndexArena, and its value.
+pub struct OccupiedArenaEntry<'a, T,
+                              I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+                              G: Num + AddAssign + Copy + Bounded + PartialOrd,
+                    Tag> {
+    arena: &'a mut GenIndexArena<T, I, G, Tag>,
+    gi: GenIndex<I, G, Tag>,
+}
+
+/// VacantArenaEntry
+///
+/// A handle not live in a GenIndexArena, which inserting a value
+/// brings to life.
+pub struct VacantArenaEntry<'a, T,
+                            I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+                            G: Num + AddAssign + Copy + Bounded + PartialOrd,
+                    Tag> {
+    arena: &'a mut GenIndexArena<T, I, G, Tag>,
+    gi: GenIndex<I, G, Tag>,
+    slot: usize,
+}
+
+impl<'a, T,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd,
+     Tag> ArenaEntry<'a, T, I, G, Tag> {
+    /// return the handle of this entry.
+    pub fn key(&self) -> GenIndex<I, G, Tag> {
+        match self {
+            ArenaEntry::Occupied(entry) => entry.key(),
+            ArenaEntry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// return the value, inserting `value` first if there is none.
+    pub fn or_insert(self, value: T) -> &'a mut T {
+        self.or_insert_with(|| value)
+    }
+
+    /// return the value, inserting the result of `f` first if
+    /// there is none.
+    pub fn or_insert_with<F: FnOnce() -> T>(self, f: F) -> &'a mut T {
+        match self {
+            ArenaEntry::Occupied(entry) => entry.into_mut(),
+            ArenaEntry::Vacant(entry) => entry.insert(f()),
+        }
+    }
+
+    /// return the value, inserting the default first if there is
+    /// none.
+    pub fn or_default(self) -> &'a mut T where T: Default {
+        self.or_insert_with(T::default)
+    }
+
+    /// modify the value, if there is one.
+    pub fn and_modify<F: FnOnce(&mut T)>(mut self, f: F) -> Self {
+        if let ArenaEntry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, T,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd,
+     Tag> OccupiedArenaEntry<'a, T, I, G, Tag> {
+    /// return the handle of this entry.
+    pub fn key(&self) -> GenIndex<I, G, Tag> { self.gi }
+
+    /// return the value.
+    pub fn get(&self) -> &T {
+        // only ever created for live handles
+        self.arena.get(self.gi).unwrap()
+    }
+
+    /// return the value for modification.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.arena.get_mut(self.gi).unwrap()
+    }
+
+    /// return the value for modification, for as long as the arena
+    /// was borrowed for.
+    pub fn into_mut(self) -> &'a mut T {
+        self.arena.get_mut(self.gi).unwrap()
+    }
+
+    /// replace the value, returning the old one.
+    pub fn insert(&mut self, value: T) -> T {
+        std::mem::replace(self.get_mut(), value)
+    }
+
+    /// remove the value, returning it, as `GenIndexArena::remove`.
+    pub fn remove(self) -> T {
+        self.arena.remove(self.gi).unwrap()
+    }
+}
+
+impl<'a, T,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd,
+     Tag> VacantArenaEntry<'a, T, I, G, Tag> {
+    /// return the handle of this entry.
+    pub fn key(&self) -> GenIndex<I, G, Tag> { self.gi }
+
+    /// bring the handle to life with the given value.
+    pub fn insert(self, value: T) -> &'a mut T {
+        // checked when the entry was made, and the arena has been
+        // borrowed ever since
+        self.arena.set.reserve_index(self.gi.untagged()).unwrap();
+        self.arena.put(self.slot, value)
+    }
+}
+
+impl<T,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd,
+     Tag> Default for GenIndexArena<T, I, G, Tag> {
+    fn default() -> Self { Self::new() }
+}
+
+/// Looks up the value stored under a handle, as `get`, for tight
+/// loops doing `arena[gi].position += v`.
+///
+/// # Panics
+///
+/// Panics if the handle is not live.
+impl<T,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd,
+     Tag> Index<GenIndex<I, G, Tag>> for GenIndexArena<T, I, G, Tag> {
+    type Output = T;
+
+    fn index(&self, gi: GenIndex<I, G, Tag>) -> &T {
+        match self.get(gi) {
+            Some(value) => value,
+            None => panic!("index: no value stored under the handle"),
+        }
+    }
+}
+
+/// Looks up the value stored under a handle for modification, as
+/// `get_mut`.
+///
+/// # Panics
+///
+/// Panics if the handle is not live.
+impl<T,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd,
+     Tag> IndexMut<GenIndex<I, G, Tag>> for GenIndexArena<T, I, G, Tag> {
+    fn index_mut(&mut self, gi: GenIndex<I, G, Tag>) -> &mut T {
+        match self.get_mut(gi) {
+            Some(value) => value,
+            None => panic!("index_mut: no value stored under the handle"),
+        }
+    }
+}
+
+/// Takes the arena apart, yielding every value with its handle, in
+/// index order, as `drain` does.
+impl<T,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd,
+     Tag> IntoIterator for GenIndexArena<T, I, G, Tag> {
+    type Item = (GenIndex<I, G, Tag>, T);
+    type IntoIter = std::vec::IntoIter<(GenIndex<I, G, Tag>, T)>;
+
+    fn into_iter(mut self) -> Self::IntoIter { self.drain() }
+}
+
+impl<T: Clone,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd,
+     Tag> Clone for GenIndexArena<T, I, G, Tag> {
+    fn clone(&self) -> Self {
+        GenIndexArena{set: self.set.clone(), values: self.values.clone(), tag: PhantomData}
+    }
+}
+
+impl<T: PartialEq,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd,
+     Tag> PartialEq for GenIndexArena<T, I, G, Tag> {
+    fn eq(&self, other: &Self) -> bool {
+        self.set == other.set && self.values == other.values
+    }
+}
+
+impl<T: fmt::Debug,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded + fmt::Debug,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + fmt::Debug,
+     Tag> fmt::Debug for GenIndexArena<T, I, G, Tag> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GenIndexArena")
+            .field("set", &self.set)
+            .field("values", &self.values)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arena() {
+        let mut arena = GenIndexArena::<String>::new();
+        let a = arena.insert("a".to_string());
+        let b = arena.insert("b".to_string());
+        assert_eq!(2, arena.len());
+
+        arena.get_mut(b).unwrap().push('!');
+        assert_eq!(Some(&"b!".to_string()), arena.get(b));
+
+        assert_eq!(Ok("a".to_string()), arena.remove(a));
+        assert_eq!(Err(GenIndexError::AlreadyDeleted), arena.remove(a));
+        assert!(!arena.contains(a));
+
+        // a stale handle never sees the value now in its slot
+        let c = arena.insert("c".to_string());
+        assert_eq!(a.get_index(), c.get_index());
+        assert_eq!(None, arena.get(a));
+        assert_eq!(None, arena.get_mut(a));
+        assert_eq!(Err(GenIndexError::StaleGeneration), arena.remove(a));
+        assert_eq!(Some(&"c".to_string()), arena.get(c));
+
+        assert_eq!(None, arena.get(GenIndex::new(99, 0)));
+        assert_eq!(Err(GenIndexError::UnknownIndex), arena.remove(GenIndex::new(99, 0)));
+    }
+
+    #[test]
+    fn test_tagged_arena() {
+        struct Mesh;
+        let mut meshes = GenIndexArena::<&str, u32, u32, Mesh>::new();
+        let cube: GenIndex<u32, u32, Mesh> = meshes.insert("cube");
+        let sphere = meshes.insert("sphere");
+        assert_eq!(Some(&"cube"), meshes.get(cube));
+        assert_eq!(vec![cube, sphere], meshes.keys().collect::<Vec<_>>());
+
+        meshes.remove(cube).unwrap();
+        let mut moved = None;
+        meshes.compact(|old, new| moved = Some((old, new)));
+        let (old, new) = moved.unwrap();
+        assert_eq!(sphere, old);
+        assert_eq!(Some(&"sphere"), meshes.get(new));
+    }
+
+    #[test]
+    fn test_arena_capacity_limit() {
+        let config = GenIndexConfig{capacity_limit: Some(1), ..GenIndexConfig::default()};
+        let mut arena = GenIndexArena::<u8, u8, u8>::with_config(config);
+        let a = arena.insert(1);
+        assert_eq!(Err(GenIndexError::CapacityExhausted), arena.try_insert(2));
+        arena.remove(a).unwrap();
+        assert!(arena.try_insert(3).is_ok());
+    }
+
+    #[test]
+    fn test_arena_iter() {
+        let mut arena = GenIndexArena::<u32>::new();
+        let handles: Vec<_> = (0..5).map(|i| arena.insert(i * 10)).collect();
+        arena.remove(handles[1]).unwrap();
+        arena.remove(handles[3]).unwrap();
+        let recycled = arena.insert(99);
+
+        for (_, value) in arena.iter_mut() {
+            *value += 1;
+        }
+        let all: Vec<_> = arena.iter().map(|(gi, &v)| (gi, v)).collect();
+        assert_eq!(vec![(handles[0], 1), (handles[2], 21), (recycled, 100), (handles[4], 41)], all);
+        assert_eq!(vec![handles[0], handles[2], recycled, handles[4]], arena.keys().collect::<Vec<_>>());
+
+        arena.values_mut().for_each(|value| *value *= 2);
+        assert_eq!(vec![2, 42, 200, 82], arena.values().copied().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_arena_drain() {
+        let mut arena = GenIndexArena::<&str>::new();
+        let a = arena.insert("a");
+        let b = arena.insert("b");
+        let c = arena.insert("c");
+        arena.remove(b).unwrap();
+
+        assert_eq!(vec![(a, "a"), (c, "c")], arena.drain().collect::<Vec<_>>());
+        assert!(arena.is_empty());
+        assert_eq!(None, arena.get(a));
+
+        // even an unconsumed drain empties the arena
+        let d = arena.insert("d");
+        assert!(d.get_generation() > 0);
+        drop(arena.drain());
+        assert!(!arena.contains(d));
+        assert_eq!(0, arena.iter().count());
+    }
+
+    #[test]
+    fn test_arena_clear() {
+        let mut arena = GenIndexArena::<String>::new();
+        let old: Vec<_> = (0..3).map(|i| arena.insert(i.to_string())).collect();
+        arena.clear();
+        assert!(arena.is_empty());
+        assert_eq!(0, arena.iter().count());
+
+        let new: Vec<_> = (0..3).map(|i| arena.insert(i.to_string())).collect();
+        for (old, new) in old.iter().zip(new.iter()) {
+            assert!(new.get_generation() > old.get_generation());
+            assert_eq!(None, arena.get(*old));
+            assert_eq!(Err(GenIndexError::StaleGeneration), arena.remove(*old));
+        }
+        assert_eq!(3, arena.len());
+    }
+
+    #[test]
+    fn test_arena_index() {
+        let mut arena = GenIndexArena::<(i32, i32)>::new();
+        let gi = arena.insert((1, 2));
+        arena[gi].0 += 10;
+        assert_eq!((11, 2), arena[gi]);
+    }
+
+    #[test]
+    #[should_panic(expected = "no value stored")]
+    fn test_arena_index_stale() {
+        let mut arena = GenIndexArena::<u32>::new();
+        let gi = arena.insert(1);
+        arena.remove(gi).unwrap();
+        arena.insert(2);
+        let _ = arena[gi];
+    }
+
+    #[test]
+    fn test_arena_into_iter() {
+        let mut arena = GenIndexArena::<String>::new();
+        let a = arena.insert("a".to_string());
+        let b = arena.insert("b".to_string());
+        let c = arena.insert("c".to_string());
+        arena.remove(b).unwrap();
+        let owned: Vec<(GenIndex<u32, u32>, String)> = arena.into_iter().collect();
+        assert_eq!(vec![(a, "a".to_string()), (c, "c".to_string())], owned);
+    }
+
+    #[test]
+    fn test_arena_entry() {
+        let mut arena = GenIndexArena::<Vec<u32>>::new();
+        let a = arena.insert(vec![1]);
+        arena.entry(a).unwrap().or_default().push(2);
+        arena.entry(a).unwrap().and_modify(|v| v.push(3)).or_default();
+        assert_eq!(Some(&vec![1, 2, 3]), arena.get(a));
+
+        match arena.entry(a).unwrap() {
+            ArenaEntry::Occupied(mut entry) => {
+                assert_eq!(vec![1, 2, 3], entry.insert(vec![4]));
+                assert_eq!(vec![4], entry.remove());
+            },
+            ArenaEntry::Vacant(_) => panic!("a is live"),
+        }
+
+        // a vacant entry brings a newer handle to life, but refuses
+        // the old one
+        assert_eq!(Err(GenIndexError::StaleGeneration), arena.entry(a).map(|e| e.key()));
+        let newer = GenIndex::new(a.get_index(), 5);
+        arena.entry(newer).unwrap().or_insert_with(|| vec![5]);
+        assert_eq!(Some(&vec![5]), arena.get(newer));
+
+        // even past the end
+        let far = GenIndex::new(10, 0);
+        assert_eq!(far, arena.entry(far).unwrap().key());
+        arena.entry(far).unwrap().or_insert(vec![10]);
+        assert_eq!(vec![newer, far], arena.keys().collect::<Vec<_>>());
+        assert_eq!(Some(&vec![10]), arena.get(far));
+    }
+
+    #[test]
+    fn test_arena_retain() {
+        let mut arena = GenIndexArena::<u32>::new();
+        let handles: Vec<_> = (0..10).map(|i| arena.insert(i)).collect();
+        arena.remove(handles[4]).unwrap();
+
+        let mut seen = Vec::new();
+        arena.retain(|gi, value| {
+            seen.push(gi);
+            *value += 100;
+            *value % 2 == 0
+        });
+        assert_eq!(9, seen.len());
+        assert_eq!(4, arena.len());
+        assert_eq!(vec![100, 102, 106, 108], arena.iter().map(|(_, &v)| v).collect::<Vec<_>>());
+        assert!(!arena.contains(handles[1]));
+        assert_eq!(Err(GenIndexError::AlreadyDeleted), arena.remove(handles[1]));
+        assert_eq!(6, arena.set().free_count());
+    }
+
+    #[test]
+    fn test_arena_get_disjoint_mut() {
+        let mut arena = GenIndexArena::<u32>::new();
+        let a = arena.insert(1);
+        let b = arena.insert(2);
+        let c = arena.insert(3);
+
+        let (x, y) = arena.get2_mut(a, c).unwrap();
+        *x += 10;
+        *y += 10;
+        assert_eq!(vec![11, 2, 13], arena.iter().map(|(_, &v)| v).collect::<Vec<_>>());
+
+        let [x, y, z] = arena.get_disjoint_mut([c, b, a]).unwrap();
+        assert_eq!((13, 2, 11), (*x, *y, *z));
+
+        assert!(arena.get2_mut(b, b).is_none());
+        arena.remove(b).unwrap();
+        let d = arena.insert(4);
+        // the stale handle shares its slot with d, but is refused all the same
+        assert!(arena.get2_mut(b, d).is_none());
+        assert!(arena.get2_mut(a, d).is_some());
+        assert!(arena.get_disjoint_mut([a, GenIndex::new(99, 0)]).is_none());
+    }
+
+    #[test]
+    fn test_arena_compact() {
+        let mut arena = GenIndexArena::<u32>::new();
+        let mut handles: Vec<_> = (0..10).map(|i| arena.insert(i)).collect();
+        for i in (0..10).step_by(3) {
+            arena.remove(handles[i]).unwrap();
+        }
+        let kept: Vec<_> = handles.iter().copied().filter(|&gi| arena.contains(gi)).collect();
+
+        let mut remapped = 0;
+        let moved = arena.compact(|old, new| {
+            remapped += 1;
+            for gi in handles.iter_mut().filter(|gi| **gi == old) {
+                *gi = new;
+            }
+        });
+        assert_eq!(moved, remapped);
+        assert_eq!(6, arena.len());
+        assert!(arena.keys().all(|gi| gi.get_index() < 6));
+        for i in (0..10).filter(|i| i % 3 != 0) {
+            assert_eq!(Some(&(i as u32)), arena.get(handles[i]));
+        }
+        let gone = kept.iter().filter(|&&gi| !arena.contains(gi)).count();
+        assert_eq!(moved, gone);
+
+        // new values go into the freed slots above
+        let gi = arena.insert(42);
+        assert!(gi.get_index() >= 6);
+        assert_eq!(Some(&42), arena.get(gi));
+
+        // a panicking callback leaves every value under its new handle
+        let mut arena = GenIndexArena::<u32>::new();
+        let handles: Vec<_> = (0..3).map(|i| arena.insert(i)).collect();
+        arena.remove(handles[0]).unwrap();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            arena.compact(|_, _| panic!("callback failed"))
+        }));
+        assert!(result.is_err());
+        assert_eq!(vec![(0, 2), (1, 1)],
+                   arena.iter().map(|(gi, &v)| (gi.get_index(), v)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_arena_capacity() {
+        let mut arena = GenIndexArena::<u32>::with_capacity(64);
+        assert!(arena.capacity() >= 64);
+        for i in 0..64 {
+            arena.insert(i);
+        }
+        arena.reserve(100);
+        assert!(arena.capacity() >= 164);
+    }
+
+    #[test]
+    fn test_arena_memory_usage() {
+        let arena = GenIndexArena::<u64>::with_capacity(64);
+        let usage = arena.memory_usage();
+        let set = arena.set().memory_usage();
+        assert!(usage.slots >= set.slots + 64 * mem::size_of::<Option<u64>>());
+        assert_eq!(set.free_list, usage.free_list);
+        assert!(usage.metadata > mem::size_of::<GenIndexArena<u64>>());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_arena_par_iter() {
+        let mut arena = GenIndexArena::<u64>::new();
+        let handles: Vec<_> = (0..1000).map(|i| arena.insert(i)).collect();
+        for gi in handles.iter().step_by(2) {
+            arena.remove(*gi).unwrap();
+        }
+
+        arena.par_iter_mut().for_each(|(gi, value)| *value += gi.get_index() as u64);
+        let sum: u64 = arena.par_iter().map(|(_, &v)| v).sum();
+        assert_eq!((0..1000).filter(|i| i % 2 == 1).map(|i| 2 * i).sum::<u64>(), sum);
+
+        let mut seen: Vec<_> = arena.par_iter().map(|(gi, _)| gi).collect();
+        seen.sort();
+        assert_eq!(arena.keys().collect::<Vec<_>>(), seen);
+
+        // values only Send are modified in parallel just as well
+        let mut cells = GenIndexArena::<std::cell::Cell<u64>>::new();
+        let gi = cells.insert(std::cell::Cell::new(1));
+        cells.par_iter_mut().for_each(|(_, cell)| cell.set(cell.get() + 1));
+        assert_eq!(2, cells[gi].get());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_arena_serde() {
+        let mut arena = GenIndexArena::<String>::new();
+        let a = arena.insert("a".to_string());
+        let b = arena.insert("b".to_string());
+        arena.remove(a).unwrap();
+        let c = arena.insert("c".to_string());
+
+        let json = serde_json::to_string(&arena).unwrap();
+        let mut reloaded: GenIndexArena<String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(arena, reloaded);
+        assert_eq!(None, reloaded.get(a));
+        assert_eq!(Some(&"b".to_string()), reloaded.get(b));
+        assert_eq!(Some(&"c".to_string()), reloaded.get(c));
+
+        // and it carries on where the saved one left off
+        reloaded.remove(c).unwrap();
+        arena.remove(c).unwrap();
+        assert_eq!(arena.insert("d".to_string()), reloaded.insert("d".to_string()));
+    }
+}
@@ -0,0 +1,132 @@
+//! An entity set for async code, behind the `tokio` feature.
+
+use std::{ops::AddAssign,
+          sync::Arc};
+
+use num::{Num, Bounded, ToPrimitive};
+use tokio::sync::{Mutex, MutexGuard};
+
+use super::{GenIndex, GenIndexConfig, GenIndexEntitySet, GenIndexError};
+
+/// AsyncGenIndexEntitySet
+///
+/// A GenIndexEntitySet shared behind a `tokio::sync::Mutex`, so
+/// that async tasks waiting for the lock yield to the executor
+/// instead of blocking the thread. Cloning is cheap, and all
+/// clones refer to the same set.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// async fn spawn_and_despawn(gi: AsyncGenIndexEntitySet<u64, u64>) {
+///     let idx = gi.next_index().await;
+///     assert!(gi.is_live(idx).await);
+///     gi.delete_index(idx).await.unwrap();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct AsyncGenIndexEntitySet<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+                                  G: Num + AddAssign + Copy + Bounded + PartialOrd> {
+    inner: Arc<Mutex<GenIndexEntitySet<I, G>>>,
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> Clone for AsyncGenIndexEntitySet<I, G> {
+    fn clone(&self) -> Self {
+        AsyncGenIndexEntitySet{inner: self.inner.clone()}
+    }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> AsyncGenIndexEntitySet<I, G> {
+
+    /// Create a new, empty set.
+    pub fn new() -> AsyncGenIndexEntitySet<I, G> {
+        Self::from_set(GenIndexEntitySet::new_unsync())
+    }
+
+    /// Create a new, empty set with the given settings.
+    pub fn with_config(config: GenIndexConfig) -> AsyncGenIndexEntitySet<I, G> {
+        Self::from_set(GenIndexEntitySet::from_config(config))
+    }
+
+    /// Share an existing set with async tasks.
+    pub fn from_set(set: GenIndexEntitySet<I, G>) -> AsyncGenIndexEntitySet<I, G> {
+        AsyncGenIndexEntitySet{inner: Arc::new(Mutex::new(set))}
+    }
+
+    /// Wait for the lock, and give access to the whole set, for
+    /// anything not covered by the shortcuts below.
+    pub async fn lock(&self) -> MutexGuard<'_, GenIndexEntitySet<I, G>> {
+        self.inner.lock().await
+    }
+
+    /// See `GenIndexEntitySet::next_index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as
+    /// `GenIndexEntitySet::next_index`.
+    pub async fn next_index(&self) -> GenIndex<I, G> {
+        self.inner.lock().await.next_index()
+    }
+
+    /// See `GenIndexEntitySet::try_next_index`.
+    pub async fn try_next_index(&self) -> Result<GenIndex<I, G>, GenIndexError> {
+        self.inner.lock().await.try_next_index()
+    }
+
+    /// See `GenIndexEntitySet::delete_index`.
+    pub async fn delete_index(&self, gi: GenIndex<I, G>) -> Result<(), GenIndexError> {
+        self.inner.lock().await.delete_index(gi)
+    }
+
+    /// See `GenIndexEntitySet::is_live`.
+    pub async fn is_live(&self, gi: GenIndex<I, G>) -> bool {
+        self.inner.lock().await.is_live(gi)
+    }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> Default for AsyncGenIndexEntitySet<I, G> {
+    fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{future::Future, pin::pin, task::Context, task::Poll, task::Waker, thread};
+    use super::*;
+
+    /// just enough of an executor to drive the futures here.
+    fn block_on<F: Future>(fut: F) -> F::Output {
+        let mut fut = pin!(fut);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(out) = fut.as_mut().poll(&mut cx) {
+                return out;
+            }
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn test_async_set() {
+        let gi = AsyncGenIndexEntitySet::<u64, u64>::new();
+        let other = gi.clone();
+
+        block_on(async {
+            let idx1 = gi.next_index().await;
+            assert!(other.is_live(idx1).await);
+            other.delete_index(idx1).await.unwrap();
+            assert_eq!(Err(GenIndexError::AlreadyDeleted), gi.delete_index(idx1).await);
+
+            let idx2 = gi.try_next_index().await.unwrap();
+            assert_eq!(idx1.get_index(), idx2.get_index());
+            assert_eq!(1, gi.lock().await.live_count());
+        });
+    }
+}
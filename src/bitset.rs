@@ -0,0 +1,277 @@
+//! A growable set of small numbers, one bit each.
+
+use std::{hash::Hash,
+          hash::Hasher,
+          sync::OnceLock};
+
+use num::ToPrimitive;
+
+/// BitSet
+///
+/// A set of `usize` numbers, kept as one bit per number in a vector
+/// of 64-bit words, which grows to fit the largest number inserted.
+/// Used for the component masks of a Registry; hand the raw words to
+/// whatever else needs to combine such sets a word at a time.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// let mut bits = BitSet::new();
+/// bits.insert(3);
+/// bits.insert(70);
+/// assert!(bits.contains(70));
+/// assert_eq!(vec![3, 70], bits.iter().collect::<Vec<_>>());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct BitSet {
+    /// never ends in a zero word, so equal sets compare equal.
+    words: Vec<u64>,
+}
+
+const BITS: usize = 64;
+
+impl BitSet {
+    /// Create a new, empty set.
+    pub const fn new() -> BitSet { BitSet{words: Vec::new()} }
+
+    /// Add a number to the set, returning true if it was not there
+    /// yet.
+    pub fn insert(&mut self, bit: usize) -> bool {
+        let (word, mask) = (bit / BITS, 1 << (bit % BITS));
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        let absent = self.words[word] & mask == 0;
+        self.words[word] |= mask;
+        absent
+    }
+
+    /// Take a number out of the set, returning true if it was there.
+    pub fn remove(&mut self, bit: usize) -> bool {
+        let (word, mask) = (bit / BITS, 1 << (bit % BITS));
+        match self.words.get_mut(word) {
+            Some(w) if *w & mask != 0 => {
+                *w &= !mask;
+                self.trim();
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Check whether the number is in the set.
+    pub fn contains(&self, bit: usize) -> bool {
+        self.words.get(bit / BITS).is_some_and(|w| w & (1 << (bit % BITS)) != 0)
+    }
+
+    /// Check whether every number in `other` is in this set too.
+    pub fn is_superset(&self, other: &BitSet) -> bool {
+        other.words.iter().enumerate()
+            .all(|(i, w)| self.words.get(i).copied().unwrap_or(0) & w == *w)
+    }
+
+    /// Keep only the numbers that are in `other` as well.
+    pub fn intersect_with(&mut self, other: &BitSet) {
+        self.words.truncate(other.words.len());
+        for (w, o) in self.words.iter_mut().zip(&other.words) {
+            *w &= o;
+        }
+        self.trim();
+    }
+
+    /// Take out every number that is in `other`.
+    pub fn difference_with(&mut self, other: &BitSet) {
+        for (w, o) in self.words.iter_mut().zip(&other.words) {
+            *w &= !o;
+        }
+        self.trim();
+    }
+
+    /// Add every number in `other`.
+    pub fn union_with(&mut self, other: &BitSet) {
+        if other.words.len() > self.words.len() {
+            self.words.resize(other.words.len(), 0);
+        }
+        for (w, o) in self.words.iter_mut().zip(&other.words) {
+            *w |= o;
+        }
+    }
+
+    /// Take every number out of the set.
+    pub fn clear(&mut self) { self.words.clear() }
+
+    /// return how many numbers are in the set.
+    pub fn len(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    /// return true if the set is empty.
+    pub fn is_empty(&self) -> bool { self.words.is_empty() }
+
+    /// return the words the bits are kept in, bit `n` being bit
+    /// `n % 64` of word `n / 64`. Past the last word, all bits are
+    /// clear.
+    pub fn as_words(&self) -> &[u64] { &self.words }
+
+    /// iterate over the numbers in the set, in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, &word)| {
+            let mut rest = word;
+            std::iter::from_fn(move || {
+                if rest == 0 {
+                    return None;
+                }
+                let bit = rest.trailing_zeros() as usize;
+                rest &= rest - 1;
+                Some(i * BITS + bit)
+            })
+        })
+    }
+
+    fn trim(&mut self) {
+        while self.words.last() == Some(&0) {
+            self.words.pop();
+        }
+    }
+}
+
+/// BitSetIntoIter
+///
+/// The numbers of a BitSet, in ascending order, as returned by its
+/// `into_iter`.
+#[derive(Debug, Clone)]
+pub struct BitSetIntoIter {
+    words: std::iter::Enumerate<std::vec::IntoIter<u64>>,
+    /// the bits of the current word not yet returned.
+    rest: u64,
+    /// the number standing for bit 0 of the current word.
+    base: usize,
+}
+
+impl Iterator for BitSetIntoIter {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        while self.rest == 0 {
+            let (i, word) = self.words.next()?;
+            self.rest = word;
+            self.base = i * BITS;
+        }
+        let bit = self.rest.trailing_zeros() as usize;
+        self.rest &= self.rest - 1;
+        Some(self.base + bit)
+    }
+}
+
+impl IntoIterator for BitSet {
+    type Item = usize;
+    type IntoIter = BitSetIntoIter;
+
+    fn into_iter(self) -> BitSetIntoIter {
+        BitSetIntoIter{words: self.words.into_iter().enumerate(), rest: 0, base: 0}
+    }
+}
+
+impl std::iter::FromIterator<usize> for BitSet {
+    fn from_iter<T: IntoIterator<Item = usize>>(iter: T) -> Self {
+        let mut bits = BitSet::new();
+        for bit in iter {
+            bits.insert(bit);
+        }
+        bits
+    }
+}
+
+/// The slot numbers of the live indices of a GenIndexEntitySet, built
+/// from its slots the first time they are asked for, and kept up to
+/// date from then on. Like its hooks, they are no part of the set's
+/// state, only derived from it, and so take no part in comparing,
+/// hashing or saving it.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LiveMask {
+    mask: OnceLock<BitSet>,
+}
+
+impl LiveMask {
+    pub(crate) fn new() -> LiveMask { LiveMask{mask: OnceLock::new()} }
+
+    /// return the mask, building it with `build` if need be.
+    pub(crate) fn get_or_build<F: FnOnce() -> BitSet>(&self, build: F) -> &BitSet {
+        self.mask.get_or_init(build)
+    }
+
+    pub(crate) fn insert<I: ToPrimitive>(&mut self, index: I) {
+        if let (Some(mask), Some(slot)) = (self.mask.get_mut(), index.to_usize()) {
+            mask.insert(slot);
+        }
+    }
+
+    pub(crate) fn remove<I: ToPrimitive>(&mut self, index: I) {
+        if let (Some(mask), Some(slot)) = (self.mask.get_mut(), index.to_usize()) {
+            mask.remove(slot);
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        if let Some(mask) = self.mask.get_mut() {
+            mask.clear();
+        }
+    }
+}
+
+impl PartialEq for LiveMask {
+    fn eq(&self, _other: &Self) -> bool { true }
+}
+
+impl Hash for LiveMask {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bitset() {
+        let mut bits = BitSet::new();
+        assert!(bits.insert(0));
+        assert!(bits.insert(130));
+        assert!(!bits.insert(130));
+        assert_eq!(2, bits.len());
+        assert_eq!(3, bits.as_words().len());
+
+        assert!(bits.remove(130));
+        assert!(!bits.remove(130));
+        assert!(!bits.remove(1000));
+        assert_eq!(&[1], bits.as_words());
+        assert_eq!(vec![0].into_iter().collect::<BitSet>(), bits);
+    }
+
+    #[test]
+    fn test_bitset_set_operations() {
+        let mut a: BitSet = vec![1, 64, 65, 200].into_iter().collect();
+        let b: BitSet = vec![1, 65].into_iter().collect();
+        assert!(a.is_superset(&b));
+        assert!(!b.is_superset(&a));
+
+        let mut c = b.clone();
+        c.union_with(&vec![300].into_iter().collect());
+        assert_eq!(vec![1, 65, 300], c.iter().collect::<Vec<_>>());
+
+        assert_eq!(vec![1, 64, 65, 200], a.clone().into_iter().collect::<Vec<_>>());
+        let mut d = a.clone();
+        d.difference_with(&vec![64, 200, 500].into_iter().collect());
+        assert_eq!(vec![1, 65], d.iter().collect::<Vec<_>>());
+        assert_eq!(2, d.as_words().len());
+
+        a.intersect_with(&b);
+        assert_eq!(b, a);
+        a.clear();
+        assert!(a.is_empty());
+        assert!(a.is_superset(&BitSet::new()));
+    }
+}
@@ -0,0 +1,107 @@
+//! Creating an entity with all of its components in one go.
+
+use std::ops::AddAssign;
+
+use num::{Num, Bounded, ToPrimitive};
+
+use super::{Component, GenIndex, Prefab, Registry};
+
+/// EntityBuilder
+///
+/// A newly created entity of a Registry, being given its
+/// components, as returned by `Registry::spawn`. The entity is live
+/// from the start; `build` just hands its handle back once all the
+/// components are in.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// struct Position(f32, f32);
+/// struct Velocity(f32, f32);
+///
+/// let mut registry = Registry::<u32, u32>::new();
+/// let ball = registry.spawn()
+///     .with(Position(0.0, 0.0))
+///     .with(Velocity(1.0, 0.5))
+///     .build();
+/// assert!(registry.has::<Position>(ball));
+/// assert!(registry.has::<Velocity>(ball));
+/// ```
+#[derive(Debug)]
+pub struct EntityBuilder<'a,
+                         I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+                         G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> {
+    registry: &'a mut Registry<I, G>,
+    gi: GenIndex<I, G>,
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> Registry<I, G> {
+    /// Create a new entity, and return a builder to give it its
+    /// components with.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `create`.
+    pub fn spawn(&mut self) -> EntityBuilder<'_, I, G> {
+        let gi = self.create();
+        EntityBuilder{registry: self, gi}
+    }
+}
+
+impl<'a,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> EntityBuilder<'a, I, G> {
+    /// Give the entity a component, replacing the one of the same
+    /// type given before, if any.
+    pub fn with<T: Component>(self, value: T) -> Self {
+        if let Err(e) = self.registry.insert(self.gi, value) {
+            // the entity is ours, and live
+            panic!("with: {}", e);
+        }
+        self
+    }
+
+    /// Give the entity the components and tags of the prefab,
+    /// replacing the ones of the same types given before, if any.
+    pub fn with_prefab(self, prefab: &Prefab<I, G>) -> Self {
+        if let Err(e) = prefab.instantiate(self.registry, self.gi) {
+            // the entity is ours, and live
+            panic!("with_prefab: {}", e);
+        }
+        self
+    }
+
+    /// return the handle of the entity being built.
+    pub fn id(&self) -> GenIndex<I, G> { self.gi }
+
+    /// Finish the entity, and return its handle.
+    pub fn build(self) -> GenIndex<I, G> { self.gi }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_fixtures::{Health, Armor};
+
+    #[test]
+    fn test_spawn() {
+        let mut registry = Registry::<u32, u32>::new();
+        let plain = registry.spawn().build();
+        assert!(registry.is_live(plain));
+        assert_eq!(0, registry.components_of(plain).count());
+
+        let knight = registry.spawn().with(Health(10)).with(Armor(5)).with(Health(12)).build();
+        assert_eq!(Some(&Health(12)), registry.get(knight));
+        assert_eq!(Some(&Armor(5)), registry.get(knight));
+        assert_eq!(vec![knight], registry.query::<(&Health, &Armor)>().map(|(gi, _)| gi).collect::<Vec<_>>());
+
+        let builder = registry.spawn();
+        let id = builder.id();
+        assert_eq!(id, builder.with(Armor(1)).build());
+    }
+}
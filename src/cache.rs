@@ -0,0 +1,153 @@
+//! Per-thread caches of pre-allocated indices.
+
+use std::{vec::Vec,
+          ops::AddAssign};
+
+use num::{Num, Bounded, ToPrimitive};
+
+use super::{GenIndex, GenIndexError, SharedGenIndexEntitySet};
+use super::sync::lock;
+
+/// LocalIndexCache
+///
+/// A handle to a SharedGenIndexEntitySet that keeps a small stock of
+/// indices allocated ahead of time. `next_index` serves from the
+/// stock, and only takes the lock to refill it, `batch` indices at a
+/// time. Give each thread its own cache, and hot spawn loops rarely
+/// touch the mutex at all.
+///
+/// The indices in stock are live as far as the set is concerned.
+/// They are handed back to the set when the cache is dropped.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use std::thread;
+/// use gen_indices::*;
+///
+/// let gi = GenIndexEntitySet::<u64, u64>::new();
+/// let workers: Vec<_> = (0..4).map(|_| {
+///     let mut cache = LocalIndexCache::new(gi.clone(), 64);
+///     thread::spawn(move || {
+///         for _ in 0..1000 {
+///             cache.next_index().unwrap();
+///         }
+///     })
+/// }).collect();
+/// for w in workers {
+///     w.join().unwrap();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct LocalIndexCache<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+                           G: Num + AddAssign + Copy + Bounded + PartialOrd> {
+    set: SharedGenIndexEntitySet<I, G>,
+    stock: Vec<GenIndex<I, G>>,
+    batch: usize,
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> LocalIndexCache<I, G> {
+
+    /// Create a new, empty cache for `set`, which will refill
+    /// `batch` indices at a time. A batch of zero is taken as one.
+    pub fn new(set: SharedGenIndexEntitySet<I, G>, batch: usize) -> LocalIndexCache<I, G> {
+        let batch = batch.max(1);
+        LocalIndexCache{set, stock: Vec::with_capacity(batch), batch}
+    }
+
+    /// return the set this cache allocates from.
+    pub fn set(&self) -> &SharedGenIndexEntitySet<I, G> { &self.set }
+
+    /// return the number of indices currently in stock.
+    pub fn cached_count(&self) -> usize { self.stock.len() }
+
+    /// hand out an index from the stock, refilling it first if it is
+    /// empty. The refill takes as many indices as the set can give,
+    /// up to the batch size; an error is only reported if it can't
+    /// give a single one.
+    pub fn next_index(&mut self) -> Result<GenIndex<I, G>, GenIndexError> {
+        if self.stock.is_empty() {
+            self.refill()?;
+        }
+        Ok(self.stock.pop().unwrap())
+    }
+
+    /// Delete an index. This goes straight to the set.
+    pub fn delete_index(&self, gi: GenIndex<I, G>) -> Result<(), GenIndexError> {
+        lock(&self.set).delete_index(gi)
+    }
+
+    /// Hand every index in stock back to the set.
+    pub fn flush(&mut self) {
+        if !self.stock.is_empty() {
+            let mut set = lock(&self.set);
+            for gi in self.stock.drain(..) {
+                // the stock is live and ours alone, so this cannot fail
+                let _ = set.delete_index(gi);
+            }
+        }
+    }
+
+    fn refill(&mut self) -> Result<(), GenIndexError> {
+        let mut set = lock(&self.set);
+        for _ in 0..self.batch {
+            match set.try_next_index() {
+                Ok(gi) => self.stock.push(gi),
+                Err(e) if self.stock.is_empty() => return Err(e),
+                Err(_) => break,
+            }
+        }
+        // serve in allocation order
+        self.stock.reverse();
+        Ok(())
+    }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> Drop for LocalIndexCache<I, G> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::GenIndexEntitySet;
+
+    #[test]
+    fn test_local_cache() {
+        let gi = GenIndexEntitySet::<u64, u64>::new();
+        let mut cache = LocalIndexCache::new(gi.clone(), 4);
+
+        let idx = cache.next_index().unwrap();
+        assert_eq!(0, idx.get_index());
+        assert_eq!(3, cache.cached_count());
+        assert_eq!(4, lock(&gi).live_count());
+
+        // served from stock, in order, without growing the set
+        assert_eq!(1, cache.next_index().unwrap().get_index());
+        assert_eq!(4, lock(&gi).total_allocated());
+
+        cache.delete_index(idx).unwrap();
+        assert!(!lock(&gi).is_live(idx));
+
+        // unused stock goes back when the cache goes away
+        drop(cache);
+        assert_eq!(1, lock(&gi).live_count());
+    }
+
+    #[test]
+    fn test_local_cache_partial_refill() {
+        let gi = GenIndexEntitySet::<u64, u64>::with_capacity_limit(3);
+        let mut cache = LocalIndexCache::new(gi.clone(), 10);
+
+        for _ in 0..3 {
+            cache.next_index().unwrap();
+        }
+        assert_eq!(Err(GenIndexError::CapacityExhausted), cache.next_index());
+    }
+}
@@ -0,0 +1,207 @@
+//! A vector in fixed-size chunks, which never moves its elements.
+
+use std::{fmt, mem};
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+
+/// the number of elements in a chunk.
+pub(crate) const CHUNK: usize = 4096;
+
+/// ChunkedVec
+///
+/// The storage behind GenIndexArena: a growable list of elements,
+/// kept in chunks of CHUNK elements each, allocated once, at full
+/// size, and never reallocated. Growing only ever allocates another
+/// chunk, instead of copying everything over as a `Vec` would, and
+/// an element stays at the same address for as long as it is
+/// stored.
+pub(crate) struct ChunkedVec<T> {
+    chunks: Vec<Vec<T>>,
+    len: usize,
+}
+
+impl<T> ChunkedVec<T> {
+    pub(crate) fn new() -> ChunkedVec<T> {
+        ChunkedVec{chunks: Vec::new(), len: 0}
+    }
+
+    /// the number of elements the chunks allocated so far hold.
+    pub(crate) fn capacity(&self) -> usize { self.chunks.len() * CHUNK }
+
+    /// the size of the table of chunks.
+    pub(crate) fn table_bytes(&self) -> usize {
+        self.chunks.capacity() * mem::size_of::<Vec<T>>()
+    }
+
+    /// allocate chunks for at least `additional` more elements.
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        let needed = (self.len + additional).div_ceil(CHUNK);
+        while self.chunks.len() < needed {
+            self.chunks.push(Vec::with_capacity(CHUNK));
+        }
+    }
+
+    pub(crate) fn get(&self, i: usize) -> Option<&T> {
+        self.chunks.get(i / CHUNK)?.get(i % CHUNK)
+    }
+
+    pub(crate) fn get_mut(&mut self, i: usize) -> Option<&mut T> {
+        self.chunks.get_mut(i / CHUNK)?.get_mut(i % CHUNK)
+    }
+
+    pub(crate) fn last(&self) -> Option<&T> {
+        self.len.checked_sub(1).and_then(|i| self.get(i))
+    }
+
+    pub(crate) fn push(&mut self, value: T) {
+        self.reserve(1);
+        self.chunks[self.len / CHUNK].push(value);
+        self.len += 1;
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<T> {
+        let i = self.len.checked_sub(1)?;
+        self.len = i;
+        self.chunks[i / CHUNK].pop()
+    }
+
+    /// grow to `len` elements, made by `f`; never shrinks.
+    pub(crate) fn resize_with<F: FnMut() -> T>(&mut self, len: usize, mut f: F) {
+        while self.len < len {
+            self.push(f());
+        }
+    }
+
+    pub(crate) fn swap(&mut self, a: usize, b: usize) {
+        if let Some([a, b]) = self.get_disjoint_mut([a, b]) {
+            std::mem::swap(a, b);
+        }
+    }
+
+    /// the elements at several positions at once, for modification,
+    /// or None if any is out of range, or given twice.
+    pub(crate) fn get_disjoint_mut<const N: usize>(&mut self, is: [usize; N]) -> Option<[&mut T; N]> {
+        for (n, &i) in is.iter().enumerate() {
+            if i >= self.len || is[..n].contains(&i) {
+                return None;
+            }
+        }
+        let chunks = self.chunks.as_mut_ptr();
+        // every position is in range and they are all different, so
+        // these are disjoint borrows of elements of `self`, which is
+        // borrowed mutably for as long as they live
+        Some(is.map(|i| unsafe {
+            &mut *(*chunks.add(i / CHUNK)).as_mut_ptr().add(i % CHUNK)
+        }))
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        self.chunks.iter().flatten()
+    }
+
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> + '_ {
+        self.chunks.iter_mut().flatten()
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<T: Send + Sync> ChunkedVec<T> {
+    /// the elements with their positions, on rayon's worker threads.
+    pub(crate) fn par_iter(&self) -> impl ParallelIterator<Item = (usize, &T)> + '_ {
+        self.chunks.par_iter().enumerate().flat_map_iter(|(c, chunk)| {
+            chunk.iter().enumerate().map(move |(i, value)| (c * CHUNK + i, value))
+        })
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<T: Send> ChunkedVec<T> {
+    /// the elements with their positions, for modification, on
+    /// rayon's worker threads.
+    pub(crate) fn par_iter_mut(&mut self) -> impl ParallelIterator<Item = (usize, &mut T)> + '_ {
+        self.chunks.par_iter_mut().enumerate().flat_map_iter(|(c, chunk)| {
+            chunk.iter_mut().enumerate().map(move |(i, value)| (c * CHUNK + i, value))
+        })
+    }
+}
+
+impl<T: Clone> Clone for ChunkedVec<T> {
+    fn clone(&self) -> Self {
+        // cloning the chunks themselves would only allocate what is
+        // in use, and the next push would reallocate
+        let mut clone = ChunkedVec::new();
+        clone.reserve(self.len);
+        for value in self.iter() {
+            clone.push(value.clone());
+        }
+        clone
+    }
+}
+
+impl<T: PartialEq> PartialEq for ChunkedVec<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for ChunkedVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: Serialize> Serialize for ChunkedVec<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for ChunkedVec<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let values = Vec::<T>::deserialize(deserializer)?;
+        let mut chunked = ChunkedVec::new();
+        chunked.reserve(values.len());
+        for value in values {
+            chunked.push(value);
+        }
+        Ok(chunked)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunked_vec() {
+        let mut v = ChunkedVec::new();
+        v.push(0usize);
+        let first: *const usize = v.get(0).unwrap();
+        v.resize_with(CHUNK * 2 + 1, || 7);
+        assert_eq!(CHUNK * 2 + 1, v.len);
+        assert_eq!(CHUNK * 3, v.capacity());
+        // growing did not move what was there
+        assert_eq!(first, v.get(0).unwrap() as *const usize);
+
+        *v.get_mut(CHUNK).unwrap() = 1;
+        v.swap(0, CHUNK);
+        assert_eq!(Some(&1), v.get(0));
+        assert_eq!(Some(&0), v.get(CHUNK));
+
+        let [a, b] = v.get_disjoint_mut([CHUNK * 2, 1]).unwrap();
+        *a = 2;
+        *b = 3;
+        assert_eq!(Some(&2), v.last());
+        assert!(v.get_disjoint_mut([1, 1]).is_none());
+        assert!(v.get_disjoint_mut([0, CHUNK * 3]).is_none());
+
+        assert_eq!(Some(2), v.pop());
+        assert_eq!(CHUNK * 2, v.iter().count());
+        assert_eq!(v, v.clone());
+    }
+}
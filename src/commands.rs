@@ -0,0 +1,337 @@
+//! Recording structural changes to a Registry, to make them later.
+
+use std::{error::Error, fmt, ops::AddAssign};
+
+use num::{Num, Bounded, ToPrimitive};
+
+use super::{Component, GenIndex, GenIndexError, Registry};
+
+/// PendingEntity
+///
+/// An entity a CommandBuffer is to spawn, which has no handle until
+/// the buffer is applied. Commands can be given for it all the same.
+#[derive(Hash, Debug, PartialEq, Eq, Copy, Clone)]
+pub struct PendingEntity(usize);
+
+/// CommandTarget
+///
+/// The entity a command is for: one already live, or one the same
+/// buffer is to spawn. Either converts into this.
+#[derive(Hash, Debug, PartialEq, Eq, Copy, Clone)]
+pub enum CommandTarget<I: Num + AddAssign + Copy,
+                       G: Num + AddAssign + Copy> {
+    /// an entity of the registry.
+    Live(GenIndex<I, G>),
+    /// an entity spawned when the buffer is applied.
+    Pending(PendingEntity),
+}
+
+impl<I: Num + AddAssign + Copy,
+     G: Num + AddAssign + Copy> From<GenIndex<I, G>> for CommandTarget<I, G> {
+    fn from(gi: GenIndex<I, G>) -> Self { CommandTarget::Live(gi) }
+}
+
+impl<I: Num + AddAssign + Copy,
+     G: Num + AddAssign + Copy> From<PendingEntity> for CommandTarget<I, G> {
+    fn from(pending: PendingEntity) -> Self { CommandTarget::Pending(pending) }
+}
+
+type Command<I, G> = Box<dyn FnOnce(&mut Registry<I, G>, GenIndex<I, G>) -> Result<(), GenIndexError> + Send>;
+
+/// CommandError
+///
+/// How applying a CommandBuffer failed: spawning its entities, in
+/// which case nothing was changed, or some of its commands, in
+/// which case the others were made all the same.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum CommandError<I: Num + AddAssign + Copy,
+                      G: Num + AddAssign + Copy> {
+    /// Spawning the entities failed, as `Registry::try_create` did.
+    Spawn(GenIndexError),
+    /// Some commands failed; the others were made.
+    Commands {
+        /// the entities spawned, as `apply` returns them otherwise.
+        spawned: Vec<GenIndex<I, G>>,
+        /// per command failed, in the order recorded, its position
+        /// among the commands recorded, spawns left out, and the
+        /// error.
+        failed: Vec<(usize, GenIndexError)>,
+    },
+}
+
+impl<I: Num + AddAssign + Copy,
+     G: Num + AddAssign + Copy> fmt::Display for CommandError<I, G> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandError::Spawn(e) => write!(f, "spawning failed: {}", e),
+            CommandError::Commands{failed, ..} => match failed.first() {
+                Some((n, e)) => write!(f, "{} commands failed, the first, command {}: {}", failed.len(), n, e),
+                None => write!(f, "no commands failed"),
+            },
+        }
+    }
+}
+
+impl<I: Num + AddAssign + Copy + fmt::Debug,
+     G: Num + AddAssign + Copy + fmt::Debug> Error for CommandError<I, G> {}
+
+/// CommandBuffer
+///
+/// Records spawning and despawning entities, and inserting and
+/// removing their components and tags, while a Registry cannot be
+/// changed, say because a query is going through it, and then makes
+/// all of these changes at once, in the order they were recorded,
+/// with `apply`.
+///
+/// Commands for entities which are no longer live by the time they
+/// are applied, having been despawned in the meantime, say, fail,
+/// as do any the Registry refuses; `apply` reports them all, after
+/// making the rest.
+///
+/// The index and generation types default to `u32`.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// struct Health(u32);
+/// struct Debris;
+///
+/// let mut registry = Registry::<u32, u32>::new();
+/// registry.spawn().with(Health(0)).build();
+/// registry.spawn().with(Health(5)).build();
+///
+/// let mut commands = CommandBuffer::new();
+/// for (gi, health) in registry.query::<&Health>() {
+///     if health.0 == 0 {
+///         commands.despawn(gi);
+///         let debris = commands.spawn();
+///         commands.add_tag::<Debris, _>(debris);
+///     }
+/// }
+/// let spawned = commands.apply(&mut registry).unwrap();
+/// assert!(registry.has_tag::<Debris>(spawned[0]));
+/// assert_eq!(2, registry.len());
+/// ```
+pub struct CommandBuffer<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static = u32,
+                         G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static = u32> {
+    spawns: usize,
+    commands: Vec<(CommandTarget<I, G>, Command<I, G>)>,
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> CommandBuffer<I, G> {
+
+    /// Create a new, empty buffer.
+    pub fn new() -> CommandBuffer<I, G> {
+        CommandBuffer{spawns: 0, commands: Vec::new()}
+    }
+
+    /// Record spawning a new entity, without components.
+    pub fn spawn(&mut self) -> PendingEntity {
+        self.spawns += 1;
+        PendingEntity(self.spawns - 1)
+    }
+
+    /// Record despawning an entity, along with all of its
+    /// components.
+    pub fn despawn<E: Into<CommandTarget<I, G>>>(&mut self, entity: E) {
+        self.push(entity, |registry, gi| registry.destroy(gi).map(|_| ()));
+    }
+
+    /// Record storing a component for an entity.
+    pub fn insert<E: Into<CommandTarget<I, G>>, T: Component>(&mut self, entity: E, value: T) {
+        self.push(entity, move |registry, gi| registry.insert(gi, value).map(|_| ()));
+    }
+
+    /// Record removing an entity's component of type `T`.
+    pub fn remove<T: Component, E: Into<CommandTarget<I, G>>>(&mut self, entity: E) {
+        self.push(entity, |registry, gi| {
+            registry.remove::<T>(gi);
+            Ok(())
+        });
+    }
+
+    /// Record tagging an entity with `T`.
+    pub fn add_tag<T: Component, E: Into<CommandTarget<I, G>>>(&mut self, entity: E) {
+        self.push(entity, |registry, gi| registry.add_tag::<T>(gi).map(|_| ()));
+    }
+
+    /// Record taking the tag `T` off an entity.
+    pub fn remove_tag<T: Component, E: Into<CommandTarget<I, G>>>(&mut self, entity: E) {
+        self.push(entity, |registry, gi| {
+            registry.remove_tag::<T>(gi);
+            Ok(())
+        });
+    }
+
+    /// return the number of commands recorded, spawns left out.
+    pub fn len(&self) -> usize { self.commands.len() }
+
+    /// return true if nothing at all is recorded.
+    pub fn is_empty(&self) -> bool { self.commands.is_empty() && self.spawns == 0 }
+
+    /// Make all the changes recorded, and return the handles of the
+    /// entities spawned, in the order `spawn` was called. The
+    /// buffer is left empty.
+    ///
+    /// The entities are spawned first, and if that fails, as
+    /// `Registry::try_create` may, the ones spawned already are
+    /// despawned again, nothing else is changed, and the error is
+    /// `CommandError::Spawn`. Otherwise every command is made, and
+    /// those that fail, as those for entities no longer live do,
+    /// failing as `GenIndexEntitySet::delete_index` would, are
+    /// reported together as `CommandError::Commands`.
+    pub fn apply(&mut self, registry: &mut Registry<I, G>) -> Result<Vec<GenIndex<I, G>>, CommandError<I, G>> {
+        let mut spawned = Vec::with_capacity(self.spawns);
+        for _ in 0..self.spawns {
+            match registry.try_create() {
+                Ok(gi) => spawned.push(gi),
+                Err(e) => {
+                    for gi in spawned {
+                        let _ = registry.destroy(gi);
+                    }
+                    return Err(CommandError::Spawn(e));
+                },
+            }
+        }
+        self.spawns = 0;
+        let mut failed = Vec::new();
+        for (n, (target, command)) in self.commands.drain(..).enumerate() {
+            let gi = match target {
+                CommandTarget::Live(gi) => gi,
+                CommandTarget::Pending(PendingEntity(n)) => spawned[n],
+            };
+            if let Err(e) = registry.entities().check_live(gi).and_then(|_| command(registry, gi)) {
+                failed.push((n, e));
+            }
+        }
+        if failed.is_empty() {
+            Ok(spawned)
+        } else {
+            Err(CommandError::Commands{spawned, failed})
+        }
+    }
+
+    fn push<E, F>(&mut self, entity: E, command: F)
+    where E: Into<CommandTarget<I, G>>,
+          F: FnOnce(&mut Registry<I, G>, GenIndex<I, G>) -> Result<(), GenIndexError> + Send + 'static {
+        let target = entity.into();
+        if let CommandTarget::Pending(PendingEntity(n)) = target {
+            assert!(n < self.spawns, "command for an entity spawned by another buffer");
+        }
+        self.commands.push((target, Box::new(command)));
+    }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> Default for CommandBuffer<I, G> {
+    fn default() -> Self { Self::new() }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> fmt::Debug for CommandBuffer<I, G> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CommandBuffer")
+            .field("spawns", &self.spawns)
+            .field("commands", &self.commands.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{GenIndexConfig, GenIndexEntitySet};
+    use super::super::test_fixtures::Health;
+
+    struct Frozen;
+
+    #[test]
+    fn test_command_buffer() {
+        let mut registry = Registry::<u32, u32>::new();
+        let a = registry.spawn().with(Health(1)).build();
+        let b = registry.spawn().with(Health(2)).build();
+        registry.add_tag::<Frozen>(b).unwrap();
+
+        let mut commands = CommandBuffer::new();
+        assert!(commands.is_empty());
+        let c = commands.spawn();
+        commands.insert(c, Health(3));
+        commands.insert(a, Health(10));
+        commands.remove::<Health, _>(b);
+        commands.remove_tag::<Frozen, _>(b);
+        commands.despawn(a);
+        assert_eq!(5, commands.len());
+        // nothing happens until applied
+        assert_eq!(Some(&Health(1)), registry.get(a));
+
+        let spawned = commands.apply(&mut registry).unwrap();
+        assert!(commands.is_empty());
+        assert_eq!(1, spawned.len());
+        assert_eq!(Some(&Health(3)), registry.get(spawned[0]));
+        assert!(!registry.is_live(a));
+        assert!(!registry.has::<Health>(b));
+        assert!(!registry.has_tag::<Frozen>(b));
+        assert_eq!(Ok(vec![]), commands.apply(&mut registry));
+    }
+
+    #[test]
+    fn test_command_buffer_atomic_spawn() {
+        let entities = GenIndexEntitySet::from_config(GenIndexConfig{capacity_limit: Some(2), ..Default::default()});
+        let mut registry = Registry::<u32, u32>::with_entities(entities);
+        let a = registry.create();
+
+        let mut commands = CommandBuffer::new();
+        commands.despawn(a);
+        commands.spawn();
+        commands.spawn();
+        assert_eq!(Err(CommandError::Spawn(GenIndexError::CapacityExhausted)), commands.apply(&mut registry));
+        assert!(registry.is_live(a));
+        assert_eq!(1, registry.len());
+    }
+
+    #[test]
+    fn test_command_buffer_despawn_then_insert() {
+        let mut registry = Registry::<u32, u32>::new();
+        let a = registry.spawn().with(Health(1)).build();
+
+        let mut commands = CommandBuffer::new();
+        commands.despawn(a);
+        commands.insert(a, Health(2));
+        commands.add_tag::<Frozen, _>(a);
+        let b = commands.spawn();
+        commands.despawn(b);
+        commands.insert(b, Health(3));
+        // even removing fails, once the entity is gone
+        commands.remove::<Health, _>(a);
+
+        let failed = vec![(1, GenIndexError::AlreadyDeleted),
+                          (2, GenIndexError::AlreadyDeleted),
+                          (4, GenIndexError::AlreadyDeleted),
+                          (5, GenIndexError::AlreadyDeleted)];
+        match commands.apply(&mut registry) {
+            Err(CommandError::Commands{spawned, failed: found}) => {
+                assert_eq!(failed, found);
+                assert!(!registry.is_live(spawned[0]));
+            },
+            other => panic!("not a failed command: {:?}", other),
+        }
+        assert!(commands.is_empty());
+        assert!(registry.is_empty());
+        assert!(!registry.has::<Health>(a));
+        assert!(!registry.has_tag::<Frozen>(a));
+    }
+
+    #[test]
+    #[should_panic(expected = "another buffer")]
+    fn test_command_buffer_foreign_pending() {
+        let mut other = CommandBuffer::<u32, u32>::new();
+        let pending = other.spawn();
+        let mut commands = CommandBuffer::<u32, u32>::new();
+        commands.insert(pending, Health(1));
+    }
+}
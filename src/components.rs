@@ -0,0 +1,383 @@
+//! Storing components of any number of types for the same entities.
+
+use std::{any::Any,
+          any::TypeId,
+          collections::HashMap,
+          fmt,
+          ops::AddAssign};
+
+use num::{Num, Bounded, ToPrimitive};
+
+use super::{Archetype, ComponentInfo, GenIndex, GenIndexError, SecondaryMap, StorageBackend};
+use super::archetype::Archetypes;
+
+/// Component
+///
+/// What can be stored in a ComponentStore: any type that can be
+/// shared between threads and holds no borrowed data. This is
+/// implemented for all such types, there is nothing to derive.
+pub trait Component: Any + Send + Sync {}
+
+impl<T: Any + Send + Sync> Component for T {}
+
+/// ComponentStore
+///
+/// Keeps one SecondaryMap per component type, looked up by the
+/// type, so that all the components of all the entities handed out
+/// by one set can live in one place. Components follow the rules of
+/// SecondaryMap: a handle only ever finds the components stored
+/// under that very handle, never those of an older or newer
+/// generation of its index.
+///
+/// The store does not know when entities are deleted; call
+/// `remove_all` on deleting one to drop all of its components.
+///
+/// The components of the types registered with `register_clone`
+/// can be copied from one entity to another with
+/// `clone_components`; the others cannot, as the store keeps them
+/// with their types erased. They can still be looked up by their
+/// type ids, with `get_dyn`, and the types described with
+/// `register_info`, for tools to show them.
+///
+/// By default, the components of every type are kept in a
+/// SecondaryMap of their own. A store created with
+/// `with_backend(StorageBackend::Archetypes)` groups the entities
+/// by the set of component types they have instead, which makes
+/// queries faster, and adding and removing components slower; it
+/// has no SecondaryMaps for `storage` to return.
+///
+/// The index and generation types default to `u32`.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// struct Position(f32, f32);
+/// struct Name(&'static str);
+///
+/// let mut entities = GenIndexEntitySet::<u32, u32>::new_unsync();
+/// let mut components = ComponentStore::new();
+///
+/// let player = entities.next_index();
+/// components.insert(player, Position(0.0, 0.0)).unwrap();
+/// components.insert(player, Name("player")).unwrap();
+/// components.get_mut::<Position>(player).unwrap().0 += 1.0;
+/// assert_eq!(1.0, components.get::<Position>(player).unwrap().0);
+///
+/// entities.delete_index(player).unwrap();
+/// assert_eq!(2, components.remove_all(player));
+/// assert!(!components.contains::<Name>(player));
+/// ```
+pub struct ComponentStore<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static = u32,
+                          G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static = u32> {
+    maps: HashMap<TypeId, Box<dyn ComponentMap<I, G>>>,
+    /// the components, if kept by archetype rather than in maps.
+    archetypes: Option<Archetypes<I, G>>,
+    /// per component type registered as cloneable, how to clone one.
+    cloners: HashMap<TypeId, Cloner<I, G>>,
+    /// per component type registered with `register_info`, the info.
+    infos: HashMap<TypeId, ComponentInfo>,
+}
+
+/// copy a component of some type from one entity to another, if the
+/// first has one, returning whether it had.
+type Cloner<I, G> = fn(&mut ComponentStore<I, G>, GenIndex<I, G>, GenIndex<I, G>) -> Result<bool, GenIndexError>;
+
+fn clone_component<T: Component + Clone,
+                   I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+                   G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static>(
+    store: &mut ComponentStore<I, G>, from: GenIndex<I, G>, to: GenIndex<I, G>) -> Result<bool, GenIndexError> {
+    match store.get::<T>(from).cloned() {
+        Some(value) => store.insert(to, value).map(|_| true),
+        None => Ok(false),
+    }
+}
+
+/// A SecondaryMap of some component type, with the type erased.
+trait ComponentMap<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+                   G: Num + AddAssign + Copy + Bounded + PartialOrd>: Send + Sync {
+    /// drop the component stored under the given handle, if any.
+    fn remove_entity(&mut self, gi: GenIndex<I, G>) -> bool;
+    /// return the component stored under the given handle, if any,
+    /// with its type erased.
+    fn get_dyn(&self, gi: GenIndex<I, G>) -> Option<&dyn Any>;
+    fn get_dyn_mut(&mut self, gi: GenIndex<I, G>) -> Option<&mut dyn Any>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Component,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> ComponentMap<I, G> for SecondaryMap<T, I, G> {
+    fn remove_entity(&mut self, gi: GenIndex<I, G>) -> bool { self.remove(gi).is_some() }
+    fn get_dyn(&self, gi: GenIndex<I, G>) -> Option<&dyn Any> { self.get(gi).map(|value| value as &dyn Any) }
+    fn get_dyn_mut(&mut self, gi: GenIndex<I, G>) -> Option<&mut dyn Any> {
+        self.get_mut(gi).map(|value| value as &mut dyn Any)
+    }
+    fn as_any(&self) -> &dyn Any { self }
+    fn as_any_mut(&mut self) -> &mut dyn Any { self }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> ComponentStore<I, G> {
+
+    /// Create a new store, without any components.
+    pub fn new() -> ComponentStore<I, G> {
+        Self::with_backend(StorageBackend::SparseSets)
+    }
+
+    /// Create a new store, without any components, keeping them as
+    /// `backend` says.
+    pub fn with_backend(backend: StorageBackend) -> ComponentStore<I, G> {
+        let archetypes = match backend {
+            StorageBackend::SparseSets => None,
+            StorageBackend::Archetypes => Some(Archetypes::new()),
+        };
+        ComponentStore{maps: HashMap::new(), archetypes, cloners: HashMap::new(), infos: HashMap::new()}
+    }
+
+    /// return how the components are kept.
+    pub fn backend(&self) -> StorageBackend {
+        match self.archetypes {
+            Some(_) => StorageBackend::Archetypes,
+            None => StorageBackend::SparseSets,
+        }
+    }
+
+    /// Store a component for the given entity, returning the one of
+    /// the same type it had, if any, as `SecondaryMap::insert`.
+    pub fn insert<T: Component>(&mut self, gi: GenIndex<I, G>, value: T) -> Result<Option<T>, GenIndexError> {
+        match &mut self.archetypes {
+            Some(archetypes) => archetypes.insert(gi, value),
+            None => self.storage_mut::<T>().insert(gi, value),
+        }
+    }
+
+    /// return the entity's component of type `T`, if it has one.
+    pub fn get<T: Component>(&self, gi: GenIndex<I, G>) -> Option<&T> {
+        match &self.archetypes {
+            Some(archetypes) => archetypes.get(gi),
+            None => self.storage::<T>()?.get(gi),
+        }
+    }
+
+    /// return the entity's component of type `T` for modification,
+    /// if it has one.
+    pub fn get_mut<T: Component>(&mut self, gi: GenIndex<I, G>) -> Option<&mut T> {
+        match &mut self.archetypes {
+            Some(archetypes) => archetypes.get_mut(gi),
+            None => self.maps.get_mut(&TypeId::of::<T>())?
+                .as_any_mut()
+                .downcast_mut::<SecondaryMap<T, I, G>>()?
+                .get_mut(gi),
+        }
+    }
+
+    /// return the entity's component of the type with the given id,
+    /// if it has one, with its type erased, for those who do not
+    /// know the type at compile time.
+    pub fn get_dyn(&self, gi: GenIndex<I, G>, id: TypeId) -> Option<&dyn Any> {
+        match &self.archetypes {
+            Some(archetypes) => archetypes.get_dyn(gi, id),
+            None => self.maps.get(&id)?.get_dyn(gi),
+        }
+    }
+
+    /// return the entity's component of the type with the given id
+    /// for modification, if it has one, with its type erased.
+    pub fn get_dyn_mut(&mut self, gi: GenIndex<I, G>, id: TypeId) -> Option<&mut dyn Any> {
+        match &mut self.archetypes {
+            Some(archetypes) => archetypes.get_dyn_mut(gi, id),
+            None => self.maps.get_mut(&id)?.get_dyn_mut(gi),
+        }
+    }
+
+    /// Remove the entity's component of type `T`, and return it.
+    pub fn remove<T: Component>(&mut self, gi: GenIndex<I, G>) -> Option<T> {
+        match &mut self.archetypes {
+            Some(archetypes) => archetypes.remove(gi),
+            None => self.storage_opt_mut::<T>()?.remove(gi),
+        }
+    }
+
+    /// Check whether the entity has a component of type `T`.
+    pub fn contains<T: Component>(&self, gi: GenIndex<I, G>) -> bool {
+        self.get::<T>(gi).is_some()
+    }
+
+    /// Drop every component of the given entity, whatever its type,
+    /// and return how many there were. Call this when deleting the
+    /// entity.
+    pub fn remove_all(&mut self, gi: GenIndex<I, G>) -> usize {
+        if let Some(archetypes) = &mut self.archetypes {
+            return archetypes.remove_all(gi);
+        }
+        self.maps.values_mut().map(|map| map.remove_entity(gi)).filter(|&removed| removed).count()
+    }
+
+    /// return the map holding the components of type `T`, if any
+    /// were ever stored. None, if the components are kept by
+    /// archetype.
+    pub fn storage<T: Component>(&self) -> Option<&SecondaryMap<T, I, G>> {
+        self.maps.get(&TypeId::of::<T>())?.as_any().downcast_ref()
+    }
+
+    /// return the map holding the components of type `T`, for
+    /// modification, creating it if need be.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the components are kept by archetype.
+    pub fn storage_mut<T: Component>(&mut self) -> &mut SecondaryMap<T, I, G> {
+        assert!(self.archetypes.is_none(), "storage_mut: components are kept by archetype");
+        self.maps.entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(SecondaryMap::<T, I, G>::new()))
+            .as_any_mut()
+            .downcast_mut()
+            // maps are only ever stored under their own type's id
+            .unwrap()
+    }
+
+    /// Register `T` as a type `clone_components` copies.
+    pub fn register_clone<T: Component + Clone>(&mut self) {
+        self.cloners.insert(TypeId::of::<T>(), clone_component::<T, I, G>);
+    }
+
+    /// Check whether `T` was registered with `register_clone`.
+    pub fn is_cloneable<T: Component>(&self) -> bool {
+        self.cloners.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Copy every component of the `from` entity whose type was
+    /// registered with `register_clone` to the `to` entity,
+    /// replacing the ones of the same types it had, and return the
+    /// types copied, in no particular order. Fails as
+    /// `SecondaryMap::insert` does if `to` is stale.
+    pub fn clone_components(&mut self, from: GenIndex<I, G>, to: GenIndex<I, G>) -> Result<Vec<TypeId>, GenIndexError> {
+        let cloners: Vec<_> = self.cloners.iter().map(|(&id, &cloner)| (id, cloner)).collect();
+        let mut cloned = Vec::new();
+        for (id, cloner) in cloners {
+            if cloner(self, from, to)? {
+                cloned.push(id);
+            }
+        }
+        Ok(cloned)
+    }
+
+    /// Register what is known of a component type, replacing the
+    /// info registered for the type before, if any.
+    pub fn register_info(&mut self, info: ComponentInfo) {
+        self.infos.insert(info.type_id(), info);
+    }
+
+    /// return the info registered for the type with the given id,
+    /// if any.
+    pub fn info(&self, id: TypeId) -> Option<&ComponentInfo> { self.infos.get(&id) }
+
+    /// iterate over the info of every type registered, in no
+    /// particular order.
+    pub fn infos(&self) -> impl Iterator<Item = &ComponentInfo> + '_ { self.infos.values() }
+
+    /// return the number of component types stored.
+    pub fn type_count(&self) -> usize {
+        self.archetypes.as_ref().map_or(self.maps.len(), Archetypes::type_count)
+    }
+
+    /// return the archetypes the entities are grouped in, if the
+    /// components are kept by archetype, and nothing otherwise.
+    pub fn archetypes(&self) -> &[Archetype<I, G>] {
+        self.archetypes.as_ref().map_or(&[], Archetypes::archetypes)
+    }
+
+    /// return the archetypes for modification, if the components
+    /// are kept by archetype.
+    pub(crate) fn archetypes_mut(&mut self) -> Option<&mut [Archetype<I, G>]> {
+        self.archetypes.as_mut().map(Archetypes::archetypes_mut)
+    }
+
+    /// return pointers to the maps of every type stored, by type
+    /// id, each to a map of its own, for SharedStore to borrow
+    /// them one by one. Nothing, if the components are kept by
+    /// archetype.
+    pub(crate) fn map_pointers(&mut self) -> HashMap<TypeId, *mut dyn Any> {
+        self.maps.iter_mut().map(|(&id, map)| (id, map.as_any_mut() as *mut dyn Any)).collect()
+    }
+
+    /// return the map holding the components of type `T`, for
+    /// modification, if any were ever stored.
+    pub(crate) fn storage_opt_mut<T: Component>(&mut self) -> Option<&mut SecondaryMap<T, I, G>> {
+        self.maps.get_mut(&TypeId::of::<T>())?.as_any_mut().downcast_mut()
+    }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> Default for ComponentStore<I, G> {
+    fn default() -> Self { Self::new() }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> fmt::Debug for ComponentStore<I, G> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ComponentStore").field("types", &self.type_count()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_fixtures::{Health, Armor};
+
+    #[test]
+    fn test_component_store() {
+        let mut store = ComponentStore::<u32, u32>::new();
+        let a = GenIndex::new(0, 0);
+        let b = GenIndex::new(1, 0);
+
+        assert_eq!(Ok(None), store.insert(a, Health(10)));
+        assert_eq!(Ok(Some(Health(10))), store.insert(a, Health(20)));
+        store.insert(a, Armor(5)).unwrap();
+        store.insert(b, Health(30)).unwrap();
+        assert_eq!(2, store.type_count());
+
+        assert_eq!(Some(&Health(20)), store.get(a));
+        assert_eq!(None, store.get::<Armor>(b));
+        assert_eq!(None, store.get_mut::<String>(a));
+        assert_eq!(Some(Armor(5)), store.remove(a));
+        assert!(!store.contains::<Armor>(a));
+
+        // a later generation of the index does not see the old components
+        let a2 = GenIndex::new(0, 1);
+        assert_eq!(None, store.get::<Health>(a2));
+        store.insert(a2, Armor(2)).unwrap();
+        assert_eq!(Err(GenIndexError::StaleGeneration), store.insert(a, Armor(3)));
+
+        assert_eq!(1, store.remove_all(b));
+        assert_eq!(0, store.remove_all(b));
+        assert_eq!(1, store.storage::<Health>().unwrap().len());
+    }
+
+    #[test]
+    fn test_get_dyn() {
+        for &backend in &[StorageBackend::SparseSets, StorageBackend::Archetypes] {
+            let mut store = ComponentStore::<u32, u32>::with_backend(backend);
+            let a = GenIndex::new(0, 0);
+            store.insert(a, Health(10)).unwrap();
+            store.insert(a, Armor(5)).unwrap();
+            let health = store.get_dyn(a, TypeId::of::<Health>()).unwrap();
+            assert_eq!(Some(&Health(10)), health.downcast_ref());
+            store.get_dyn_mut(a, TypeId::of::<Armor>()).unwrap().downcast_mut::<Armor>().unwrap().0 = 6;
+            assert_eq!(Some(&Armor(6)), store.get(a));
+            assert!(store.get_dyn(a, TypeId::of::<String>()).is_none());
+            assert!(store.get_dyn(GenIndex::new(0, 1), TypeId::of::<Health>()).is_none());
+
+            store.register_info(ComponentInfo::of_debug::<Health>());
+            let info = store.info(TypeId::of::<Health>()).unwrap();
+            assert_eq!(Some("Health(10)".to_string()), info.format(store.get_dyn(a, info.type_id()).unwrap()));
+            assert!(store.info(TypeId::of::<Armor>()).is_none());
+            assert_eq!(1, store.infos().count());
+        }
+    }
+}
@@ -0,0 +1,349 @@
+//! The thread-safe core: lock-free allocation of `GenIndex<u32, u32>`
+//! handles.
+//!
+//! Everything here is built on a handful of atomics, which come from
+//! loom when compiled with `--cfg loom`, so that the state machine can
+//! be model-checked over every interleaving:
+//!
+//! ```text
+//! RUSTFLAGS="--cfg loom" cargo test --release --lib concurrent
+//! ```
+
+use std::vec::Vec;
+
+#[cfg(not(loom))]
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+#[cfg(loom)]
+use loom::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+use super::{GenIndex, GenIndexError};
+
+/// marks the end of the free list.
+const NIL: u32 = u32::MAX;
+
+/// the low bit of a slot's state word tells whether it is live.
+const LIVE: u64 = 1;
+
+/// AtomicGenIndexAllocator
+///
+/// A lock-free counterpart of GenIndexEntitySet for `u32` indices
+/// and generations. `next_index` and `delete_index` take `&self`,
+/// so the allocator can be shared between threads with a plain
+/// `Arc`, and never block one another.
+///
+/// All slot storage is allocated up front, so the allocator has
+/// a fixed capacity. Deleted indices are kept on a Treiber stack
+/// whose head packs the slot number together with a tag that is
+/// bumped on every update, to guard against the ABA problem.
+/// Recycling is always LIFO.
+///
+/// # Guarantees
+///
+/// `next_index`, `delete_index` and `is_live` are linearizable: every
+/// call appears to take effect at a single instant between its start
+/// and its return, so any concurrent history can be explained by
+/// some sequential ordering of the calls. In particular, no two
+/// threads are ever handed the same index at the same generation,
+/// an index is never handed out while live, and of several threads
+/// deleting the same handle exactly one succeeds.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use std::sync::Arc;
+/// use gen_indices::*;
+///
+/// let gi = Arc::new(AtomicGenIndexAllocator::with_capacity(1024));
+/// let idx = gi.next_index().unwrap();
+/// assert!(gi.is_live(idx));
+/// gi.delete_index(idx).unwrap();
+/// assert!(!gi.is_live(idx));
+/// ```
+#[derive(Debug)]
+pub struct AtomicGenIndexAllocator {
+    /// tag in the high 32 bits, slot number of the top of the stack
+    /// in the low 32 bits.
+    head: AtomicU64,
+    /// next fresh slot number.
+    fresh: AtomicU32,
+    /// per slot, the next entry of the free list.
+    next: Vec<AtomicU32>,
+    /// per slot, the generation in the high 32 bits, and the live
+    /// flag in the low bit.
+    state: Vec<AtomicU64>,
+}
+
+impl AtomicGenIndexAllocator {
+    /// Create a new allocator able to hold `capacity` live indices.
+    /// Since indices run from zero to `capacity - 1`, the largest
+    /// `u32` is never handed out.
+    pub fn with_capacity(capacity: u32) -> AtomicGenIndexAllocator {
+        AtomicGenIndexAllocator {
+            head: AtomicU64::new(NIL as u64),
+            fresh: AtomicU32::new(0),
+            next: (0..capacity).map(|_| AtomicU32::new(NIL)).collect(),
+            state: (0..capacity).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    /// return the number of slots this allocator was created with.
+    pub fn capacity(&self) -> usize { self.state.len() }
+
+    /// allocate and provide a "new" index, recycling the most
+    /// recently deleted one if there is any, with its generation
+    /// incremented. Fails with `CapacityExhausted` once every slot
+    /// is live. Takes effect when the slot is taken off the free
+    /// list, or from the fresh slots.
+    pub fn next_index(&self) -> Result<GenIndex<u32, u32>, GenIndexError> {
+        let mut head = self.head.load(Ordering::Acquire);
+        loop {
+            let top = head as u32;
+            if top == NIL {
+                return self.next_fresh();
+            }
+            let next = self.next[top as usize].load(Ordering::Relaxed);
+            let tag = (head >> 32).wrapping_add(1);
+            match self.head.compare_exchange_weak(head, tag << 32 | next as u64,
+                                                  Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => {
+                    let state = &self.state[top as usize];
+                    let generation = (state.load(Ordering::Relaxed) >> 32) as u32 + 1;
+                    state.store((generation as u64) << 32 | LIVE, Ordering::Release);
+                    return Ok(GenIndex::new(top, generation));
+                },
+                Err(actual) => head = actual,
+            }
+        }
+    }
+
+    /// Delete an index, with the same validation as
+    /// `GenIndexEntitySet::delete_index`. Exactly one of several
+    /// threads racing to delete the same index will succeed.
+    /// Slots whose generation is exhausted are retired. Takes effect
+    /// when the slot's live flag is cleared; the slot is only pushed
+    /// on the free list after that.
+    pub fn delete_index(&self, gi: GenIndex<u32, u32>) -> Result<(), GenIndexError> {
+        let state = self.state.get(gi.index as usize)
+            .filter(|_| gi.index < self.fresh.load(Ordering::Acquire))
+            .ok_or(GenIndexError::UnknownIndex)?;
+        let live = (gi.generation as u64) << 32 | LIVE;
+        if let Err(actual) = state.compare_exchange(live, live & !LIVE,
+                                                    Ordering::AcqRel, Ordering::Acquire) {
+            return Err(if (actual >> 32) as u32 != gi.generation {
+                GenIndexError::StaleGeneration
+            } else {
+                GenIndexError::AlreadyDeleted
+            });
+        }
+        if gi.generation == u32::MAX {
+            return Ok(());
+        }
+
+        let mut head = self.head.load(Ordering::Acquire);
+        loop {
+            self.next[gi.index as usize].store(head as u32, Ordering::Relaxed);
+            let tag = (head >> 32).wrapping_add(1);
+            match self.head.compare_exchange_weak(head, tag << 32 | gi.index as u64,
+                                                  Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => return Ok(()),
+                Err(actual) => head = actual,
+            }
+        }
+    }
+
+    /// Check whether the given index is still live.
+    pub fn is_live(&self, gi: GenIndex<u32, u32>) -> bool {
+        match self.state.get(gi.index as usize) {
+            Some(state) => state.load(Ordering::Acquire) == (gi.generation as u64) << 32 | LIVE,
+            None => false,
+        }
+    }
+
+    fn next_fresh(&self) -> Result<GenIndex<u32, u32>, GenIndexError> {
+        let capacity = self.state.len() as u32;
+        let index = self.fresh
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire,
+                          |f| if f < capacity { Some(f + 1) } else { None })
+            .map_err(|_| GenIndexError::CapacityExhausted)?;
+        self.state[index as usize].store(LIVE, Ordering::Release);
+        Ok(GenIndex::new(index, 0))
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use std::{thread::*, sync::Arc, collections::HashSet};
+    use super::*;
+    use super::super::GenIndexEntitySet;
+    use super::super::sync::lock;
+
+    const THREADS: usize = 8;
+    const ROUNDS: usize = 2000;
+
+    #[test]
+    fn test_atomic_allocation() {
+        let gi = AtomicGenIndexAllocator::with_capacity(2);
+
+        let idx1 = gi.next_index().unwrap();
+        let idx2 = gi.next_index().unwrap();
+        assert_eq!(Err(GenIndexError::CapacityExhausted), gi.next_index());
+
+        gi.delete_index(idx1).unwrap();
+        assert_eq!(Err(GenIndexError::AlreadyDeleted), gi.delete_index(idx1));
+        let idx3 = gi.next_index().unwrap();
+        assert_eq!(idx1.get_index(), idx3.get_index());
+        assert_eq!(1, idx3.get_generation());
+        assert_eq!(Err(GenIndexError::StaleGeneration), gi.delete_index(idx1));
+        assert!(gi.is_live(idx2));
+        assert!(gi.is_live(idx3));
+        assert!(!gi.is_live(idx1));
+
+        let bogus = GenIndex::new(1000, 0);
+        assert_eq!(Err(GenIndexError::UnknownIndex), gi.delete_index(bogus));
+    }
+
+    #[test]
+    fn test_atomic_contention() {
+        let gi = Arc::new(AtomicGenIndexAllocator::with_capacity((THREADS * 4) as u32));
+        let threads: Vec<_> = (0..THREADS).map(|_| {
+            let gi = gi.clone();
+            spawn(move || {
+                let mut held = Vec::new();
+                for i in 0..ROUNDS {
+                    held.push(gi.next_index().unwrap());
+                    if i % 2 == 1 {
+                        for idx in held.drain(..) {
+                            gi.delete_index(idx).unwrap();
+                        }
+                    }
+                }
+                held.push(gi.next_index().unwrap());
+                held
+            })
+        }).collect();
+
+        // no two threads may ever hold the same live slot
+        let mut seen = HashSet::new();
+        for t in threads {
+            for idx in t.join().unwrap() {
+                assert!(gi.is_live(idx));
+                assert!(seen.insert(idx.get_index()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_shared_set_contention() {
+        let gi = GenIndexEntitySet::<u64, u64>::new();
+        let threads: Vec<_> = (0..THREADS).map(|_| {
+            let gi = gi.clone();
+            spawn(move || {
+                let mut held = Vec::new();
+                for i in 0..ROUNDS {
+                    held.push(lock(&gi).next_index());
+                    if i % 2 == 1 {
+                        for idx in held.drain(..) {
+                            lock(&gi).delete_index(idx).unwrap();
+                        }
+                    }
+                }
+                held.push(lock(&gi).next_index());
+                held
+            })
+        }).collect();
+
+        let mut seen = HashSet::new();
+        for t in threads {
+            for idx in t.join().unwrap() {
+                assert!(lock(&gi).is_live(idx));
+                assert!(seen.insert(idx.get_index()));
+            }
+        }
+        assert_eq!(THREADS, lock(&gi).live_count());
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use loom::{thread, sync::Arc, sync::Mutex};
+    use super::*;
+    use super::super::GenIndexEntitySet;
+
+    #[test]
+    fn loom_allocations_are_unique() {
+        loom::model(|| {
+            let gi = Arc::new(AtomicGenIndexAllocator::with_capacity(2));
+            let idx = gi.next_index().unwrap();
+            gi.delete_index(idx).unwrap();
+
+            let threads: Vec<_> = (0..2).map(|_| {
+                let gi = gi.clone();
+                thread::spawn(move || gi.next_index().unwrap())
+            }).collect();
+            let idxs: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+            assert_ne!(idxs[0].get_index(), idxs[1].get_index());
+            assert!(idxs.iter().all(|&idx| gi.is_live(idx)));
+        });
+    }
+
+    #[test]
+    fn loom_delete_succeeds_once() {
+        loom::model(|| {
+            let gi = Arc::new(AtomicGenIndexAllocator::with_capacity(1));
+            let idx = gi.next_index().unwrap();
+
+            let threads: Vec<_> = (0..2).map(|_| {
+                let gi = gi.clone();
+                thread::spawn(move || gi.delete_index(idx).is_ok())
+            }).collect();
+            let wins = threads.into_iter().map(|t| t.join().unwrap()).filter(|&ok| ok).count();
+            assert_eq!(1, wins);
+            assert!(!gi.is_live(idx));
+        });
+    }
+
+    #[test]
+    fn loom_recycle_races_delete() {
+        loom::model(|| {
+            let gi = Arc::new(AtomicGenIndexAllocator::with_capacity(1));
+            let old = gi.next_index().unwrap();
+
+            let deleter = {
+                let gi = gi.clone();
+                thread::spawn(move || gi.delete_index(old).unwrap())
+            };
+            let fresh = gi.next_index();
+            deleter.join().unwrap();
+
+            assert!(!gi.is_live(old));
+            match fresh {
+                Ok(idx) => {
+                    assert_eq!(old.get_index(), idx.get_index());
+                    assert_eq!(1, idx.get_generation());
+                    assert!(gi.is_live(idx));
+                },
+                Err(e) => assert_eq!(GenIndexError::CapacityExhausted, e),
+            }
+        });
+    }
+
+    #[test]
+    fn loom_shared_set() {
+        loom::model(|| {
+            let gi = Arc::new(Mutex::new(GenIndexEntitySet::<u32, u32>::new_unsync()));
+            let threads: Vec<_> = (0..2).map(|_| {
+                let gi = gi.clone();
+                thread::spawn(move || {
+                    let idx = gi.lock().unwrap().next_index();
+                    gi.lock().unwrap().delete_index(idx).unwrap();
+                    idx
+                })
+            }).collect();
+            let idxs: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+            assert_ne!(idxs[0], idxs[1]);
+            assert_eq!(0, gi.lock().unwrap().live_count());
+        });
+    }
+}
@@ -0,0 +1,199 @@
+//! Converting handles between index and generation widths, and to
+//! and from text.
+
+use std::{convert::TryFrom,
+          ops::AddAssign,
+          str::FromStr};
+
+use num::{Num, Bounded, NumCast, cast};
+
+use super::{GenIndex, GenIndexError};
+
+impl<I: Num + AddAssign + Copy + Bounded + NumCast,
+     G: Num + AddAssign + Copy + Bounded + NumCast,
+     Tag> GenIndex<I, G, Tag> {
+    /// Convert this handle to other index and generation types,
+    /// failing with `OutOfRange` if either value does not fit. The
+    /// invalid handle always converts to the invalid handle.
+    pub fn try_cast<I2, G2>(self) -> Result<GenIndex<I2, G2, Tag>, GenIndexError>
+    where I2: Num + AddAssign + Copy + Bounded + NumCast,
+          G2: Num + AddAssign + Copy + Bounded + NumCast {
+        if !self.is_valid() {
+            return Ok(GenIndex::invalid());
+        }
+        match (cast(self.index), cast(self.generation)) {
+            (Some(index), Some(generation)) => Ok(GenIndex::new(index, generation)),
+            _ => Err(GenIndexError::OutOfRange),
+        }
+    }
+}
+
+/// `From` for handles whose index and generation both get wider.
+macro_rules! widen {
+    ($narrow:ty => $($wide:ty),*) => {$(
+        impl<Tag> From<GenIndex<$narrow, $narrow, Tag>> for GenIndex<$wide, $wide, Tag> {
+            fn from(gi: GenIndex<$narrow, $narrow, Tag>) -> Self {
+                // widening cannot fail
+                gi.try_cast().unwrap()
+            }
+        }
+
+        impl<Tag> TryFrom<GenIndex<$wide, $wide, Tag>> for GenIndex<$narrow, $narrow, Tag> {
+            type Error = GenIndexError;
+
+            fn try_from(gi: GenIndex<$wide, $wide, Tag>) -> Result<Self, Self::Error> {
+                gi.try_cast()
+            }
+        }
+    )*};
+}
+
+widen!(u8 => u16, u32, u64);
+widen!(u16 => u32, u64);
+widen!(u32 => u64);
+
+/// Reads the compact form shown by `Display`, as in `42v3` for index
+/// 42 at generation 3.
+impl<I: Num + AddAssign + Copy,
+     G: Num + AddAssign + Copy,
+     Tag> FromStr for GenIndex<I, G, Tag> {
+    type Err = GenIndexError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (index, generation) = s.split_once('v').ok_or(GenIndexError::ParseFailed)?;
+        let digits = |t: &str| !t.is_empty() && t.bytes().all(|b| b.is_ascii_digit());
+        if !digits(index) || !digits(generation) {
+            return Err(GenIndexError::ParseFailed);
+        }
+        match (I::from_str_radix(index, 10), G::from_str_radix(generation, 10)) {
+            (Ok(index), Ok(generation)) => Ok(GenIndex::new(index, generation)),
+            _ => Err(GenIndexError::OutOfRange),
+        }
+    }
+}
+
+/// the digits of `encode`, in order; all of them are safe in URLs.
+const BASE62: &[u8; 62] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// the length of every string produced by `encode`; 62^11 > 2^64.
+const ENCODED_LEN: usize = 11;
+
+impl<Tag> GenIndex<u32, u32, Tag> {
+    /// Encode this handle as a short, URL-safe string of 11 base62
+    /// digits, for entity handles in routes and logs. The bits are
+    /// scrambled first, so that neighbouring handles do not come out
+    /// as neighbouring strings, and the index and generation cannot
+    /// be read off. This hides the numbers from casual view; it is
+    /// not encryption.
+    ///
+    /// ```
+    /// # use gen_indices::PackedGenIndex;
+    /// let gi = PackedGenIndex::new(42, 3);
+    /// let s = gi.encode();
+    /// assert_eq!(11, s.len());
+    /// assert_eq!(Ok(gi), PackedGenIndex::decode(&s));
+    /// ```
+    pub fn encode(self) -> String {
+        let mut bits = scramble(self.to_bits());
+        let mut digits = [0u8; ENCODED_LEN];
+        for digit in digits.iter_mut().rev() {
+            *digit = BASE62[(bits % 62) as usize];
+            bits /= 62;
+        }
+        // only ever ASCII digits and letters
+        String::from_utf8(digits.to_vec()).unwrap()
+    }
+
+    /// decode a string produced by `encode`, failing with
+    /// `ParseFailed` if it is anything else.
+    pub fn decode(s: &str) -> Result<Self, GenIndexError> {
+        if s.len() != ENCODED_LEN {
+            return Err(GenIndexError::ParseFailed);
+        }
+        let mut bits: u64 = 0;
+        for b in s.bytes() {
+            let digit = BASE62.iter().position(|&d| d == b).ok_or(GenIndexError::ParseFailed)?;
+            bits = bits.checked_mul(62)
+                .and_then(|bits| bits.checked_add(digit as u64))
+                .ok_or(GenIndexError::ParseFailed)?;
+        }
+        Ok(Self::from_bits(unscramble(bits)))
+    }
+}
+
+/// a bijection on `u64` spreading every bit over the whole word,
+/// the finalizer of MurmurHash3.
+fn scramble(mut x: u64) -> u64 {
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    x ^= x >> 33;
+    x
+}
+
+/// the inverse of `scramble`, multiplying by the inverses of its
+/// constants modulo 2^64.
+fn unscramble(mut x: u64) -> u64 {
+    x ^= x >> 33;
+    x = x.wrapping_mul(0x9cb4_b2f8_1293_37db);
+    x ^= x >> 33;
+    x = x.wrapping_mul(0x4f74_430c_22a5_4005);
+    x ^= x >> 33;
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_widening() {
+        let narrow = GenIndex::<u32, u32>::new(3, 7);
+        let wide = GenIndex::<u64, u64>::from(narrow);
+        assert_eq!(GenIndex::new(3, 7), wide);
+        assert_eq!(Ok(narrow), GenIndex::<u32, u32>::try_from(wide));
+
+        let invalid: GenIndex<u64, u64> = GenIndex::<u8, u8>::invalid().into();
+        assert!(!invalid.is_valid());
+    }
+
+    #[test]
+    fn test_narrowing() {
+        let wide = GenIndex::<u64, u64>::new(1 << 40, 0);
+        assert_eq!(Err(GenIndexError::OutOfRange), GenIndex::<u32, u32>::try_from(wide));
+        assert_eq!(Err(GenIndexError::OutOfRange), GenIndex::<u16, u16>::new(3, 300).try_cast::<u8, u8>());
+        assert_eq!(Ok(GenIndex::<u64, u16>::new(3, 300)), GenIndex::<u16, u64>::new(3, 300).try_cast());
+
+        let invalid = GenIndex::<u64, u64>::invalid();
+        assert_eq!(Ok(GenIndex::invalid()), GenIndex::<u32, u32>::try_from(invalid));
+    }
+
+    #[test]
+    fn test_from_str() {
+        let gi: GenIndex<u32, u16> = "42v3".parse().unwrap();
+        assert_eq!(GenIndex::new(42, 3), gi);
+        assert_eq!(Ok(gi), gi.to_string().parse());
+
+        for bad in &["", "42", "v3", "42v", "42x3", "-1v3", "4 2v3", "42v3v1", "+4v2"] {
+            assert_eq!(Err(GenIndexError::ParseFailed), bad.parse::<GenIndex<u32, u16>>(), "{}", bad);
+        }
+        assert_eq!(Err(GenIndexError::OutOfRange), "1v70000".parse::<GenIndex<u32, u16>>());
+    }
+
+    #[test]
+    fn test_encode() {
+        for gi in &[GenIndex::<u32, u32>::new(0, 0), GenIndex::new(1, 0), GenIndex::new(42, 3),
+                    GenIndex::invalid()] {
+            let s = gi.encode();
+            assert_eq!(11, s.len());
+            assert!(s.bytes().all(|b| b.is_ascii_alphanumeric()));
+            assert_eq!(Ok(*gi), GenIndex::decode(&s));
+        }
+        assert_ne!(GenIndex::<u32, u32>::new(1, 0).encode()[..8], GenIndex::<u32, u32>::new(2, 0).encode()[..8]);
+
+        for bad in &["", "0000000000", "000000000000", "0000000000-", "zzzzzzzzzzz"] {
+            assert_eq!(Err(GenIndexError::ParseFailed), GenIndex::<u32, u32>::decode(bad), "{}", bad);
+        }
+    }
+}
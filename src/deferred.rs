@@ -0,0 +1,204 @@
+//! Deferring deletions, so that they never wait on the allocator.
+
+use std::{ops::AddAssign,
+          sync::mpsc,
+          sync::mpsc::Receiver,
+          sync::mpsc::Sender,
+          sync::atomic::AtomicUsize,
+          sync::atomic::Ordering};
+
+use num::{Num, Bounded, ToPrimitive};
+
+use super::{GenIndex, GenIndexConfig, GenIndexEntitySet, GenIndexError};
+use super::sync::{Mutex, MutexGuard, lock};
+
+/// DeferredGenIndexEntitySet
+///
+/// A shared GenIndexEntitySet whose `delete_index` does not take
+/// the lock. Deletions go into a queue, and are only absorbed into
+/// the free list by `flush_deletions`, which `next_index` calls
+/// before allocating. Worker threads can delete as fast as they
+/// like without ever contending with the thread spawning entities.
+///
+/// Until the queue is flushed, a deleted index is still live as
+/// far as the set is concerned, and its slot is not recycled.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// let gi = DeferredGenIndexEntitySet::<u64, u64>::new();
+/// let idx = gi.next_index();
+/// gi.delete_index(idx);
+/// assert_eq!(1, gi.pending_deletions());
+///
+/// // the deletion is absorbed, and the slot reused
+/// let again = gi.next_index();
+/// assert_eq!(idx.get_index(), again.get_index());
+/// assert_eq!(0, gi.pending_deletions());
+/// ```
+#[derive(Debug)]
+pub struct DeferredGenIndexEntitySet<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+                                     G: Num + AddAssign + Copy + Bounded + PartialOrd> {
+    set: Mutex<GenIndexEntitySet<I, G>>,
+    sender: Sender<GenIndex<I, G>>,
+    receiver: Mutex<Receiver<GenIndex<I, G>>>,
+    pending: AtomicUsize,
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> DeferredGenIndexEntitySet<I, G> {
+
+    /// Create a new, empty set.
+    pub fn new() -> DeferredGenIndexEntitySet<I, G> {
+        Self::from_set(GenIndexEntitySet::new_unsync())
+    }
+
+    /// Create a new, empty set with the given settings.
+    pub fn with_config(config: GenIndexConfig) -> DeferredGenIndexEntitySet<I, G> {
+        Self::from_set(GenIndexEntitySet::from_config(config))
+    }
+
+    /// Defer the deletions of an existing set.
+    pub fn from_set(set: GenIndexEntitySet<I, G>) -> DeferredGenIndexEntitySet<I, G> {
+        let (sender, receiver) = mpsc::channel();
+        DeferredGenIndexEntitySet {
+            set: Mutex::new(set),
+            sender,
+            receiver: Mutex::new(receiver),
+            pending: AtomicUsize::new(0),
+        }
+    }
+
+    /// Take the lock, and give access to the whole set, for
+    /// anything not covered by the shortcuts below. Deletions still
+    /// in the queue are not flushed.
+    pub fn lock(&self) -> MutexGuard<'_, GenIndexEntitySet<I, G>> {
+        lock(&self.set)
+    }
+
+    /// Flush the queued deletions, then allocate and provide a
+    /// "new" index.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as
+    /// `GenIndexEntitySet::next_index`.
+    pub fn next_index(&self) -> GenIndex<I, G> {
+        match self.try_next_index() {
+            Ok(gi) => gi,
+            Err(e) => panic!("next_index: {}", e),
+        }
+    }
+
+    /// Flush the queued deletions, then allocate and provide a
+    /// "new" index, reporting an error instead of panicking. Errors
+    /// from the flushed deletions are not reported here.
+    pub fn try_next_index(&self) -> Result<GenIndex<I, G>, GenIndexError> {
+        let mut set = lock(&self.set);
+        let _ = self.absorb(&mut set);
+        set.try_next_index()
+    }
+
+    /// Queue an index for deletion, without taking the lock. It is
+    /// validated when the queue is flushed.
+    pub fn delete_index(&self, gi: GenIndex<I, G>) {
+        self.pending.fetch_add(1, Ordering::Relaxed);
+        // we own the receiver, so the channel is never disconnected
+        let _ = self.sender.send(gi);
+    }
+
+    /// Absorb every queued deletion into the free list, returning
+    /// how many indices were freed. Each one is validated as in
+    /// `GenIndexEntitySet::delete_index`; the whole queue is worked
+    /// through regardless, and the first error met, if any, is
+    /// returned at the end.
+    pub fn flush_deletions(&self) -> Result<usize, GenIndexError> {
+        self.absorb(&mut lock(&self.set))
+    }
+
+    /// return the number of deletions queued but not yet flushed.
+    pub fn pending_deletions(&self) -> usize {
+        self.pending.load(Ordering::Relaxed)
+    }
+
+    /// Check whether the given index is still live. Deletions still
+    /// in the queue are not taken into account.
+    pub fn is_live(&self, gi: GenIndex<I, G>) -> bool {
+        lock(&self.set).is_live(gi)
+    }
+
+    fn absorb(&self, set: &mut GenIndexEntitySet<I, G>) -> Result<usize, GenIndexError> {
+        let receiver = lock(&self.receiver);
+        let mut count = 0;
+        let mut first_err = None;
+        for gi in receiver.try_iter() {
+            self.pending.fetch_sub(1, Ordering::Relaxed);
+            match set.delete_index(gi) {
+                Ok(()) => count += 1,
+                Err(e) => { first_err.get_or_insert(e); },
+            }
+        }
+        match first_err {
+            Some(e) => Err(e),
+            None => Ok(count),
+        }
+    }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> Default for DeferredGenIndexEntitySet<I, G> {
+    fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread::*, sync::Arc};
+    use super::*;
+
+    const THREADS: usize = 8;
+
+    #[test]
+    fn test_deferred_deletion() {
+        let gi = DeferredGenIndexEntitySet::<u64, u64>::new();
+        let idx1 = gi.next_index();
+        let idx2 = gi.next_index();
+
+        gi.delete_index(idx1);
+        assert!(gi.is_live(idx1));
+        assert_eq!(1, gi.pending_deletions());
+
+        assert_eq!(Ok(1), gi.flush_deletions());
+        assert!(!gi.is_live(idx1));
+        assert_eq!(0, gi.pending_deletions());
+
+        // bad deletions are reported, but do not hold up the rest
+        gi.delete_index(idx1);
+        gi.delete_index(idx2);
+        assert_eq!(Err(GenIndexError::AlreadyDeleted), gi.flush_deletions());
+        assert!(!gi.is_live(idx2));
+        assert_eq!(0, gi.lock().live_count());
+    }
+
+    #[test]
+    fn test_deferred_threaded() {
+        let gi = Arc::new(DeferredGenIndexEntitySet::<u64, u64>::new());
+        let idxs: Vec<_> = (0..THREADS * 100).map(|_| gi.next_index()).collect();
+
+        let threads: Vec<_> = idxs.chunks(100).map(|chunk| {
+            let gi = gi.clone();
+            let chunk = chunk.to_vec();
+            spawn(move || chunk.into_iter().for_each(|idx| gi.delete_index(idx)))
+        }).collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        assert_eq!(THREADS * 100, gi.pending_deletions());
+        gi.next_index();
+        assert_eq!(1, gi.lock().live_count());
+    }
+}
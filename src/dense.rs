@@ -0,0 +1,570 @@
+//! Storing values contiguously under generational handles.
+
+use std::{ops::AddAssign, ops::Index, ops::IndexMut, mem};
+
+use num::{Num, Bounded, ToPrimitive};
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+use super::{GenIndex, GenIndexConfig, GenIndexEntitySet, GenIndexError, MemoryUsage};
+
+/// DenseGenArena
+///
+/// A generational arena like GenIndexArena, but keeping its values
+/// packed together in one slice, with no gaps where values were
+/// removed, so that going through all of them is as cache friendly
+/// as it gets. Removing a value moves the last one into its place,
+/// so the order of the values changes; handles stay valid all the
+/// same, through a table from handle to position.
+///
+/// The price is one more lookup per access, and the extra table.
+///
+/// With the `serde` feature, an arena serializes with its handle
+/// bookkeeping, like GenIndexArena, so a reloaded arena refuses
+/// stale handles just as the saved one did.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// let mut arena = DenseGenArena::<u32>::new();
+/// let a = arena.insert(1);
+/// let b = arena.insert(2);
+/// let c = arena.insert(3);
+///
+/// arena.remove(a).unwrap();
+/// // the last value moved into the gap
+/// assert_eq!(&[3, 2], arena.as_slice());
+/// assert_eq!(Some(&2), arena.get(b));
+/// assert_eq!(Some(&3), arena.get(c));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DenseGenArena<T,
+                         I: Num + AddAssign + Copy + ToPrimitive + Bounded = u32,
+                         G: Num + AddAssign + Copy + Bounded + PartialOrd = u32> {
+    set: GenIndexEntitySet<I, G>,
+    values: Vec<T>,
+    /// the handle of each value, in the same order.
+    handles: Vec<GenIndex<I, G>>,
+    /// per index, the position of its value, if live.
+    positions: Vec<usize>,
+}
+
+impl<T,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> DenseGenArena<T, I, G> {
+
+    /// Create a new, empty arena.
+    pub fn new() -> DenseGenArena<T, I, G> {
+        Self::with_config(GenIndexConfig::default())
+    }
+
+    /// Create a new, empty arena, whose handles are handed out
+    /// according to the given settings.
+    pub fn with_config(config: GenIndexConfig) -> DenseGenArena<T, I, G> {
+        DenseGenArena {
+            set: GenIndexEntitySet::from_config(config),
+            values: Vec::new(),
+            handles: Vec::new(),
+            positions: Vec::new(),
+        }
+    }
+
+    /// Create a new, empty arena with room for `capacity` values
+    /// before its storage has to grow.
+    pub fn with_capacity(capacity: usize) -> DenseGenArena<T, I, G> {
+        let mut arena = Self::new();
+        arena.reserve(capacity);
+        arena
+    }
+
+    /// Make room for at least `additional` more values without the
+    /// storage growing.
+    pub fn reserve(&mut self, additional: usize) {
+        self.set.reserve(additional);
+        self.values.reserve(additional);
+        self.handles.reserve(additional);
+        self.positions.reserve(additional);
+    }
+
+    /// return the number of values the arena can hold without its
+    /// storage growing.
+    pub fn capacity(&self) -> usize { self.values.capacity() }
+
+    /// return the set handing out this arena's handles, for its
+    /// counters and settings.
+    pub fn set(&self) -> &GenIndexEntitySet<I, G> { &self.set }
+
+    /// return the memory this arena holds, its set's included, broken
+    /// down by what it is used for. The values and their handles
+    /// count as slot storage, the table of positions as metadata.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.set.memory_usage() + MemoryUsage {
+            slots: self.values.capacity() * mem::size_of::<T>()
+                + self.handles.capacity() * mem::size_of::<GenIndex<I, G>>(),
+            free_list: 0,
+            metadata: mem::size_of::<Self>() - mem::size_of::<GenIndexEntitySet<I, G>>()
+                + self.positions.capacity() * mem::size_of::<usize>(),
+        }
+    }
+
+    /// Store a value at the end, and return its handle.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as
+    /// `GenIndexEntitySet::next_index`.
+    pub fn insert(&mut self, value: T) -> GenIndex<I, G> {
+        match self.try_insert(value) {
+            Ok(gi) => gi,
+            Err(e) => panic!("insert: {}", e),
+        }
+    }
+
+    /// Store a value at the end, and return its handle, reporting
+    /// an error instead of panicking if no handle can be handed
+    /// out. The value is dropped in that case.
+    pub fn try_insert(&mut self, value: T) -> Result<GenIndex<I, G>, GenIndexError> {
+        let gi = self.set.try_next_index()?;
+        let slot = Self::slot(gi).ok_or(GenIndexError::UnknownIndex)?;
+        if slot >= self.positions.len() {
+            self.positions.resize(slot + 1, 0);
+        }
+        self.positions[slot] = self.values.len();
+        self.values.push(value);
+        self.handles.push(gi);
+        Ok(gi)
+    }
+
+    /// return the value stored under the given handle, or None if
+    /// it has been removed.
+    pub fn get(&self, gi: GenIndex<I, G>) -> Option<&T> {
+        let pos = self.position(gi)?;
+        self.values.get(pos)
+    }
+
+    /// return the value stored under the given handle for
+    /// modification, or None if it has been removed.
+    pub fn get_mut(&mut self, gi: GenIndex<I, G>) -> Option<&mut T> {
+        let pos = self.position(gi)?;
+        self.values.get_mut(pos)
+    }
+
+    /// return the values stored under several handles at once, all
+    /// for modification, or None if any handle is not live, or the
+    /// same handle is given twice.
+    pub fn get_disjoint_mut<const N: usize>(&mut self, gis: [GenIndex<I, G>; N]) -> Option<[&mut T; N]> {
+        let mut positions = [0; N];
+        for (pos, gi) in positions.iter_mut().zip(gis.iter()) {
+            *pos = self.position(*gi)?;
+        }
+        self.values.get_disjoint_mut(positions).ok()
+    }
+
+    /// return the values stored under two different handles, both
+    /// for modification, as `get_disjoint_mut`.
+    pub fn get2_mut(&mut self, a: GenIndex<I, G>, b: GenIndex<I, G>) -> Option<(&mut T, &mut T)> {
+        let [a, b] = self.get_disjoint_mut([a, b])?;
+        Some((a, b))
+    }
+
+    /// Remove the value stored under the given handle, and return
+    /// it, moving the last value into its place. The handle is
+    /// validated as in `GenIndexEntitySet::delete_index`.
+    pub fn remove(&mut self, gi: GenIndex<I, G>) -> Result<T, GenIndexError> {
+        let pos = self.position(gi);
+        self.set.delete_index(gi)?;
+        let pos = pos.ok_or(GenIndexError::UnknownIndex)?;
+        let value = self.values.swap_remove(pos);
+        self.handles.swap_remove(pos);
+        if let Some(&moved) = self.handles.get(pos) {
+            if let Some(slot) = Self::slot(moved) {
+                self.positions[slot] = pos;
+            }
+        }
+        Ok(value)
+    }
+
+    /// Check whether a value is stored under the given handle.
+    pub fn contains(&self, gi: GenIndex<I, G>) -> bool { self.set.is_live(gi) }
+
+    /// return the number of values stored.
+    pub fn len(&self) -> usize { self.values.len() }
+
+    /// return true if no values are stored.
+    pub fn is_empty(&self) -> bool { self.values.is_empty() }
+
+    /// return all the values, packed together, in no particular
+    /// order.
+    pub fn as_slice(&self) -> &[T] { &self.values }
+
+    /// return all the values for modification, packed together, in
+    /// no particular order.
+    pub fn as_mut_slice(&mut self) -> &mut [T] { &mut self.values }
+
+    /// return the handles of the values, in the same order as
+    /// `as_slice`.
+    pub fn handles(&self) -> &[GenIndex<I, G>] { &self.handles }
+
+    /// return where in `as_slice` the value of the given handle is,
+    /// or None if it has been removed.
+    pub fn position(&self, gi: GenIndex<I, G>) -> Option<usize> {
+        if !self.set.is_live(gi) {
+            return None;
+        }
+        self.positions.get(Self::slot(gi)?).copied()
+    }
+
+    /// iterate over every value, with its handle, in the order of
+    /// `as_slice`.
+    pub fn iter(&self) -> impl Iterator<Item = (GenIndex<I, G>, &T)> + '_ {
+        self.handles.iter().copied().zip(self.values.iter())
+    }
+
+    /// iterate over every value for modification, with its handle,
+    /// in the order of `as_slice`.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (GenIndex<I, G>, &mut T)> + '_ {
+        self.handles.iter().copied().zip(self.values.iter_mut())
+    }
+
+    /// iterate over the handle of every value, in the order of
+    /// `as_slice`.
+    pub fn keys(&self) -> impl Iterator<Item = GenIndex<I, G>> + '_ {
+        self.handles.iter().copied()
+    }
+
+    /// iterate over every value, without the handles, in the order
+    /// of `as_slice`.
+    pub fn values(&self) -> std::slice::Iter<'_, T> { self.values.iter() }
+
+    /// iterate over every value for modification, without the
+    /// handles, in the order of `as_slice`.
+    pub fn values_mut(&mut self) -> std::slice::IterMut<'_, T> { self.values.iter_mut() }
+
+    /// Remove every value, returning them with their handles, in
+    /// the order of `as_slice`. All outstanding handles become
+    /// stale, as with `GenIndexEntitySet::clear`. Everything is
+    /// removed at once, even if the iterator is dropped before the
+    /// end.
+    pub fn drain(&mut self) -> std::vec::IntoIter<(GenIndex<I, G>, T)> {
+        let drained: Vec<_> = self.handles.drain(..).zip(self.values.drain(..)).collect();
+        self.set.clear();
+        drained.into_iter()
+    }
+
+    /// Remove every value at once, dropping them. Every handle
+    /// handed out before becomes stale, and stays so, as with
+    /// `GenIndexArena::clear`.
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.handles.clear();
+        self.set.clear();
+    }
+
+    /// Keep only the values for which `f` returns true, removing
+    /// the rest in a single pass, as `remove` would. The handles of
+    /// removed values become stale.
+    pub fn retain<F: FnMut(GenIndex<I, G>, &mut T) -> bool>(&mut self, mut f: F) {
+        let mut pos = 0;
+        while pos < self.values.len() {
+            let gi = self.handles[pos];
+            if f(gi, &mut self.values[pos]) {
+                pos += 1;
+            } else {
+                // the last value moves into `pos`, and is looked at next
+                let _ = self.remove(gi);
+            }
+        }
+    }
+
+    fn slot(gi: GenIndex<I, G>) -> Option<usize> { gi.get_index().to_usize() }
+}
+
+#[cfg(feature = "rayon")]
+impl<T: Send + Sync,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded + Send + Sync,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync> DenseGenArena<T, I, G> {
+
+    /// iterate over every value, with its handle, on rayon's
+    /// worker threads. The values being packed together, they
+    /// split evenly.
+    pub fn par_iter(&self) -> impl IndexedParallelIterator<Item = (GenIndex<I, G>, &T)> + '_ {
+        self.handles.par_iter().copied().zip(self.values.par_iter())
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<T: Send,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded + Send + Sync,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync> DenseGenArena<T, I, G> {
+
+    /// iterate over every value for modification, with its handle,
+    /// on rayon's worker threads.
+    pub fn par_iter_mut(&mut self) -> impl IndexedParallelIterator<Item = (GenIndex<I, G>, &mut T)> + '_ {
+        self.handles.par_iter().copied().zip(self.values.par_iter_mut())
+    }
+}
+
+/// Looks up the value stored under a handle, as `get`.
+///
+/// # Panics
+///
+/// Panics if the handle is not live.
+impl<T,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> Index<GenIndex<I, G>> for DenseGenArena<T, I, G> {
+    type Output = T;
+
+    fn index(&self, gi: GenIndex<I, G>) -> &T {
+        match self.get(gi) {
+            Some(value) => value,
+            None => panic!("index: no value stored under the handle"),
+        }
+    }
+}
+
+/// Looks up the value stored under a handle for modification, as
+/// `get_mut`.
+///
+/// # Panics
+///
+/// Panics if the handle is not live.
+impl<T,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> IndexMut<GenIndex<I, G>> for DenseGenArena<T, I, G> {
+    fn index_mut(&mut self, gi: GenIndex<I, G>) -> &mut T {
+        match self.get_mut(gi) {
+            Some(value) => value,
+            None => panic!("index_mut: no value stored under the handle"),
+        }
+    }
+}
+
+/// Takes the arena apart, yielding every value with its handle, in
+/// the order of `as_slice`.
+impl<T,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> IntoIterator for DenseGenArena<T, I, G> {
+    type Item = (GenIndex<I, G>, T);
+    type IntoIter = std::vec::IntoIter<(GenIndex<I, G>, T)>;
+
+    fn into_iter(mut self) -> Self::IntoIter { self.drain() }
+}
+
+impl<T,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> Default for DenseGenArena<T, I, G> {
+    fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dense_arena() {
+        let mut arena = DenseGenArena::<char>::new();
+        let a = arena.insert('a');
+        let b = arena.insert('b');
+        let c = arena.insert('c');
+
+        assert_eq!(Ok('a'), arena.remove(a));
+        assert_eq!(Err(GenIndexError::AlreadyDeleted), arena.remove(a));
+        assert_eq!(&['c', 'b'], arena.as_slice());
+        assert_eq!(&[c, b], arena.handles());
+        assert_eq!(Some(0), arena.position(c));
+
+        *arena.get_mut(c).unwrap() = 'C';
+        assert_eq!(Some(&'C'), arena.get(c));
+
+        // the recycled slot goes to the end, and the old handle is stale
+        let d = arena.insert('d');
+        assert_eq!(a.get_index(), d.get_index());
+        assert_eq!(None, arena.get(a));
+        assert_eq!(Some(&'d'), arena.get(d));
+        assert_eq!(Some(2), arena.position(d));
+
+        // removing the last value moves nothing
+        assert_eq!(Ok('d'), arena.remove(d));
+        assert_eq!(Ok('b'), arena.remove(b));
+        assert_eq!(&['C'], arena.as_slice());
+        assert_eq!(1, arena.len());
+    }
+
+    #[test]
+    fn test_dense_arena_mirrors_handles() {
+        let mut arena = DenseGenArena::<u64, u16, u16>::new();
+        let mut live: Vec<_> = (0..100).map(|i| (arena.insert(i), i)).collect();
+        for round in 0..50 {
+            let (gi, value) = live.swap_remove((round * 7) % live.len());
+            assert_eq!(Ok(value), arena.remove(gi));
+            if round % 3 == 0 {
+                live.push((arena.insert(1000 + round as u64), 1000 + round as u64));
+            }
+        }
+        assert_eq!(live.len(), arena.len());
+        for &(gi, value) in &live {
+            assert_eq!(Some(&value), arena.get(gi));
+            assert_eq!(gi, arena.handles()[arena.position(gi).unwrap()]);
+        }
+    }
+
+    #[test]
+    fn test_dense_arena_iter() {
+        let mut arena = DenseGenArena::<u32>::new();
+        let a = arena.insert(1);
+        let b = arena.insert(2);
+        let c = arena.insert(3);
+        arena.remove(a).unwrap();
+
+        for (gi, value) in arena.iter_mut() {
+            if gi == b {
+                *value *= 10;
+            }
+        }
+        assert_eq!(vec![(c, &3), (b, &20)], arena.iter().collect::<Vec<_>>());
+        assert_eq!(vec![c, b], arena.keys().collect::<Vec<_>>());
+
+        arena.values_mut().for_each(|value| *value += 1);
+        assert_eq!(vec![&4, &21], arena.values().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_dense_arena_drain() {
+        let mut arena = DenseGenArena::<u32>::new();
+        let a = arena.insert(1);
+        let b = arena.insert(2);
+        assert_eq!(vec![(a, 1), (b, 2)], arena.drain().collect::<Vec<_>>());
+        assert!(arena.is_empty());
+        assert_eq!(None, arena.get(a));
+
+        let c = arena.insert(3);
+        assert_ne!(a, c);
+        assert_eq!(&[3], arena.as_slice());
+    }
+
+    #[test]
+    fn test_dense_arena_index() {
+        let mut arena = DenseGenArena::<u32>::new();
+        let a = arena.insert(1);
+        let b = arena.insert(2);
+        arena[b] *= 10;
+        assert_eq!(20, arena[b]);
+        arena.remove(a).unwrap();
+        assert!(std::panic::catch_unwind(|| arena[a]).is_err());
+    }
+
+    #[test]
+    fn test_dense_arena_into_iter() {
+        let mut arena = DenseGenArena::<Vec<u8>>::new();
+        let a = arena.insert(vec![1]);
+        let b = arena.insert(vec![2]);
+        arena.remove(a).unwrap();
+        assert_eq!(vec![(b, vec![2])], arena.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_dense_arena_clear() {
+        let mut arena = DenseGenArena::<u32>::new();
+        let a = arena.insert(1);
+        arena.clear();
+        assert!(arena.as_slice().is_empty());
+        let b = arena.insert(2);
+        assert_eq!(a.get_index(), b.get_index());
+        assert_eq!(None, arena.get(a));
+        assert_eq!(Err(GenIndexError::StaleGeneration), arena.remove(a));
+    }
+
+    #[test]
+    fn test_dense_arena_retain() {
+        let mut arena = DenseGenArena::<u32>::new();
+        let handles: Vec<_> = (0..10).map(|i| arena.insert(i)).collect();
+        let mut calls = 0;
+        arena.retain(|_, value| {
+            calls += 1;
+            *value % 3 == 0
+        });
+        assert_eq!(10, calls);
+        let mut kept = arena.as_slice().to_vec();
+        kept.sort();
+        assert_eq!(vec![0, 3, 6, 9], kept);
+        assert_eq!(Some(&9), arena.get(handles[9]));
+        assert_eq!(None, arena.get(handles[8]));
+    }
+
+    #[test]
+    fn test_dense_arena_get_disjoint_mut() {
+        let mut arena = DenseGenArena::<u32>::new();
+        let a = arena.insert(1);
+        let b = arena.insert(2);
+        let c = arena.insert(3);
+        arena.remove(a).unwrap();
+
+        let (x, y) = arena.get2_mut(b, c).unwrap();
+        std::mem::swap(x, y);
+        assert_eq!(Some(&3), arena.get(b));
+        assert!(arena.get2_mut(c, c).is_none());
+        assert!(arena.get2_mut(a, c).is_none());
+        assert!(arena.get_disjoint_mut([c, b]).is_some());
+    }
+
+    #[test]
+    fn test_dense_arena_capacity() {
+        let mut arena = DenseGenArena::<u32>::with_capacity(64);
+        assert!(arena.capacity() >= 64);
+        arena.insert(1);
+        arena.reserve(100);
+        assert!(arena.capacity() >= 101);
+        assert!(arena.handles.capacity() >= 101);
+    }
+
+    #[test]
+    fn test_dense_arena_memory_usage() {
+        let mut arena = DenseGenArena::<u64>::new();
+        arena.insert(1);
+        let usage = arena.memory_usage();
+        assert!(usage.slots >= mem::size_of::<u64>() + mem::size_of::<GenIndex<u32, u32>>());
+        assert!(usage.metadata >= mem::size_of::<DenseGenArena<u64>>() + mem::size_of::<usize>());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_dense_arena_par_iter() {
+        let mut arena = DenseGenArena::<u64>::new();
+        let handles: Vec<_> = (0..1000).map(|i| arena.insert(i)).collect();
+        arena.remove(handles[0]).unwrap();
+
+        arena.par_iter_mut().for_each(|(_, value)| *value *= 2);
+        assert_eq!(999, arena.par_iter().count());
+        assert_eq!(2 * (1..1000).sum::<u64>(), arena.par_iter().map(|(_, &v)| v).sum::<u64>());
+        assert!(arena.par_iter().all(|(gi, &v)| arena.get(gi) == Some(&v)));
+
+        // values only Send are modified in parallel just as well
+        let mut cells = DenseGenArena::<std::cell::Cell<u64>>::new();
+        let gi = cells.insert(std::cell::Cell::new(1));
+        cells.par_iter_mut().for_each(|(_, cell)| cell.set(cell.get() + 1));
+        assert_eq!(2, cells[gi].get());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_dense_arena_serde() {
+        let mut arena = DenseGenArena::<u32>::new();
+        let a = arena.insert(1);
+        let b = arena.insert(2);
+        arena.remove(a).unwrap();
+
+        let json = serde_json::to_string(&arena).unwrap();
+        let reloaded: DenseGenArena<u32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(arena, reloaded);
+        assert_eq!(None, reloaded.get(a));
+        assert_eq!(Some(&2), reloaded.get(b));
+    }
+}
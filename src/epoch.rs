@@ -0,0 +1,301 @@
+//! Lock-free allocation with epoch-based reclamation, behind the
+//! `crossbeam-epoch` feature.
+
+use std::{vec::Vec,
+          sync::Arc,
+          sync::atomic::AtomicU32,
+          sync::atomic::AtomicU64,
+          sync::atomic::Ordering};
+
+use crossbeam_epoch::{self as epoch, Atomic, Guard, Owned};
+
+use super::{GenIndex, GenIndexError};
+
+/// the low bit of a slot's state word tells whether it is live.
+const LIVE: u64 = 1;
+
+/// EpochGenIndexAllocator
+///
+/// A lock-free allocator of `GenIndex<u32, u32>` handles, like
+/// AtomicGenIndexAllocator, whose free list is managed with
+/// crossbeam-epoch. A deleted index only becomes reusable once every
+/// thread that was pinned at the time of deletion has unpinned.
+///
+/// This makes lock-free validation safe: a reader that pins the
+/// allocator and sees a handle live can go on using whatever the
+/// handle refers to for as long as it stays pinned. The handle may
+/// be deleted meanwhile, but its slot will not be handed to anyone
+/// else until the reader is done.
+///
+/// The flip side is that deleted slots come back with some delay,
+/// so an allocator running close to capacity may report
+/// `CapacityExhausted` while deletions are still pending.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// let gi = EpochGenIndexAllocator::with_capacity(1024);
+/// let idx = gi.next_index().unwrap();
+/// {
+///     let reader = gi.pin();
+///     if reader.is_live(idx) {
+///         // idx's slot cannot be recycled until reader is dropped
+///     }
+/// }
+/// gi.delete_index(idx).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct EpochGenIndexAllocator {
+    inner: Arc<Inner>,
+}
+
+/// EpochGuard
+///
+/// Keeps the current thread pinned, so that no index deleted while
+/// the guard lives is recycled before it is dropped. Obtained from
+/// `EpochGenIndexAllocator::pin`.
+#[derive(Debug)]
+pub struct EpochGuard<'a> {
+    alloc: &'a EpochGenIndexAllocator,
+    _guard: Guard,
+}
+
+#[derive(Debug)]
+struct Inner {
+    /// top of the free list.
+    head: Atomic<Node>,
+    /// next fresh slot number.
+    fresh: AtomicU32,
+    /// per slot, the generation in the high 32 bits, and the live
+    /// flag in the low bit.
+    state: Vec<AtomicU64>,
+}
+
+#[derive(Debug)]
+struct Node {
+    index: u32,
+    next: Atomic<Node>,
+}
+
+impl EpochGenIndexAllocator {
+    /// Create a new allocator able to hold `capacity` live indices.
+    pub fn with_capacity(capacity: u32) -> EpochGenIndexAllocator {
+        EpochGenIndexAllocator {
+            inner: Arc::new(Inner {
+                head: Atomic::null(),
+                fresh: AtomicU32::new(0),
+                state: (0..capacity).map(|_| AtomicU64::new(0)).collect(),
+            })
+        }
+    }
+
+    /// return the number of slots this allocator was created with.
+    pub fn capacity(&self) -> usize { self.inner.state.len() }
+
+    /// Pin the current thread, for validating handles without the
+    /// fear of their slots being recycled underneath.
+    pub fn pin(&self) -> EpochGuard<'_> {
+        EpochGuard{alloc: self, _guard: epoch::pin()}
+    }
+
+    /// allocate and provide a "new" index, recycling one whose
+    /// deletion all readers have seen, with its generation
+    /// incremented. Fails with `CapacityExhausted` if there is no
+    /// such index, and every fresh slot has been used.
+    pub fn next_index(&self) -> Result<GenIndex<u32, u32>, GenIndexError> {
+        let inner = &self.inner;
+        if let Some(index) = inner.pop_free(&epoch::pin()) {
+            let state = &inner.state[index as usize];
+            let generation = (state.load(Ordering::Relaxed) >> 32) as u32 + 1;
+            state.store((generation as u64) << 32 | LIVE, Ordering::Release);
+            return Ok(GenIndex::new(index, generation));
+        }
+        let capacity = inner.state.len() as u32;
+        let index = inner.fresh
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire,
+                          |f| if f < capacity { Some(f + 1) } else { None })
+            .map_err(|_| GenIndexError::CapacityExhausted)?;
+        inner.state[index as usize].store(LIVE, Ordering::Release);
+        Ok(GenIndex::new(index, 0))
+    }
+
+    /// Delete an index, with the same validation as
+    /// `GenIndexEntitySet::delete_index`. The slot goes on the free
+    /// list once every thread pinned right now has unpinned. Slots
+    /// whose generation is exhausted are retired.
+    pub fn delete_index(&self, gi: GenIndex<u32, u32>) -> Result<(), GenIndexError> {
+        let inner = &self.inner;
+        let state = inner.state.get(gi.index as usize)
+            .filter(|_| gi.index < inner.fresh.load(Ordering::Acquire))
+            .ok_or(GenIndexError::UnknownIndex)?;
+        let live = (gi.generation as u64) << 32 | LIVE;
+        if let Err(actual) = state.compare_exchange(live, live & !LIVE,
+                                                    Ordering::AcqRel, Ordering::Acquire) {
+            return Err(if (actual >> 32) as u32 != gi.generation {
+                GenIndexError::StaleGeneration
+            } else {
+                GenIndexError::AlreadyDeleted
+            });
+        }
+        if gi.generation != u32::MAX {
+            let guard = epoch::pin();
+            let inner = inner.clone();
+            guard.defer(move || inner.push_free(gi.index, &epoch::pin()));
+            guard.flush();
+        }
+        Ok(())
+    }
+
+    /// Check whether the given index is still live.
+    pub fn is_live(&self, gi: GenIndex<u32, u32>) -> bool {
+        self.inner.is_live(gi)
+    }
+}
+
+impl<'a> EpochGuard<'a> {
+    /// Check whether the given index is still live. If it is, its
+    /// slot will not be handed out again while this guard lives.
+    pub fn is_live(&self, gi: GenIndex<u32, u32>) -> bool {
+        self.alloc.inner.is_live(gi)
+    }
+}
+
+impl Inner {
+    fn is_live(&self, gi: GenIndex<u32, u32>) -> bool {
+        match self.state.get(gi.index as usize) {
+            Some(state) => state.load(Ordering::Acquire) == (gi.generation as u64) << 32 | LIVE,
+            None => false,
+        }
+    }
+
+    fn push_free(&self, index: u32, guard: &Guard) {
+        let mut node = Owned::new(Node{index, next: Atomic::null()});
+        loop {
+            let head = self.head.load(Ordering::Relaxed, guard);
+            node.next.store(head, Ordering::Relaxed);
+            match self.head.compare_exchange(head, node, Ordering::Release, Ordering::Relaxed, guard) {
+                Ok(_) => return,
+                Err(e) => node = e.new,
+            }
+        }
+    }
+
+    fn pop_free(&self, guard: &Guard) -> Option<u32> {
+        loop {
+            let head = self.head.load(Ordering::Acquire, guard);
+            // safe, since nodes are only destroyed once no thread
+            // pinned when they were unlinked remains pinned
+            let node = unsafe { head.as_ref() }?;
+            let next = node.next.load(Ordering::Relaxed, guard);
+            if self.head.compare_exchange(head, next, Ordering::Acquire, Ordering::Relaxed, guard).is_ok() {
+                let index = node.index;
+                unsafe { guard.defer_destroy(head) };
+                return Some(index);
+            }
+        }
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        // nobody else can see the list any more
+        unsafe {
+            let guard = epoch::unprotected();
+            let mut node = self.head.load(Ordering::Relaxed, guard);
+            while !node.is_null() {
+                let next = node.deref().next.load(Ordering::Relaxed, guard);
+                drop(node.into_owned());
+                node = next;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread::*, collections::HashSet};
+    use super::*;
+
+    const THREADS: usize = 8;
+    const ROUNDS: usize = 2000;
+
+    /// nudge the global collector until `f` succeeds, yielding so
+    /// that any thread still pinned gets to unpin.
+    fn eventually<T>(mut f: impl FnMut() -> Option<T>) -> T {
+        for _ in 0..100_000 {
+            if let Some(t) = f() {
+                return t;
+            }
+            epoch::pin().flush();
+            yield_now();
+        }
+        panic!("deferred deletions never ran");
+    }
+
+    #[test]
+    fn test_epoch_allocation() {
+        let gi = EpochGenIndexAllocator::with_capacity(1);
+        let idx1 = gi.next_index().unwrap();
+        assert_eq!(Err(GenIndexError::CapacityExhausted), gi.next_index());
+
+        gi.delete_index(idx1).unwrap();
+        assert_eq!(Err(GenIndexError::AlreadyDeleted), gi.delete_index(idx1));
+        assert!(!gi.is_live(idx1));
+
+        let idx2 = eventually(|| gi.next_index().ok());
+        assert_eq!(idx1.get_index(), idx2.get_index());
+        assert_eq!(1, idx2.get_generation());
+        assert_eq!(Err(GenIndexError::StaleGeneration), gi.delete_index(idx1));
+    }
+
+    #[test]
+    fn test_epoch_pinned_reader() {
+        let gi = EpochGenIndexAllocator::with_capacity(1);
+        let idx = gi.next_index().unwrap();
+
+        let reader = gi.pin();
+        assert!(reader.is_live(idx));
+        gi.delete_index(idx).unwrap();
+        for _ in 0..1000 {
+            epoch::pin().flush();
+            // the slot stays out of reach while the reader is pinned
+            assert_eq!(Err(GenIndexError::CapacityExhausted), gi.next_index());
+        }
+        drop(reader);
+
+        eventually(|| gi.next_index().ok());
+    }
+
+    #[test]
+    fn test_epoch_contention() {
+        let gi = Arc::new(EpochGenIndexAllocator::with_capacity((THREADS * 8) as u32));
+        let threads: Vec<_> = (0..THREADS).map(|_| {
+            let gi = gi.clone();
+            spawn(move || {
+                let mut held = Vec::new();
+                for i in 0..ROUNDS {
+                    held.push(eventually(|| gi.next_index().ok()));
+                    if i % 2 == 1 {
+                        for idx in held.drain(..) {
+                            gi.delete_index(idx).unwrap();
+                        }
+                    }
+                }
+                held.push(eventually(|| gi.next_index().ok()));
+                held
+            })
+        }).collect();
+
+        let mut seen = HashSet::new();
+        for t in threads {
+            for idx in t.join().unwrap() {
+                assert!(gi.is_live(idx));
+                assert!(seen.insert(idx.get_index()));
+            }
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Generating handles and operation sequences for property tests and
+//! fuzzing, behind the `arbitrary` and `proptest` features.
+
+use std::ops::AddAssign;
+
+use num::Num;
+#[cfg(feature = "proptest")]
+use num::{Bounded, ToPrimitive};
+
+use super::GenIndex;
+#[cfg(feature = "proptest")]
+use super::{GenIndexConfig, GenIndexEntitySet, GenIndexOp};
+
+#[cfg(feature = "arbitrary")]
+impl<'a, I: Num + AddAssign + Copy + arbitrary::Arbitrary<'a>,
+     G: Num + AddAssign + Copy + arbitrary::Arbitrary<'a>,
+     Tag> arbitrary::Arbitrary<'a> for GenIndex<I, G, Tag> {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(GenIndex::new(u.arbitrary()?, u.arbitrary()?))
+    }
+
+    fn size_hint(depth: usize) -> (usize, Option<usize>) {
+        arbitrary::size_hint::and(I::size_hint(depth), G::size_hint(depth))
+    }
+}
+
+#[cfg(feature = "proptest")]
+impl<I: Num + AddAssign + Copy + proptest::arbitrary::Arbitrary,
+     G: Num + AddAssign + Copy + proptest::arbitrary::Arbitrary,
+     Tag> proptest::arbitrary::Arbitrary for GenIndex<I, G, Tag> {
+    type Parameters = ();
+    type Strategy = proptest::strategy::Map<(I::Strategy, G::Strategy), fn((I, G)) -> Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        use proptest::strategy::Strategy;
+        (proptest::arbitrary::any::<I>(), proptest::arbitrary::any::<G>())
+            .prop_map(|(index, generation)| GenIndex::new(index, generation))
+    }
+}
+
+/// one step of a generated sequence, before it is run against a set.
+#[cfg(feature = "proptest")]
+#[derive(Debug, Clone)]
+enum Action {
+    Allocate,
+    /// delete one of the live handles, picked by this number.
+    Delete(usize),
+    Clear,
+    AdvanceTick,
+}
+
+/// Generate sequences of up to `len` operations which a set created
+/// with `config` accepts without error, as recorded by
+/// `GenIndexConfig::record_operations`. Feed them to
+/// `GenIndexEntitySet::from_recording` to rebuild the set, or walk
+/// them to drive your own model of the world.
+///
+/// Allocations the set refuses, say for a capacity limit, are left
+/// out. Sequences shrink towards fewer operations.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+/// extern crate proptest;
+///
+/// use gen_indices::*;
+/// use proptest::test_runner::TestRunner;
+///
+/// let config = GenIndexConfig::default();
+/// TestRunner::default().run(&gen_index_ops::<u32, u32>(config, 100), |ops| {
+///     assert!(GenIndexEntitySet::from_recording(config, &ops).is_ok());
+///     Ok(())
+/// }).unwrap();
+/// ```
+#[cfg(feature = "proptest")]
+pub fn gen_index_ops<I: Num + AddAssign + Copy + ToPrimitive + Bounded + std::fmt::Debug,
+                     G: Num + AddAssign + Copy + Bounded + PartialOrd + std::fmt::Debug>(
+    config: GenIndexConfig,
+    len: usize) -> impl proptest::strategy::Strategy<Value = Vec<GenIndexOp<I, G>>> {
+    use proptest::{prelude::*, collection::vec};
+
+    let action = prop_oneof![
+        4 => Just(Action::Allocate),
+        3 => any::<usize>().prop_map(Action::Delete),
+        1 => Just(Action::Clear),
+        1 => Just(Action::AdvanceTick),
+    ];
+    vec(action, 0..=len).prop_map(move |actions| {
+        let mut set = GenIndexEntitySet::<I, G>::from_config(
+            GenIndexConfig{record_operations: true, ..config});
+        let mut live = Vec::new();
+        for action in actions {
+            match action {
+                Action::Allocate => live.extend(set.try_next_index().ok()),
+                Action::Delete(_) if live.is_empty() => (),
+                Action::Delete(n) => {
+                    let gi = live.swap_remove(n % live.len());
+                    let _ = set.delete_index(gi);
+                },
+                Action::Clear => {
+                    set.clear();
+                    live.clear();
+                },
+                Action::AdvanceTick => set.advance_tick(),
+            }
+        }
+        set.recorded_operations().to_vec()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn test_arbitrary() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        let mut u = Unstructured::new(&[3, 0, 7]);
+        let gi = GenIndex::<u16, u8>::arbitrary(&mut u).unwrap();
+        assert_eq!(GenIndex::new(3, 7), gi);
+        assert_eq!((3, Some(3)), GenIndex::<u16, u8>::size_hint(0));
+    }
+
+    #[cfg(feature = "proptest")]
+    proptest::proptest! {
+        #[test]
+        fn test_gen_index_ops_replay(ops in gen_index_ops::<u8, u8>(GenIndexConfig::default(), 200)) {
+            let config = GenIndexConfig{record_operations: true, ..GenIndexConfig::default()};
+            let set = GenIndexEntitySet::from_recording(config, &ops).unwrap();
+            proptest::prop_assert_eq!(&ops[..], set.recorded_operations());
+        }
+
+        #[test]
+        fn test_gen_index_ops_limited(ops in gen_index_ops::<u32, u32>(GenIndexConfig{capacity_limit: Some(4), ..GenIndexConfig::default()}, 50)) {
+            let config = GenIndexConfig{capacity_limit: Some(4), ..GenIndexConfig::default()};
+            let set = GenIndexEntitySet::from_recording(config, &ops).unwrap();
+            proptest::prop_assert!(set.live_count() <= 4);
+        }
+
+        #[test]
+        fn test_any_gen_index(gi in proptest::arbitrary::any::<GenIndex<u16, u8>>()) {
+            proptest::prop_assert_eq!(Ok(gi), gi.to_string().parse());
+        }
+    }
+}
@@ -0,0 +1,208 @@
+//! Named groups of entities, deleted all at once.
+
+use std::{collections::HashMap,
+          hash::Hash,
+          ops::AddAssign};
+
+use num::{Num, Bounded, ToPrimitive};
+
+use super::{GenIndex, GenIndexEntitySet, GenIndexError, GenIndexHashSet, SecondaryMap};
+
+/// EntityGroups
+///
+/// Sorts the entities handed out by one set into named groups, say
+/// "level-1", "ui", and "particles", each entity into one group at
+/// most, so that a whole group can be gone through, or deleted,
+/// in one go. Groups come into being with their first member, and
+/// go away with their last.
+///
+/// As in a SecondaryMap, a handle only ever finds the group that
+/// very handle was assigned to. The groups do not know when
+/// entities are deleted through the set, however: call `remove` on
+/// deleting one, or only ever delete them with `delete_group`. One
+/// left behind is dropped from its group at the latest when its
+/// slot's next occupant is assigned.
+///
+/// The index and generation types default to `u32`.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// let mut entities = GenIndexEntitySet::<u32, u32>::new_unsync();
+/// let mut groups = EntityGroups::new();
+/// for _ in 0..3 {
+///     let spark = entities.next_index();
+///     groups.assign(&entities, spark, "particles").unwrap();
+/// }
+/// let button = entities.next_index();
+/// groups.assign(&entities, button, "ui").unwrap();
+///
+/// assert_eq!(3, groups.delete_group(&mut entities, "particles").len());
+/// assert_eq!(1, entities.live_count());
+/// assert_eq!(Some("ui"), groups.group_of(button));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntityGroups<I: Num + AddAssign + Copy + ToPrimitive + Bounded + Hash + Eq = u32,
+                        G: Num + AddAssign + Copy + Bounded + PartialOrd + Hash + Eq = u32> {
+    members: HashMap<String, GenIndexHashSet<GenIndex<I, G>>>,
+    /// per entity assigned, the name of its group.
+    groups: SecondaryMap<String, I, G>,
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + Hash + Eq,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Hash + Eq> EntityGroups<I, G> {
+
+    /// Create a new set of groups, all empty.
+    pub fn new() -> EntityGroups<I, G> {
+        EntityGroups{members: HashMap::new(), groups: SecondaryMap::new()}
+    }
+
+    /// Put a live entity into the named group, taking it out of the
+    /// one it was in before, if any. Fails as
+    /// `GenIndexEntitySet::delete_index` would if the entity is not
+    /// live in `entities`.
+    pub fn assign(&mut self, entities: &GenIndexEntitySet<I, G>,
+                  gi: GenIndex<I, G>, group: &str) -> Result<(), GenIndexError> {
+        entities.check_live(gi)?;
+        // the membership of an entity deleted, in the same slot
+        let previous = self.groups.occupant(gi).map(|(previous, _)| previous);
+        if let Some(previous) = previous.filter(|&previous| previous != gi) {
+            self.remove(previous);
+        }
+        self.remove(gi);
+        self.groups.insert(gi, group.to_string())?;
+        self.members.entry(group.to_string()).or_default().insert(gi);
+        Ok(())
+    }
+
+    /// Take an entity out of its group, on deleting it, say, and
+    /// return the name of the group it was in, if any.
+    pub fn remove(&mut self, gi: GenIndex<I, G>) -> Option<String> {
+        let group = self.groups.remove(gi)?;
+        if let Some(members) = self.members.get_mut(&group) {
+            members.remove(&gi);
+            if members.is_empty() {
+                self.members.remove(&group);
+            }
+        }
+        Some(group)
+    }
+
+    /// return the name of the group the entity is in, if any.
+    pub fn group_of(&self, gi: GenIndex<I, G>) -> Option<&str> {
+        self.groups.get(gi).map(String::as_str)
+    }
+
+    /// iterate over the members of the named group, in no
+    /// particular order.
+    pub fn members(&self, group: &str) -> impl Iterator<Item = GenIndex<I, G>> + '_ {
+        self.members.get(group).into_iter().flatten().copied()
+    }
+
+    /// return the number of members of the named group.
+    pub fn group_len(&self, group: &str) -> usize {
+        self.members.get(group).map_or(0, |members| members.len())
+    }
+
+    /// iterate over the names of the groups with any members, in
+    /// no particular order.
+    pub fn groups(&self) -> impl Iterator<Item = &str> + '_ {
+        self.members.keys().map(String::as_str)
+    }
+
+    /// Delete every member of the named group from `entities`, and
+    /// the group itself, and return the handles deleted. Members no
+    /// longer live are just dropped from the group.
+    pub fn delete_group(&mut self, entities: &mut GenIndexEntitySet<I, G>, group: &str) -> Vec<GenIndex<I, G>> {
+        let members = self.members.remove(group).unwrap_or_default();
+        let mut deleted = Vec::new();
+        for gi in members {
+            self.groups.remove(gi);
+            if entities.delete_index(gi).is_ok() {
+                deleted.push(gi);
+            }
+        }
+        deleted
+    }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + Hash + Eq,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Hash + Eq> Default for EntityGroups<I, G> {
+    fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_groups() {
+        let mut entities = GenIndexEntitySet::<u32, u32>::new_unsync();
+        let mut groups = EntityGroups::new();
+        let idxs = entities.next_indices(4);
+        groups.assign(&entities, idxs[0], "level-1").unwrap();
+        groups.assign(&entities, idxs[1], "level-1").unwrap();
+        groups.assign(&entities, idxs[2], "ui").unwrap();
+        assert_eq!(2, groups.group_len("level-1"));
+        assert_eq!(0, groups.group_len("nowhere"));
+
+        // moving between groups
+        groups.assign(&entities, idxs[1], "ui").unwrap();
+        assert_eq!(Some("ui"), groups.group_of(idxs[1]));
+        assert_eq!(vec![idxs[0]], groups.members("level-1").collect::<Vec<_>>());
+        let mut names: Vec<_> = groups.groups().collect();
+        names.sort();
+        assert_eq!(vec!["level-1", "ui"], names);
+
+        // the last member leaving takes the group with it
+        assert_eq!(Some("level-1".to_string()), groups.remove(idxs[0]));
+        assert_eq!(None, groups.remove(idxs[0]));
+        assert_eq!(vec!["ui"], groups.groups().collect::<Vec<_>>());
+
+        entities.delete_index(idxs[3]).unwrap();
+        assert_eq!(Err(GenIndexError::AlreadyDeleted), groups.assign(&entities, idxs[3], "ui"));
+    }
+
+    #[test]
+    fn test_delete_group() {
+        let mut entities = GenIndexEntitySet::<u32, u32>::new_unsync();
+        let mut groups = EntityGroups::new();
+        let idxs = entities.next_indices(3);
+        for &gi in &idxs {
+            groups.assign(&entities, gi, "level-1").unwrap();
+        }
+        // one deleted behind the groups' back
+        entities.delete_index(idxs[0]).unwrap();
+
+        let mut deleted = groups.delete_group(&mut entities, "level-1");
+        deleted.sort();
+        assert_eq!(vec![idxs[1], idxs[2]], deleted);
+        assert_eq!(0, entities.live_count());
+        assert_eq!(None, groups.group_of(idxs[1]));
+        assert!(groups.delete_group(&mut entities, "level-1").is_empty());
+    }
+
+    #[test]
+    fn test_groups_recycled_slot() {
+        let mut entities = GenIndexEntitySet::<u32, u32>::new_unsync();
+        let mut groups = EntityGroups::new();
+        let old = entities.next_index();
+        groups.assign(&entities, old, "level-1").unwrap();
+
+        // deleted behind the groups' back, its slot handed out again
+        entities.delete_index(old).unwrap();
+        let new = entities.next_index();
+        assert_eq!(old.get_index(), new.get_index());
+        groups.assign(&entities, new, "ui").unwrap();
+
+        assert_eq!(None, groups.group_of(old));
+        assert_eq!(0, groups.group_len("level-1"));
+        assert_eq!(vec!["ui"], groups.groups().collect::<Vec<_>>());
+        assert!(groups.delete_group(&mut entities, "level-1").is_empty());
+        assert!(entities.is_live(new));
+    }
+}
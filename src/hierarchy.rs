@@ -0,0 +1,326 @@
+//! Parents and children among entities.
+
+use std::ops::AddAssign;
+
+use num::{Num, Bounded, ToPrimitive};
+
+use super::{GenIndex, GenIndexEntitySet, GenIndexError, SecondaryMap};
+
+/// Hierarchy
+///
+/// Arranges the entities handed out by some set into trees: every
+/// entity has at most one parent, and any number of children, kept
+/// in the order they were attached. Entities never given a parent
+/// or a child are roots of their own.
+///
+/// Links are only ever made between live entities, and a handle
+/// only ever finds the links made for that very handle, as in a
+/// SecondaryMap. The hierarchy does not know when entities are
+/// deleted, however: call `remove` on deleting one, or find the
+/// links left dangling with `dangling`.
+///
+/// The index and generation types default to `u32`.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// let mut entities = GenIndexEntitySet::<u32, u32>::new_unsync();
+/// let mut scene = Hierarchy::new();
+/// let [ship, turret, barrel, wing] = [0; 4].map(|_| entities.next_index());
+/// scene.set_parent(&entities, turret, ship).unwrap();
+/// scene.set_parent(&entities, barrel, turret).unwrap();
+/// scene.set_parent(&entities, wing, ship).unwrap();
+///
+/// assert_eq!(&[turret, wing], scene.children(ship));
+/// assert_eq!(Some(ship), scene.parent(turret));
+/// assert_eq!(vec![ship, turret, barrel, wing], scene.depth_first(ship).collect::<Vec<_>>());
+/// assert_eq!(Err(GenIndexError::HierarchyCycle), scene.set_parent(&entities, ship, barrel));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hierarchy<I: Num + AddAssign + Copy + ToPrimitive + Bounded = u32,
+                     G: Num + AddAssign + Copy + Bounded + PartialOrd = u32> {
+    nodes: SecondaryMap<Node<I, G>, I, G>,
+}
+
+/// the links of one entity.
+#[derive(Debug, Clone, PartialEq)]
+struct Node<I: Num + AddAssign + Copy,
+            G: Num + AddAssign + Copy> {
+    parent: Option<GenIndex<I, G>>,
+    children: Vec<GenIndex<I, G>>,
+}
+
+impl<I: Num + AddAssign + Copy,
+     G: Num + AddAssign + Copy> Default for Node<I, G> {
+    fn default() -> Self { Node{parent: None, children: Vec::new()} }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> Hierarchy<I, G> {
+
+    /// Create a new hierarchy, where every entity is a root.
+    pub fn new() -> Hierarchy<I, G> {
+        Hierarchy{nodes: SecondaryMap::new()}
+    }
+
+    /// Make `child` a child of `parent`, the last of its children,
+    /// detaching it from its former parent, if any. Both must be
+    /// live in `entities`, failing as
+    /// `GenIndexEntitySet::delete_index` would otherwise, and fails
+    /// with `HierarchyCycle` if `parent` is `child` itself or one of
+    /// its descendants.
+    pub fn set_parent(&mut self, entities: &GenIndexEntitySet<I, G>,
+                      child: GenIndex<I, G>, parent: GenIndex<I, G>) -> Result<(), GenIndexError> {
+        entities.check_live(child)?;
+        entities.check_live(parent)?;
+        if self.ancestors_and_self(parent).any(|gi| gi == child) {
+            return Err(GenIndexError::HierarchyCycle);
+        }
+        self.evict(parent);
+        self.evict(child);
+        self.remove_parent(child);
+        self.nodes.entry(parent)?.or_default().children.push(child);
+        self.nodes.entry(child)?.or_default().parent = Some(parent);
+        Ok(())
+    }
+
+    /// Detach `child` from its parent, making it a root, and return
+    /// the former parent, if any.
+    pub fn remove_parent(&mut self, child: GenIndex<I, G>) -> Option<GenIndex<I, G>> {
+        let parent = self.nodes.get_mut(child)?.parent.take()?;
+        if let Some(node) = self.nodes.get_mut(parent) {
+            node.children.retain(|&gi| gi != child);
+        }
+        Some(parent)
+    }
+
+    /// Take an entity out of the hierarchy, on deleting it: detach
+    /// it from its parent, and its children from it, and return the
+    /// children, which are roots now.
+    pub fn remove(&mut self, gi: GenIndex<I, G>) -> Vec<GenIndex<I, G>> {
+        self.remove_parent(gi);
+        let children = self.nodes.remove(gi).map(|node| node.children).unwrap_or_default();
+        for &child in &children {
+            if let Some(node) = self.nodes.get_mut(child) {
+                node.parent = None;
+            }
+        }
+        children
+    }
+
+    /// return the parent of the entity, if it has one.
+    pub fn parent(&self, gi: GenIndex<I, G>) -> Option<GenIndex<I, G>> {
+        self.nodes.get(gi)?.parent
+    }
+
+    /// return the children of the entity, in the order they were
+    /// attached.
+    pub fn children(&self, gi: GenIndex<I, G>) -> &[GenIndex<I, G>] {
+        self.nodes.get(gi).map_or(&[], |node| &node.children)
+    }
+
+    /// iterate over the ancestors of the entity, its parent first,
+    /// and the root of its tree last.
+    pub fn ancestors(&self, gi: GenIndex<I, G>) -> impl Iterator<Item = GenIndex<I, G>> + '_ {
+        self.ancestors_and_self(gi).skip(1)
+    }
+
+    /// iterate over the tree below `root`, `root` included, in depth
+    /// first order: every entity comes before its children, and all
+    /// of its descendants before its next sibling.
+    pub fn depth_first(&self, root: GenIndex<I, G>) -> impl Iterator<Item = GenIndex<I, G>> + '_ {
+        let mut stack = vec![root];
+        std::iter::from_fn(move || {
+            let gi = stack.pop()?;
+            stack.extend(self.children(gi).iter().rev());
+            Some(gi)
+        })
+    }
+
+    /// iterate over the tree below `root`, `root` left out, in depth
+    /// first order, as `depth_first`.
+    pub fn descendants(&self, root: GenIndex<I, G>) -> impl Iterator<Item = GenIndex<I, G>> + '_ {
+        self.depth_first(root).skip(1)
+    }
+
+    /// Delete an entity and all of its descendants from `entities`,
+    /// taking them out of the hierarchy, and return the handles
+    /// freed, in depth first order, for their components to be
+    /// dropped. Descendants no longer live are taken out, but not
+    /// among those returned. Fails as
+    /// `GenIndexEntitySet::delete_index` would if `root` is not
+    /// live, changing nothing.
+    ///
+    /// ```
+    /// # use gen_indices::*;
+    /// let mut entities = GenIndexEntitySet::<u32, u32>::new_unsync();
+    /// let mut scene = Hierarchy::new();
+    /// let [ship, turret, barrel] = [0; 3].map(|_| entities.next_index());
+    /// scene.set_parent(&entities, turret, ship).unwrap();
+    /// scene.set_parent(&entities, barrel, turret).unwrap();
+    ///
+    /// assert_eq!(Ok(vec![turret, barrel]), scene.despawn_recursive(&mut entities, turret));
+    /// assert_eq!(1, entities.live_count());
+    /// assert!(scene.children(ship).is_empty());
+    /// ```
+    pub fn despawn_recursive(&mut self, entities: &mut GenIndexEntitySet<I, G>,
+                             root: GenIndex<I, G>) -> Result<Vec<GenIndex<I, G>>, GenIndexError> {
+        entities.check_live(root)?;
+        let subtree: Vec<_> = self.depth_first(root).collect();
+        self.remove_parent(root);
+        let mut freed = Vec::with_capacity(subtree.len());
+        for gi in subtree {
+            self.nodes.remove(gi);
+            if entities.delete_index(gi).is_ok() {
+                freed.push(gi);
+            }
+        }
+        Ok(freed)
+    }
+
+    /// return every entity of the hierarchy which has a parent no
+    /// longer live in `entities`, or is no longer live itself, but
+    /// still has a parent or children, or is still listed among the
+    /// children of another. `remove` them to clean up.
+    pub fn dangling(&self, entities: &GenIndexEntitySet<I, G>) -> Vec<GenIndex<I, G>> {
+        let mut found: Vec<_> = self.nodes.iter()
+            .filter(|&(gi, node)| {
+                !entities.is_live(gi) || node.parent.is_some_and(|parent| !entities.is_live(parent))
+            })
+            .map(|(gi, _)| gi)
+            .collect();
+        for (_, node) in self.nodes.iter() {
+            for &child in &node.children {
+                if !entities.is_live(child) && !found.contains(&child) {
+                    found.push(child);
+                }
+            }
+        }
+        found
+    }
+
+    /// Take the earlier occupant of the entity's slot, one deleted
+    /// without being removed, out of the hierarchy, so that the
+    /// entity does not take over its links.
+    fn evict(&mut self, gi: GenIndex<I, G>) {
+        let previous = self.nodes.occupant(gi).map(|(previous, _)| previous);
+        if let Some(previous) = previous.filter(|&previous| previous != gi) {
+            self.remove(previous);
+        }
+    }
+
+    fn ancestors_and_self(&self, gi: GenIndex<I, G>) -> impl Iterator<Item = GenIndex<I, G>> + '_ {
+        std::iter::successors(Some(gi), move |&gi| self.parent(gi))
+    }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> Default for Hierarchy<I, G> {
+    fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hierarchy() {
+        let mut entities = GenIndexEntitySet::<u32, u32>::new_unsync();
+        let mut tree = Hierarchy::new();
+        let idxs = entities.next_indices(5);
+        let (a, b, c, d, e) = (idxs[0], idxs[1], idxs[2], idxs[3], idxs[4]);
+        tree.set_parent(&entities, b, a).unwrap();
+        tree.set_parent(&entities, c, a).unwrap();
+        tree.set_parent(&entities, d, b).unwrap();
+        assert_eq!(vec![a, b, d, c], tree.depth_first(a).collect::<Vec<_>>());
+        assert_eq!(vec![b, a], tree.ancestors(d).collect::<Vec<_>>());
+        assert_eq!(Err(GenIndexError::HierarchyCycle), tree.set_parent(&entities, a, d));
+        assert_eq!(Err(GenIndexError::HierarchyCycle), tree.set_parent(&entities, a, a));
+
+        // moving a subtree
+        tree.set_parent(&entities, b, c).unwrap();
+        assert_eq!(&[c], tree.children(a));
+        assert_eq!(vec![c, b, d], tree.descendants(a).collect::<Vec<_>>());
+        assert_eq!(Some(c), tree.remove_parent(b));
+        assert_eq!(None, tree.remove_parent(b));
+        assert_eq!(0, tree.children(c).len());
+
+        entities.delete_index(e).unwrap();
+        assert_eq!(Err(GenIndexError::AlreadyDeleted), tree.set_parent(&entities, e, a));
+    }
+
+    #[test]
+    fn test_despawn_recursive() {
+        let mut entities = GenIndexEntitySet::<u32, u32>::new_unsync();
+        let mut tree = Hierarchy::new();
+        let idxs = entities.next_indices(5);
+        let (a, b, c, d, e) = (idxs[0], idxs[1], idxs[2], idxs[3], idxs[4]);
+        tree.set_parent(&entities, b, a).unwrap();
+        tree.set_parent(&entities, c, b).unwrap();
+        tree.set_parent(&entities, d, b).unwrap();
+        tree.set_parent(&entities, e, a).unwrap();
+        // one deleted behind the hierarchy's back
+        entities.delete_index(c).unwrap();
+
+        assert_eq!(Ok(vec![b, d]), tree.despawn_recursive(&mut entities, b));
+        assert_eq!(vec![a, e], entities.slot_handles().filter(|&gi| entities.is_live(gi)).collect::<Vec<_>>());
+        assert_eq!(&[e], tree.children(a));
+        assert!(tree.dangling(&entities).is_empty());
+        assert_eq!(Err(GenIndexError::AlreadyDeleted), tree.despawn_recursive(&mut entities, b));
+
+        // a leaf, and then all that is left
+        assert_eq!(Ok(vec![e]), tree.despawn_recursive(&mut entities, e));
+        assert_eq!(Ok(vec![a]), tree.despawn_recursive(&mut entities, a));
+        assert_eq!(0, entities.live_count());
+    }
+
+    #[test]
+    fn test_hierarchy_dangling() {
+        let mut entities = GenIndexEntitySet::<u32, u32>::new_unsync();
+        let mut tree = Hierarchy::new();
+        let idxs = entities.next_indices(3);
+        let (parent, child, grandchild) = (idxs[0], idxs[1], idxs[2]);
+        tree.set_parent(&entities, child, parent).unwrap();
+        tree.set_parent(&entities, grandchild, child).unwrap();
+        assert!(tree.dangling(&entities).is_empty());
+
+        entities.delete_index(child).unwrap();
+        assert_eq!(vec![child, grandchild], tree.dangling(&entities));
+        assert_eq!(vec![grandchild], tree.remove(child));
+        assert!(tree.dangling(&entities).is_empty());
+        assert_eq!(None, tree.parent(grandchild));
+        assert_eq!(0, tree.children(parent).len());
+
+        // the recycled slot starts out without links
+        let newcomer = entities.next_index();
+        assert_eq!(child.get_index(), newcomer.get_index());
+        assert_eq!(None, tree.parent(newcomer));
+        tree.set_parent(&entities, newcomer, parent).unwrap();
+        assert_eq!(&[newcomer], tree.children(parent));
+    }
+
+    #[test]
+    fn test_hierarchy_recycled_slot() {
+        let mut entities = GenIndexEntitySet::<u32, u32>::new_unsync();
+        let mut tree = Hierarchy::new();
+        let idxs = entities.next_indices(3);
+        let (grandparent, old, kid) = (idxs[0], idxs[1], idxs[2]);
+        tree.set_parent(&entities, old, grandparent).unwrap();
+        tree.set_parent(&entities, kid, old).unwrap();
+        entities.delete_index(old).unwrap();
+
+        // linking the newcomer in the slot unlinks the old one, unremoved
+        let newcomer = entities.next_index();
+        assert_eq!(old.get_index(), newcomer.get_index());
+        tree.set_parent(&entities, newcomer, grandparent).unwrap();
+        assert_eq!(&[newcomer], tree.children(grandparent));
+        assert_eq!(vec![grandparent, newcomer], tree.depth_first(grandparent).collect::<Vec<_>>());
+        assert_eq!(None, tree.parent(kid));
+        assert!(tree.dangling(&entities).is_empty());
+        assert!(tree.remove(old).is_empty());
+    }
+}
@@ -0,0 +1,101 @@
+//! Callbacks fired as a set hands out and deletes indices.
+
+use std::{fmt,
+          hash::Hash,
+          hash::Hasher,
+          ops::AddAssign,
+          panic::RefUnwindSafe,
+          panic::UnwindSafe,
+          sync::Arc};
+
+use num::Num;
+
+use super::GenIndex;
+
+type Hook<I, G> = Arc<dyn Fn(GenIndex<I, G>) + Send + Sync>;
+
+/// Hooks
+///
+/// The callbacks registered on a GenIndexEntitySet. Like the
+/// counters of a StatsCell, they are no part of the set's state: a
+/// clone of the set shares them, and they take no part in
+/// comparing, hashing or saving it.
+pub(crate) struct Hooks<I: Num + AddAssign + Copy,
+                        G: Num + AddAssign + Copy> {
+    on_allocate: Vec<Hook<I, G>>,
+    on_delete: Vec<Hook<I, G>>,
+}
+
+impl<I: Num + AddAssign + Copy,
+     G: Num + AddAssign + Copy> Hooks<I, G> {
+    pub(crate) fn new() -> Hooks<I, G> {
+        Hooks{on_allocate: Vec::new(), on_delete: Vec::new()}
+    }
+
+    pub(crate) fn add_allocate(&mut self, hook: Hook<I, G>) { self.on_allocate.push(hook) }
+
+    pub(crate) fn add_delete(&mut self, hook: Hook<I, G>) { self.on_delete.push(hook) }
+
+    pub(crate) fn clear(&mut self) {
+        self.on_allocate.clear();
+        self.on_delete.clear();
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.on_allocate.is_empty() && self.on_delete.is_empty()
+    }
+
+    /// fire the hooks for an index just handed out.
+    pub(crate) fn allocated(&self, gi: GenIndex<I, G>) {
+        for hook in &self.on_allocate {
+            hook(gi);
+        }
+    }
+
+    /// fire the hooks for an index just deleted.
+    pub(crate) fn deleted(&self, gi: GenIndex<I, G>) {
+        for hook in &self.on_delete {
+            hook(gi);
+        }
+    }
+}
+
+impl<I: Num + AddAssign + Copy,
+     G: Num + AddAssign + Copy> Clone for Hooks<I, G> {
+    fn clone(&self) -> Self {
+        Hooks{on_allocate: self.on_allocate.clone(), on_delete: self.on_delete.clone()}
+    }
+}
+
+// hooks are only ever called once the set is done changing, so one
+// that panics cannot leave the set half updated
+impl<I: Num + AddAssign + Copy,
+     G: Num + AddAssign + Copy> UnwindSafe for Hooks<I, G> {}
+
+impl<I: Num + AddAssign + Copy,
+     G: Num + AddAssign + Copy> RefUnwindSafe for Hooks<I, G> {}
+
+impl<I: Num + AddAssign + Copy,
+     G: Num + AddAssign + Copy> Default for Hooks<I, G> {
+    fn default() -> Self { Hooks::new() }
+}
+
+impl<I: Num + AddAssign + Copy,
+     G: Num + AddAssign + Copy> PartialEq for Hooks<I, G> {
+    fn eq(&self, _other: &Self) -> bool { true }
+}
+
+impl<I: Num + AddAssign + Copy,
+     G: Num + AddAssign + Copy> Hash for Hooks<I, G> {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
+impl<I: Num + AddAssign + Copy,
+     G: Num + AddAssign + Copy> fmt::Debug for Hooks<I, G> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("on_allocate", &self.on_allocate.len())
+            .field("on_delete", &self.on_delete.len())
+            .finish()
+    }
+}
@@ -2,23 +2,149 @@
 //!
 //! This is a simple implementation of generational indices. It will serve
 //! as the basis for you to implement your own Entity Component System. It uses
-//! a Mutex so that it can be thread safe.
+//! a Mutex so that it can be thread safe, though the plain, unsynchronized
+//! set is available as well for single threaded use.
 //!
 //! Please see https://github.com/flajann2/gen-indices for more details.
 
 #![warn(missing_docs)]
+#![cfg_attr(feature = "nightly", feature(allocator_api))]
 
 extern crate num;
 
 use std::{result::Result,
+          fmt,
+          error::Error,
           hash::Hash,
+          hash::Hasher,
+          cmp::Ordering,
           vec::Vec,
+          collections::VecDeque,
           ops::AddAssign,
           marker::Copy,
-          sync::Arc,
-          sync::Mutex};
+          marker::PhantomData,
+          mem,
+          sync::Arc};
 
-use num::{Num, zero, one};
+use num::{Num, Bounded, ToPrimitive, zero, one};
+
+use stats::StatsCell;
+use hooks::Hooks;
+use bitset::LiveMask;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+#[macro_use]
+mod macros;
+mod sync;
+mod allocator;
+mod concurrent;
+mod sharded;
+mod cache;
+mod shared;
+mod tickets;
+mod deferred;
+mod stats;
+mod snapshot;
+mod hooks;
+mod policy;
+mod nonzero;
+mod tagged;
+mod convert;
+mod weak;
+mod owned;
+mod hasher;
+mod bitset;
+mod chunked;
+mod arena;
+mod dense;
+mod secondary;
+mod persistent;
+mod pinned;
+mod components;
+mod reflect;
+mod archetype;
+mod query;
+mod registry;
+mod builder;
+mod prefab;
+mod hierarchy;
+mod relations;
+mod names;
+mod groups;
+mod commands;
+mod resources;
+mod access;
+mod world;
+#[cfg(feature = "tokio")]
+mod async_set;
+#[cfg(feature = "crossbeam-epoch")]
+mod epoch;
+#[cfg(any(feature = "arbitrary", feature = "proptest"))]
+mod fuzz;
+#[cfg(feature = "rkyv")]
+mod archive;
+#[cfg(feature = "bytemuck")]
+mod pod;
+#[cfg(feature = "uuid")]
+mod uuids;
+#[cfg(test)]
+mod test_fixtures;
+
+pub use sync::{Mutex, MutexGuard};
+pub use allocator::{Allocator, Global};
+pub use policy::ReusePolicy;
+#[cfg(feature = "rkyv")]
+pub use policy::ArchivedReusePolicy;
+pub use concurrent::AtomicGenIndexAllocator;
+pub use sharded::{ShardedGenIndexEntitySet, ShardRouting};
+pub use cache::LocalIndexCache;
+pub use shared::{SharedGenIndexEntitySetExt, SharedGenIndexSet};
+pub use tickets::{TicketDispenser, GenIndexTicket};
+pub use deferred::DeferredGenIndexEntitySet;
+pub use stats::{GenIndexStats, GenIndexStatsReader, MemoryUsage};
+pub use snapshot::GenIndexSnapshot;
+pub use nonzero::GenIndexNZ;
+pub use tagged::{TaggedGenIndexEntitySet, SharedTaggedGenIndexEntitySet};
+pub use weak::WeakGenIndex;
+pub use owned::OwnedIndex;
+pub use hasher::{GenIndexHasher, BuildGenIndexHasher, GenIndexMap, GenIndexHashSet};
+pub use arena::{GenIndexArena, ArenaEntry, OccupiedArenaEntry, VacantArenaEntry};
+pub use dense::DenseGenArena;
+pub use persistent::PersistentGenArena;
+pub use pinned::PinnedGenArena;
+pub use components::{Component, ComponentStore};
+pub use reflect::ComponentInfo;
+pub use archetype::{Archetype, StorageBackend};
+pub use bitset::{BitSet, BitSetIntoIter};
+pub use query::{Query, TagFilter, With, Without};
+pub use registry::Registry;
+pub use builder::EntityBuilder;
+pub use prefab::Prefab;
+pub use hierarchy::Hierarchy;
+pub use relations::Relations;
+pub use names::EntityNames;
+pub use groups::EntityGroups;
+pub use commands::{CommandBuffer, CommandError, CommandTarget, PendingEntity};
+pub use resources::Resources;
+pub use access::{Access, AccessChecker, AccessGuard, SharedStore, StoreBorrow};
+pub use world::World;
+pub use secondary::{SecondaryMap, SparseSecondaryMap,
+                    SecondaryEntry, OccupiedSecondaryEntry, VacantSecondaryEntry};
+#[cfg(feature = "tokio")]
+pub use async_set::AsyncGenIndexEntitySet;
+#[cfg(feature = "crossbeam-epoch")]
+pub use epoch::{EpochGenIndexAllocator, EpochGuard};
+#[cfg(feature = "proptest")]
+pub use fuzz::gen_index_ops;
+#[cfg(feature = "uuid")]
+pub use uuids::UuidRegistry;
+
+#[doc(hidden)]
+pub mod __private {
+    #[cfg(feature = "serde")]
+    pub use serde;
+}
 
 /// GenIndex
 ///
@@ -27,26 +153,354 @@ use num::{Num, zero, one};
 /// and a new one is entered, it won't confuse the new one with
 /// the old one on lookups.
 ///
-#[derive(Hash, Debug, PartialEq, Copy, Clone)]
+/// Handles order by index first, then by generation, so they can
+/// be sorted, or kept in a BTreeMap or BTreeSet.
+///
+/// The optional `Tag` is a marker type which keeps handles from
+/// different sets apart, see TaggedGenIndexEntitySet. It costs
+/// nothing at runtime, and places no requirements on the tag; any
+/// type will do, even one that is not `Copy` or `Send`.
+///
+/// With the `serde` feature, a handle serializes as a struct with
+/// the fields `index` and `generation`. With the `rkyv` feature, it
+/// archives as an ArchivedGenIndex, readable in place.
+///
+/// The layout is fixed as that of the C struct
+/// `struct { I index; G generation; }`, so handles can be passed
+/// across a C boundary, or embedded in GPU and engine structs, as
+/// they are. The tag takes up no space. With the `bytemuck`
+/// feature, handles whose index and generation are of the same
+/// width are `Pod`, so whole arrays of them cast to bytes and back.
+///
+#[repr(C)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
 pub struct GenIndex<I: Num + AddAssign + Copy,
-                    G: Num + AddAssign + Copy> {
+                    G: Num + AddAssign + Copy,
+                    Tag = ()> {
     index: I,
-    generation: G
+    generation: G,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    tag: PhantomData<fn() -> Tag>,
 }
 
 impl<I: Num + AddAssign + Copy,
-     G: Num + AddAssign + Copy> GenIndex<I, G> {
+     G: Num + AddAssign + Copy,
+     Tag> GenIndex<I, G, Tag> {
+    /// Build a handle from its index and generation. Usable in
+    /// const contexts, for static tables of well-known handles:
+    ///
+    /// ```
+    /// # use gen_indices::GenIndex;
+    /// const PLAYER: GenIndex<u32, u32> = GenIndex::new(0, 0);
+    /// const CAMERA: GenIndex<u32, u32> = GenIndex::new(1, 0);
+    /// assert_eq!(1, CAMERA.get_index());
+    /// ```
+    ///
+    /// Nothing is checked here; whether the handle refers to
+    /// anything is for the set to say.
+    pub const fn new(index: I, generation: G) -> Self {
+        GenIndex{index, generation, tag: PhantomData}
+    }
+
+    /// Build a handle from its index and generation, as read back
+    /// from a network message or a database row. Nothing is
+    /// checked here; whether the handle refers to anything is for
+    /// the set to say, with `is_live` or `contains_index`.
+    pub const fn from_raw_parts(index: I, generation: G) -> Self { GenIndex::new(index, generation) }
+
+    /// split the handle into its index and generation, the inverse
+    /// of `from_raw_parts`.
+    pub fn into_raw_parts(self) -> (I, G) { (self.index, self.generation) }
+
     /// return the index number for GenIndex.
-    pub fn get_index(&self) -> I { self.index }
+    pub const fn get_index(&self) -> I { self.index }
 
     /// return the generation number for GenIndex.
-    pub fn get_generation(&self) -> G { self.generation }
+    pub const fn get_generation(&self) -> G { self.generation }
+
+    /// return the null handle, index zero at generation zero. A set
+    /// configured with `null_sentinel` never hands this out, so it
+    /// can stand in for "no entity".
+    pub fn null() -> Self { GenIndex::new(zero(), zero()) }
+
+    /// check whether this is the null handle.
+    pub fn is_null(&self) -> bool { *self == Self::null() }
+
+    /// return the same handle with the tag stripped off.
+    pub fn untagged(self) -> GenIndex<I, G> { GenIndex::new(self.index, self.generation) }
+
+    /// return the same handle with a different tag. This undoes
+    /// the protection that tags give, so keep it to the places
+    /// where untagged handles come into your program.
+    pub fn retag<T>(self) -> GenIndex<I, G, T> { GenIndex::new(self.index, self.generation) }
+}
+
+impl<I: Num + AddAssign + Copy + Bounded,
+     G: Num + AddAssign + Copy + Bounded,
+     Tag> GenIndex<I, G, Tag> {
+    /// return the invalid handle, the largest index at the largest
+    /// generation. No set ever hands out the largest index, so
+    /// unlike `null`, this can stand in for "no entity" whatever
+    /// the set's configuration.
+    pub fn invalid() -> Self { GenIndex::new(I::max_value(), G::max_value()) }
+
+    /// check whether this is anything other than the invalid handle.
+    /// This says nothing about whether the handle is live.
+    pub fn is_valid(&self) -> bool { *self != Self::invalid() }
+}
+
+// The traits below are implemented by hand rather than derived, so
+// that they do not demand anything of the tag.
+
+impl<I: Num + AddAssign + Copy,
+     G: Num + AddAssign + Copy,
+     Tag> Clone for GenIndex<I, G, Tag> {
+    fn clone(&self) -> Self { *self }
+}
+
+impl<I: Num + AddAssign + Copy,
+     G: Num + AddAssign + Copy,
+     Tag> Copy for GenIndex<I, G, Tag> {}
+
+impl<I: Num + AddAssign + Copy,
+     G: Num + AddAssign + Copy,
+     Tag> PartialEq for GenIndex<I, G, Tag> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index && self.generation == other.generation
+    }
+}
+
+impl<I: Num + AddAssign + Copy + Eq,
+     G: Num + AddAssign + Copy + Eq,
+     Tag> Eq for GenIndex<I, G, Tag> {}
+
+impl<I: Num + AddAssign + Copy + PartialOrd,
+     G: Num + AddAssign + Copy + PartialOrd,
+     Tag> PartialOrd for GenIndex<I, G, Tag> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match self.index.partial_cmp(&other.index) {
+            Some(Ordering::Equal) => self.generation.partial_cmp(&other.generation),
+            ord => ord,
+        }
+    }
+}
+
+impl<I: Num + AddAssign + Copy + Ord,
+     G: Num + AddAssign + Copy + Ord,
+     Tag> Ord for GenIndex<I, G, Tag> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.index.cmp(&other.index).then(self.generation.cmp(&other.generation))
+    }
+}
+
+impl<I: Num + AddAssign + Copy + Hash,
+     G: Num + AddAssign + Copy + Hash,
+     Tag> Hash for GenIndex<I, G, Tag> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+        self.generation.hash(state);
+    }
+}
+
+impl<I: Num + AddAssign + Copy + fmt::Debug,
+     G: Num + AddAssign + Copy + fmt::Debug,
+     Tag> fmt::Debug for GenIndex<I, G, Tag> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GenIndex")
+            .field("index", &self.index)
+            .field("generation", &self.generation)
+            .finish()
+    }
+}
+
+/// The default handle is the invalid one.
+impl<I: Num + AddAssign + Copy + Bounded,
+     G: Num + AddAssign + Copy + Bounded,
+     Tag> Default for GenIndex<I, G, Tag> {
+    fn default() -> Self { Self::invalid() }
+}
+
+/// Shows a handle compactly as index and generation, so that index
+/// 3 at generation 7 comes out as `3v7`. The same form is read back
+/// by `str::parse`.
+impl<I: Num + AddAssign + Copy + fmt::Display,
+     G: Num + AddAssign + Copy + fmt::Display,
+     Tag> fmt::Display for GenIndex<I, G, Tag> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}v{}", self.index, self.generation)
+    }
+}
+
+/// PackedGenIndex
+///
+/// The common case of 32-bit indices and generations, which packs
+/// losslessly into a single `u64` with `to_bits`, for storing
+/// handles in GPU buffers, atomics and FFI structs.
+pub type PackedGenIndex = GenIndex<u32, u32>;
+
+impl<Tag> GenIndex<u32, u32, Tag> {
+    /// pack this handle into one word, the generation in the high
+    /// 32 bits and the index in the low 32 bits.
+    pub fn to_bits(self) -> u64 {
+        (self.generation as u64) << 32 | self.index as u64
+    }
+
+    /// unpack a handle packed with `to_bits`.
+    pub fn from_bits(bits: u64) -> Self {
+        GenIndex::new(bits as u32, (bits >> 32) as u32)
+    }
+}
+
+/// GenIndexError
+///
+/// The ways in which an operation on a GenIndexEntitySet can fail.
+#[derive(Hash, Debug, PartialEq, Eq, Copy, Clone)]
+pub enum GenIndexError {
+    /// The index was never handed out by this set.
+    UnknownIndex,
+    /// The index has already been deleted.
+    AlreadyDeleted,
+    /// Replaying a recording handed out a different index than
+    /// the one that was recorded.
+    ReplayDiverged,
+    /// The generation of the handle does not match the current
+    /// generation of its slot; the handle refers to an entity
+    /// that no longer exists.
+    StaleGeneration,
+    /// The index is currently live, or set aside for a ticket, and
+    /// cannot be claimed again.
+    IndexInUse,
+    /// The set is bounded and already has as many live indices
+    /// as its capacity limit allows.
+    CapacityExhausted,
+    /// Every index representable in the index type has been
+    /// handed out, and none are free to be recycled.
+    IndexSpaceExhausted,
+    /// The handle has generation zero, which a GenIndexNZ cannot
+    /// hold. Sets configured with `null_sentinel` never hand such
+    /// handles out.
+    ZeroGeneration,
+    /// The index or generation does not fit in the type the handle
+    /// is being converted to.
+    OutOfRange,
+    /// The text is not a handle in the compact form shown by
+    /// `Display`, such as `3v7`.
+    ParseFailed,
+    /// The entity cannot be made a child of itself, or of one of
+    /// its own descendants.
+    HierarchyCycle,
+    /// A component type is borrowed by a running system in a way
+    /// conflicting with the access asked for.
+    AccessConflict,
+    /// The name is already given to another live entity.
+    NameTaken,
+    /// The snapshot is not of a state a set can be in: its free
+    /// list names a slot out of range, live, or twice, say.
+    InvalidSnapshot,
+    /// A thread panicked while holding the lock of the shared set,
+    /// which may have been left part way through an operation.
+    Poisoned,
+}
+
+impl fmt::Display for GenIndexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GenIndexError::UnknownIndex => write!(f, "index was never allocated"),
+            GenIndexError::AlreadyDeleted => write!(f, "index has already been deleted"),
+            GenIndexError::StaleGeneration => write!(f, "index generation is stale"),
+            GenIndexError::ReplayDiverged => write!(f, "replay diverged from the recording"),
+            GenIndexError::IndexInUse => write!(f, "index is already in use"),
+            GenIndexError::CapacityExhausted => write!(f, "capacity limit reached"),
+            GenIndexError::IndexSpaceExhausted => write!(f, "index space exhausted"),
+            GenIndexError::ZeroGeneration => write!(f, "generation zero cannot be represented"),
+            GenIndexError::OutOfRange => write!(f, "index or generation out of range"),
+            GenIndexError::ParseFailed => write!(f, "not a handle of the form 3v7"),
+            GenIndexError::HierarchyCycle => write!(f, "entity would become its own ancestor"),
+            GenIndexError::AccessConflict => write!(f, "component type already borrowed in a conflicting way"),
+            GenIndexError::NameTaken => write!(f, "name already given to another entity"),
+            GenIndexError::InvalidSnapshot => write!(f, "snapshot is not of a valid state"),
+            GenIndexError::Poisoned => write!(f, "lock poisoned by a thread panicking while holding it"),
+        }
+    }
+}
+
+impl Error for GenIndexError {}
+
+/// GenIndexConfig
+///
+/// Construction time settings for a GenIndexEntitySet. Start from
+/// `GenIndexConfig::default()` and override what you need.
+#[derive(Hash, Debug, PartialEq, Eq, Copy, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
+pub struct GenIndexConfig {
+    /// how deleted indices are picked for reuse.
+    pub reuse_policy: ReusePolicy,
+    /// deleted indices are only recycled once more than this many
+    /// are waiting in the free list; until then, fresh indices are
+    /// allocated. Zero (the default) recycles immediately.
+    pub recycle_threshold: usize,
+    /// the maximum number of indices that may be live at once,
+    /// or None (the default) for no limit.
+    pub capacity_limit: Option<usize>,
+    /// start every slot at generation one rather than zero, so that
+    /// generation zero, and with it `GenIndex::null()`, is never
+    /// handed out. Off by default.
+    pub null_sentinel: bool,
+    /// keep a log of every operation that changes the set, which
+    /// can be fed to `GenIndexEntitySet::replay`. Off by default.
+    pub record_operations: bool,
+    /// a deleted index is quarantined, and not recycled, until at
+    /// least this many ticks have passed since its deletion. See
+    /// `GenIndexEntitySet::advance_tick`. Zero (the default)
+    /// disables the quarantine.
+    pub quarantine_ticks: u64,
+    /// the number of slots that may ever be handed out, so that
+    /// fresh indices run out once the indices below it are all in
+    /// use, or None (the default) for as many as `I` can count.
+    pub index_limit: Option<usize>,
+}
+
+/// GenIndexOp
+///
+/// One recorded operation on a GenIndexEntitySet, see
+/// `GenIndexConfig::record_operations`.
+#[derive(Hash, Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
+pub enum GenIndexOp<I: Num + AddAssign + Copy,
+                    G: Num + AddAssign + Copy> {
+    /// `next_index` handed out this index.
+    Allocate(GenIndex<I, G>),
+    /// `delete_index` freed this index.
+    Delete(GenIndex<I, G>),
+    /// `reserve_index` claimed this index.
+    Reserve(GenIndex<I, G>),
+    /// `clear` deleted every live index.
+    Clear,
+    /// `advance_tick` moved the clock forward.
+    AdvanceTick,
+    /// a TicketDispenser set aside this many fresh indices.
+    Claim(usize),
+    /// a ticket was redeemed for this index.
+    Redeem(GenIndex<I, G>),
+    /// a ticket for this slot was given up, or never handed out.
+    Release(I),
+    /// `compact` moved live indices into the lowest free slots.
+    Compact,
 }
 
 /// GenIndexEntitySet
 /// This maintains the "state" for your entities. It is designed with
 /// a Mutex, so that it is rendered thread safe.
 ///
+/// The set itself holds no lock. `new()` and the other `with_...`
+/// constructors hand it to you already wrapped up as a
+/// SharedGenIndexEntitySet; if you have no need to share it
+/// between threads, create it with `new_unsync()` or
+/// `from_config()` instead and skip the locking altogether.
+///
 /// Example:
 ///
 /// ```
@@ -56,6 +510,8 @@ impl<I: Num + AddAssign + Copy,
 /// use gen_indices::*;
 /// use num::{Num, zero, one};
 ///
+/// # #[cfg(not(feature = "parking_lot"))]
+/// # fn main() {
 /// let gi = GenIndexEntitySet::<u64, u64>::new();
 ///
 /// // first index
@@ -72,114 +528,1927 @@ impl<I: Num + AddAssign + Copy,
 /// }
 /// let idx3 = gi.lock().unwrap().next_index();
 /// println!("first: {:?}", idx3);
+/// # }
+/// # #[cfg(feature = "parking_lot")]
+/// # fn main() {}
 /// ```
-#[derive(Hash, Debug, PartialEq, Clone)]
-pub struct GenIndexEntitySet<I: Num + AddAssign + Copy,
-                             G: Num + AddAssign + Copy> {
+///
+/// # Serialization
+///
+/// With the `serde` feature, a set serializes as a struct holding
+/// its complete state, so that a reloaded set carries on exactly
+/// where the saved one left off, and handles saved along with it
+/// stay valid, or stale, just as they were:
+///
+/// - `next_index`: the next fresh index.
+/// - `free_list`: the deleted handles waiting to be recycled, in
+///   deletion order, each with the tick it was deleted at.
+/// - `slots`: per index handed out so far, its `generation`, and
+///   whether it is `live` or `claimed` by a ticket.
+/// - `retired`: the indices whose generations are exhausted.
+/// - `live`, `high_water`, `allocations`: the counters.
+/// - `config`, `rng`, `log`, `tick`: the settings, the state of
+///   the random reuse policy, the recording, and the clock.
+///
+/// New fields may be added in later versions, but these will keep
+/// their names and meaning.
+///
+/// With the `rkyv` feature, a set archives as an
+/// ArchivedGenIndexEntitySet, which answers `is_live` straight from
+/// the archived bytes, say from a memory-mapped save file, without a
+/// deserialization pass.
+///
+/// # Allocator
+///
+/// With the `nightly` feature, the third type parameter is the
+/// `std::alloc::Allocator` the set keeps its free list, slots and
+/// recording in, say an arena or bump allocator per level, as given
+/// to `new_in` or `from_config_in`. It defaults to the global
+/// allocator, and without the feature that is the only one there
+/// is. A set in another allocator saves and archives just as one in
+/// the global allocator does, and is loaded back into a default
+/// constructed allocator.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(all(feature = "serde", feature = "nightly"),
+           serde(bound(serialize = "I: Serialize, G: Serialize",
+                       deserialize = "I: Deserialize<'de>, G: Deserialize<'de>, A: Default")))]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
+pub struct GenIndexEntitySet<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+                             G: Num + AddAssign + Copy + Bounded + PartialOrd,
+                             A: Allocator + Clone = Global> {
+    #[cfg_attr(feature = "serde", serde(rename = "next_index"))]
     index_note: I,
-    deleted: Vec<GenIndex<I, G>>,
+    #[cfg(not(feature = "nightly"))]
+    #[cfg_attr(feature = "serde", serde(rename = "free_list"))]
+    deleted: VecDeque<(GenIndex<I, G>, u64)>,
+    #[cfg(feature = "nightly")]
+    #[cfg_attr(feature = "serde", serde(rename = "free_list", with = "allocator::in_alloc"))]
+    #[cfg_attr(feature = "rkyv", rkyv(with = allocator::InAlloc))]
+    deleted: VecDeque<(GenIndex<I, G>, u64), A>,
+    #[cfg(not(feature = "nightly"))]
+    slots: Vec<Slot<G>>,
+    #[cfg(feature = "nightly")]
+    #[cfg_attr(feature = "serde", serde(with = "allocator::in_alloc"))]
+    #[cfg_attr(feature = "rkyv", rkyv(with = allocator::InAlloc))]
+    slots: Vec<Slot<G>, A>,
+    #[cfg(not(feature = "nightly"))]
+    retired: Vec<I>,
+    #[cfg(feature = "nightly")]
+    #[cfg_attr(feature = "serde", serde(with = "allocator::in_alloc"))]
+    #[cfg_attr(feature = "rkyv", rkyv(with = allocator::InAlloc))]
+    retired: Vec<I, A>,
+    live: usize,
+    high_water: usize,
+    allocations: usize,
+    config: GenIndexConfig,
+    rng: u64,
+    #[cfg(not(feature = "nightly"))]
+    log: Vec<GenIndexOp<I, G>>,
+    #[cfg(feature = "nightly")]
+    #[cfg_attr(feature = "serde", serde(with = "allocator::in_alloc"))]
+    #[cfg_attr(feature = "rkyv", rkyv(with = allocator::InAlloc))]
+    log: Vec<GenIndexOp<I, G>, A>,
+    tick: u64,
+    /// the allocator the vectors above are kept in, for when they
+    /// are built anew.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    #[cfg_attr(feature = "rkyv", rkyv(with = rkyv::with::Skip))]
+    alloc: A,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    #[cfg_attr(feature = "rkyv", rkyv(with = rkyv::with::Skip))]
+    stats: StatsCell,
+    #[cfg_attr(feature = "serde", serde(skip, default = "Hooks::new"))]
+    #[cfg_attr(feature = "rkyv", rkyv(with = rkyv::with::Skip))]
+    hooks: Hooks<I, G>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    #[cfg_attr(feature = "rkyv", rkyv(with = rkyv::with::Skip))]
+    live_mask: LiveMask,
 }
 
-impl<I: Num + AddAssign + Copy,
-     G: Num + AddAssign + Copy> GenIndexEntitySet<I, G> {
+/// SharedGenIndexEntitySet
+///
+/// A GenIndexEntitySet wrapped up to be shared between threads,
+/// as returned by `GenIndexEntitySet::new()`.
+///
+/// With the `parking_lot` feature enabled, the Mutex is a
+/// `parking_lot::Mutex`. It cannot be poisoned, so its `lock()`
+/// returns the guard itself, with no `Result` to unwrap.
+pub type SharedGenIndexEntitySet<I, G> = Arc<Mutex<GenIndexEntitySet<I, G>>>;
+
+/// Slot
+///
+/// Internal bookkeeping for every index ever handed out: the
+/// current generation of the slot, whether it is live, and whether
+/// it is set aside for a GenIndexTicket that has yet to be redeemed.
+#[derive(Hash, Debug, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
+struct Slot<G: Num + AddAssign + Copy> {
+    generation: G,
+    live: bool,
+    claimed: bool,
+}
+
+/// return the handles of the live slots, in index order.
+fn live_handles<I: Num + AddAssign + Copy, G: Num + AddAssign + Copy>(slots: &[Slot<G>]) -> Vec<GenIndex<I, G>> {
+    let mut live = Vec::new();
+    let mut index = zero();
+    for slot in slots {
+        if slot.live {
+            live.push(GenIndex::new(index, slot.generation));
+        }
+        index += one();
+    }
+    live
+}
+
+/// Where the next index comes from: a fresh slot, or the entry
+/// at the given position of the free list, along with the state
+/// of the random number generator after picking it.
+enum NextSlot {
+    Fresh,
+    Recycle(usize, u64),
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> GenIndexEntitySet<I, G> {
 
     /// Create a new GenIndexEntitySet object, wrapped with
     /// a Mutex to allow for thread safety.
-    pub fn new() -> Arc<Mutex<GenIndexEntitySet<I, G>>> {
-        Arc::new(Mutex::new(GenIndexEntitySet {
+    pub fn new() -> SharedGenIndexEntitySet<I, G> {
+        Self::with_config(GenIndexConfig::default())
+    }
+
+    /// Create a new GenIndexEntitySet object with the given
+    /// settings, wrapped with a Mutex to allow for thread safety.
+    pub fn with_config(config: GenIndexConfig) -> SharedGenIndexEntitySet<I, G> {
+        Self::from_config(config).into_shared()
+    }
+
+    /// Create a new GenIndexEntitySet object which recycles deleted
+    /// indices according to the given policy.
+    pub fn with_reuse_policy(policy: ReusePolicy) -> SharedGenIndexEntitySet<I, G> {
+        Self::with_config(GenIndexConfig{reuse_policy: policy, ..GenIndexConfig::default()})
+    }
+
+    /// Create a new GenIndexEntitySet object which only recycles
+    /// deleted indices once more than `threshold` of them are
+    /// waiting to be reused.
+    pub fn with_recycle_threshold(threshold: usize) -> SharedGenIndexEntitySet<I, G> {
+        Self::with_config(GenIndexConfig{recycle_threshold: threshold, ..GenIndexConfig::default()})
+    }
+
+    /// Create a new bounded GenIndexEntitySet object, which refuses
+    /// to allocate once `limit` indices are live.
+    pub fn with_capacity_limit(limit: usize) -> SharedGenIndexEntitySet<I, G> {
+        Self::with_config(GenIndexConfig{capacity_limit: Some(limit), ..GenIndexConfig::default()})
+    }
+
+    /// Create a new GenIndexEntitySet object with room for
+    /// `capacity` indices before its storage has to grow.
+    pub fn with_capacity(capacity: usize) -> SharedGenIndexEntitySet<I, G> {
+        let mut set = Self::new_unsync();
+        set.reserve(capacity);
+        set.into_shared()
+    }
+
+    /// Create a new, unsynchronized GenIndexEntitySet object, for
+    /// use from a single thread.
+    pub fn new_unsync() -> GenIndexEntitySet<I, G> {
+        Self::from_config(GenIndexConfig::default())
+    }
+
+    /// Create a new, unsynchronized GenIndexEntitySet object with
+    /// the given settings.
+    pub fn from_config(config: GenIndexConfig) -> GenIndexEntitySet<I, G> {
+        Self::from_config_in(config, Global)
+    }
+
+    /// Wrap this set in a Mutex so that it can be shared between
+    /// threads.
+    pub fn into_shared(self) -> SharedGenIndexEntitySet<I, G> {
+        Arc::new(Mutex::new(self))
+    }
+
+    /// Rebuild a set by replaying a recording made with
+    /// `record_operations`, such as the one returned by
+    /// `recorded_operations`. Given the same config, the result
+    /// is identical to the set the recording was taken from,
+    /// including its free list order. If the replayed set hands
+    /// out a different index than was recorded (most likely due
+    /// to a different config), `ReplayDiverged` is returned.
+    pub fn replay(config: GenIndexConfig,
+                  ops: &[GenIndexOp<I, G>]) -> Result<SharedGenIndexEntitySet<I, G>,
+                                                      GenIndexError> {
+        Self::from_recording(config, ops).map(Self::into_shared)
+    }
+
+    /// Like `replay`, but return an unsynchronized set.
+    pub fn from_recording(config: GenIndexConfig,
+                          ops: &[GenIndexOp<I, G>]) -> Result<GenIndexEntitySet<I, G>,
+                                                              GenIndexError> {
+        let mut set = Self::from_config(config);
+        for op in ops {
+            match *op {
+                GenIndexOp::Allocate(gi) => {
+                    if set.try_next_index()? != gi {
+                        return Err(GenIndexError::ReplayDiverged);
+                    }
+                },
+                GenIndexOp::Delete(gi) => set.delete_index(gi)?,
+                GenIndexOp::Reserve(gi) => set.reserve_index(gi)?,
+                GenIndexOp::Clear => set.clear(),
+                GenIndexOp::AdvanceTick => set.advance_tick(),
+                GenIndexOp::Claim(n) => { set.claim_fresh(n)?; },
+                GenIndexOp::Redeem(gi) => {
+                    if set.redeem_claimed(gi.index)? != gi {
+                        return Err(GenIndexError::ReplayDiverged);
+                    }
+                },
+                GenIndexOp::Release(index) => set.release_claimed(index)?,
+                GenIndexOp::Compact => { set.compact(|_, _| ()); },
+            }
+        }
+        Ok(set)
+    }
+
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd,
+     A: Allocator + Clone> GenIndexEntitySet<I, G, A> {
+
+    /// Create a new, unsynchronized GenIndexEntitySet object, whose
+    /// internal vectors are kept in `alloc`.
+    pub fn new_in(alloc: A) -> GenIndexEntitySet<I, G, A> {
+        Self::from_config_in(GenIndexConfig::default(), alloc)
+    }
+
+    /// Create a new, unsynchronized GenIndexEntitySet object with
+    /// the given settings, whose internal vectors are kept in
+    /// `alloc`.
+    pub fn from_config_in(config: GenIndexConfig, alloc: A) -> GenIndexEntitySet<I, G, A> {
+        let rng = match config.reuse_policy {
+            ReusePolicy::Randomized{seed} => seed,
+            _ => 0,
+        };
+        GenIndexEntitySet {
             index_note: zero(),
-            deleted: vec!{}
-        }))
+            deleted: allocator::deque_in(&alloc),
+            slots: allocator::vec_in(&alloc),
+            retired: allocator::vec_in(&alloc),
+            live: 0,
+            high_water: 0,
+            allocations: 0,
+            config,
+            // xorshift must never be seeded with zero.
+            rng: if rng == 0 { 0x9E37_79B9_7F4A_7C15 } else { rng },
+            log: allocator::vec_in(&alloc),
+            tick: 0,
+            alloc,
+            stats: StatsCell::new(),
+            hooks: Hooks::new(),
+            live_mask: LiveMask::new(),
+        }
     }
 
+    /// return the operations recorded so far, oldest first. This is
+    /// empty unless `record_operations` is set.
+    pub fn recorded_operations(&self) -> &[GenIndexOp<I, G>] { &self.log }
+
     /// allocate and provide a "new" index. If an old
     /// index was deleted, that index is reused, with the
     /// generation number incremented, so that references
-    /// to the deleted entity are not found.
+    /// to the deleted entity are not found. Which deleted
+    /// index is picked is determined by the `ReusePolicy`,
+    /// and no index is recycled until the free list grows past
+    /// the configured recycle threshold. Indices still in
+    /// quarantine are not considered at all.
     ///
     /// You are responsible for the corresponding maitenence in your
     /// ECS.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the set has a capacity limit and it has been
+    /// reached, or if every index representable in `I` has been
+    /// handed out and none can be recycled. The index counter is
+    /// never allowed to wrap around. Use `try_next_index` to
+    /// handle these cases.
     pub fn next_index(&mut self) -> GenIndex<I, G> {
-        if self.deleted.is_empty() {
-            let g = GenIndex{index: self.index_note, generation: zero()};
-            self.index_note += one();
-            g
-        } else {
-            let mut oldidx = self.deleted.pop().unwrap();
-            oldidx.generation += one();
-            oldidx
+        match self.try_next_index() {
+            Ok(gi) => gi,
+            Err(e) => panic!("next_index: {}", e),
+        }
+    }
+
+    /// allocate and provide a "new" index, exactly as `next_index`
+    /// does, but report an error instead of panicking if no index
+    /// can be handed out.
+    ///
+    /// The largest value of `I` is never handed out, so fresh
+    /// indices run out once the counter reaches it, or the
+    /// configured `index_limit`. From then on,
+    /// only deleted indices can be recycled, regardless of the
+    /// recycle threshold.
+    pub fn try_next_index(&mut self) -> Result<GenIndex<I, G>, GenIndexError> {
+        let gi = match self.plan_next()? {
+            NextSlot::Fresh => {
+                let g = GenIndex::new(self.index_note, self.first_generation());
+                self.index_note += one();
+                self.slots.push(Slot{generation: g.generation, live: true, claimed: false});
+                g
+            },
+            NextSlot::Recycle(i, rng) => {
+                self.rng = rng;
+                let mut oldidx = self.deleted.remove(i).unwrap().0;
+                oldidx.generation += one();
+                if let Some(slot) = self.slot_mut(oldidx.index) {
+                    *slot = Slot{generation: oldidx.generation, live: true, claimed: false};
+                }
+                oldidx
+            },
+        };
+        self.note_allocation(gi);
+        self.record(GenIndexOp::Allocate(gi));
+        Ok(gi)
+    }
+
+    /// return the index that the next call to `try_next_index` would
+    /// hand out, or the error it would report, without allocating
+    /// anything.
+    pub fn peek_next_index(&self) -> Result<GenIndex<I, G>, GenIndexError> {
+        match self.plan_next()? {
+            NextSlot::Fresh => Ok(GenIndex::new(self.index_note, self.first_generation())),
+            NextSlot::Recycle(i, _) => {
+                let mut oldidx = self.deleted[i].0;
+                oldidx.generation += one();
+                Ok(oldidx)
+            },
         }
     }
 
+    /// allocate `n` indices at once, exactly as if `next_index` had
+    /// been called `n` times. Handy to spawn a batch of entities
+    /// under a single lock acquisition.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `next_index`.
+    pub fn next_indices(&mut self, n: usize) -> Vec<GenIndex<I, G>> {
+        self.next_indices_iter(n).collect()
+    }
+
+    /// Like `next_indices`, but allocates lazily as the returned
+    /// iterator is consumed. Indices not pulled from the iterator
+    /// are not allocated.
+    pub fn next_indices_iter(&mut self, n: usize) -> impl Iterator<Item = GenIndex<I, G>> + '_ {
+        (0..n).map(move |_| self.next_index())
+    }
+
     /// Delete an entity's index. You will be responsible for the cleanup
     /// in the corresponding ECS.
-    pub fn delete_index(&mut self, gi: GenIndex<I, G>) -> Result<(), &'static str> {
-        self.deleted.push(gi);
+    ///
+    /// Deleting an index that was never allocated, one that has
+    /// already been deleted, or one whose generation is not the
+    /// current generation of its slot, is rejected with an error
+    /// and leaves the set untouched.
+    ///
+    /// If the slot's generation has reached the maximum value of `G`,
+    /// the slot is retired rather than recycled, since incrementing
+    /// the generation again would wrap and make stale handles live.
+    pub fn delete_index(&mut self, gi: GenIndex<I, G>) -> Result<(), GenIndexError> {
+        let slot = self.slot_mut(gi.index).ok_or(GenIndexError::UnknownIndex)?;
+        if slot.generation != gi.generation {
+            return Err(GenIndexError::StaleGeneration);
+        }
+        if !slot.live {
+            return Err(GenIndexError::AlreadyDeleted);
+        }
+        slot.live = false;
+        self.live -= 1;
+        self.live_mask.remove(gi.index);
+        if gi.generation == G::max_value() {
+            self.retired.push(gi.index);
+        } else {
+            self.deleted.push_back((gi, self.tick));
+        }
+        self.record(GenIndexOp::Delete(gi));
         Ok(())
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use std::thread::*;
-    use std::vec::*;    
-    use super::*;
-    
-    const THREADS: u32 = 100;
-    
-    #[test]
-    fn test_gen_index_generation() {
-        let gi = GenIndexEntitySet::<u64, u64>::new();
-        let chk = GenIndex::<u64, u64> {index: zero(), generation: zero()};
 
-        // first index
-        let idx1 = gi.lock().unwrap().next_index();
-        assert_eq!(chk, idx1);
+    /// Delete an index and retire its slot straight away, whatever
+    /// its generation, so that it is never handed out again.
+    pub(crate) fn retire_index(&mut self, gi: GenIndex<I, G>) -> Result<(), GenIndexError> {
+        self.delete_index(gi)?;
+        if self.deleted.back().is_some_and(|&(d, _)| d == gi) {
+            self.deleted.pop_back();
+            self.retired.push(gi.index);
+        }
+        Ok(())
+    }
 
-        // second index
-        let mut chk2 = chk.clone();
-        chk2.index += 1;
-        let idx2 = gi.lock().unwrap().next_index();
-        assert_eq!(chk2, idx2);
+    /// Delete many indices in one pass, returning how many were
+    /// freed. Each index is validated as in `delete_index`; on
+    /// the first one that fails, deletion stops and its error is
+    /// returned. Indices before it remain deleted.
+    pub fn delete_indices<T>(&mut self, iter: T) -> Result<usize, GenIndexError>
+    where T: IntoIterator<Item = GenIndex<I, G>> {
+        let mut count = 0;
+        for gi in iter {
+            self.delete_index(gi)?;
+            count += 1;
+        }
+        Ok(count)
+    }
 
-        // delete first index and then get next index
-        let mut chk3 = chk.clone();
-        chk3.generation += 1;
-        if let Err(e) = gi.lock().unwrap().delete_index(idx1) {
-            println!("Error: {}", e);
+    /// Claim a specific index at a specific generation, as when
+    /// recreating the entities of a saved world. Afterwards the
+    /// index is live exactly as if `next_index` had returned it.
+    ///
+    /// If the index lies beyond any index handed out so far, the
+    /// counter is advanced past it, and the indices skipped over
+    /// are treated as deleted, so they will be recycled later at
+    /// a higher generation. If the slot has been used before, the
+    /// requested generation must be newer than the slot's last
+    /// generation, otherwise old handles would come back to life.
+    /// With `null_sentinel` set, generation zero is never accepted.
+    pub fn reserve_index(&mut self, gi: GenIndex<I, G>) -> Result<(), GenIndexError> {
+        let i = self.check_reserve(gi)?;
+        if i >= self.slots.len() {
+            while self.slots.len() < i {
+                let gap = GenIndex::new(self.index_note, zero());
+                self.deleted.push_back((gap, self.tick));
+                self.slots.push(Slot{generation: zero(), live: false, claimed: false});
+                self.index_note += one();
+            }
+            self.slots.push(Slot{generation: gi.generation, live: true, claimed: false});
+            self.index_note += one();
+            self.note_allocation(gi);
+            self.record(GenIndexOp::Reserve(gi));
+            return Ok(());
         }
-        let idx3 = gi.lock().unwrap().next_index();
-        assert_eq!(chk3, idx3);        
+
+        self.deleted.retain(|(d, _)| d.index != gi.index);
+        self.retired.retain(|r| *r != gi.index);
+        self.slots[i] = Slot{generation: gi.generation, live: true, claimed: false};
+        self.note_allocation(gi);
+        self.record(GenIndexOp::Reserve(gi));
+        Ok(())
     }
 
-    #[test]
-    fn test_multithreaded_index_generation() {
-        // TODO: this test is to see if we get any seg faults-- since it
-        // TODO: is asynchronous, it makes it difficult to test for anything
-        // TODO: more specific. One may look at the output by uncommenting
-        // TODO: the println!() below. Sucks, I know. I'll do something better
-        // TODO: later.
-        let gi = GenIndexEntitySet::<u64, u64>::new();
-        let mut threads = Vec::new();
-
-        for _ in 0..THREADS {
-            let cgi = gi.clone();
-            threads.push(spawn(move || {
-                let idx = {
-                    let mut gi = cgi.lock().unwrap();
-                    gi.next_index()
-                };
-                //println!("thread_{}: {:?}", i, idx);
-                if let Err(e) = cgi.lock().unwrap().delete_index(idx) {
-                    println!("error: {:?}", e);
+    /// Delete every live index at once, as if `delete_index` had
+    /// been called on each. All outstanding handles become stale,
+    /// and the slots are recycled at a higher generation later.
+    /// This is the natural way to "unload a level".
+    pub fn clear(&mut self) {
+        let tick = self.tick;
+        let mut cleared = Vec::new();
+        let mut index = zero();
+        for slot in self.slots.iter_mut() {
+            if slot.live {
+                slot.live = false;
+                let gi = GenIndex::new(index, slot.generation);
+                if gi.generation == G::max_value() {
+                    self.retired.push(index);
+                } else {
+                    self.deleted.push_back((gi, tick));
+                }
+                if !self.hooks.is_empty() {
+                    cleared.push(gi);
                 }
-            }));
+            }
+            index += one();
+        }
+        self.live = 0;
+        self.live_mask.clear();
+        self.record(GenIndexOp::Clear);
+        cleared.into_iter().for_each(|gi| self.hooks.deleted(gi));
+    }
+
+    /// Move the live indices into the lowest slots, so that they
+    /// form one contiguous run from zero, and return how many were
+    /// moved. Each index that moves is deleted, and a free slot
+    /// further down brought to life in its place at a newer
+    /// generation; once all have moved, `f` is called with every
+    /// old handle and its replacement, so references kept elsewhere
+    /// can be updated. Handles that do not move stay valid.
+    ///
+    /// The slots left free at the top are kept, and recycled as
+    /// usual, so old handles to them stay stale. Retired slots, and
+    /// those claimed by tickets, are left where they are.
+    ///
+    /// ```
+    /// # use gen_indices::*;
+    /// let mut gi = GenIndexEntitySet::<u32, u32>::new_unsync();
+    /// let idxs = gi.next_indices(3);
+    /// gi.delete_index(idxs[0]).unwrap();
+    ///
+    /// let mut moved = vec![];
+    /// gi.compact(|old, new| moved.push((old, new)));
+    /// assert_eq!(vec![(idxs[2], GenIndex::new(0, 1))], moved);
+    /// assert!(gi.is_live(idxs[1]));
+    /// ```
+    pub fn compact<F: FnMut(GenIndex<I, G>, GenIndex<I, G>)>(&mut self, mut f: F) -> usize {
+        let tick = self.tick;
+        let is_hole = |slot: &Slot<G>| !slot.live && !slot.claimed && slot.generation != G::max_value();
+        let mut moved = Vec::new();
+        let (mut hole, mut hole_index) = (0, zero());
+        let (mut end, mut end_index) = (self.slots.len(), self.index_note);
+        loop {
+            while hole < end && !is_hole(&self.slots[hole]) {
+                hole += 1;
+                hole_index += one();
+            }
+            while end > hole && !self.slots[end - 1].live {
+                end -= 1;
+                end_index = end_index - one();
+            }
+            if hole >= end {
+                break;
+            }
+            // slots[hole] is free, and slots[end - 1] live, above it
+            end -= 1;
+            end_index = end_index - one();
+            let old = GenIndex::new(end_index, self.slots[end].generation);
+            let new = GenIndex::new(hole_index, self.slots[hole].generation + one());
+            self.slots[end].live = false;
+            self.slots[hole] = Slot{generation: new.generation, live: true, claimed: false};
+            self.live_mask.remove(old.index);
+            self.live_mask.insert(new.index);
+            moved.push((old, new));
+        }
+        if moved.is_empty() {
+            return 0;
+        }
+
+        let slots = &self.slots;
+        self.deleted.retain(|(d, _)| !d.index.to_usize().is_some_and(|i| slots[i].live));
+        for &(old, _) in &moved {
+            if old.generation == G::max_value() {
+                self.retired.push(old.index);
+            } else {
+                self.deleted.push_back((old, tick));
+            }
         }
-        
-        for j in threads {
-            if let Err(e) =  j.join() {
-                println!("thead_error: {:?}", e);
+        self.record(GenIndexOp::Compact);
+        // only now the set is consistent again, should `f` panic
+        for &(old, new) in &moved {
+            f(old, new);
+            self.hooks.deleted(old);
+            self.hooks.allocated(new);
+        }
+        moved.len()
+    }
+
+    /// Wipe the set back to its freshly constructed state. Unlike
+    /// `clear`, all generation history is forgotten, so indices
+    /// will be handed out again starting at generation zero, and
+    /// handles from before the reset may compare equal to new
+    /// ones. Only use this when no old handles remain anywhere.
+    /// Any recorded operations are discarded as well. The settings
+    /// and hooks are kept, and, as with `clear`, the `on_delete`
+    /// hooks fire for each index live before the reset.
+    pub fn reset(&mut self) {
+        let mut cleared = Vec::new();
+        if !self.hooks.is_empty() {
+            let mut index = zero();
+            for slot in &self.slots {
+                if slot.live {
+                    cleared.push(GenIndex::new(index, slot.generation));
+                }
+                index += one();
             }
         }
-    }   
+        let stats = std::mem::take(&mut self.stats);
+        let hooks = std::mem::take(&mut self.hooks);
+        *self = Self::from_config_in(self.config, self.alloc.clone());
+        self.stats = stats;
+        self.hooks = hooks;
+        self.publish_stats();
+        cleared.into_iter().for_each(|gi| self.hooks.deleted(gi));
+    }
+
+    /// Make room for at least `additional` more indices to be
+    /// handed out fresh without the storage growing, so that a
+    /// burst of spawning does not reallocate halfway through.
+    pub fn reserve(&mut self, additional: usize) {
+        self.slots.reserve(additional);
+    }
+
+    /// Release the memory held by the internal storage beyond what
+    /// is needed for its current contents, such as a free list
+    /// left oversized after a burst of deletions.
+    pub fn shrink_to_fit(&mut self) {
+        self.deleted.shrink_to_fit();
+        self.slots.shrink_to_fit();
+        self.retired.shrink_to_fit();
+    }
+
+    /// Like `shrink_to_fit`, but keep room for at least `minimum`
+    /// entries in each of the internal vectors.
+    pub fn shrink_to(&mut self, minimum: usize) {
+        self.deleted.shrink_to(minimum);
+        self.slots.shrink_to(minimum);
+        self.retired.shrink_to(minimum);
+    }
+
+    /// return the number of slots which have been retired because
+    /// their generation numbers were exhausted. These slots will never be handed out again.
+    pub fn retired_count(&self) -> usize { self.retired.len() }
+
+    /// return the number of indices currently live.
+    pub fn live_count(&self) -> usize { self.live }
+
+    /// return the slot numbers of the live indices, as a BitSet, to
+    /// intersect with the masks of other systems, a word of 64
+    /// slots at a time.
+    ///
+    /// ```
+    /// # use gen_indices::*;
+    /// let mut gi = GenIndexEntitySet::<u32, u32>::new_unsync();
+    /// let idxs = gi.next_indices(3);
+    /// gi.delete_index(idxs[1]).unwrap();
+    ///
+    /// let burning: BitSet = vec![1, 2].into_iter().collect();
+    /// let mut found = gi.live_mask().clone();
+    /// found.intersect_with(&burning);
+    /// assert_eq!(vec![2], found.iter().collect::<Vec<_>>());
+    /// ```
+    pub fn live_mask(&self) -> &BitSet {
+        self.live_mask.get_or_build(|| {
+            self.slots.iter().enumerate().filter(|(_, slot)| slot.live).map(|(i, _)| i).collect()
+        })
+    }
+
+    /// return the number of deleted indices waiting to be recycled,
+    /// including those still in quarantine.
+    pub fn free_count(&self) -> usize { self.deleted.len() }
+
+    /// Advance the clock used by the quarantine by one tick. Call
+    /// this once per frame, or whatever your unit of time is.
+    pub fn advance_tick(&mut self) {
+        self.tick += 1;
+        self.record(GenIndexOp::AdvanceTick);
+    }
+
+    /// return the current tick, see `advance_tick`.
+    pub fn current_tick(&self) -> u64 { self.tick }
+
+    /// return the total number of indices ever handed out, counting
+    /// every recycling of a slot as a separate allocation.
+    pub fn total_allocated(&self) -> usize { self.allocations }
+
+    /// return the largest number of indices that were ever live
+    /// at the same time.
+    pub fn high_water_mark(&self) -> usize { self.high_water }
+
+    /// return all the counters above at once.
+    pub fn snapshot_stats(&self) -> GenIndexStats {
+        GenIndexStats {
+            live: self.live,
+            free: self.deleted.len(),
+            retired: self.retired.len(),
+            total_allocated: self.allocations,
+            high_water_mark: self.high_water,
+        }
+    }
+
+    /// return the state of the set, to `restore` later on.
+    pub fn snapshot(&self) -> GenIndexSnapshot<I, G> {
+        GenIndexSnapshot {
+            index_note: self.index_note,
+            deleted: self.deleted.iter().copied().collect(),
+            slots: self.slots.to_vec(),
+            retired: self.retired.to_vec(),
+            live: self.live,
+            high_water: self.high_water,
+            allocations: self.allocations,
+            rng: self.rng,
+            tick: self.tick,
+        }
+    }
+
+    /// Take the set back to the state it was in when the snapshot
+    /// was taken, keeping its settings and hooks. The `on_delete`
+    /// hooks are called for every index live before and not in the
+    /// snapshot, and then the `on_allocate` hooks for every index
+    /// live in the snapshot and not before. The recording, if any,
+    /// starts over, as the operations recorded so far no longer
+    /// lead to the restored state.
+    ///
+    /// Fails with `InvalidSnapshot`, leaving the set as it was, if
+    /// the snapshot is not one of a state a set can be in, as one
+    /// read back from a corrupt file may be.
+    pub fn restore(&mut self, snapshot: &GenIndexSnapshot<I, G>) -> Result<(), GenIndexError> {
+        snapshot.check()?;
+        let (mut gone, mut back) = (Vec::new(), Vec::new());
+        if !self.hooks.is_empty() {
+            gone = live_handles(&self.slots);
+            gone.retain(|&gi| !snapshot.is_live(gi));
+            back = live_handles(&snapshot.slots);
+            back.retain(|&gi| !self.is_live(gi));
+        }
+        self.index_note = snapshot.index_note;
+        self.deleted = allocator::deque_from_in(&snapshot.deleted, &self.alloc);
+        self.slots = allocator::vec_from_in(&snapshot.slots, &self.alloc);
+        self.retired = allocator::vec_from_in(&snapshot.retired, &self.alloc);
+        self.live = snapshot.live;
+        self.high_water = snapshot.high_water;
+        self.allocations = snapshot.allocations;
+        self.rng = snapshot.rng;
+        self.tick = snapshot.tick;
+        self.log.clear();
+        self.live_mask = LiveMask::new();
+        self.publish_stats();
+        gone.into_iter().for_each(|gi| self.hooks.deleted(gi));
+        back.into_iter().for_each(|gi| self.hooks.allocated(gi));
+        Ok(())
+    }
+
+    /// return the memory this set holds, broken down by what it is
+    /// used for; the set itself and its operation log count as
+    /// metadata.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            slots: self.slots.capacity() * mem::size_of::<Slot<G>>(),
+            free_list: self.deleted.capacity() * mem::size_of::<(GenIndex<I, G>, u64)>()
+                + self.retired.capacity() * mem::size_of::<I>(),
+            metadata: mem::size_of::<Self>() + self.log.capacity() * mem::size_of::<GenIndexOp<I, G>>(),
+        }
+    }
+
+    /// Register a callback to be called with every index handed out
+    /// from now on, by `next_index` and its relations, or brought to
+    /// life by `reserve_index`, a ticket, `compact`, or `restore`.
+    ///
+    /// Hooks are called while the set is borrowed, or its lock held,
+    /// so they cannot use the set themselves. They are shared with
+    /// clones of the set, and neither saved nor restored with it.
+    ///
+    /// ```
+    /// # use gen_indices::*;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let positions = Arc::new(Mutex::new(SecondaryMap::new()));
+    /// let mut entities = GenIndexEntitySet::<u32, u32>::new_unsync();
+    /// let store = positions.clone();
+    /// entities.on_allocate(move |gi| { store.lock().unwrap().insert(gi, (0.0, 0.0)).unwrap(); });
+    /// let store = positions.clone();
+    /// entities.on_delete(move |gi| { store.lock().unwrap().remove(gi); });
+    ///
+    /// let rock = entities.next_index();
+    /// assert_eq!(Some(&(0.0, 0.0)), positions.lock().unwrap().get(rock));
+    /// entities.delete_index(rock).unwrap();
+    /// assert!(positions.lock().unwrap().is_empty());
+    /// ```
+    pub fn on_allocate<F: Fn(GenIndex<I, G>) + Send + Sync + 'static>(&mut self, hook: F) {
+        self.hooks.add_allocate(Arc::new(hook));
+    }
+
+    /// Register a callback to be called with every index deleted
+    /// from now on, by `delete_index` and its relations, `clear`,
+    /// `compact`, or `restore`. See `on_allocate`.
+    pub fn on_delete<F: Fn(GenIndex<I, G>) + Send + Sync + 'static>(&mut self, hook: F) {
+        self.hooks.add_delete(Arc::new(hook));
+    }
+
+    /// Drop every callback registered with `on_allocate` and
+    /// `on_delete`.
+    pub fn clear_hooks(&mut self) { self.hooks.clear() }
+
+    /// return a reader for the counters, which another thread can
+    /// poll without taking the lock this set is behind.
+    pub fn stats_reader(&self) -> GenIndexStatsReader {
+        self.publish_stats();
+        self.stats.reader()
+    }
+
+    fn note_allocation(&mut self, gi: GenIndex<I, G>) {
+        self.live_mask.insert(gi.index);
+        self.live += 1;
+        self.allocations += 1;
+        if self.live > self.high_water {
+            self.high_water = self.live;
+        }
+    }
+
+    /// Check whether the given index is still live, that is, it has
+    /// been handed out and not deleted since, and its generation
+    /// matches the current generation of its slot. Stale handles to
+    /// deleted (or recycled) entities will return false.
+    pub fn is_live(&self, gi: GenIndex<I, G>) -> bool {
+        match self.slot(gi.index) {
+            Some(slot) => slot.live && slot.generation == gi.generation,
+            None => false,
+        }
+    }
+
+    /// Check whether the raw slot number is currently occupied by a
+    /// live index of any generation. Useful when all you have is the
+    /// bare number, say from external data; prefer `is_live` when
+    /// you have the full handle.
+    pub fn contains_index(&self, raw: I) -> bool {
+        self.slot(raw).is_some_and(|slot| slot.live)
+    }
+
+    /// Check that the given index is live, failing as
+    /// `delete_index` would if it is not.
+    fn check_live(&self, gi: GenIndex<I, G>) -> Result<(), GenIndexError> {
+        let slot = self.slot(gi.index).ok_or(GenIndexError::UnknownIndex)?;
+        if slot.generation != gi.generation {
+            return Err(GenIndexError::StaleGeneration);
+        }
+        if !slot.live {
+            return Err(GenIndexError::AlreadyDeleted);
+        }
+        Ok(())
+    }
+
+    /// Check whether `reserve_index` would accept the given handle,
+    /// without changing anything, returning its slot number.
+    fn check_reserve(&self, gi: GenIndex<I, G>) -> Result<usize, GenIndexError> {
+        if gi.index == I::max_value() {
+            return Err(GenIndexError::IndexSpaceExhausted);
+        }
+        let i = gi.index.to_usize().ok_or(GenIndexError::UnknownIndex)?;
+        if self.config.index_limit.is_some_and(|limit| i >= limit) {
+            return Err(GenIndexError::IndexSpaceExhausted);
+        }
+        if self.slots.get(i).is_some_and(|slot| slot.live || slot.claimed) {
+            return Err(GenIndexError::IndexInUse);
+        }
+        self.check_capacity()?;
+        if gi.generation < self.first_generation() {
+            return Err(GenIndexError::StaleGeneration);
+        }
+        if self.slots.get(i).is_some_and(|slot| gi.generation <= slot.generation) {
+            return Err(GenIndexError::StaleGeneration);
+        }
+        Ok(i)
+    }
+
+    /// Set aside `n` fresh indices for a TicketDispenser, returning
+    /// the first of them. They are neither live nor free until
+    /// redeemed or released.
+    fn claim_fresh(&mut self, n: usize) -> Result<usize, GenIndexError> {
+        let start = self.slots.len();
+        let fits = match (start.checked_add(n), I::max_value().to_usize()) {
+            (Some(end), Some(max)) => end <= max,
+            (None, _) => false,
+            (_, None) => true,
+        };
+        if !fits || self.config.index_limit.is_some_and(|limit| start + n > limit) {
+            return Err(GenIndexError::IndexSpaceExhausted);
+        }
+        let generation = self.first_generation();
+        for _ in 0..n {
+            self.slots.push(Slot{generation, live: false, claimed: true});
+            self.index_note += one();
+        }
+        self.record(GenIndexOp::Claim(n));
+        Ok(start)
+    }
+
+    /// Turn a slot set aside by `claim_fresh` into a live index.
+    fn redeem_claimed(&mut self, index: I) -> Result<GenIndex<I, G>, GenIndexError> {
+        self.check_capacity()?;
+        let slot = self.slot_mut(index).ok_or(GenIndexError::UnknownIndex)?;
+        if !slot.claimed {
+            return Err(if slot.live { GenIndexError::IndexInUse } else { GenIndexError::UnknownIndex });
+        }
+        slot.claimed = false;
+        slot.live = true;
+        let gi = GenIndex::new(index, slot.generation);
+        self.note_allocation(gi);
+        self.record(GenIndexOp::Redeem(gi));
+        Ok(gi)
+    }
+
+    /// Hand a slot set aside by `claim_fresh` over to the free list.
+    fn release_claimed(&mut self, index: I) -> Result<(), GenIndexError> {
+        let slot = self.slot_mut(index).ok_or(GenIndexError::UnknownIndex)?;
+        if !slot.claimed {
+            return Err(GenIndexError::UnknownIndex);
+        }
+        slot.claimed = false;
+        let gi = GenIndex::new(index, slot.generation);
+        self.deleted.push_back((gi, self.tick));
+        self.record(GenIndexOp::Release(index));
+        Ok(())
+    }
+
+    /// the handle of every slot handed out so far, in index order,
+    /// at its current generation, whether live or not.
+    fn slot_handles(&self) -> impl Iterator<Item = GenIndex<I, G>> + '_ {
+        let mut index = zero();
+        self.slots.iter().map(move |slot| {
+            let gi = GenIndex::new(index, slot.generation);
+            index += one();
+            gi
+        })
+    }
+
+    /// the handle of the given slot at its current generation,
+    /// whether live or not.
+    fn slot_handle(&self, index: I) -> Option<GenIndex<I, G>> {
+        self.slot(index).map(|slot| GenIndex::new(index, slot.generation))
+    }
+
+    /// every change to the set passes through here, which makes it
+    /// the place to publish the counters as well.
+    fn record(&mut self, op: GenIndexOp<I, G>) {
+        if self.config.record_operations {
+            self.log.push(op);
+        }
+        self.publish_stats();
+        match op {
+            GenIndexOp::Allocate(gi) | GenIndexOp::Reserve(gi) | GenIndexOp::Redeem(gi) => self.hooks.allocated(gi),
+            GenIndexOp::Delete(gi) => self.hooks.deleted(gi),
+            _ => {},
+        }
+    }
+
+    fn publish_stats(&self) {
+        self.stats.publish(self.snapshot_stats());
+    }
+
+    fn first_generation(&self) -> G {
+        if self.config.null_sentinel { one() } else { zero() }
+    }
+
+    fn check_capacity(&self) -> Result<(), GenIndexError> {
+        match self.config.capacity_limit {
+            Some(limit) if self.live >= limit => Err(GenIndexError::CapacityExhausted),
+            _ => Ok(()),
+        }
+    }
+
+    /// The free list is kept in deletion order, so the indices out
+    /// of quarantine are always a prefix of it.
+    fn eligible_count(&self) -> usize {
+        let quarantine = self.config.quarantine_ticks;
+        if quarantine == 0 {
+            self.deleted.len()
+        } else {
+            let tick = self.tick;
+            self.deleted.partition_point(|(_, t)| t.saturating_add(quarantine) <= tick)
+        }
+    }
+
+    /// Decide where the next index will come from, without
+    /// changing anything.
+    fn plan_next(&self) -> Result<NextSlot, GenIndexError> {
+        self.check_capacity()?;
+        let exhausted = self.index_note == I::max_value()
+            || self.config.index_limit.is_some_and(|limit| self.slots.len() >= limit);
+        let eligible = self.eligible_count();
+        if exhausted && eligible == 0 {
+            return Err(GenIndexError::IndexSpaceExhausted);
+        }
+        if !exhausted && eligible <= self.config.recycle_threshold {
+            return Ok(NextSlot::Fresh);
+        }
+
+        // pick one of the first `eligible` entries of the free list.
+        let mut rng = self.rng;
+        let i = match self.config.reuse_policy {
+            ReusePolicy::Lifo => eligible - 1,
+            ReusePolicy::Fifo => 0,
+            ReusePolicy::Randomized{..} => {
+                // xorshift64
+                rng ^= rng << 13;
+                rng ^= rng >> 7;
+                rng ^= rng << 17;
+                (rng % eligible as u64) as usize
+            }
+        };
+        Ok(NextSlot::Recycle(i, rng))
+    }
+
+    fn slot(&self, index: I) -> Option<&Slot<G>> {
+        index.to_usize().and_then(move |i| self.slots.get(i))
+    }
+
+    fn slot_mut(&mut self, index: I) -> Option<&mut Slot<G>> {
+        index.to_usize().and_then(move |i| self.slots.get_mut(i))
+    }
+}
+
+/// Compares the state of the sets, whichever allocators they use.
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd,
+     A: Allocator + Clone> PartialEq for GenIndexEntitySet<I, G, A> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index_note == other.index_note
+            && self.deleted == other.deleted
+            && self.slots == other.slots
+            && self.retired == other.retired
+            && self.live == other.live
+            && self.high_water == other.high_water
+            && self.allocations == other.allocations
+            && self.config == other.config
+            && self.rng == other.rng
+            && self.log == other.log
+            && self.tick == other.tick
+    }
+}
+
+/// Hashes the state of the set, leaving out the allocator.
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + Hash,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Hash,
+     A: Allocator + Clone> Hash for GenIndexEntitySet<I, G, A> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index_note.hash(state);
+        self.deleted.hash(state);
+        self.slots.hash(state);
+        self.retired.hash(state);
+        self.live.hash(state);
+        self.high_water.hash(state);
+        self.allocations.hash(state);
+        self.config.hash(state);
+        self.rng.hash(state);
+        self.log.hash(state);
+        self.tick.hash(state);
+    }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> Default for GenIndexEntitySet<I, G> {
+    fn default() -> Self { Self::new_unsync() }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::*;    
+    use super::*;
+    use super::sync::lock;
+    
+    #[test]
+    fn test_gen_index_generation() {
+        let gi = GenIndexEntitySet::<u64, u64>::new();
+        let chk = GenIndex::<u64, u64>::new(zero(), zero());
+
+        // first index
+        let idx1 = lock(&gi).next_index();
+        assert_eq!(chk, idx1);
+
+        // second index
+        let mut chk2 = chk;
+        chk2.index += 1;
+        let idx2 = lock(&gi).next_index();
+        assert_eq!(chk2, idx2);
+
+        // delete first index and then get next index
+        let mut chk3 = chk;
+        chk3.generation += 1;
+        if let Err(e) = lock(&gi).delete_index(idx1) {
+            println!("Error: {}", e);
+        }
+        let idx3 = lock(&gi).next_index();
+        assert_eq!(chk3, idx3);        
+    }
+
+    #[test]
+    fn test_ordering() {
+        use std::collections::BTreeSet;
+
+        let a = GenIndex::<u64, u64>::new(1, 5);
+        let b = GenIndex::<u64, u64>::new(2, 0);
+        let c = GenIndex::<u64, u64>::new(2, 1);
+        assert!(a < b && b < c);
+
+        let set: BTreeSet<_> = vec![c, a, b, a].into_iter().collect();
+        assert_eq!(vec![a, b, c], set.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_invalid() {
+        let invalid = GenIndex::<u8, u8>::default();
+        assert_eq!(GenIndex::invalid(), invalid);
+        assert!(!invalid.is_valid());
+        assert_eq!(255, invalid.get_index());
+
+        // nothing a set hands out is ever mistaken for it
+        let mut gi = GenIndexEntitySet::<u8, u8>::new_unsync();
+        while let Ok(idx) = gi.try_next_index() {
+            assert!(idx.is_valid());
+        }
+        assert!(!gi.is_live(invalid));
+        assert_eq!(Err(GenIndexError::UnknownIndex), gi.delete_index(invalid));
+    }
+
+    #[test]
+    fn test_raw_parts() {
+        let mut gi = GenIndexEntitySet::<u64, u32>::new_unsync();
+        let idx = gi.next_index();
+        let (index, generation) = idx.into_raw_parts();
+        assert_eq!((0, 0), (index, generation));
+
+        let back = GenIndex::from_raw_parts(index, generation);
+        assert_eq!(idx, back);
+        assert!(gi.is_live(back));
+        assert!(!gi.is_live(GenIndex::from_raw_parts(index, generation + 1)));
+    }
+
+    #[test]
+    fn test_repr_c() {
+        #[repr(C)]
+        struct Mirror { index: u32, generation: u16 }
+
+        let gi = GenIndex::<u32, u16, GenIndexConfig>::from_raw_parts(5, 9);
+        assert_eq!(std::mem::size_of::<Mirror>(), std::mem::size_of_val(&gi));
+        assert_eq!(std::mem::align_of::<Mirror>(), std::mem::align_of_val(&gi));
+
+        let mirror: &Mirror = unsafe { &*(&gi as *const GenIndex<u32, u16, GenIndexConfig> as *const Mirror) };
+        assert_eq!((5, 9), (mirror.index, mirror.generation));
+    }
+
+    #[test]
+    fn test_const_handles() {
+        const RESERVED: [GenIndex<u32, u32>; 2] = [GenIndex::new(0, 1), GenIndex::new(1, 1)];
+        const SECOND: u32 = RESERVED[1].get_index();
+        const GENERATION: u32 = RESERVED[0].get_generation();
+
+        assert_eq!(1, SECOND);
+        assert_eq!(1, GENERATION);
+    }
+
+    #[test]
+    fn test_display() {
+        let gi = GenIndex::<u64, u64>::new(3, 7);
+        assert_eq!("3v7", gi.to_string());
+        assert_eq!("0v0", GenIndex::<u8, u16>::null().to_string());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde() {
+        let config = GenIndexConfig{reuse_policy: ReusePolicy::Fifo, ..GenIndexConfig::default()};
+        let mut gi = GenIndexEntitySet::<u64, u32>::from_config(config);
+        let idxs = gi.next_indices(3);
+        gi.delete_index(idxs[1]).unwrap();
+
+        let json = serde_json::to_string(&idxs[0]).unwrap();
+        assert_eq!(r#"{"index":0,"generation":0}"#, json);
+        assert_eq!(idxs[0], serde_json::from_str(&json).unwrap());
+
+        let saved = serde_json::to_string(&gi).unwrap();
+        let mut loaded: GenIndexEntitySet<u64, u32> = serde_json::from_str(&saved).unwrap();
+        assert_eq!(gi, loaded);
+        assert!(loaded.is_live(idxs[0]));
+        assert!(!loaded.is_live(idxs[1]));
+        assert_eq!(3, loaded.stats_reader().snapshot_stats().total_allocated);
+        assert_eq!(gi.next_index(), loaded.next_index());
+    }
+
+    #[test]
+    fn test_to_bits() {
+        let gi = PackedGenIndex::new(7, 3);
+        assert_eq!(3 << 32 | 7, gi.to_bits());
+        assert_eq!(gi, PackedGenIndex::from_bits(gi.to_bits()));
+
+        let max = PackedGenIndex::new(u32::MAX, u32::MAX);
+        assert_eq!(u64::MAX, max.to_bits());
+        assert_eq!(max, PackedGenIndex::from_bits(u64::MAX));
+    }
+
+    #[test]
+    fn test_unsync() {
+        let mut gi = GenIndexEntitySet::<u64, u64>::new_unsync();
+
+        let idx1 = gi.next_index();
+        gi.delete_index(idx1).unwrap();
+        let idx2 = gi.next_index();
+        assert_eq!(1, idx2.get_generation());
+        assert_eq!(GenIndexEntitySet::<u64, u64>::default(), GenIndexEntitySet::new_unsync());
+
+        // and it can be shared after the fact
+        let shared: SharedGenIndexEntitySet<u64, u64> = gi.into_shared();
+        assert!(lock(&shared).is_live(idx2));
+    }
+
+    #[test]
+    fn test_is_live() {
+        let gi = GenIndexEntitySet::<u64, u64>::new();
+        let mut gi = lock(&gi);
+
+        let idx1 = gi.next_index();
+        let idx2 = gi.next_index();
+        assert!(gi.is_live(idx1));
+        assert!(gi.is_live(idx2));
+
+        // deleted handles are no longer live
+        gi.delete_index(idx1).unwrap();
+        assert!(!gi.is_live(idx1));
+        assert!(gi.is_live(idx2));
+
+        // the recycled slot is live again, but only for the new generation
+        let idx3 = gi.next_index();
+        assert_eq!(idx1.get_index(), idx3.get_index());
+        assert!(gi.is_live(idx3));
+        assert!(!gi.is_live(idx1));
+
+        // never allocated
+        let bogus = GenIndex::<u64, u64>::new(42, zero());
+        assert!(!gi.is_live(bogus));
+    }
+
+    #[test]
+    fn test_contains_index() {
+        let gi = GenIndexEntitySet::<u64, u64>::new();
+        let mut gi = lock(&gi);
+
+        let idx = gi.next_index();
+        assert!(gi.contains_index(0));
+        assert!(!gi.contains_index(1));
+
+        gi.delete_index(idx).unwrap();
+        assert!(!gi.contains_index(0));
+        let idx = gi.next_index();
+        assert_eq!(1, idx.get_generation());
+        assert!(gi.contains_index(0));
+    }
+
+    #[test]
+    fn test_double_delete() {
+        let gi = GenIndexEntitySet::<u64, u64>::new();
+        let mut gi = lock(&gi);
+
+        let idx1 = gi.next_index();
+        let idx2 = gi.next_index();
+        assert_eq!(Ok(()), gi.delete_index(idx1));
+        assert_eq!(Err(GenIndexError::AlreadyDeleted), gi.delete_index(idx1));
+
+        // the free list was not corrupted: only one recycled slot
+        let idx3 = gi.next_index();
+        let idx4 = gi.next_index();
+        assert_eq!(idx1.get_index(), idx3.get_index());
+        assert_ne!(idx3.get_index(), idx4.get_index());
+        assert_ne!(idx2.get_index(), idx4.get_index());
+
+        let bogus = GenIndex::<u64, u64>::new(42, zero());
+        assert_eq!(Err(GenIndexError::UnknownIndex), gi.delete_index(bogus));
+    }
+
+    #[test]
+    fn test_stale_delete() {
+        let gi = GenIndexEntitySet::<u64, u64>::new();
+        let mut gi = lock(&gi);
+
+        let idx1 = gi.next_index();
+        gi.delete_index(idx1).unwrap();
+        let idx2 = gi.next_index();
+        assert_eq!(idx1.get_index(), idx2.get_index());
+
+        // the old handle must not free the recycled slot
+        assert_eq!(Err(GenIndexError::StaleGeneration), gi.delete_index(idx1));
+        assert!(gi.is_live(idx2));
+        assert_eq!(Ok(()), gi.delete_index(idx2));
+    }
+
+    #[test]
+    fn test_generation_exhaustion_retires_slot() {
+        let gi = GenIndexEntitySet::<u32, u8>::new();
+        let mut gi = lock(&gi);
+
+        let mut idx = gi.next_index();
+        for _ in 0..u8::MAX {
+            gi.delete_index(idx).unwrap();
+            idx = gi.next_index();
+            assert_eq!(0, idx.get_index());
+        }
+        assert_eq!(u8::MAX, idx.get_generation());
+        assert_eq!(0, gi.retired_count());
+
+        // the exhausted slot must not be recycled with a wrapped generation
+        gi.delete_index(idx).unwrap();
+        assert_eq!(1, gi.retired_count());
+        let fresh = gi.next_index();
+        assert_eq!(1, fresh.get_index());
+        assert_eq!(0, fresh.get_generation());
+        assert!(!gi.is_live(idx));
+    }
+
+    fn reuse_order(policy: ReusePolicy) -> Vec<u64> {
+        let gi = GenIndexEntitySet::<u64, u64>::with_reuse_policy(policy);
+        let mut gi = lock(&gi);
+        let idxs: Vec<_> = (0..8).map(|_| gi.next_index()).collect();
+        for idx in idxs {
+            gi.delete_index(idx).unwrap();
+        }
+        (0..8).map(|_| gi.next_index().get_index()).collect()
+    }
+
+    #[test]
+    fn test_reuse_policy() {
+        assert_eq!(vec![7, 6, 5, 4, 3, 2, 1, 0], reuse_order(ReusePolicy::Lifo));
+        assert_eq!(vec![0, 1, 2, 3, 4, 5, 6, 7], reuse_order(ReusePolicy::Fifo));
+
+        // randomized is a permutation, and reproducible with the same seed
+        let order = reuse_order(ReusePolicy::Randomized{seed: 42});
+        let mut sorted = order.clone();
+        sorted.sort();
+        assert_eq!(vec![0, 1, 2, 3, 4, 5, 6, 7], sorted);
+        assert_eq!(order, reuse_order(ReusePolicy::Randomized{seed: 42}));
+    }
+
+    #[test]
+    fn test_next_indices() {
+        let gi = GenIndexEntitySet::<u64, u64>::new();
+        let mut gi = lock(&gi);
+
+        let batch = gi.next_indices(3);
+        assert_eq!(vec![0, 1, 2], batch.iter().map(|i| i.get_index()).collect::<Vec<_>>());
+        gi.delete_index(batch[1]).unwrap();
+
+        // recycled slots are handed out within a batch too
+        let batch = gi.next_indices(2);
+        assert_eq!(batch[0].get_index(), 1);
+        assert_eq!(batch[0].get_generation(), 1);
+        assert_eq!(batch[1].get_index(), 3);
+
+        // only the first index is allocated
+        let first = gi.next_indices_iter(10).next().unwrap();
+        assert_eq!(4, first.get_index());
+        assert_eq!(5, gi.next_index().get_index());
+    }
+
+    #[test]
+    fn test_delete_indices() {
+        let gi = GenIndexEntitySet::<u64, u64>::new();
+        let mut gi = lock(&gi);
+
+        let batch = gi.next_indices(4);
+        assert_eq!(Ok(2), gi.delete_indices(batch[..2].iter().cloned()));
+        assert!(!gi.is_live(batch[0]));
+        assert!(!gi.is_live(batch[1]));
+
+        // stops at the first failure, earlier deletions stick
+        let again = vec![batch[2], batch[0], batch[3]];
+        assert_eq!(Err(GenIndexError::AlreadyDeleted), gi.delete_indices(again));
+        assert!(!gi.is_live(batch[2]));
+        assert!(gi.is_live(batch[3]));
+    }
+
+    #[test]
+    fn test_reserve_index() {
+        let gi = GenIndexEntitySet::<u64, u64>::new();
+        let mut gi = lock(&gi);
+
+        let saved = GenIndex::<u64, u64>::new(3, 5);
+        assert_eq!(Ok(()), gi.reserve_index(saved));
+        assert!(gi.is_live(saved));
+        assert_eq!(Err(GenIndexError::IndexInUse), gi.reserve_index(saved));
+
+        // the skipped indices are recycled before fresh ones
+        let mut recycled: Vec<_> = gi.next_indices(3).iter().map(|i| i.get_index()).collect();
+        recycled.sort();
+        assert_eq!(vec![0, 1, 2], recycled);
+        assert_eq!(4, gi.next_index().get_index());
+
+        // a previously used slot only accepts a newer generation
+        gi.delete_index(saved).unwrap();
+        assert_eq!(Err(GenIndexError::StaleGeneration), gi.reserve_index(saved));
+        let newer = GenIndex::<u64, u64>::new(3, 9);
+        assert_eq!(Ok(()), gi.reserve_index(newer));
+        assert!(gi.is_live(newer));
+
+        // and it is no longer in the free list
+        assert_eq!(5, gi.next_index().get_index());
+    }
+
+    #[cfg(feature = "nightly")]
+    #[test]
+    fn test_allocator() {
+        use std::{alloc::{AllocError, Layout},
+                  ptr::NonNull,
+                  sync::atomic::{AtomicUsize, Ordering}};
+
+        #[derive(Clone, Default)]
+        struct Counting(Arc<AtomicUsize>);
+
+        unsafe impl Allocator for Counting {
+            fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+                self.0.fetch_add(1, Ordering::Relaxed);
+                Global.allocate(layout)
+            }
+
+            unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+                Global.deallocate(ptr, layout)
+            }
+        }
+
+        let counting = Counting::default();
+        let mut gi = GenIndexEntitySet::<u32, u32, _>::new_in(counting.clone());
+        let idxs = gi.next_indices(100);
+        gi.delete_index(idxs[0]).unwrap();
+        let allocated = counting.0.load(Ordering::Relaxed);
+        assert!(allocated >= 2);
+
+        // what is built anew is kept in the same allocator
+        let snapshot = gi.snapshot();
+        gi.reset();
+        gi.next_index();
+        gi.restore(&snapshot).unwrap();
+        assert!(counting.0.load(Ordering::Relaxed) > allocated);
+        assert!(!gi.is_live(idxs[0]));
+        assert!(gi.is_live(idxs[1]));
+    }
+
+    #[test]
+    fn test_index_limit() {
+        let config = GenIndexConfig{index_limit: Some(2), ..GenIndexConfig::default()};
+        let mut gi = GenIndexEntitySet::<u64, u64>::from_config(config);
+        assert_eq!(Err(GenIndexError::IndexSpaceExhausted), gi.reserve_index(GenIndex::new(2, 0)));
+        let a = gi.next_index();
+        gi.next_index();
+        assert_eq!(Err(GenIndexError::IndexSpaceExhausted), gi.try_next_index());
+        assert_eq!(2, gi.slots.len());
+        gi.delete_index(a).unwrap();
+        assert_eq!(GenIndex::new(0, 1), gi.next_index());
+    }
+
+    #[test]
+    fn test_capacity_limit() {
+        let gi = GenIndexEntitySet::<u64, u64>::with_capacity_limit(2);
+        let mut gi = lock(&gi);
+
+        let idx1 = gi.try_next_index().unwrap();
+        let _idx2 = gi.try_next_index().unwrap();
+        assert_eq!(Err(GenIndexError::CapacityExhausted), gi.try_next_index());
+        let far = GenIndex::<u64, u64>::new(10, zero());
+        assert_eq!(Err(GenIndexError::CapacityExhausted), gi.reserve_index(far));
+
+        // freeing one makes room again
+        gi.delete_index(idx1).unwrap();
+        let idx3 = gi.try_next_index().unwrap();
+        assert_eq!(idx1.get_index(), idx3.get_index());
+        assert_eq!(Err(GenIndexError::CapacityExhausted), gi.try_next_index());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_capacity_limit_panics() {
+        let gi = GenIndexEntitySet::<u64, u64>::with_capacity_limit(1);
+        let mut gi = lock(&gi);
+        gi.next_indices(2);
+    }
+
+    #[test]
+    fn test_clear_and_reset() {
+        let gi = GenIndexEntitySet::<u64, u64>::new();
+        let mut gi = lock(&gi);
+
+        let batch = gi.next_indices(3);
+        gi.delete_index(batch[1]).unwrap();
+        gi.clear();
+        assert!(batch.iter().all(|&idx| !gi.is_live(idx)));
+
+        // nothing fresh is allocated until the cleared slots are used up
+        let again = gi.next_indices(3);
+        assert!(again.iter().all(|idx| idx.get_index() < 3 && idx.get_generation() == 1));
+        assert_eq!(3, gi.next_index().get_index());
+
+        gi.reset();
+        let idx = gi.next_index();
+        assert_eq!(0, idx.get_index());
+        assert_eq!(0, idx.get_generation());
+    }
+
+    #[test]
+    fn test_reset_keeps_hooks() {
+        use std::sync::Mutex;
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut gi = GenIndexEntitySet::<u32, u32>::new_unsync();
+        let log = seen.clone();
+        gi.on_allocate(move |idx| log.lock().unwrap().push(("allocate", idx)));
+        let log = seen.clone();
+        gi.on_delete(move |idx| log.lock().unwrap().push(("delete", idx)));
+
+        let a = gi.next_index();
+        let b = gi.next_index();
+        gi.delete_index(a).unwrap();
+        seen.lock().unwrap().clear();
+        gi.reset();
+        assert_eq!(vec![("delete", b)], *seen.lock().unwrap());
+        let c = gi.next_index();
+        assert_eq!(GenIndex::new(0, 0), c);
+        assert_eq!(vec![("delete", b), ("allocate", c)], *seen.lock().unwrap());
+    }
+
+    #[test]
+    fn test_statistics() {
+        let gi = GenIndexEntitySet::<u64, u64>::new();
+        let mut gi = lock(&gi);
+
+        let batch = gi.next_indices(5);
+        gi.delete_indices(batch[..3].iter().cloned()).unwrap();
+        assert_eq!(2, gi.live_count());
+        assert_eq!(3, gi.free_count());
+        assert_eq!(5, gi.total_allocated());
+        assert_eq!(5, gi.high_water_mark());
+
+        gi.next_index();
+        assert_eq!(3, gi.live_count());
+        assert_eq!(2, gi.free_count());
+        assert_eq!(6, gi.total_allocated());
+        assert_eq!(5, gi.high_water_mark());
+
+        gi.clear();
+        assert_eq!(0, gi.live_count());
+        assert_eq!(5, gi.free_count());
+        assert_eq!(5, gi.high_water_mark());
+    }
+
+    #[test]
+    fn test_shrink() {
+        let gi = GenIndexEntitySet::<u64, u64>::new();
+        let mut gi = lock(&gi);
+
+        let batch = gi.next_indices(1000);
+        gi.delete_indices(batch).unwrap();
+        gi.next_indices(990);
+        assert!(gi.deleted.capacity() >= 1000);
+
+        gi.shrink_to(100);
+        assert!(gi.deleted.capacity() >= 100 && gi.deleted.capacity() < 1000);
+        gi.shrink_to_fit();
+        assert!(gi.deleted.capacity() >= 10 && gi.deleted.capacity() < 100);
+        assert_eq!(10, gi.free_count());
+    }
+
+    #[test]
+    fn test_fifo_spreads_generations() {
+        let gi = GenIndexEntitySet::<u64, u8>::with_reuse_policy(ReusePolicy::Fifo);
+        let mut gi = lock(&gi);
+
+        let mut live = gi.next_indices(4);
+        for _ in 0..40 {
+            let idx = live.remove(0);
+            gi.delete_index(idx).unwrap();
+            live.push(gi.next_index());
+        }
+        assert!(live.iter().all(|idx| idx.get_generation() == 10));
+    }
+
+    #[test]
+    fn test_index_space_exhaustion() {
+        let gi = GenIndexEntitySet::<u8, u64>::with_recycle_threshold(1000);
+        let mut gi = lock(&gi);
+
+        let all: Vec<_> = (0..u8::MAX).map(|_| gi.try_next_index().unwrap()).collect();
+        assert_eq!(254, all.last().unwrap().get_index());
+        assert_eq!(Err(GenIndexError::IndexSpaceExhausted), gi.try_next_index());
+
+        // deleted indices are still recycled, despite the threshold
+        gi.delete_index(all[7]).unwrap();
+        let idx = gi.try_next_index().unwrap();
+        assert_eq!(7, idx.get_index());
+        assert_eq!(1, idx.get_generation());
+        assert_eq!(Err(GenIndexError::IndexSpaceExhausted), gi.try_next_index());
+
+        let top = GenIndex::<u8, u64>::new(u8::MAX, 0);
+        assert_eq!(Err(GenIndexError::IndexSpaceExhausted), gi.reserve_index(top));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_index_space_exhaustion_panics() {
+        let gi = GenIndexEntitySet::<u8, u64>::new();
+        let mut gi = lock(&gi);
+        gi.next_indices(256);
+    }
+
+    #[test]
+    fn test_null_sentinel() {
+        let config = GenIndexConfig{null_sentinel: true, ..GenIndexConfig::default()};
+        let gi = GenIndexEntitySet::<u64, u64>::with_config(config);
+        let mut gi = lock(&gi);
+
+        let idx = gi.next_index();
+        assert_eq!(0, idx.get_index());
+        assert_eq!(1, idx.get_generation());
+        assert!(!idx.is_null());
+        assert!(GenIndex::<u64, u64>::null().is_null());
+        assert!(!gi.is_live(GenIndex::null()));
+        assert_eq!(Err(GenIndexError::StaleGeneration), gi.delete_index(GenIndex::null()));
+
+        // generation zero cannot be smuggled in through reserve_index
+        let zero_gen = GenIndex::<u64, u64>::new(5, 0);
+        assert_eq!(Err(GenIndexError::StaleGeneration), gi.reserve_index(zero_gen));
+
+        // without the flag, the very first handle is the null handle
+        let plain = GenIndexEntitySet::<u64, u64>::new();
+        assert!(lock(&plain).next_index().is_null());
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let config = GenIndexConfig{reuse_policy: ReusePolicy::Randomized{seed: 7},
+                                    record_operations: true,
+                                    ..GenIndexConfig::default()};
+        let gi = GenIndexEntitySet::<u64, u64>::with_config(config);
+        let mut gi = lock(&gi);
+
+        let batch = gi.next_indices(10);
+        gi.delete_indices(batch.iter().step_by(2).cloned()).unwrap();
+        gi.reserve_index(GenIndex::new(20, 3)).unwrap();
+        gi.next_indices(4);
+        gi.clear();
+        gi.next_indices(2);
+        assert_eq!(GenIndexOp::Allocate(batch[0]), gi.recorded_operations()[0]);
+
+        let replayed = GenIndexEntitySet::replay(config, gi.recorded_operations()).unwrap();
+        let mut replayed = lock(&replayed);
+        assert_eq!(*gi, *replayed);
+        assert_eq!(gi.next_indices(8), replayed.next_indices(8));
+
+        // replaying with a different policy is detected
+        let lifo = GenIndexConfig{record_operations: true, ..GenIndexConfig::default()};
+        assert_eq!(Err(GenIndexError::ReplayDiverged),
+                   GenIndexEntitySet::replay(lifo, gi.recorded_operations()).map(|_| ()));
+
+        // nothing is recorded by default
+        let plain = GenIndexEntitySet::<u64, u64>::new();
+        lock(&plain).next_index();
+        assert!(lock(&plain).recorded_operations().is_empty());
+    }
+
+    #[test]
+    fn test_quarantine() {
+        let config = GenIndexConfig{quarantine_ticks: 2, ..GenIndexConfig::default()};
+        let gi = GenIndexEntitySet::<u64, u64>::with_config(config);
+        let mut gi = lock(&gi);
+
+        let batch = gi.next_indices(3);
+        gi.delete_index(batch[0]).unwrap();
+        gi.advance_tick();
+        gi.delete_index(batch[1]).unwrap();
+
+        // nothing has been quarantined long enough yet
+        assert_eq!(3, gi.next_index().get_index());
+
+        // batch[0] leaves quarantine first, even with LIFO reuse
+        gi.advance_tick();
+        assert_eq!(2, gi.current_tick());
+        assert_eq!(batch[0].get_index(), gi.next_index().get_index());
+        assert_eq!(4, gi.next_index().get_index());
+
+        gi.advance_tick();
+        assert_eq!(batch[1].get_index(), gi.next_index().get_index());
+        assert_eq!(0, gi.free_count());
+    }
+
+    #[test]
+    fn test_peek_next_index() {
+        let gi = GenIndexEntitySet::<u64, u64>::with_reuse_policy(ReusePolicy::Randomized{seed: 3});
+        let mut gi = lock(&gi);
+
+        assert_eq!(gi.peek_next_index(), gi.peek_next_index());
+        assert_eq!(gi.peek_next_index(), gi.try_next_index());
+
+        let batch = gi.next_indices(20);
+        gi.delete_indices(batch).unwrap();
+        for _ in 0..20 {
+            let peeked = gi.peek_next_index().unwrap();
+            assert_eq!(peeked, gi.next_index());
+        }
+
+        let bounded = GenIndexEntitySet::<u64, u64>::with_capacity_limit(0);
+        assert_eq!(Err(GenIndexError::CapacityExhausted),
+                   lock(&bounded).peek_next_index());
+    }
+
+    #[test]
+    fn test_recycle_threshold() {
+        let gi = GenIndexEntitySet::<u64, u64>::with_recycle_threshold(2);
+        let mut gi = lock(&gi);
+
+        let idxs: Vec<_> = (0..4).map(|_| gi.next_index()).collect();
+        gi.delete_index(idxs[0]).unwrap();
+        gi.delete_index(idxs[1]).unwrap();
+
+        // two in the free list is not above the threshold
+        assert_eq!(4, gi.next_index().get_index());
+
+        // three is, so the next one gets recycled
+        gi.delete_index(idxs[2]).unwrap();
+        let idx = gi.next_index();
+        assert_eq!(idxs[2].get_index(), idx.get_index());
+        assert_eq!(1, idx.get_generation());
+        assert_eq!(5, gi.next_index().get_index());
+    }
+
+    #[test]
+    fn test_compact() {
+        let config = GenIndexConfig{record_operations: true, ..GenIndexConfig::default()};
+        let mut gi = GenIndexEntitySet::<u32, u32>::from_config(config);
+        let idxs = gi.next_indices(8);
+        for &i in &[0, 2, 3, 6] {
+            gi.delete_index(idxs[i]).unwrap();
+        }
+
+        let mut moved = vec![];
+        assert_eq!(3, gi.compact(|old, new| moved.push((old, new))));
+        assert_eq!(vec![(idxs[7], GenIndex::new(0, 1)),
+                        (idxs[5], GenIndex::new(2, 1)),
+                        (idxs[4], GenIndex::new(3, 1))], moved);
+        for &(old, new) in &moved {
+            assert!(!gi.is_live(old));
+            assert!(gi.is_live(new));
+        }
+        assert!(gi.is_live(idxs[1]));
+        assert!(!gi.is_live(idxs[0]));
+        assert_eq!(4, gi.live_count());
+        assert_eq!(4, gi.free_count());
+        assert_eq!(0, gi.compact(|_, _| panic!("nothing to move")));
+
+        // the freed slots are recycled at a newer generation
+        let next = gi.next_index();
+        assert!(next.get_index() >= 4);
+        assert!(next.get_generation() > 0);
+
+        let replayed = GenIndexEntitySet::from_recording(config, gi.recorded_operations()).unwrap();
+        assert_eq!(gi, replayed);
+    }
+
+    #[test]
+    fn test_compact_panicking_callback() {
+        let mut gi = GenIndexEntitySet::<u32, u32>::new_unsync();
+        let idxs = gi.next_indices(4);
+        gi.delete_index(idxs[0]).unwrap();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            gi.compact(|_, _| panic!("callback failed"))
+        }));
+        assert!(result.is_err());
+        // the move is complete, and the moved index not handed out twice
+        let moved = GenIndex::new(0, 1);
+        assert!(gi.is_live(moved) && !gi.is_live(idxs[3]));
+        assert_eq!(3, gi.live_count());
+        let next = gi.next_index();
+        assert_ne!(moved, next);
+        assert_eq!(4, gi.live_count());
+    }
+
+    #[test]
+    fn test_reserve() {
+        let gi = GenIndexEntitySet::<u32, u32>::with_capacity(100);
+        let mut set = lock(&gi);
+        assert!(set.slots.capacity() >= 100);
+        set.next_indices(10);
+        set.reserve(1000);
+        assert!(set.slots.capacity() >= 1010);
+    }
+
+    #[test]
+    fn test_hooks() {
+        use std::sync::Mutex;
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut gi = GenIndexEntitySet::<u32, u32>::new_unsync();
+        let log = seen.clone();
+        gi.on_allocate(move |idx| log.lock().unwrap().push(("allocate", idx)));
+        let log = seen.clone();
+        gi.on_delete(move |idx| log.lock().unwrap().push(("delete", idx)));
+
+        let a = gi.next_index();
+        let b = gi.next_index();
+        gi.delete_index(a).unwrap();
+        assert!(gi.delete_index(a).is_err());
+        gi.reserve_index(GenIndex::new(5, 0)).unwrap();
+        gi.compact(|_, _| {});
+        gi.clear();
+        assert_eq!(vec![("allocate", a), ("allocate", b), ("delete", a),
+                        ("allocate", GenIndex::new(5, 0)),
+                        // compact moves 5 down to 0
+                        ("delete", GenIndex::new(5, 0)), ("allocate", GenIndex::new(0, 1)),
+                        ("delete", GenIndex::new(0, 1)), ("delete", b)],
+                   *seen.lock().unwrap());
+
+        // clones share the hooks, until cleared
+        let mut clone = gi.clone();
+        clone.next_index();
+        assert_eq!(9, seen.lock().unwrap().len());
+        clone.clear_hooks();
+        clone.next_index();
+        gi.next_index();
+        assert_eq!(10, seen.lock().unwrap().len());
+    }
+
+    #[test]
+    fn test_live_mask() {
+        let mut gi = GenIndexEntitySet::<u32, u32>::new_unsync();
+        let idxs = gi.next_indices(4);
+        // built on first use...
+        let expected = |gi: &GenIndexEntitySet<u32, u32>| -> BitSet {
+            (0..gi.slots.len()).filter(|&i| gi.contains_index(i as u32)).collect()
+        };
+        assert_eq!(&expected(&gi), gi.live_mask());
+
+        // ...and kept up to date from then on
+        gi.delete_index(idxs[1]).unwrap();
+        gi.reserve_index(GenIndex::new(9, 0)).unwrap();
+        assert_eq!(vec![0, 2, 3, 9], gi.live_mask().iter().collect::<Vec<_>>());
+        gi.compact(|_, _| {});
+        gi.next_index();
+        assert_eq!(&expected(&gi), gi.live_mask());
+        let copy = gi.clone();
+        gi.clear();
+        assert!(gi.live_mask().is_empty());
+        assert_eq!(5, copy.live_mask().len());
+        // the mask is no part of the state compared
+        gi.next_index();
+        assert_eq!(gi, gi.clone());
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let config = GenIndexConfig{reuse_policy: ReusePolicy::Randomized{seed: 7}, ..GenIndexConfig::default()};
+        let mut gi = GenIndexEntitySet::<u32, u32>::from_config(config);
+        let idxs = gi.next_indices(6);
+        for &idx in &idxs[1..4] {
+            gi.delete_index(idx).unwrap();
+        }
+        gi.advance_tick();
+        let saved = gi.snapshot();
+        assert!(saved.is_live(idxs[0]) && !saved.is_live(idxs[1]));
+        assert_eq!((3, 3), (saved.live_count(), saved.free_count()));
+
+        let ahead: Vec<_> = (0..4).map(|_| gi.next_index()).collect();
+        gi.delete_index(idxs[0]).unwrap();
+        assert!(gi.live_mask().contains(5));
+        gi.restore(&saved).unwrap();
+        assert_eq!(saved, gi.snapshot());
+        assert!(gi.is_live(idxs[0]));
+        assert!(ahead.iter().all(|&idx| !gi.is_live(idx)));
+        assert_eq!(vec![0, 4, 5], gi.live_mask().iter().collect::<Vec<_>>());
+        assert_eq!(3, gi.snapshot_stats().live);
+        // the random reuse picks the same indices again
+        assert_eq!(ahead, (0..4).map(|_| gi.next_index()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_restore_invalid() {
+        let mut gi = GenIndexEntitySet::<u32, u32>::new_unsync();
+        let idxs = gi.next_indices(3);
+        gi.delete_index(idxs[1]).unwrap();
+        let saved = gi.snapshot();
+        let before = gi.next_index();
+
+        let mut bad = Vec::new();
+        bad.push(GenIndexSnapshot{index_note: 5, ..saved.clone()});
+        let mut out_of_range = saved.clone();
+        out_of_range.deleted.push_back((GenIndex::new(9, 0), 0));
+        bad.push(out_of_range);
+        let mut live = saved.clone();
+        live.deleted.push_back((idxs[0], 0));
+        bad.push(live);
+        let mut twice = saved.clone();
+        twice.retired.push(idxs[1].get_index());
+        bad.push(twice);
+        bad.push(GenIndexSnapshot{live: 3, ..saved.clone()});
+        for snapshot in &bad {
+            assert_eq!(Err(GenIndexError::InvalidSnapshot), gi.restore(snapshot));
+            assert!(gi.is_live(before));
+        }
+        gi.restore(&saved).unwrap();
+        assert!(!gi.is_live(before));
+    }
+
+    #[test]
+    fn test_restore_hooks() {
+        use std::sync::Mutex;
+        let mut gi = GenIndexEntitySet::<u32, u32>::new_unsync();
+        let idxs = gi.next_indices(3);
+        gi.delete_index(idxs[1]).unwrap();
+        let saved = gi.snapshot();
+        gi.delete_index(idxs[0]).unwrap();
+        let later = gi.next_index();
+
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let seen = log.clone();
+        gi.on_allocate(move |idx| seen.lock().unwrap().push(("allocate", idx)));
+        let seen = log.clone();
+        gi.on_delete(move |idx| seen.lock().unwrap().push(("delete", idx)));
+        gi.restore(&saved).unwrap();
+        assert_eq!(vec![("delete", later), ("allocate", idxs[0])], *log.lock().unwrap());
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let mut set = GenIndexEntitySet::<u32, u32>::new_unsync();
+        let empty = set.memory_usage();
+        assert_eq!(0, empty.slots);
+        assert_eq!(0, empty.free_list);
+        assert_eq!(mem::size_of::<GenIndexEntitySet<u32, u32>>(), empty.metadata);
+
+        let gis = set.next_indices(100);
+        set.delete_index(gis[0]).unwrap();
+        let usage = set.memory_usage();
+        assert!(usage.slots >= 100 * mem::size_of::<Slot<u32>>());
+        assert!(usage.free_list >= mem::size_of::<(GenIndex<u32, u32>, u64)>());
+        assert_eq!(usage.slots + usage.free_list + usage.metadata, usage.total());
+    }
+
 }
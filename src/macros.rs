@@ -0,0 +1,149 @@
+//! Strongly typed newtype handles, generated in one line.
+
+/// define_index_type!
+///
+/// Defines a newtype around a tagged `GenIndex`, tagged with the
+/// newtype itself, so that it cannot be mixed up with any other
+/// handle. The newtype is `Copy`, compares, orders and hashes as
+/// the handle does, shows as `3v7` with `Display`, defaults to the
+/// invalid handle, and converts to and from the `GenIndex`. With the
+/// `serde` feature, it serializes as the `GenIndex` does.
+///
+/// Optionally, an alias for the matching TaggedGenIndexEntitySet
+/// can be defined along with it.
+///
+/// Example:
+///
+/// ```
+/// #[macro_use]
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// define_index_type! {
+///     /// a handle to one of my entities.
+///     pub struct EntityId(u32, u32);
+///     pub type EntitySet;
+/// }
+///
+/// # fn main() {
+/// let mut entities = EntitySet::new_unsync();
+/// let e: EntityId = entities.next_index().into();
+/// assert!(entities.is_live(e.into()));
+/// assert_eq!("0v0", e.to_string());
+/// # }
+/// ```
+#[macro_export]
+macro_rules! define_index_type {
+    ($(#[$meta:meta])*
+     $vis:vis struct $name:ident($index:ty, $generation:ty);
+     $($setvis:vis type $set:ident;)?) => {
+        $(#[$meta])*
+        #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+        $vis struct $name($crate::GenIndex<$index, $generation, $name>);
+
+        impl $name {
+            /// return the index number.
+            #[allow(dead_code)]
+            pub fn get_index(&self) -> $index { self.0.get_index() }
+
+            /// return the generation number.
+            #[allow(dead_code)]
+            pub fn get_generation(&self) -> $generation { self.0.get_generation() }
+
+            /// return the handle underneath, with the tag stripped off.
+            #[allow(dead_code)]
+            pub fn untagged(self) -> $crate::GenIndex<$index, $generation> { self.0.untagged() }
+        }
+
+        impl ::std::convert::From<$crate::GenIndex<$index, $generation, $name>> for $name {
+            fn from(gi: $crate::GenIndex<$index, $generation, $name>) -> Self { $name(gi) }
+        }
+
+        impl ::std::convert::From<$name> for $crate::GenIndex<$index, $generation, $name> {
+            fn from(id: $name) -> Self { id.0 }
+        }
+
+        impl ::std::fmt::Display for $name {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                ::std::fmt::Display::fmt(&self.0, f)
+            }
+        }
+
+        $crate::__define_index_type_serde!($name, $index, $generation);
+
+        $($setvis type $set = $crate::TaggedGenIndexEntitySet<$index, $generation, $name>;)?
+    };
+}
+
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __define_index_type_serde {
+    ($name:ident, $index:ty, $generation:ty) => {
+        impl $crate::__private::serde::Serialize for $name {
+            fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+            where S: $crate::__private::serde::Serializer {
+                $crate::__private::serde::Serialize::serialize(&self.0, serializer)
+            }
+        }
+
+        impl<'de> $crate::__private::serde::Deserialize<'de> for $name {
+            fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+            where D: $crate::__private::serde::Deserializer<'de> {
+                <$crate::GenIndex<$index, $generation, $name>
+                 as $crate::__private::serde::Deserialize<'de>>::deserialize(deserializer).map($name)
+            }
+        }
+    };
+}
+
+#[cfg(not(feature = "serde"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __define_index_type_serde {
+    ($name:ident, $index:ty, $generation:ty) => {};
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use super::super::{GenIndex, TaggedGenIndexEntitySet};
+
+    define_index_type! {
+        struct SoundId(u16, u32);
+        type SoundSet;
+    }
+
+    define_index_type! {
+        struct BareId(u64, u64);
+    }
+
+    #[test]
+    fn test_define_index_type() {
+        let mut sounds: SoundSet = TaggedGenIndexEntitySet::new_unsync();
+        let a: SoundId = sounds.next_index().into();
+        let b: SoundId = sounds.next_index().into();
+        assert_eq!(1, b.get_index());
+        assert_eq!(0, b.get_generation());
+        assert_eq!("1v0", b.to_string());
+        assert!(a < b);
+
+        sounds.delete_index(a.into()).unwrap();
+        assert!(!sounds.is_live(a.into()));
+        let ids: BTreeSet<_> = vec![b, a].into_iter().collect();
+        assert_eq!(Some(&a), ids.iter().next());
+
+        assert!(!GenIndex::from(SoundId::default()).is_valid());
+        assert_eq!(GenIndex::<u64, u64>::null(), BareId::from(GenIndex::null()).untagged());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_define_index_type_serde() {
+        let id = BareId::from(GenIndex::null());
+        let json = serde_json::to_string(&id).unwrap();
+        assert_eq!(r#"{"index":0,"generation":0}"#, json);
+        assert_eq!(id, serde_json::from_str(&json).unwrap());
+    }
+}
@@ -0,0 +1,194 @@
+//! Human readable names of entities, both ways.
+
+use std::{collections::HashMap,
+          ops::AddAssign};
+
+use num::{Num, Bounded, ToPrimitive};
+
+use super::{GenIndex, GenIndexEntitySet, GenIndexError, SecondaryMap};
+
+/// EntityNames
+///
+/// Gives the entities handed out by one set names, "player" or
+/// "door-3" say, at most one each, and no two live entities the
+/// same, for debugging consoles and editors to find them by.
+///
+/// As in a SecondaryMap, a handle only ever finds the name given to
+/// that very handle. The names do not know when entities are
+/// deleted, however: call `remove` on deleting one, or drop the
+/// names of all the entities deleted with `prune`. Until then, a
+/// name of an entity no longer live can be given to another.
+///
+/// The index and generation types default to `u32`.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// let mut entities = GenIndexEntitySet::<u32, u32>::new_unsync();
+/// let mut names = EntityNames::new();
+/// let player = entities.next_index();
+/// let door = entities.next_index();
+/// names.set_name(&entities, player, "player").unwrap();
+///
+/// assert_eq!(Some("player"), names.name(player));
+/// assert_eq!(Some(player), names.find_by_name("player"));
+/// assert_eq!(Err(GenIndexError::NameTaken), names.set_name(&entities, door, "player"));
+///
+/// entities.delete_index(player).unwrap();
+/// assert_eq!(1, names.prune(&entities));
+/// assert_eq!(None, names.find_by_name("player"));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntityNames<I: Num + AddAssign + Copy + ToPrimitive + Bounded = u32,
+                       G: Num + AddAssign + Copy + Bounded + PartialOrd = u32> {
+    by_name: HashMap<String, GenIndex<I, G>>,
+    /// per entity named, its name.
+    names: SecondaryMap<String, I, G>,
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> EntityNames<I, G> {
+
+    /// Create a new set of names, without any.
+    pub fn new() -> EntityNames<I, G> {
+        EntityNames{by_name: HashMap::new(), names: SecondaryMap::new()}
+    }
+
+    /// Name a live entity, returning the name it had before, if
+    /// any. Fails as `GenIndexEntitySet::delete_index` would if the
+    /// entity is not live in `entities`, and with `NameTaken` if
+    /// another live entity has the name.
+    pub fn set_name(&mut self, entities: &GenIndexEntitySet<I, G>,
+                    gi: GenIndex<I, G>, name: &str) -> Result<Option<String>, GenIndexError> {
+        entities.check_live(gi)?;
+        match self.by_name.get(name) {
+            Some(&owner) if owner == gi => return Ok(Some(name.to_string())),
+            Some(&owner) if entities.is_live(owner) => return Err(GenIndexError::NameTaken),
+            Some(&owner) => { self.remove(owner); },
+            None => (),
+        }
+        // the name of an entity deleted, in the same slot
+        let previous = self.names.occupant(gi).map(|(previous, _)| previous);
+        if let Some(previous) = previous.filter(|&previous| previous != gi) {
+            self.remove(previous);
+        }
+        let old = self.remove(gi);
+        self.names.insert(gi, name.to_string())?;
+        self.by_name.insert(name.to_string(), gi);
+        Ok(old)
+    }
+
+    /// Drop the name of an entity, on deleting it, say, and return
+    /// it, if it had one.
+    pub fn remove(&mut self, gi: GenIndex<I, G>) -> Option<String> {
+        let name = self.names.remove(gi)?;
+        self.by_name.remove(&name);
+        Some(name)
+    }
+
+    /// return the name of the entity, if it has one.
+    pub fn name(&self, gi: GenIndex<I, G>) -> Option<&str> {
+        self.names.get(gi).map(String::as_str)
+    }
+
+    /// return the entity with the given name, if any. It may no
+    /// longer be live, if deleted since it was named.
+    pub fn find_by_name(&self, name: &str) -> Option<GenIndex<I, G>> {
+        self.by_name.get(name).copied()
+    }
+
+    /// iterate over the names, along with the entities having them,
+    /// in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, GenIndex<I, G>)> + '_ {
+        self.by_name.iter().map(|(name, &gi)| (name.as_str(), gi))
+    }
+
+    /// return the number of entities named.
+    pub fn len(&self) -> usize { self.by_name.len() }
+
+    /// return true if no entity is named.
+    pub fn is_empty(&self) -> bool { self.by_name.is_empty() }
+
+    /// Drop the names of every entity no longer live in `entities`,
+    /// and return how many there were.
+    pub fn prune(&mut self, entities: &GenIndexEntitySet<I, G>) -> usize {
+        let gone: Vec<_> = self.by_name.iter()
+            .filter(|&(_, &gi)| !entities.is_live(gi))
+            .map(|(name, &gi)| (name.clone(), gi))
+            .collect();
+        for (name, gi) in &gone {
+            self.by_name.remove(name);
+            if self.names.get(*gi) == Some(name) {
+                self.names.remove(*gi);
+            }
+        }
+        gone.len()
+    }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> Default for EntityNames<I, G> {
+    fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        let mut entities = GenIndexEntitySet::<u32, u32>::new_unsync();
+        let mut names = EntityNames::new();
+        let idxs = entities.next_indices(3);
+        let (a, b, c) = (idxs[0], idxs[1], idxs[2]);
+        assert_eq!(Ok(None), names.set_name(&entities, a, "alpha"));
+        assert_eq!(Ok(Some("alpha".to_string())), names.set_name(&entities, a, "alpha"));
+        assert_eq!(Ok(Some("alpha".to_string())), names.set_name(&entities, a, "first"));
+        assert_eq!(None, names.find_by_name("alpha"));
+        names.set_name(&entities, b, "alpha").unwrap();
+        assert_eq!(Err(GenIndexError::NameTaken), names.set_name(&entities, c, "first"));
+        let mut all: Vec<_> = names.iter().collect();
+        all.sort();
+        assert_eq!(vec![("alpha", b), ("first", a)], all);
+
+        // the name of an entity deleted can be taken over
+        entities.delete_index(a).unwrap();
+        assert_eq!(Ok(None), names.set_name(&entities, c, "first"));
+        assert_eq!(None, names.name(a));
+        assert_eq!(Some(c), names.find_by_name("first"));
+        assert_eq!(Err(GenIndexError::AlreadyDeleted), names.set_name(&entities, a, "again"));
+
+        // a recycled slot does not see the old name
+        entities.delete_index(b).unwrap();
+        let newcomer = entities.next_index();
+        assert_eq!(b.get_index(), newcomer.get_index());
+        assert_eq!(None, names.name(newcomer));
+        assert_eq!(1, names.prune(&entities));
+        assert_eq!(None, names.remove(b));
+        assert_eq!(None, names.find_by_name("alpha"));
+        assert_eq!(1, names.len());
+    }
+
+    #[test]
+    fn test_names_recycled_slot() {
+        let mut entities = GenIndexEntitySet::<u32, u32>::new_unsync();
+        let mut names = EntityNames::new();
+        let old = entities.next_index();
+        names.set_name(&entities, old, "old").unwrap();
+        entities.delete_index(old).unwrap();
+
+        // naming the newcomer in the slot drops the old name, unpruned
+        let newcomer = entities.next_index();
+        assert_eq!(old.get_index(), newcomer.get_index());
+        assert_eq!(Ok(None), names.set_name(&entities, newcomer, "new"));
+        assert_eq!(None, names.find_by_name("old"));
+        assert_eq!(Some(newcomer), names.find_by_name("new"));
+        assert_eq!(1, names.len());
+        assert_eq!(0, names.prune(&entities));
+        assert_eq!(1, names.len());
+    }
+}
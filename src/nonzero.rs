@@ -0,0 +1,98 @@
+//! A handle with a niche, so that `Option` of it costs nothing.
+
+use std::{convert::TryFrom,
+          fmt,
+          num::NonZeroU32};
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+use super::{GenIndex, GenIndexError};
+
+/// GenIndexNZ
+///
+/// A `GenIndex<u32, u32>` whose generation is never zero, which
+/// lets the compiler use zero to represent `None`: an
+/// `Option<GenIndexNZ>` is the same size as the handle itself. Worth
+/// having when components hold millions of optional references to
+/// other entities.
+///
+/// Create your set with `null_sentinel` set, and every handle it
+/// hands out converts.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use std::{convert::TryFrom, mem::size_of};
+/// use gen_indices::*;
+///
+/// let config = GenIndexConfig{null_sentinel: true, ..GenIndexConfig::default()};
+/// let mut gi = GenIndexEntitySet::<u32, u32>::from_config(config);
+/// let idx = GenIndexNZ::try_from(gi.next_index()).unwrap();
+///
+/// assert_eq!(size_of::<GenIndexNZ>(), size_of::<Option<GenIndexNZ>>());
+/// assert!(gi.is_live(idx.into()));
+/// ```
+#[derive(Hash, Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
+pub struct GenIndexNZ {
+    index: u32,
+    generation: NonZeroU32,
+}
+
+impl GenIndexNZ {
+    /// return the index number.
+    pub fn get_index(&self) -> u32 { self.index }
+
+    /// return the generation number, which is never zero.
+    pub fn get_generation(&self) -> NonZeroU32 { self.generation }
+}
+
+impl TryFrom<GenIndex<u32, u32>> for GenIndexNZ {
+    type Error = GenIndexError;
+
+    fn try_from(gi: GenIndex<u32, u32>) -> Result<Self, Self::Error> {
+        let generation = NonZeroU32::new(gi.generation).ok_or(GenIndexError::ZeroGeneration)?;
+        Ok(GenIndexNZ{index: gi.index, generation})
+    }
+}
+
+impl From<GenIndexNZ> for GenIndex<u32, u32> {
+    fn from(gi: GenIndexNZ) -> Self {
+        GenIndex::new(gi.index, gi.generation.get())
+    }
+}
+
+/// Shows the handle as `GenIndex` does, as in `3v7`.
+impl fmt::Display for GenIndexNZ {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}v{}", self.index, self.generation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::size_of;
+    use super::*;
+
+    #[test]
+    fn test_niche() {
+        assert_eq!(8, size_of::<GenIndexNZ>());
+        assert_eq!(8, size_of::<Option<GenIndexNZ>>());
+    }
+
+    #[test]
+    fn test_conversion() {
+        let gi = GenIndex::<u32, u32>::new(3, 7);
+        let nz = GenIndexNZ::try_from(gi).unwrap();
+        assert_eq!(3, nz.get_index());
+        assert_eq!(7, nz.get_generation().get());
+        assert_eq!("3v7", nz.to_string());
+        assert_eq!(gi, GenIndex::from(nz));
+
+        assert_eq!(Err(GenIndexError::ZeroGeneration), GenIndexNZ::try_from(GenIndex::null()));
+    }
+}
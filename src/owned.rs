@@ -0,0 +1,137 @@
+//! Handles which delete their index when dropped.
+
+use std::ops::AddAssign;
+
+use num::{Num, Bounded, ToPrimitive};
+
+use super::{GenIndex, GenIndexError, SharedGenIndexEntitySet};
+use super::sync::lock_checked;
+
+/// OwnedIndex
+///
+/// A live index, owned: when the OwnedIndex is dropped, the index is
+/// deleted from its set again. Whatever way out a function takes, an
+/// early return, a `?`, or a panic, no index it allocated is left
+/// behind live by mistake. To keep an index live for good, `release`
+/// it.
+///
+/// The OwnedIndex holds a clone of the shared set, so the set lives
+/// as long as it does. A poisoned lock is reported as `Poisoned`
+/// rather than panicking, as with SharedGenIndexEntitySetExt, and
+/// an index owned in a set whose lock is poisoned is left live
+/// when dropped.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// let gi = GenIndexEntitySet::<u64, u64>::new();
+/// let idx = {
+///     let owned = OwnedIndex::new(gi.clone()).unwrap();
+///     assert!(gi.is_live(owned.get()));
+///     owned.get()
+/// };
+/// // deleted again as the OwnedIndex went out of scope
+/// assert!(!gi.is_live(idx));
+/// ```
+#[derive(Debug)]
+pub struct OwnedIndex<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+                      G: Num + AddAssign + Copy + Bounded + PartialOrd> {
+    set: SharedGenIndexEntitySet<I, G>,
+    /// None once released.
+    gi: Option<GenIndex<I, G>>,
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> OwnedIndex<I, G> {
+
+    /// allocate a "new" index in `set`, see
+    /// `GenIndexEntitySet::try_next_index`, owned by the returned
+    /// OwnedIndex.
+    pub fn new(set: SharedGenIndexEntitySet<I, G>) -> Result<OwnedIndex<I, G>, GenIndexError> {
+        let gi = lock_checked(&set)?.try_next_index()?;
+        Ok(OwnedIndex{set, gi: Some(gi)})
+    }
+
+    /// Take ownership of an index already live in `set`, so that it
+    /// is deleted when the OwnedIndex is dropped.
+    pub fn adopt(set: SharedGenIndexEntitySet<I, G>, gi: GenIndex<I, G>) -> Result<OwnedIndex<I, G>, GenIndexError> {
+        if !lock_checked(&set)?.is_live(gi) {
+            return Err(GenIndexError::UnknownIndex);
+        }
+        Ok(OwnedIndex{set, gi: Some(gi)})
+    }
+
+    /// return the index owned.
+    pub fn get(&self) -> GenIndex<I, G> {
+        // only taken by release, which consumes self
+        self.gi.unwrap()
+    }
+
+    /// return the set the index lives in.
+    pub fn set(&self) -> &SharedGenIndexEntitySet<I, G> { &self.set }
+
+    /// Give up ownership, returning the index, which stays live.
+    pub fn release(mut self) -> GenIndex<I, G> {
+        self.gi.take().unwrap()
+    }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> Drop for OwnedIndex<I, G> {
+    fn drop(&mut self) {
+        if let (Some(gi), Ok(mut set)) = (self.gi, lock_checked(&self.set)) {
+            // fails only if it was deleted behind our back already
+            let _ = set.delete_index(gi);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{GenIndexEntitySet, SharedGenIndexSet};
+    use super::super::sync::lock;
+
+    #[test]
+    fn test_owned_index() {
+        let gi = GenIndexEntitySet::<u32, u32>::new();
+        let owned = OwnedIndex::new(gi.clone()).unwrap();
+        let idx = owned.get();
+        drop(owned);
+        assert!(!lock(&gi).is_live(idx));
+
+        let kept = OwnedIndex::new(gi.clone()).unwrap().release();
+        assert!(lock(&gi).is_live(kept));
+
+        let adopted = OwnedIndex::adopt(gi.clone(), kept).unwrap();
+        assert_eq!(Err(GenIndexError::UnknownIndex), OwnedIndex::adopt(gi.clone(), idx).map(|_| ()));
+        drop(adopted);
+        assert_eq!(0, lock(&gi).live_count());
+    }
+
+    #[test]
+    fn test_owned_index_on_error_paths() {
+        fn spawn_or_fail(set: &SharedGenIndexSet<u32, u32>, fail: bool) -> Result<GenIndex<u32, u32>, GenIndexError> {
+            let owned = set.allocate_owned()?;
+            if fail {
+                return Err(GenIndexError::CapacityExhausted);
+            }
+            Ok(owned.release())
+        }
+        let set = SharedGenIndexSet::new();
+        assert!(spawn_or_fail(&set, true).is_err());
+        assert_eq!(0, set.lock().unwrap().live_count());
+        let idx = spawn_or_fail(&set, false).unwrap();
+        assert!(set.is_live(idx));
+
+        // an index deleted elsewhere already is simply left alone
+        let owned = set.allocate_owned().unwrap();
+        set.free(owned.get()).unwrap();
+        drop(owned);
+        assert_eq!(1, set.lock().unwrap().live_count());
+    }
+}
@@ -0,0 +1,285 @@
+//! An immutable arena, whose versions share their storage.
+
+use std::{ops::AddAssign, sync::Arc};
+
+use num::{Num, Bounded, ToPrimitive, zero, one};
+
+use super::{GenIndex, GenIndexError};
+
+/// the number of slots in a chunk, the unit of sharing.
+const CHUNK: usize = 64;
+
+/// PersistentGenArena
+///
+/// A generational arena which is never changed in place: `insert`,
+/// `replace` and `remove` leave the arena alone and return a new
+/// version of it instead. Versions share every chunk of slots they
+/// have in common through `Arc`, so a change copies one chunk of 64
+/// slots and the table of chunk pointers, never the values. Keeping
+/// old versions around is therefore cheap, for undo stacks, or for
+/// branching off a speculative simulation and dropping it again.
+///
+/// Handles behave as with GenIndexArena: a slot's generation goes up
+/// each time it is reused, so a handle to a removed value never
+/// finds a later one, in this version or any later version. Slots
+/// whose generation is exhausted are retired. Removed slots are
+/// reused most recent first.
+///
+/// The index and generation types default to `u32`.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// let empty = PersistentGenArena::<&str>::new();
+/// let (before, player) = empty.insert("player");
+/// let after = before.replace(player, "ghost").unwrap();
+///
+/// // the old version is still there to go back to
+/// assert_eq!(Some(&"player"), before.get(player));
+/// assert_eq!(Some(&"ghost"), after.get(player));
+/// assert!(empty.is_empty());
+/// ```
+#[derive(Debug)]
+pub struct PersistentGenArena<T,
+                              I: Num + AddAssign + Copy + ToPrimitive + Bounded = u32,
+                              G: Num + AddAssign + Copy + Bounded + PartialOrd = u32> {
+    chunks: Vec<Arc<Vec<Slot<T, G>>>>,
+    free: Option<Arc<FreeNode<I>>>,
+    index_note: I,
+    len: usize,
+}
+
+#[derive(Debug)]
+struct Slot<T, G> {
+    generation: G,
+    value: Option<Arc<T>>,
+}
+
+/// a cell of the free list, which is a stack shared between
+/// versions.
+#[derive(Debug)]
+struct FreeNode<I> {
+    index: I,
+    next: Option<Arc<FreeNode<I>>>,
+}
+
+impl<T, G: Copy> Clone for Slot<T, G> {
+    fn clone(&self) -> Self {
+        Slot{generation: self.generation, value: self.value.clone()}
+    }
+}
+
+impl<T,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> Clone for PersistentGenArena<T, I, G> {
+    fn clone(&self) -> Self {
+        PersistentGenArena{chunks: self.chunks.clone(),
+                           free: self.free.clone(),
+                           index_note: self.index_note,
+                           len: self.len}
+    }
+}
+
+impl<T,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> Default for PersistentGenArena<T, I, G> {
+    fn default() -> Self { Self::new() }
+}
+
+impl<T,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> PersistentGenArena<T, I, G> {
+
+    /// Create a new, empty arena.
+    pub fn new() -> PersistentGenArena<T, I, G> {
+        PersistentGenArena{chunks: Vec::new(), free: None, index_note: zero(), len: 0}
+    }
+
+    /// return a version with the value stored, and the value's
+    /// handle.
+    ///
+    /// # Panics
+    ///
+    /// Panics if every index has been handed out, and no slot is
+    /// free.
+    pub fn insert(&self, value: T) -> (PersistentGenArena<T, I, G>, GenIndex<I, G>) {
+        match self.try_insert(value) {
+            Ok(version) => version,
+            Err(e) => panic!("insert: {}", e),
+        }
+    }
+
+    /// Like `insert`, but report an error instead of panicking if no
+    /// handle can be handed out.
+    pub fn try_insert(&self, value: T) -> Result<(Self, GenIndex<I, G>), GenIndexError> {
+        let mut next = self.clone();
+        let gi = match &self.free {
+            Some(node) => {
+                next.free = node.next.clone();
+                let generation = self.slot(node.index).ok_or(GenIndexError::UnknownIndex)?.generation + one();
+                GenIndex::new(node.index, generation)
+            },
+            None => {
+                if self.index_note == I::max_value() {
+                    return Err(GenIndexError::IndexSpaceExhausted);
+                }
+                next.index_note += one();
+                GenIndex::new(self.index_note, zero())
+            },
+        };
+        *next.slot_mut(gi.index)? = Slot{generation: gi.generation, value: Some(Arc::new(value))};
+        next.len += 1;
+        Ok((next, gi))
+    }
+
+    /// return a version with the given handle's value replaced, if
+    /// the handle is live, with the same errors as `remove`.
+    pub fn replace(&self, gi: GenIndex<I, G>, value: T) -> Result<PersistentGenArena<T, I, G>, GenIndexError> {
+        self.check_live(gi)?;
+        let mut next = self.clone();
+        next.slot_mut(gi.index)?.value = Some(Arc::new(value));
+        Ok(next)
+    }
+
+    /// return a version without the given handle's value. Handles
+    /// that were never handed out, have already been removed, or are
+    /// of an older generation are rejected, as with
+    /// `GenIndexEntitySet::delete_index`.
+    pub fn remove(&self, gi: GenIndex<I, G>) -> Result<PersistentGenArena<T, I, G>, GenIndexError> {
+        self.check_live(gi)?;
+        let mut next = self.clone();
+        next.slot_mut(gi.index)?.value = None;
+        next.len -= 1;
+        if gi.generation != G::max_value() {
+            next.free = Some(Arc::new(FreeNode{index: gi.index, next: next.free.take()}));
+        }
+        Ok(next)
+    }
+
+    /// return the value stored under the given handle, if it is live.
+    pub fn get(&self, gi: GenIndex<I, G>) -> Option<&T> {
+        match self.slot(gi.index) {
+            Some(slot) if slot.generation == gi.generation => slot.value.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Check whether the given handle is live in this version.
+    pub fn contains(&self, gi: GenIndex<I, G>) -> bool { self.get(gi).is_some() }
+
+    /// return the number of values stored.
+    pub fn len(&self) -> usize { self.len }
+
+    /// Check whether the arena stores no values.
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    /// Iterate over the live handles and their values, in slot order.
+    pub fn iter(&self) -> impl Iterator<Item = (GenIndex<I, G>, &T)> + '_ {
+        let mut index: I = zero();
+        self.chunks.iter().flat_map(|chunk| chunk.iter()).filter_map(move |slot| {
+            let gi = GenIndex::new(index, slot.generation);
+            index += one();
+            slot.value.as_deref().map(|value| (gi, value))
+        })
+    }
+
+    fn check_live(&self, gi: GenIndex<I, G>) -> Result<(), GenIndexError> {
+        let slot = self.slot(gi.index).ok_or(GenIndexError::UnknownIndex)?;
+        if slot.generation != gi.generation {
+            return Err(GenIndexError::StaleGeneration);
+        }
+        if slot.value.is_none() {
+            return Err(GenIndexError::AlreadyDeleted);
+        }
+        Ok(())
+    }
+
+    fn slot(&self, index: I) -> Option<&Slot<T, G>> {
+        let i = index.to_usize()?;
+        self.chunks.get(i / CHUNK)?.get(i % CHUNK)
+    }
+
+    /// the slot for the given index, in a chunk of this version's
+    /// own, copied if it is shared, and added if it is fresh.
+    fn slot_mut(&mut self, index: I) -> Result<&mut Slot<T, G>, GenIndexError> {
+        let i = index.to_usize().ok_or(GenIndexError::UnknownIndex)?;
+        if i / CHUNK == self.chunks.len() {
+            self.chunks.push(Arc::new(Vec::with_capacity(CHUNK)));
+        }
+        let chunk = Arc::make_mut(self.chunks.get_mut(i / CHUNK).ok_or(GenIndexError::UnknownIndex)?);
+        if i % CHUNK == chunk.len() {
+            chunk.push(Slot{generation: zero(), value: None});
+        }
+        chunk.get_mut(i % CHUNK).ok_or(GenIndexError::UnknownIndex)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_persistent_arena() {
+        let v0 = PersistentGenArena::<&str>::new();
+        let (v1, a) = v0.insert("a");
+        let (v2, b) = v1.insert("b");
+        let v3 = v2.remove(a).unwrap();
+        assert_eq!(Err(GenIndexError::AlreadyDeleted), v3.remove(a).map(|_| ()));
+        assert_eq!(Err(GenIndexError::UnknownIndex), v3.remove(GenIndex::new(7, 0)).map(|_| ()));
+
+        // the freed slot comes back with a new generation
+        let (v4, c) = v3.insert("c");
+        assert_eq!(a.get_index(), c.get_index());
+        assert_eq!(1, c.get_generation());
+        assert_eq!(Err(GenIndexError::StaleGeneration), v4.replace(a, "x").map(|_| ()));
+        assert_eq!(vec![(c, &"c"), (b, &"b")], v4.iter().collect::<Vec<_>>());
+
+        // and every earlier version is untouched
+        assert_eq!(0, v0.len());
+        assert_eq!(vec![(a, &"a")], v1.iter().collect::<Vec<_>>());
+        assert_eq!(Some(&"a"), v2.get(a));
+        assert_eq!(None, v3.get(a));
+        assert_eq!(2, v4.len());
+    }
+
+    #[test]
+    fn test_persistent_arena_sharing() {
+        let mut arena = PersistentGenArena::<usize>::new();
+        let mut handles = Vec::new();
+        for n in 0..CHUNK * 3 {
+            let (next, gi) = arena.insert(n);
+            arena = next;
+            handles.push(gi);
+        }
+        let branch = arena.replace(handles[CHUNK], 0).unwrap();
+        assert!(Arc::ptr_eq(&arena.chunks[0], &branch.chunks[0]));
+        assert!(!Arc::ptr_eq(&arena.chunks[1], &branch.chunks[1]));
+        assert!(Arc::ptr_eq(&arena.chunks[2], &branch.chunks[2]));
+        assert_eq!(Some(&CHUNK), arena.get(handles[CHUNK]));
+        assert_eq!(Some(&0), branch.get(handles[CHUNK]));
+    }
+
+    #[test]
+    fn test_persistent_arena_exhaustion() {
+        let mut arena = PersistentGenArena::<(), u8, u8>::new();
+        for _ in 0..255 {
+            arena = arena.insert(()).0;
+        }
+        assert_eq!(Err(GenIndexError::IndexSpaceExhausted), arena.try_insert(()).map(|_| ()));
+
+        // a slot at the last generation is retired, not reused
+        let last = GenIndex::new(0, 255);
+        let mut slot = arena.remove(GenIndex::new(0, 0)).unwrap();
+        for g in 1..=255 {
+            let (next, gi) = slot.insert(());
+            assert_eq!(GenIndex::new(0, g), gi);
+            slot = next.remove(gi).unwrap();
+        }
+        assert!(!slot.contains(last));
+        assert_eq!(Err(GenIndexError::IndexSpaceExhausted), slot.try_insert(()).map(|_| ()));
+    }
+}
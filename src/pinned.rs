@@ -0,0 +1,255 @@
+//! An arena whose values never move, for self-referential values.
+
+use std::{ops::AddAssign, pin::Pin};
+
+use num::{Num, Bounded, ToPrimitive, zero, one};
+
+use super::{GenIndex, GenIndexConfig, GenIndexEntitySet, GenIndexError};
+use super::chunked::ChunkedVec;
+
+/// PinnedGenArena
+///
+/// A generational arena like GenIndexArena, whose values are pinned:
+/// once stored, a value stays at the same address until it is
+/// dropped, in place, so it can be intrusive or self-referential.
+/// Values are kept in the same fixed-size chunks as GenIndexArena
+/// uses, which never move what they hold as the arena grows.
+///
+/// In return, the arena never lets a value out, nor lets it be
+/// moved: there is no compaction, and `remove` drops the value
+/// where it is. Values are modified through `get_pin_mut`. Only
+/// `Unpin` values, which do not mind being moved, can also be
+/// modified through a plain `get_mut`, and taken out with `take`.
+///
+/// The index and generation types default to `u32`.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use std::marker::PhantomPinned;
+/// use gen_indices::*;
+///
+/// struct Node {
+///     value: u32,
+///     _pinned: PhantomPinned,
+/// }
+///
+/// let mut nodes = PinnedGenArena::<Node>::new();
+/// let (gi, node) = nodes.insert_pinned(Node{value: 1, _pinned: PhantomPinned});
+/// let address = &*node as *const Node;
+///
+/// // plenty more values, and the first one has not moved
+/// for value in 2..10_000 {
+///     nodes.insert(Node{value, _pinned: PhantomPinned});
+/// }
+/// assert_eq!(address, nodes.get(gi).unwrap() as *const Node);
+/// nodes.remove(gi).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct PinnedGenArena<T,
+                          I: Num + AddAssign + Copy + ToPrimitive + Bounded = u32,
+                          G: Num + AddAssign + Copy + Bounded + PartialOrd = u32> {
+    set: GenIndexEntitySet<I, G>,
+    /// only ever written to where a slot is None, and only ever
+    /// emptied by dropping the value in place.
+    values: ChunkedVec<Option<T>>,
+}
+
+impl<T,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> PinnedGenArena<T, I, G> {
+
+    /// Create a new, empty arena.
+    pub fn new() -> PinnedGenArena<T, I, G> {
+        Self::with_config(GenIndexConfig::default())
+    }
+
+    /// Create a new, empty arena, whose handles are handed out
+    /// according to the given settings.
+    pub fn with_config(config: GenIndexConfig) -> PinnedGenArena<T, I, G> {
+        PinnedGenArena{set: GenIndexEntitySet::from_config(config), values: ChunkedVec::new()}
+    }
+
+    /// return the set handing out this arena's handles, for its
+    /// counters and settings.
+    pub fn set(&self) -> &GenIndexEntitySet<I, G> { &self.set }
+
+    /// Store a value, and return its handle.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as
+    /// `GenIndexEntitySet::next_index`.
+    pub fn insert(&mut self, value: T) -> GenIndex<I, G> {
+        self.insert_pinned(value).0
+    }
+
+    /// Store a value, and return its handle along with the value,
+    /// pinned where it now lives, to finish setting it up.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as
+    /// `GenIndexEntitySet::next_index`.
+    pub fn insert_pinned(&mut self, value: T) -> (GenIndex<I, G>, Pin<&mut T>) {
+        match self.try_insert_pinned(value) {
+            Ok(inserted) => inserted,
+            Err(e) => panic!("insert_pinned: {}", e),
+        }
+    }
+
+    /// Like `insert_pinned`, but report an error instead of
+    /// panicking if no handle can be handed out. The value is
+    /// dropped in that case.
+    pub fn try_insert_pinned(&mut self, value: T) -> Result<(GenIndex<I, G>, Pin<&mut T>), GenIndexError> {
+        let gi = self.set.try_next_index()?;
+        let slot = gi.get_index().to_usize().ok_or(GenIndexError::UnknownIndex)?;
+        self.values.resize_with(slot + 1, || None);
+        // just made sure it is there, and it is empty, since its
+        // index was not live
+        let value = self.values.get_mut(slot).unwrap().insert(value);
+        // the value is never moved out of its slot, only dropped in
+        // place, and the chunks never move what they hold
+        Ok((gi, unsafe { Pin::new_unchecked(value) }))
+    }
+
+    /// return the value stored under the given handle, or None if
+    /// it has been removed.
+    pub fn get(&self, gi: GenIndex<I, G>) -> Option<&T> {
+        if !self.set.is_live(gi) {
+            return None;
+        }
+        self.values.get(gi.get_index().to_usize()?)?.as_ref()
+    }
+
+    /// return the value stored under the given handle, pinned, for
+    /// modification, or None if it has been removed.
+    pub fn get_pin_mut(&mut self, gi: GenIndex<I, G>) -> Option<Pin<&mut T>> {
+        let value = self.slot_mut(gi)?.as_mut()?;
+        // as in try_insert_pinned
+        Some(unsafe { Pin::new_unchecked(value) })
+    }
+
+    /// return the value stored under the given handle for
+    /// modification, or None if it has been removed. Only for values
+    /// which may be moved anyway.
+    pub fn get_mut(&mut self, gi: GenIndex<I, G>) -> Option<&mut T> where T: Unpin {
+        Some(self.get_pin_mut(gi)?.get_mut())
+    }
+
+    /// Remove the value stored under the given handle, dropping it
+    /// where it is. The handle is validated as in
+    /// `GenIndexEntitySet::delete_index`.
+    pub fn remove(&mut self, gi: GenIndex<I, G>) -> Result<(), GenIndexError> {
+        self.set.delete_index(gi)?;
+        if let Some(value) = gi.get_index().to_usize().and_then(|slot| self.values.get_mut(slot)) {
+            *value = None;
+        }
+        Ok(())
+    }
+
+    /// Remove the value stored under the given handle, and return
+    /// it, as `GenIndexArena::remove`. Only for values which may be
+    /// moved anyway.
+    pub fn take(&mut self, gi: GenIndex<I, G>) -> Result<T, GenIndexError> where T: Unpin {
+        self.set.delete_index(gi)?;
+        gi.get_index().to_usize()
+            .and_then(|slot| self.values.get_mut(slot))
+            .and_then(Option::take)
+            .ok_or(GenIndexError::UnknownIndex)
+    }
+
+    /// Check whether a value is stored under the given handle.
+    pub fn contains(&self, gi: GenIndex<I, G>) -> bool { self.set.is_live(gi) }
+
+    /// return the number of values stored.
+    pub fn len(&self) -> usize { self.set.live_count() }
+
+    /// return true if no values are stored.
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// iterate over every value, with its handle, in index order.
+    pub fn iter(&self) -> impl Iterator<Item = (GenIndex<I, G>, &T)> + '_ {
+        let mut index: I = zero();
+        let set = &self.set;
+        self.values.iter().filter_map(move |value| {
+            let gi = set.slot_handle(index);
+            index += one();
+            Some((gi?, value.as_ref()?))
+        })
+    }
+
+    fn slot_mut(&mut self, gi: GenIndex<I, G>) -> Option<&mut Option<T>> {
+        if !self.set.is_live(gi) {
+            return None;
+        }
+        self.values.get_mut(gi.get_index().to_usize()?)
+    }
+}
+
+impl<T,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> Default for PinnedGenArena<T, I, G> {
+    fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{marker::PhantomPinned, rc::Rc, cell::Cell};
+    use super::*;
+
+    /// points at itself once set up, and checks on drop that it
+    /// still lives there.
+    struct SelfRef {
+        this: *const SelfRef,
+        drops: Rc<Cell<usize>>,
+        _pinned: PhantomPinned,
+    }
+
+    impl Drop for SelfRef {
+        fn drop(&mut self) {
+            assert_eq!(self.this, self as *const SelfRef);
+            self.drops.set(self.drops.get() + 1);
+        }
+    }
+
+    fn self_ref(arena: &mut PinnedGenArena<SelfRef>, drops: &Rc<Cell<usize>>) -> GenIndex<u32, u32> {
+        let value = SelfRef{this: std::ptr::null(), drops: drops.clone(), _pinned: PhantomPinned};
+        let (gi, mut pinned) = arena.insert_pinned(value);
+        let this = &*pinned as *const SelfRef;
+        unsafe { pinned.as_mut().get_unchecked_mut().this = this };
+        gi
+    }
+
+    #[test]
+    fn test_pinned_arena() {
+        let drops = Rc::new(Cell::new(0));
+        let mut arena = PinnedGenArena::new();
+        let handles: Vec<_> = (0..10_000).map(|_| self_ref(&mut arena, &drops)).collect();
+        assert_eq!(10_000, arena.len());
+
+        arena.remove(handles[0]).unwrap();
+        assert_eq!(1, drops.get());
+        assert_eq!(Err(GenIndexError::AlreadyDeleted), arena.remove(handles[0]));
+        assert!(arena.get_pin_mut(handles[0]).is_none());
+
+        let reused = self_ref(&mut arena, &drops);
+        assert_eq!(handles[0].get_index(), reused.get_index());
+        assert_eq!(None, arena.get(handles[0]).map(|_| ()));
+        assert_eq!(10_000, arena.iter().count());
+
+        drop(arena);
+        assert_eq!(10_001, drops.get());
+    }
+
+    #[test]
+    fn test_pinned_arena_unpin() {
+        let mut arena = PinnedGenArena::<String>::new();
+        let gi = arena.insert("a".to_string());
+        arena.get_mut(gi).unwrap().push('b');
+        assert_eq!(Ok("ab".to_string()), arena.take(gi));
+        assert!(arena.is_empty());
+    }
+}
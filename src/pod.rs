@@ -0,0 +1,39 @@
+//! Casting handle arrays to and from bytes, behind the `bytemuck`
+//! feature.
+
+use bytemuck::{Pod, Zeroable};
+
+use super::GenIndex;
+
+/// `Zeroable` and `Pod` for handles whose index and generation are
+/// of the same width, so that the `repr(C)` layout has no padding.
+macro_rules! pod {
+    ($($t:ty),*) => {$(
+        // safe: two fields of the same plain integer type, no
+        // padding, and the tag is a zero-sized PhantomData
+        unsafe impl<Tag: 'static> Zeroable for GenIndex<$t, $t, Tag> {}
+        unsafe impl<Tag: 'static> Pod for GenIndex<$t, $t, Tag> {}
+    )*};
+}
+
+pod!(u8, u16, u32, u64);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::PackedGenIndex;
+
+    #[test]
+    fn test_cast_slice() {
+        let handles = [PackedGenIndex::new(1, 2), PackedGenIndex::new(3, 4)];
+        let bytes: &[u8] = bytemuck::cast_slice(&handles);
+        assert_eq!(16, bytes.len());
+
+        let words: &[u32] = bytemuck::cast_slice(&handles);
+        assert_eq!(&[1, 2, 3, 4], words);
+
+        let back: Vec<PackedGenIndex> = bytemuck::pod_collect_to_vec(bytes);
+        assert_eq!(&handles[..], &back[..]);
+        assert_eq!(PackedGenIndex::null(), PackedGenIndex::zeroed());
+    }
+}
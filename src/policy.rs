@@ -0,0 +1,32 @@
+//! Which deleted index is handed out again.
+//!
+//! A module of its own, so that the resolver rkyv's derive generates
+//! for ReusePolicy, whose field goes undocumented, is not exported.
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+/// ReusePolicy
+///
+/// Controls which deleted index `next_index` hands out again
+/// when there is more than one to choose from.
+#[derive(Hash, Debug, PartialEq, Eq, Copy, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
+pub enum ReusePolicy {
+    /// Reuse the most recently deleted index first. This is the
+    /// default, and recycles indices very quickly.
+    #[default]
+    Lifo,
+    /// Reuse the least recently deleted index first, so that a
+    /// deleted index sits idle for as long as possible. This also
+    /// spreads generation increments evenly across all slots,
+    /// which pushes generation exhaustion out as far as possible.
+    Fifo,
+    /// Reuse a pseudo-randomly chosen deleted index. The sequence
+    /// is fully determined by the seed.
+    Randomized {
+        /// seed for the internal pseudo-random number generator.
+        seed: u64
+    },
+}
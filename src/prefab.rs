@@ -0,0 +1,193 @@
+//! Templates of entities, spawned any number of times.
+
+use std::{any::TypeId,
+          fmt,
+          ops::AddAssign,
+          sync::Arc};
+
+use num::{Num, Bounded, ToPrimitive};
+
+use super::{Component, GenIndex, GenIndexError, Registry};
+
+type Part<I, G> = Arc<dyn Fn(&mut Registry<I, G>, GenIndex<I, G>) -> Result<(), GenIndexError> + Send + Sync>;
+
+type TagPart<I, G> = fn(&mut Registry<I, G>, GenIndex<I, G>) -> Result<bool, GenIndexError>;
+
+/// Prefab
+///
+/// A bundle of components, and tags, to give every entity spawned
+/// from it with `Registry::spawn_prefab`: each one gets clones of
+/// the components the prefab was given. To have some of the
+/// components differ per entity, spawn with `Registry::spawn` and
+/// `EntityBuilder::with_prefab` instead, and give the entity its own
+/// components of those types after the prefab's.
+///
+/// Cloning a prefab is cheap, the components are shared between the
+/// clones.
+///
+/// The index and generation types default to `u32`.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// #[derive(Clone)]
+/// struct Health(u32);
+/// #[derive(Clone)]
+/// struct Position(f32, f32);
+/// struct Hostile;
+///
+/// let goblin = Prefab::new()
+///     .with(Health(7))
+///     .with(Position(0.0, 0.0))
+///     .with_tag::<Hostile>();
+///
+/// let mut registry = Registry::<u32, u32>::new();
+/// let first = registry.spawn_prefab(&goblin);
+/// let second = registry.spawn().with_prefab(&goblin).with(Position(4.0, 2.0)).build();
+///
+/// assert_eq!(7, registry.get::<Health>(second).unwrap().0);
+/// assert_eq!(4.0, registry.get::<Position>(second).unwrap().0);
+/// assert_eq!(0.0, registry.get::<Position>(first).unwrap().0);
+/// assert!(registry.has_tag::<Hostile>(first));
+/// ```
+pub struct Prefab<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static = u32,
+                  G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static = u32> {
+    /// per component type, in the order first given, how to give
+    /// an entity a clone of the component.
+    components: Vec<(TypeId, Part<I, G>)>,
+    tags: Vec<(TypeId, TagPart<I, G>)>,
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> Prefab<I, G> {
+
+    /// Create a new prefab, without components or tags.
+    pub fn new() -> Prefab<I, G> {
+        Prefab{components: Vec::new(), tags: Vec::new()}
+    }
+
+    /// Add a component to the prefab, replacing the one of the same
+    /// type added before, if any.
+    pub fn with<T: Component + Clone>(mut self, value: T) -> Self {
+        let part: Part<I, G> = Arc::new(move |registry, gi| registry.insert(gi, value.clone()).map(|_| ()));
+        match self.components.iter_mut().find(|(id, _)| *id == TypeId::of::<T>()) {
+            Some((_, old)) => *old = part,
+            None => self.components.push((TypeId::of::<T>(), part)),
+        }
+        self
+    }
+
+    /// Add the tag `T` to the prefab.
+    pub fn with_tag<T: Component>(mut self) -> Self {
+        if !self.has_tag::<T>() {
+            self.tags.push((TypeId::of::<T>(), Registry::add_tag::<T>));
+        }
+        self
+    }
+
+    /// Check whether the prefab has a component of type `T`.
+    pub fn has<T: Component>(&self) -> bool {
+        self.components.iter().any(|(id, _)| *id == TypeId::of::<T>())
+    }
+
+    /// Check whether the prefab has the tag `T`.
+    pub fn has_tag<T: Component>(&self) -> bool {
+        self.tags.iter().any(|(id, _)| *id == TypeId::of::<T>())
+    }
+
+    /// return the number of components of the prefab.
+    pub fn component_count(&self) -> usize { self.components.len() }
+
+    /// return the number of tags of the prefab.
+    pub fn tag_count(&self) -> usize { self.tags.len() }
+
+    /// give a live entity the components and tags of the prefab.
+    pub(crate) fn instantiate(&self, registry: &mut Registry<I, G>, gi: GenIndex<I, G>) -> Result<(), GenIndexError> {
+        for (_, part) in &self.components {
+            part(registry, gi)?;
+        }
+        for (_, tag) in &self.tags {
+            tag(registry, gi)?;
+        }
+        Ok(())
+    }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> Registry<I, G> {
+    /// Create a new entity with the components and tags of the
+    /// prefab, and return it.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `create`.
+    pub fn spawn_prefab(&mut self, prefab: &Prefab<I, G>) -> GenIndex<I, G> {
+        self.spawn().with_prefab(prefab).build()
+    }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> Clone for Prefab<I, G> {
+    fn clone(&self) -> Self {
+        Prefab{components: self.components.clone(), tags: self.tags.clone()}
+    }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> Default for Prefab<I, G> {
+    fn default() -> Self { Self::new() }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> fmt::Debug for Prefab<I, G> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Prefab")
+            .field("components", &self.components.len())
+            .field("tags", &self.tags.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_fixtures::{Health, Armor};
+
+    struct Boss;
+
+    #[test]
+    fn test_prefab() {
+        let knight = Prefab::<u32, u32>::new()
+            .with(Health(10))
+            .with(Armor(3))
+            .with(Health(12))
+            .with_tag::<Boss>()
+            .with_tag::<Boss>();
+        assert_eq!((2, 1), (knight.component_count(), knight.tag_count()));
+        assert!(knight.has::<Armor>());
+        assert!(knight.has_tag::<Boss>());
+        assert!(!knight.has_tag::<Armor>());
+        let empty = Prefab::<u32, u32>::new();
+        assert_eq!((0, 0), (empty.component_count(), empty.tag_count()));
+
+        let mut registry = Registry::new();
+        let spawned: Vec<_> = (0..3).map(|_| registry.spawn_prefab(&knight)).collect();
+        for &gi in &spawned {
+            assert_eq!(Some(&Health(12)), registry.get(gi));
+            assert_eq!(Some(&Armor(3)), registry.get(gi));
+            assert!(registry.has_tag::<Boss>(gi));
+        }
+        registry.get_mut::<Health>(spawned[0]).unwrap().0 = 0;
+        assert_eq!(Some(&Health(12)), registry.get(spawned[1]));
+
+        // overrides, and clones of the prefab
+        let weak = registry.spawn().with_prefab(&knight.clone()).with(Health(1)).build();
+        assert_eq!(Some(&Health(1)), registry.get(weak));
+        assert_eq!(Some(&Armor(3)), registry.get(weak));
+        assert_eq!(4, registry.query::<(&Health, &Armor)>().count());
+    }
+}
@@ -0,0 +1,238 @@
+//! Looking up several components of every entity at once.
+
+use std::{any::TypeId, marker::PhantomData, ops::AddAssign};
+
+use num::{Num, Bounded, ToPrimitive};
+
+use super::{Archetype, Component, ComponentStore, GenIndex, SecondaryMap};
+
+/// Query
+///
+/// What `Registry::query` can look up for each entity: a shared
+/// reference `&T` or a mutable reference `&mut T` to one of its
+/// components, or a tuple of up to eight of those, each of which
+/// the entity must have to be found. The empty tuple finds every
+/// live entity.
+///
+/// A type may be asked for more than once in a query, as long as
+/// every time it is shared; asking for it mutably and any other
+/// way besides makes `query` panic.
+///
+/// # Safety
+///
+/// Implementations must only ever hand out the components of the
+/// types they report in `access`, and mutably only those reported
+/// as such. This is meant to be implemented by this crate alone.
+pub unsafe trait Query<'a,
+                       I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+                       G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> {
+    /// what is found for each entity.
+    type Item;
+    /// the storages looked in.
+    type Fetch;
+    /// the columns looked in, in a table of archetypes.
+    type Column;
+
+    /// list every component type looked up, along with whether it
+    /// is borrowed mutably.
+    fn access(out: &mut Vec<(TypeId, bool)>);
+
+    /// find the storages to look in, or None if one of them was
+    /// never created, and nothing can be found.
+    ///
+    /// # Safety
+    ///
+    /// `store` must be valid for `'a`, and nothing else may look at
+    /// the storages of the types reported by `access` during `'a`,
+    /// save other shared borrows of shared ones.
+    unsafe fn fetcher(store: *mut ComponentStore<I, G>) -> Option<Self::Fetch>;
+
+    /// look up the entity.
+    ///
+    /// # Safety
+    ///
+    /// Every entity may only be looked up once per Fetch, unless
+    /// nothing is borrowed mutably.
+    unsafe fn fetch(fetch: &mut Self::Fetch, gi: GenIndex<I, G>) -> Option<Self::Item>;
+
+    /// find the columns to look in, or None if the entities of the
+    /// archetype lack one of the types.
+    ///
+    /// # Safety
+    ///
+    /// As for `fetcher`, with `archetype` in place of `store`.
+    unsafe fn column(archetype: *mut Archetype<I, G>) -> Option<Self::Column>;
+
+    /// look up the entity in the given row.
+    ///
+    /// # Safety
+    ///
+    /// `row` must be in the archetype the columns were found in,
+    /// and every row may only be looked up once per Column, unless
+    /// nothing is borrowed mutably.
+    unsafe fn fetch_row(column: &mut Self::Column, row: usize) -> Self::Item;
+}
+
+unsafe impl<'a, T: Component,
+            I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+            G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> Query<'a, I, G> for &'a T {
+    type Item = &'a T;
+    type Fetch = &'a SecondaryMap<T, I, G>;
+    type Column = *const T;
+
+    fn access(out: &mut Vec<(TypeId, bool)>) { out.push((TypeId::of::<T>(), false)); }
+
+    unsafe fn fetcher(store: *mut ComponentStore<I, G>) -> Option<Self::Fetch> {
+        (*store).storage::<T>()
+    }
+
+    unsafe fn fetch(fetch: &mut Self::Fetch, gi: GenIndex<I, G>) -> Option<&'a T> {
+        let map: &'a SecondaryMap<T, I, G> = fetch;
+        map.get(gi)
+    }
+
+    unsafe fn column(archetype: *mut Archetype<I, G>) -> Option<Self::Column> {
+        (*archetype).column::<T>().map(|column| column.as_ptr())
+    }
+
+    unsafe fn fetch_row(column: &mut Self::Column, row: usize) -> &'a T {
+        &*column.add(row)
+    }
+}
+
+unsafe impl<'a, T: Component,
+            I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+            G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> Query<'a, I, G> for &'a mut T {
+    type Item = &'a mut T;
+    type Fetch = *mut SecondaryMap<T, I, G>;
+    type Column = *mut T;
+
+    fn access(out: &mut Vec<(TypeId, bool)>) { out.push((TypeId::of::<T>(), true)); }
+
+    unsafe fn fetcher(store: *mut ComponentStore<I, G>) -> Option<Self::Fetch> {
+        (*store).storage_opt_mut::<T>().map(|map| map as *mut _)
+    }
+
+    unsafe fn fetch(fetch: &mut Self::Fetch, gi: GenIndex<I, G>) -> Option<&'a mut T> {
+        // distinct entities have distinct components, so the
+        // references handed out never overlap
+        (**fetch).get_mut(gi)
+    }
+
+    unsafe fn column(archetype: *mut Archetype<I, G>) -> Option<Self::Column> {
+        (*archetype).column_mut::<T>().map(|column| column.as_mut_ptr())
+    }
+
+    unsafe fn fetch_row(column: &mut Self::Column, row: usize) -> &'a mut T {
+        // distinct rows hold distinct components
+        &mut *column.add(row)
+    }
+}
+
+macro_rules! impl_query_tuple {
+    ($($q:ident $f:ident),*) => {
+        unsafe impl<'a, $($q: Query<'a, I, G>,)*
+                    I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+                    G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> Query<'a, I, G> for ($($q,)*) {
+            type Item = ($($q::Item,)*);
+            type Fetch = ($($q::Fetch,)*);
+            type Column = ($($q::Column,)*);
+
+            #[allow(unused_variables)]
+            fn access(out: &mut Vec<(TypeId, bool)>) { $($q::access(out);)* }
+
+            #[allow(unused_variables)]
+            unsafe fn fetcher(store: *mut ComponentStore<I, G>) -> Option<Self::Fetch> {
+                Some(($($q::fetcher(store)?,)*))
+            }
+
+            #[allow(unused_variables)]
+            unsafe fn fetch(fetch: &mut Self::Fetch, gi: GenIndex<I, G>) -> Option<Self::Item> {
+                let ($($f,)*) = fetch;
+                Some(($($q::fetch($f, gi)?,)*))
+            }
+
+            #[allow(unused_variables)]
+            unsafe fn column(archetype: *mut Archetype<I, G>) -> Option<Self::Column> {
+                Some(($($q::column(archetype)?,)*))
+            }
+
+            #[allow(unused_variables, clippy::unused_unit)]
+            unsafe fn fetch_row(column: &mut Self::Column, row: usize) -> Self::Item {
+                let ($($f,)*) = column;
+                ($($q::fetch_row($f, row),)*)
+            }
+        }
+    }
+}
+
+impl_query_tuple!();
+impl_query_tuple!(A a);
+impl_query_tuple!(A a, B b);
+impl_query_tuple!(A a, B b, C c);
+impl_query_tuple!(A a, B b, C c, D d);
+impl_query_tuple!(A a, B b, C c, D d, E e);
+impl_query_tuple!(A a, B b, C c, D d, E e, F f);
+impl_query_tuple!(A a, B b, C c, D d, E e, F f, H h);
+impl_query_tuple!(A a, B b, C c, D d, E e, F f, H h, J j);
+
+/// TagFilter
+///
+/// Which tags the entities found by `Registry::query_filtered` must
+/// have, or must not have: `With<T>` for entities tagged with `T`,
+/// `Without<T>` for those that are not, or a tuple of up to eight
+/// of those, all of which must hold. The empty tuple lets every
+/// entity through.
+pub trait TagFilter {
+    /// list the tags required, and those ruled out.
+    fn tags(with: &mut Vec<TypeId>, without: &mut Vec<TypeId>);
+}
+
+/// With
+///
+/// A TagFilter letting through only entities tagged with `T`.
+pub struct With<T>(PhantomData<fn() -> T>);
+
+/// Without
+///
+/// A TagFilter letting through only entities not tagged with `T`.
+pub struct Without<T>(PhantomData<fn() -> T>);
+
+impl<T: Component> TagFilter for With<T> {
+    fn tags(with: &mut Vec<TypeId>, _: &mut Vec<TypeId>) { with.push(TypeId::of::<T>()); }
+}
+
+impl<T: Component> TagFilter for Without<T> {
+    fn tags(_: &mut Vec<TypeId>, without: &mut Vec<TypeId>) { without.push(TypeId::of::<T>()); }
+}
+
+macro_rules! impl_tag_filter_tuple {
+    ($($f:ident),*) => {
+        impl<$($f: TagFilter),*> TagFilter for ($($f,)*) {
+            #[allow(unused_variables)]
+            fn tags(with: &mut Vec<TypeId>, without: &mut Vec<TypeId>) { $($f::tags(with, without);)* }
+        }
+    }
+}
+
+impl_tag_filter_tuple!();
+impl_tag_filter_tuple!(A);
+impl_tag_filter_tuple!(A, B);
+impl_tag_filter_tuple!(A, B, C);
+impl_tag_filter_tuple!(A, B, C, D);
+impl_tag_filter_tuple!(A, B, C, D, E);
+impl_tag_filter_tuple!(A, B, C, D, E, F);
+impl_tag_filter_tuple!(A, B, C, D, E, F, H);
+impl_tag_filter_tuple!(A, B, C, D, E, F, H, J);
+
+/// panic if the access list borrows a type mutably and any other
+/// way besides.
+pub(crate) fn check_access(access: &[(TypeId, bool)]) {
+    for (i, (id, mutable)) in access.iter().enumerate() {
+        for (other, other_mutable) in &access[i + 1..] {
+            if id == other && (*mutable || *other_mutable) {
+                panic!("query: a component type is borrowed mutably and more than once");
+            }
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! What is known of component types at run time.
+
+use std::{any::Any,
+          any::TypeId,
+          any::type_name,
+          fmt,
+          mem};
+
+use super::Component;
+
+type DebugFn = fn(&dyn Any, &mut fmt::Formatter) -> fmt::Result;
+
+fn debug_component<T: Component + fmt::Debug>(value: &dyn Any, f: &mut fmt::Formatter) -> fmt::Result {
+    match value.downcast_ref::<T>() {
+        Some(value) => fmt::Debug::fmt(value, f),
+        None => write!(f, "<not a {}>", type_name::<T>()),
+    }
+}
+
+/// ComponentInfo
+///
+/// What an editor, or a scripting layer, may want to know of a
+/// component type it knows nothing of at compile time: its name, its
+/// size, and, if the type is Debug, how to show a value of it. The
+/// info is registered with `Registry::register_info`, after which
+/// the components of an entity, found with `Registry::get_dyn` by
+/// their type ids, can be shown along with the type's name.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// #[derive(Debug)]
+/// struct Health(u32);
+/// struct Secret(u32);
+///
+/// let mut registry = Registry::<u32, u32>::new();
+/// registry.register_info(ComponentInfo::of_debug::<Health>());
+/// registry.register_info(ComponentInfo::of::<Secret>());
+/// let player = registry.spawn().with(Health(7)).with(Secret(42)).build();
+///
+/// let shown: Vec<_> = registry.components_of(player)
+///     .map(|id| {
+///         let info = registry.component_info(id).unwrap();
+///         (info.short_name(), info.format(registry.get_dyn(player, id).unwrap()))
+///     })
+///     .collect();
+/// assert_eq!(vec![("Health", Some("Health(7)".to_string())), ("Secret", None)], shown);
+/// ```
+#[derive(Clone, Copy)]
+pub struct ComponentInfo {
+    id: TypeId,
+    name: &'static str,
+    size: usize,
+    debug: Option<DebugFn>,
+}
+
+impl ComponentInfo {
+
+    /// return the info of the component type `T`, with no way of
+    /// showing its values.
+    pub fn of<T: Component>() -> ComponentInfo {
+        ComponentInfo{id: TypeId::of::<T>(), name: type_name::<T>(), size: mem::size_of::<T>(), debug: None}
+    }
+
+    /// return the info of the component type `T`, showing its
+    /// values as Debug does.
+    pub fn of_debug<T: Component + fmt::Debug>() -> ComponentInfo {
+        ComponentInfo{debug: Some(debug_component::<T>), ..Self::of::<T>()}
+    }
+
+    /// return the type id of the type.
+    pub fn type_id(&self) -> TypeId { self.id }
+
+    /// return the full name of the type, with its module path, as
+    /// `std::any::type_name` gives it.
+    pub fn name(&self) -> &'static str { self.name }
+
+    /// return the name of the type, without its module path, nor
+    /// those of its type parameters.
+    pub fn short_name(&self) -> &'static str {
+        let name = self.name.split('<').next().unwrap_or(self.name);
+        name.rsplit("::").next().unwrap_or(name)
+    }
+
+    /// return the size of a value of the type, in bytes.
+    pub fn size(&self) -> usize { self.size }
+
+    /// Check whether values of the type can be shown with `format`.
+    pub fn is_debug(&self) -> bool { self.debug.is_some() }
+
+    /// return the value shown as Debug does, if the info knows how,
+    /// and the value is of the type.
+    pub fn format(&self, value: &dyn Any) -> Option<String> {
+        struct Shown<'a>(&'a dyn Any, DebugFn);
+
+        impl fmt::Debug for Shown<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { (self.1)(self.0, f) }
+        }
+
+        let debug = self.debug?;
+        if (*value).type_id() != self.id {
+            return None;
+        }
+        Some(format!("{:?}", Shown(value, debug)))
+    }
+}
+
+impl fmt::Debug for ComponentInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ComponentInfo")
+            .field("name", &self.name)
+            .field("size", &self.size)
+            .field("debug", &self.is_debug())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_fixtures::Health;
+
+    struct Wrapper<T>(T);
+
+    #[test]
+    fn test_component_info() {
+        let health = ComponentInfo::of_debug::<Health>();
+        assert_eq!(TypeId::of::<Health>(), health.type_id());
+        assert!(health.name().ends_with("::Health"));
+        assert_eq!("Health", health.short_name());
+        assert_eq!(4, health.size());
+        assert_eq!(Some("Health(3)".to_string()), health.format(&Health(3)));
+        // values of other types are not shown
+        assert_eq!(None, health.format(&3u32));
+
+        let wrapped = ComponentInfo::of::<Wrapper<Health>>();
+        assert_eq!("Wrapper", wrapped.short_name());
+        assert!(!wrapped.is_debug());
+        assert_eq!(None, wrapped.format(&Wrapper(Health(3))));
+    }
+}
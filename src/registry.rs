@@ -0,0 +1,824 @@
+//! Entities together with their components, and which ones they have.
+
+use std::{any::Any,
+          any::TypeId,
+          collections::HashMap,
+          ops::AddAssign};
+
+use num::{Num, Bounded, NumCast, ToPrimitive, cast};
+
+use super::{Archetype, BitSet, Component, ComponentInfo, ComponentStore, GenIndex, GenIndexEntitySet, GenIndexError,
+            Query, SecondaryMap, StorageBackend, TagFilter};
+use super::query::check_access;
+
+/// Registry
+///
+/// Hands out entities, as GenIndexEntitySet does, stores their
+/// components, as ComponentStore does, and keeps track of which
+/// component types every entity has, as a bit mask per entity, with
+/// a bit per component type. Components can only be stored for
+/// live entities, and go away with the entity when it is destroyed.
+///
+/// Bits are given out to component types in the order they are
+/// first stored, and never reused. For every type, the registry also
+/// keeps the set of entities which have it, by slot number, so that
+/// `query` finds the entities with all of the requested components
+/// by intersecting these sets, a word of 64 entities at a time.
+///
+/// Entities can also be tagged with marker types, `struct Frozen;`
+/// say, which are kept as nothing but a bit per entity in a set per
+/// tag type, and so cost next to nothing even for millions of
+/// entities. Tags are separate from components: `add_tag::<T>`
+/// does not make `has::<T>` true, and queries filter on tags with
+/// `query_filtered`.
+///
+/// Every change to a component, by `insert`, `get_mut`, or a query
+/// asking for it mutably, is stamped with the registry's `tick`, a
+/// counter going up with every change, so `changed_since` can find
+/// the components changed after some earlier tick.
+///
+/// The index and generation types default to `u32`.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use std::any::TypeId;
+/// use gen_indices::*;
+///
+/// struct Position(f32, f32);
+/// struct Velocity(f32, f32);
+///
+/// let mut registry = Registry::<u32, u32>::new();
+/// let rock = registry.create();
+/// registry.insert(rock, Position(0.0, 0.0)).unwrap();
+/// assert!(registry.has::<Position>(rock));
+/// assert!(!registry.has::<Velocity>(rock));
+/// assert_eq!(vec![TypeId::of::<Position>()], registry.components_of(rock).collect::<Vec<_>>());
+///
+/// registry.destroy(rock).unwrap();
+/// assert!(!registry.has::<Position>(rock));
+/// ```
+#[derive(Debug)]
+pub struct Registry<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static = u32,
+                    G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static = u32> {
+    entities: GenIndexEntitySet<I, G>,
+    components: ComponentStore<I, G>,
+    /// per live entity, the bits of the component types it has.
+    masks: SecondaryMap<BitSet, I, G>,
+    /// the bit of every component type ever stored...
+    bits: HashMap<TypeId, usize>,
+    /// ...and the other way around.
+    types: Vec<TypeId>,
+    /// per component type bit, the slots of the entities having it.
+    members: Vec<BitSet>,
+    /// per tag type, the slots of the entities tagged with it.
+    tags: HashMap<TypeId, BitSet>,
+    /// the tick of the latest change.
+    tick: u64,
+    /// per component type bit, per slot, the tick its component
+    /// was last changed at.
+    changed: Vec<Vec<u64>>,
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> Registry<I, G> {
+
+    /// Create a new registry, without any entities.
+    pub fn new() -> Registry<I, G> {
+        Self::with_entities(GenIndexEntitySet::new_unsync())
+    }
+
+    /// Create a new registry handing out entities from `entities`,
+    /// which may be configured as needed, or already have live
+    /// entities, which start out without components.
+    pub fn with_entities(entities: GenIndexEntitySet<I, G>) -> Registry<I, G> {
+        Self::with_backend(entities, StorageBackend::SparseSets)
+    }
+
+    /// Create a new registry handing out entities from `entities`,
+    /// as `with_entities`, keeping their components as `backend`
+    /// says.
+    pub fn with_backend(entities: GenIndexEntitySet<I, G>, backend: StorageBackend) -> Registry<I, G> {
+        Registry {
+            entities,
+            components: ComponentStore::with_backend(backend),
+            masks: SecondaryMap::new(),
+            bits: HashMap::new(),
+            types: Vec::new(),
+            members: Vec::new(),
+            tags: HashMap::new(),
+            tick: 0,
+            changed: Vec::new(),
+        }
+    }
+
+    /// return the set the entities are handed out by.
+    pub fn entities(&self) -> &GenIndexEntitySet<I, G> { &self.entities }
+
+    /// the set the entities are handed out by, to register hooks
+    /// with; deleting from it behind the registry's back would
+    /// leave the components of the entities behind.
+    pub(crate) fn entities_mut(&mut self) -> &mut GenIndexEntitySet<I, G> { &mut self.entities }
+
+    /// return the store the components are kept in.
+    pub fn components(&self) -> &ComponentStore<I, G> { &self.components }
+
+    /// Create a new entity, without components.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as
+    /// `GenIndexEntitySet::next_index`.
+    pub fn create(&mut self) -> GenIndex<I, G> {
+        match self.try_create() {
+            Ok(gi) => gi,
+            Err(e) => panic!("create: {}", e),
+        }
+    }
+
+    /// Like `create`, but report an error instead of panicking.
+    pub fn try_create(&mut self) -> Result<GenIndex<I, G>, GenIndexError> {
+        self.entities.try_next_index()
+    }
+
+    /// Destroy an entity, dropping all of its components, and
+    /// return how many there were. The handle is validated as in
+    /// `GenIndexEntitySet::delete_index`.
+    pub fn destroy(&mut self, gi: GenIndex<I, G>) -> Result<usize, GenIndexError> {
+        self.entities.delete_index(gi)?;
+        let slot = Self::slot(gi);
+        for bit in self.masks.remove(gi).into_iter().flatten() {
+            self.members[bit].remove(slot);
+        }
+        for tagged in self.tags.values_mut() {
+            tagged.remove(slot);
+        }
+        Ok(self.components.remove_all(gi))
+    }
+
+    /// Check whether the entity is live.
+    pub fn is_live(&self, gi: GenIndex<I, G>) -> bool { self.entities.is_live(gi) }
+
+    /// return the number of live entities.
+    pub fn len(&self) -> usize { self.entities.live_count() }
+
+    /// return true if there are no live entities.
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// Store a component for a live entity, returning the one of
+    /// the same type it had, if any. Fails as
+    /// `GenIndexEntitySet::delete_index` would if the entity is not
+    /// live.
+    pub fn insert<T: Component>(&mut self, gi: GenIndex<I, G>, value: T) -> Result<Option<T>, GenIndexError> {
+        self.entities.check_live(gi)?;
+        let bit = self.bit_or_new::<T>();
+        let old = self.components.insert(gi, value)?;
+        self.masks.entry(gi)?.or_default().insert(bit);
+        self.members[bit].insert(Self::slot(gi));
+        self.mark_changed(bit, Self::slot(gi));
+        Ok(old)
+    }
+
+    /// Remove the entity's component of type `T`, and return it.
+    pub fn remove<T: Component>(&mut self, gi: GenIndex<I, G>) -> Option<T> {
+        let value = self.components.remove::<T>(gi)?;
+        if let (Some(&bit), Some(mask)) = (self.bits.get(&TypeId::of::<T>()), self.masks.get_mut(gi)) {
+            mask.remove(bit);
+            self.members[bit].remove(Self::slot(gi));
+        }
+        Some(value)
+    }
+
+    /// Register `T` as a component type `clone_entity` copies, as
+    /// `ComponentStore::register_clone`.
+    pub fn register_clone<T: Component + Clone>(&mut self) {
+        self.components.register_clone::<T>();
+    }
+
+    /// Create a new entity with copies of the components of a live
+    /// one, of every type registered with `register_clone`, and
+    /// with all of its tags, and return it. The other components
+    /// are left out.
+    ///
+    /// ```
+    /// # use gen_indices::*;
+    /// #[derive(Clone)]
+    /// struct Speed(f32);
+    /// struct Owner(GenIndex<u32, u32>);
+    ///
+    /// let mut registry = Registry::<u32, u32>::new();
+    /// registry.register_clone::<Speed>();
+    /// let player = registry.create();
+    /// let bullet = registry.spawn().with(Speed(9.0)).with(Owner(player)).build();
+    ///
+    /// let copy = registry.clone_entity(bullet);
+    /// assert_eq!(9.0, registry.get::<Speed>(copy).unwrap().0);
+    /// assert!(!registry.has::<Owner>(copy));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `try_clone_entity`
+    /// fails.
+    pub fn clone_entity(&mut self, gi: GenIndex<I, G>) -> GenIndex<I, G> {
+        match self.try_clone_entity(gi) {
+            Ok(copy) => copy,
+            Err(e) => panic!("clone_entity: {}", e),
+        }
+    }
+
+    /// Like `clone_entity`, but report an error instead of
+    /// panicking: as `GenIndexEntitySet::delete_index` would if the
+    /// entity is not live, and as `try_create` does if no new
+    /// entity can be created.
+    pub fn try_clone_entity(&mut self, gi: GenIndex<I, G>) -> Result<GenIndex<I, G>, GenIndexError> {
+        self.entities.check_live(gi)?;
+        let copy = self.try_create()?;
+        let cloned = match self.components.clone_components(gi, copy) {
+            Ok(cloned) => cloned,
+            Err(e) => {
+                let _ = self.destroy(copy);
+                return Err(e);
+            },
+        };
+        let slot = Self::slot(copy);
+        for id in cloned {
+            // having a component means its type has a bit
+            let bit = self.bits[&id];
+            self.masks.entry(copy)?.or_default().insert(bit);
+            self.members[bit].insert(slot);
+            self.mark_changed(bit, slot);
+        }
+        let original = Self::slot(gi);
+        for tagged in self.tags.values_mut() {
+            if tagged.contains(original) {
+                tagged.insert(slot);
+            }
+        }
+        Ok(copy)
+    }
+
+    /// return the entity's component of type `T`, if it has one.
+    pub fn get<T: Component>(&self, gi: GenIndex<I, G>) -> Option<&T> {
+        self.components.get(gi)
+    }
+
+    /// return the entity's component of type `T` for modification,
+    /// if it has one, marking it changed.
+    pub fn get_mut<T: Component>(&mut self, gi: GenIndex<I, G>) -> Option<&mut T> {
+        if !self.components.contains::<T>(gi) {
+            return None;
+        }
+        // having a component means its type has a bit
+        self.mark_changed(self.bits[&TypeId::of::<T>()], Self::slot(gi));
+        self.components.get_mut(gi)
+    }
+
+    /// return the entity's component of the type with the given id,
+    /// if it has one, with its type erased, as
+    /// `ComponentStore::get_dyn`.
+    pub fn get_dyn(&self, gi: GenIndex<I, G>, id: TypeId) -> Option<&dyn Any> {
+        self.components.get_dyn(gi, id)
+    }
+
+    /// return the entity's component of the type with the given id
+    /// for modification, if it has one, with its type erased,
+    /// marking it changed.
+    pub fn get_dyn_mut(&mut self, gi: GenIndex<I, G>, id: TypeId) -> Option<&mut dyn Any> {
+        self.components.get_dyn(gi, id)?;
+        // having a component means its type has a bit
+        self.mark_changed(self.bits[&id], Self::slot(gi));
+        self.components.get_dyn_mut(gi, id)
+    }
+
+    /// Register what is known of a component type, for tools to
+    /// show the components found with `get_dyn`, as
+    /// `ComponentStore::register_info`.
+    pub fn register_info(&mut self, info: ComponentInfo) {
+        self.components.register_info(info);
+    }
+
+    /// return the info registered for the component type with the
+    /// given id, if any.
+    pub fn component_info(&self, id: TypeId) -> Option<&ComponentInfo> { self.components.info(id) }
+
+    /// return the tick of the latest change to any component, to
+    /// hand to `changed_since` later on.
+    pub fn tick(&self) -> u64 { self.tick }
+
+    /// return the tick the entity's component of type `T` was last
+    /// changed at, if it has one.
+    pub fn changed_at<T: Component>(&self, gi: GenIndex<I, G>) -> Option<u64> {
+        if !self.has::<T>(gi) {
+            return None;
+        }
+        let bit = self.bit::<T>()?;
+        self.changed[bit].get(Self::slot(gi)).copied()
+    }
+
+    /// Check whether the entity is live, and has a component of
+    /// type `T`.
+    pub fn has<T: Component>(&self, gi: GenIndex<I, G>) -> bool {
+        match (self.bit::<T>(), self.mask(gi)) {
+            (Some(bit), Some(mask)) => mask.contains(bit),
+            _ => false,
+        }
+    }
+
+    /// iterate over the types of the components the entity has, in
+    /// the order the types were first stored in the registry.
+    /// Nothing, if the entity is not live.
+    pub fn components_of(&self, gi: GenIndex<I, G>) -> impl Iterator<Item = TypeId> + '_ {
+        self.mask(gi).into_iter().flat_map(BitSet::iter).map(move |bit| self.types[bit])
+    }
+
+    /// return the bits of the component types the entity has, or
+    /// None if it is not live.
+    pub fn mask(&self, gi: GenIndex<I, G>) -> Option<&BitSet> {
+        if !self.entities.is_live(gi) {
+            return None;
+        }
+        static EMPTY: BitSet = BitSet::new();
+        Some(self.masks.get(gi).unwrap_or(&EMPTY))
+    }
+
+    /// return the bit standing for component type `T` in the masks,
+    /// if any component of the type was ever stored.
+    pub fn bit<T: Component>(&self) -> Option<usize> {
+        self.bits.get(&TypeId::of::<T>()).copied()
+    }
+
+    /// Tag a live entity with the marker type `T`, returning
+    /// whether it was not tagged with it yet. Fails as
+    /// `GenIndexEntitySet::delete_index` would if the entity is not
+    /// live.
+    pub fn add_tag<T: Component>(&mut self, gi: GenIndex<I, G>) -> Result<bool, GenIndexError> {
+        self.entities.check_live(gi)?;
+        Ok(self.tags.entry(TypeId::of::<T>()).or_default().insert(Self::slot(gi)))
+    }
+
+    /// Take the tag `T` off a live entity, returning whether it was
+    /// tagged with it.
+    pub fn remove_tag<T: Component>(&mut self, gi: GenIndex<I, G>) -> bool {
+        if !self.entities.is_live(gi) {
+            return false;
+        }
+        self.tags.get_mut(&TypeId::of::<T>()).is_some_and(|tagged| tagged.remove(Self::slot(gi)))
+    }
+
+    /// Check whether the entity is live, and tagged with `T`.
+    pub fn has_tag<T: Component>(&self, gi: GenIndex<I, G>) -> bool {
+        self.entities.is_live(gi)
+            && self.tags.get(&TypeId::of::<T>()).is_some_and(|tagged| tagged.contains(Self::slot(gi)))
+    }
+
+    /// return the slot numbers of the entities tagged with `T`.
+    pub fn tagged<T: Component>(&self) -> Option<&BitSet> {
+        self.tags.get(&TypeId::of::<T>())
+    }
+
+    /// return the slot numbers of the entities having every one of
+    /// the given component types, and live.
+    pub fn entities_with(&self, types: &[TypeId]) -> BitSet {
+        let mut found = self.entities.live_mask().clone();
+        for id in types {
+            match self.bits.get(id) {
+                Some(&bit) => found.intersect_with(&self.members[bit]),
+                None => found.clear(),
+            }
+        }
+        found
+    }
+
+    /// return the slot numbers of the entities having every one of
+    /// the given component types, and live, which pass the tag
+    /// filter `F`.
+    fn entities_filtered<F: TagFilter>(&self, types: &[TypeId]) -> BitSet {
+        let mut found = self.entities_with(types);
+        let (mut with, mut without) = (Vec::new(), Vec::new());
+        F::tags(&mut with, &mut without);
+        for id in &with {
+            match self.tags.get(id) {
+                Some(tagged) => found.intersect_with(tagged),
+                None => found.clear(),
+            }
+        }
+        for tagged in without.iter().filter_map(|id| self.tags.get(id)) {
+            found.difference_with(tagged);
+        }
+        found
+    }
+
+    fn bit_or_new<T: Component>(&mut self) -> usize {
+        let (types, members, changed) = (&mut self.types, &mut self.members, &mut self.changed);
+        *self.bits.entry(TypeId::of::<T>()).or_insert_with(|| {
+            types.push(TypeId::of::<T>());
+            members.push(BitSet::new());
+            changed.push(Vec::new());
+            types.len() - 1
+        })
+    }
+
+    /// stamp the component of the given type bit in the slot with
+    /// a new tick.
+    fn mark_changed(&mut self, bit: usize, slot: usize) {
+        self.tick += 1;
+        stamp(&mut self.changed[bit], slot, self.tick);
+    }
+
+    /// the slot number of a handle the set handed out.
+    fn slot(gi: GenIndex<I, G>) -> usize {
+        // the set only hands out indices it can keep a slot for
+        gi.get_index().to_usize().unwrap()
+    }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + NumCast + 'static,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> Registry<I, G> {
+
+    /// iterate over every entity having all the components asked
+    /// for, along with them, in index order, or archetype by
+    /// archetype if the components are kept by archetype. Ask for
+    /// `&T` to read the component of type `T`, for `&mut T` to
+    /// modify it, and for several at once in a tuple:
+    ///
+    /// ```
+    /// # use gen_indices::*;
+    /// struct Position(f32);
+    /// struct Velocity(f32);
+    ///
+    /// let mut registry = Registry::<u32, u32>::new();
+    /// let ball = registry.create();
+    /// registry.insert(ball, Position(0.0)).unwrap();
+    /// registry.insert(ball, Velocity(2.0)).unwrap();
+    /// let wall = registry.create();
+    /// registry.insert(wall, Position(10.0)).unwrap();
+    ///
+    /// for (_, (velocity, position)) in registry.query::<(&Velocity, &mut Position)>() {
+    ///     position.0 += velocity.0;
+    /// }
+    /// assert_eq!(2.0, registry.get::<Position>(ball).unwrap().0);
+    /// assert_eq!(10.0, registry.get::<Position>(wall).unwrap().0);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if a component type is asked for mutably, and any
+    /// other time in the same query besides.
+    pub fn query<'a, Q: Query<'a, I, G> + 'a>(&'a mut self) -> impl Iterator<Item = (GenIndex<I, G>, Q::Item)> + 'a {
+        self.query_filtered::<Q, ()>()
+    }
+
+    /// Like `query`, but only finding the entities which pass the
+    /// tag filter `F` as well.
+    ///
+    /// ```
+    /// # use gen_indices::*;
+    /// struct Position(f32);
+    /// struct Frozen;
+    ///
+    /// let mut registry = Registry::<u32, u32>::new();
+    /// let ice = registry.spawn().with(Position(0.0)).build();
+    /// let water = registry.spawn().with(Position(0.0)).build();
+    /// registry.add_tag::<Frozen>(ice).unwrap();
+    ///
+    /// for (_, position) in registry.query_filtered::<&mut Position, Without<Frozen>>() {
+    ///     position.0 += 1.0;
+    /// }
+    /// assert_eq!(0.0, registry.get::<Position>(ice).unwrap().0);
+    /// assert_eq!(1.0, registry.get::<Position>(water).unwrap().0);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics as `query` does.
+    pub fn query_filtered<'a, Q: Query<'a, I, G> + 'a, F: TagFilter>(&'a mut self)
+        -> impl Iterator<Item = (GenIndex<I, G>, Q::Item)> + 'a {
+        let mut access = Vec::new();
+        Q::access(&mut access);
+        check_access(&access);
+        let types: Vec<_> = access.iter().map(|&(id, _)| id).collect();
+        let found = self.entities_filtered::<F>(&types);
+        // the types borrowed mutably are found in every entity
+        // found, so they all have bits
+        let stamped: Vec<usize> = access.iter()
+            .filter(|&&(_, mutable)| mutable)
+            .filter_map(|(id, _)| self.bits.get(id).copied())
+            .collect();
+        if !stamped.is_empty() {
+            self.tick += 1;
+        }
+        let tick = self.tick;
+
+        let (entities, changed) = (&self.entities, &mut self.changed);
+        // the registry is borrowed mutably for 'a, and the access
+        // is checked for overlapping borrows
+        let mut fetch = unsafe { Q::fetcher(&mut self.components) };
+        // with archetypes, the entities are found by going through
+        // the tables of those having all the types, row by row, and
+        // by slot otherwise, and when any entity will do, as the
+        // ones without components are in no table
+        let by_table = self.components.backend() == StorageBackend::Archetypes && !types.is_empty();
+        let mut tables = match self.components.archetypes_mut() {
+            Some(archetypes) if by_table => archetypes.iter_mut()
+                .filter(|archetype| !archetype.is_empty() && types.iter().all(|&id| archetype.has(id)))
+                .filter_map(|archetype| {
+                    let archetype: *mut Archetype<I, G> = archetype;
+                    Some((archetype, unsafe { Q::column(archetype)? }))
+                })
+                .collect(),
+            _ => Vec::new(),
+        }.into_iter();
+        let (by_slot, in_tables) = if by_table { (BitSet::new(), found) } else { (found, BitSet::new()) };
+
+        let slots = by_slot.into_iter().filter_map(move |slot| {
+            let gi = entities.slot_handle(cast(slot)?)?;
+            // every slot is only found once
+            let item = unsafe { Q::fetch(fetch.as_mut()?, gi)? };
+            Some((gi, slot, item))
+        });
+        let (mut table, mut row) = (None, 0);
+        let rows = std::iter::from_fn(move || loop {
+            if let Some((archetype, column)) = &mut table {
+                let archetype: *mut Archetype<I, G> = *archetype;
+                let entities = unsafe { (*archetype).entities() };
+                if let Some(&gi) = entities.get(row) {
+                    row += 1;
+                    let slot = Self::slot(gi);
+                    if in_tables.contains(slot) {
+                        // every row is only gone through once
+                        return Some((gi, slot, unsafe { Q::fetch_row(column, row - 1) }));
+                    }
+                    continue;
+                }
+            }
+            table = Some(tables.next()?);
+            row = 0;
+        });
+        slots.chain(rows).map(move |(gi, slot, item)| {
+            for &bit in &stamped {
+                stamp(&mut changed[bit], slot, tick);
+            }
+            (gi, item)
+        })
+    }
+
+    /// iterate over the entities whose component of type `T` was
+    /// changed after `tick`, along with it, in index order.
+    ///
+    /// ```
+    /// # use gen_indices::*;
+    /// struct Position(f32);
+    ///
+    /// let mut registry = Registry::<u32, u32>::new();
+    /// let still = registry.spawn().with(Position(0.0)).build();
+    /// let moving = registry.spawn().with(Position(0.0)).build();
+    /// let last = registry.tick();
+    ///
+    /// registry.get_mut::<Position>(moving).unwrap().0 += 1.0;
+    /// let changed: Vec<_> = registry.changed_since::<Position>(last).map(|(gi, _)| gi).collect();
+    /// assert_eq!(vec![moving], changed);
+    /// ```
+    pub fn changed_since<T: Component>(&self, tick: u64) -> impl Iterator<Item = (GenIndex<I, G>, &T)> + '_ {
+        self.bit::<T>().into_iter().flat_map(move |bit| {
+            let ticks = &self.changed[bit];
+            self.members[bit].iter()
+                .filter(move |&slot| ticks.get(slot).is_some_and(|&changed| changed > tick))
+                .filter_map(move |slot| {
+                    let gi = self.entities.slot_handle(cast(slot)?)?;
+                    Some((gi, self.components.get(gi)?))
+                })
+        })
+    }
+}
+
+/// set the tick of a slot, growing the ticks as need be.
+fn stamp(ticks: &mut Vec<u64>, slot: usize, tick: u64) {
+    if ticks.len() <= slot {
+        ticks.resize(slot + 1, 0);
+    }
+    ticks[slot] = tick;
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> Default for Registry<I, G> {
+    fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{With, Without};
+    use super::super::test_fixtures::{Health, Armor};
+
+    #[test]
+    fn test_registry() {
+        let mut registry = Registry::<u32, u32>::new();
+        let a = registry.create();
+        let b = registry.create();
+        assert_eq!(Some(&BitSet::new()), registry.mask(a));
+
+        registry.insert(a, Health(10)).unwrap();
+        registry.insert(a, Armor(1)).unwrap();
+        registry.insert(b, Armor(2)).unwrap();
+        assert_eq!(Some(0), registry.bit::<Health>());
+        assert_eq!(Some(1), registry.bit::<Armor>());
+        assert_eq!(vec![TypeId::of::<Health>(), TypeId::of::<Armor>()],
+                   registry.components_of(a).collect::<Vec<_>>());
+        assert!(!registry.has::<Health>(b));
+
+        assert_eq!(Some(Armor(1)), registry.remove(a));
+        assert_eq!(None, registry.remove::<Armor>(a));
+        assert!(!registry.has::<Armor>(a));
+        assert_eq!(vec![TypeId::of::<Health>()], registry.components_of(a).collect::<Vec<_>>());
+
+        assert_eq!(Ok(1), registry.destroy(a));
+        assert_eq!(None, registry.mask(a));
+        assert_eq!(0, registry.components_of(a).count());
+        assert_eq!(Err(GenIndexError::AlreadyDeleted), registry.insert(a, Health(1)));
+
+        // the slot comes back without the old components
+        let c = registry.create();
+        assert_eq!(a.get_index(), c.get_index());
+        assert!(!registry.has::<Health>(c));
+        assert_eq!(Err(GenIndexError::StaleGeneration), registry.insert(a, Health(1)));
+        assert_eq!(2, registry.len());
+    }
+
+    #[test]
+    fn test_query() {
+        let mut registry = Registry::<u32, u32>::new();
+        let a = registry.create();
+        let b = registry.create();
+        let c = registry.create();
+        registry.insert(a, Health(10)).unwrap();
+        registry.insert(a, Armor(1)).unwrap();
+        registry.insert(b, Health(20)).unwrap();
+        registry.insert(c, Health(30)).unwrap();
+        registry.insert(c, Armor(3)).unwrap();
+
+        for (_, (armor, health)) in registry.query::<(&Armor, &mut Health)>() {
+            health.0 += armor.0;
+        }
+        assert_eq!(vec![(a, &Health(11)), (b, &Health(20)), (c, &Health(33))],
+                   registry.query::<&Health>().collect::<Vec<_>>());
+        assert_eq!(vec![a, c], registry.query::<(&Armor, &Armor)>().map(|(gi, _)| gi).collect::<Vec<_>>());
+
+        // destroyed entities and removed components are not found
+        registry.destroy(a).unwrap();
+        registry.remove::<Armor>(c);
+        assert_eq!(0, registry.query::<(&Health, &Armor)>().count());
+        assert_eq!(vec![b, c], registry.query::<()>().map(|(gi, _)| gi).collect::<Vec<_>>());
+        assert_eq!(0, registry.query::<&String>().count());
+
+        let d = registry.create();
+        registry.insert(d, Armor(4)).unwrap();
+        assert_eq!(vec![(d, (&mut Armor(4),))], registry.query::<(&mut Armor,)>().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_tags() {
+        struct Frozen;
+        struct Selected;
+
+        let mut registry = Registry::<u32, u32>::new();
+        let a = registry.spawn().with(Health(1)).build();
+        let b = registry.spawn().with(Health(2)).build();
+        let c = registry.spawn().with(Health(3)).build();
+        assert_eq!(Ok(true), registry.add_tag::<Frozen>(a));
+        assert_eq!(Ok(false), registry.add_tag::<Frozen>(a));
+        registry.add_tag::<Frozen>(b).unwrap();
+        registry.add_tag::<Selected>(b).unwrap();
+        assert!(registry.has_tag::<Frozen>(a));
+        assert!(!registry.has::<Frozen>(a));
+        assert_eq!(2, registry.tagged::<Frozen>().unwrap().len());
+
+        fn found<F: TagFilter>(registry: &mut Registry<u32, u32>) -> Vec<GenIndex<u32, u32>> {
+            registry.query_filtered::<&Health, F>().map(|(gi, _)| gi).collect()
+        }
+        assert_eq!(vec![a, b], found::<With<Frozen>>(&mut registry));
+        assert_eq!(vec![c], found::<Without<Frozen>>(&mut registry));
+        assert_eq!(vec![a], found::<(With<Frozen>, Without<Selected>)>(&mut registry));
+        assert_eq!(0, registry.query_filtered::<(), With<Health>>().count());
+
+        assert!(registry.remove_tag::<Frozen>(a));
+        assert!(!registry.remove_tag::<Frozen>(a));
+        assert!(!registry.has_tag::<Frozen>(a));
+
+        // tags go away with the entity, and do not come back with its slot
+        registry.destroy(b).unwrap();
+        assert!(!registry.has_tag::<Selected>(b));
+        assert!(!registry.remove_tag::<Selected>(b));
+        assert_eq!(Err(GenIndexError::AlreadyDeleted), registry.add_tag::<Selected>(b));
+        let d = registry.create();
+        assert_eq!(b.get_index(), d.get_index());
+        assert!(!registry.has_tag::<Selected>(d));
+    }
+
+    #[test]
+    fn test_query_archetypes() {
+        struct Frozen;
+        let mut registry = Registry::<u32, u32>::with_backend(GenIndexEntitySet::new_unsync(), StorageBackend::Archetypes);
+        assert_eq!(StorageBackend::Archetypes, registry.components().backend());
+        let a = registry.spawn().with(Health(1)).with(Armor(1)).build();
+        let b = registry.spawn().with(Health(2)).build();
+        let c = registry.spawn().with(Armor(3)).with(Health(3)).build();
+        let d = registry.create();
+        registry.add_tag::<Frozen>(c).unwrap();
+
+        let start = registry.tick();
+        for (_, (health, armor)) in registry.query::<(&mut Health, &Armor)>() {
+            health.0 += armor.0;
+        }
+        assert_eq!(vec![(a, &Health(2)), (c, &Health(6))],
+                   registry.changed_since::<Health>(start).collect::<Vec<_>>());
+        let mut found: Vec<_> = registry.query_filtered::<&Health, Without<Frozen>>().map(|(gi, h)| (gi, h.0)).collect();
+        found.sort();
+        assert_eq!(vec![(a, 2), (b, 2)], found);
+        assert_eq!(4, registry.query::<()>().count());
+
+        // moving between tables, and away
+        registry.remove::<Armor>(a);
+        registry.destroy(b).unwrap();
+        registry.insert(d, Armor(4)).unwrap();
+        let mut found: Vec<_> = registry.query::<&Armor>().map(|(gi, _)| gi).collect();
+        found.sort();
+        assert_eq!(vec![c, d], found);
+        assert_eq!(vec![a, c], registry.query::<&Health>().map(|(gi, _)| gi).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_clone_entity() {
+        #[derive(Debug, PartialEq, Clone)]
+        struct Position(i32);
+        struct Selected;
+
+        let mut registry = Registry::<u32, u32>::new();
+        registry.register_clone::<Position>();
+        assert!(registry.components().is_cloneable::<Position>());
+        assert!(!registry.components().is_cloneable::<Health>());
+        let a = registry.spawn().with(Position(3)).with(Health(1)).build();
+        registry.add_tag::<Selected>(a).unwrap();
+
+        let start = registry.tick();
+        let b = registry.clone_entity(a);
+        assert_ne!(a, b);
+        assert_eq!(Some(&Position(3)), registry.get(b));
+        assert!(!registry.has::<Health>(b));
+        assert!(registry.has_tag::<Selected>(b));
+        assert_eq!(vec![TypeId::of::<Position>()], registry.components_of(b).collect::<Vec<_>>());
+        assert_eq!(vec![b], registry.changed_since::<Position>(start).map(|(gi, _)| gi).collect::<Vec<_>>());
+
+        // the copy is its own
+        registry.get_mut::<Position>(b).unwrap().0 = 4;
+        assert_eq!(Some(&Position(3)), registry.get(a));
+
+        registry.destroy(a).unwrap();
+        assert_eq!(Err(GenIndexError::AlreadyDeleted), registry.try_clone_entity(a));
+        assert_eq!(1, registry.len());
+    }
+
+    #[test]
+    fn test_change_ticks() {
+        let mut registry = Registry::<u32, u32>::new();
+        let a = registry.spawn().with(Health(1)).with(Armor(1)).build();
+        let b = registry.spawn().with(Health(2)).build();
+        let start = registry.tick();
+        assert_eq!(0, registry.changed_since::<Health>(start).count());
+        assert_eq!(2, registry.changed_since::<Health>(0).count());
+
+        // reading does not count as a change
+        registry.query::<&Health>().count();
+        assert_eq!(start, registry.tick());
+
+        registry.get_mut::<Health>(b).unwrap().0 += 1;
+        assert_eq!(Some(registry.tick()), registry.changed_at::<Health>(b));
+        assert_eq!(vec![(b, &Health(3))], registry.changed_since::<Health>(start).collect::<Vec<_>>());
+        assert_eq!(0, registry.changed_since::<Armor>(start).count());
+
+        // a mutable query stamps only the entities it went through
+        let before_query = registry.tick();
+        registry.query::<(&mut Health, &Armor)>().count();
+        let changed: Vec<_> = registry.changed_since::<Health>(before_query).map(|(gi, _)| gi).collect();
+        assert_eq!(vec![a], changed);
+        assert_eq!(0, registry.changed_since::<Armor>(before_query).count());
+
+        // removed components, and those of destroyed entities, are not found
+        registry.destroy(a).unwrap();
+        registry.remove::<Health>(b);
+        assert_eq!(0, registry.changed_since::<Health>(0).count());
+        assert_eq!(None, registry.changed_at::<Health>(b));
+        assert_eq!(None, registry.changed_since::<String>(0).next());
+    }
+
+    #[test]
+    #[should_panic(expected = "borrowed mutably")]
+    fn test_query_aliasing() {
+        let mut registry = Registry::<u32, u32>::new();
+        let a = registry.create();
+        registry.insert(a, Health(10)).unwrap();
+        registry.query::<(&Health, &mut Health)>().count();
+    }
+}
@@ -0,0 +1,335 @@
+//! Typed links between entities, any number each way.
+
+use std::{any::TypeId,
+          collections::HashMap,
+          ops::AddAssign};
+
+use num::{Num, Bounded, ToPrimitive};
+
+use super::{Component, GenIndex, GenIndexEntitySet, GenIndexError, SecondaryMap};
+
+/// Relations
+///
+/// Links the entities handed out by some set to each other, from a
+/// source to a target, each link of a kind named by a marker type:
+/// `struct Targets;` or `struct OwnedBy;`, say. An entity may have
+/// any number of links of every kind, both ways, and they can be
+/// looked up from either end, as kept in the order they were made.
+///
+/// Links are only ever made between live entities, and a handle
+/// only ever finds the links made for that very handle, as in a
+/// SecondaryMap. The relations do not know when entities are
+/// deleted, however: call `remove_entity` on deleting one, or find
+/// the links left dangling with `dangling`, and drop them with
+/// `prune`.
+///
+/// The index and generation types default to `u32`.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// struct Targets;
+///
+/// let mut entities = GenIndexEntitySet::<u32, u32>::new_unsync();
+/// let mut relations = Relations::new();
+/// let [turret, tower, orc, troll] = [0; 4].map(|_| entities.next_index());
+/// relations.add::<Targets>(&entities, turret, orc).unwrap();
+/// relations.add::<Targets>(&entities, turret, troll).unwrap();
+/// relations.add::<Targets>(&entities, tower, orc).unwrap();
+///
+/// assert_eq!(&[orc, troll], relations.targets::<Targets>(turret));
+/// assert_eq!(&[turret, tower], relations.sources::<Targets>(orc));
+///
+/// entities.delete_index(orc).unwrap();
+/// assert_eq!(vec![(turret, orc), (tower, orc)], relations.dangling::<Targets>(&entities));
+/// assert_eq!(2, relations.prune(&entities));
+/// assert_eq!(&[troll], relations.targets::<Targets>(turret));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Relations<I: Num + AddAssign + Copy + ToPrimitive + Bounded = u32,
+                     G: Num + AddAssign + Copy + Bounded + PartialOrd = u32> {
+    kinds: HashMap<TypeId, Links<I, G>>,
+}
+
+/// the links of one kind.
+#[derive(Debug, Clone, PartialEq)]
+struct Links<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+             G: Num + AddAssign + Copy + Bounded + PartialOrd> {
+    /// per source, its targets...
+    targets: SecondaryMap<Vec<GenIndex<I, G>>, I, G>,
+    /// ...and per target, its sources.
+    sources: SecondaryMap<Vec<GenIndex<I, G>>, I, G>,
+    len: usize,
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> Links<I, G> {
+    fn new() -> Links<I, G> {
+        Links{targets: SecondaryMap::new(), sources: SecondaryMap::new(), len: 0}
+    }
+
+    fn remove(&mut self, source: GenIndex<I, G>, target: GenIndex<I, G>) -> bool {
+        let from = unlink(&mut self.targets, source, target);
+        let to = unlink(&mut self.sources, target, source);
+        let removed = from || to;
+        if removed {
+            self.len -= 1;
+        }
+        removed
+    }
+
+    fn remove_entity(&mut self, gi: GenIndex<I, G>) -> usize {
+        let mut removed = 0;
+        for target in self.targets.get(gi).cloned().unwrap_or_default() {
+            removed += self.remove(gi, target) as usize;
+        }
+        for source in self.sources.get(gi).cloned().unwrap_or_default() {
+            removed += self.remove(source, gi) as usize;
+        }
+        removed
+    }
+
+    /// drop the links of an older generation of the entity's index,
+    /// deleted without `remove_entity`, before the entity takes its
+    /// place in the maps.
+    fn evict(&mut self, gi: GenIndex<I, G>) {
+        let earlier: Vec<_> = vec![self.targets.occupant(gi), self.sources.occupant(gi)].into_iter()
+            .flatten()
+            .map(|(previous, _)| previous)
+            .filter(|&previous| previous != gi)
+            .collect();
+        for previous in earlier {
+            self.remove_entity(previous);
+        }
+    }
+
+    fn dangling(&self, entities: &GenIndexEntitySet<I, G>) -> Vec<(GenIndex<I, G>, GenIndex<I, G>)> {
+        let is_dangling = |&(source, target): &(GenIndex<I, G>, GenIndex<I, G>)| {
+            !entities.is_live(source) || !entities.is_live(target)
+        };
+        let mut dangling: Vec<_> = self.targets.iter()
+            .flat_map(|(source, targets)| targets.iter().map(move |&target| (source, target)))
+            .filter(is_dangling)
+            .collect();
+        // a link only its target still knows of
+        let from_targets: Vec<_> = self.sources.iter()
+            .flat_map(|(target, sources)| sources.iter().map(move |&source| (source, target)))
+            .filter(|link| is_dangling(link) && !dangling.contains(link))
+            .collect();
+        dangling.extend(from_targets);
+        dangling
+    }
+}
+
+/// take `to` out of the links of `from`, returning whether it was in.
+fn unlink<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+          G: Num + AddAssign + Copy + Bounded + PartialOrd>(
+    links: &mut SecondaryMap<Vec<GenIndex<I, G>>, I, G>, from: GenIndex<I, G>, to: GenIndex<I, G>) -> bool {
+    let list = match links.get_mut(from) {
+        Some(list) => list,
+        None => return false,
+    };
+    match list.iter().position(|&gi| gi == to) {
+        Some(position) => list.remove(position),
+        None => return false,
+    };
+    if list.is_empty() {
+        links.remove(from);
+    }
+    true
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> Relations<I, G> {
+
+    /// Create a new set of relations, without any links.
+    pub fn new() -> Relations<I, G> {
+        Relations{kinds: HashMap::new()}
+    }
+
+    /// Link `source` to `target` with the kind `R`, returning
+    /// whether they were not linked so yet. Both must be live in
+    /// `entities`, failing as `GenIndexEntitySet::delete_index`
+    /// would otherwise.
+    pub fn add<R: Component>(&mut self, entities: &GenIndexEntitySet<I, G>,
+                             source: GenIndex<I, G>, target: GenIndex<I, G>) -> Result<bool, GenIndexError> {
+        entities.check_live(source)?;
+        entities.check_live(target)?;
+        let links = self.kinds.entry(TypeId::of::<R>()).or_insert_with(Links::new);
+        links.evict(source);
+        links.evict(target);
+        let targets = links.targets.entry(source)?.or_default();
+        if targets.contains(&target) {
+            return Ok(false);
+        }
+        targets.push(target);
+        links.sources.entry(target)?.or_default().push(source);
+        links.len += 1;
+        Ok(true)
+    }
+
+    /// Drop the link of kind `R` from `source` to `target`,
+    /// returning whether there was one.
+    pub fn remove<R: Component>(&mut self, source: GenIndex<I, G>, target: GenIndex<I, G>) -> bool {
+        self.kinds.get_mut(&TypeId::of::<R>()).is_some_and(|links| links.remove(source, target))
+    }
+
+    /// Check whether `source` is linked to `target` with the kind
+    /// `R`.
+    pub fn has<R: Component>(&self, source: GenIndex<I, G>, target: GenIndex<I, G>) -> bool {
+        self.targets::<R>(source).contains(&target)
+    }
+
+    /// return the entities `source` is linked to with the kind `R`,
+    /// in the order the links were made.
+    pub fn targets<R: Component>(&self, source: GenIndex<I, G>) -> &[GenIndex<I, G>] {
+        self.kinds.get(&TypeId::of::<R>())
+            .and_then(|links| links.targets.get(source))
+            .map_or(&[], |targets| targets)
+    }
+
+    /// return the entities linked to `target` with the kind `R`, in
+    /// the order the links were made.
+    pub fn sources<R: Component>(&self, target: GenIndex<I, G>) -> &[GenIndex<I, G>] {
+        self.kinds.get(&TypeId::of::<R>())
+            .and_then(|links| links.sources.get(target))
+            .map_or(&[], |sources| sources)
+    }
+
+    /// return the number of links of kind `R`.
+    pub fn len<R: Component>(&self) -> usize {
+        self.kinds.get(&TypeId::of::<R>()).map_or(0, |links| links.len)
+    }
+
+    /// return true if there are no links of any kind.
+    pub fn is_empty(&self) -> bool { self.kinds.values().all(|links| links.len == 0) }
+
+    /// Drop every link of every kind from and to an entity, on
+    /// deleting it, and return how many there were.
+    pub fn remove_entity(&mut self, gi: GenIndex<I, G>) -> usize {
+        self.kinds.values_mut().map(|links| links.remove_entity(gi)).sum()
+    }
+
+    /// return every link of kind `R` from or to an entity no longer
+    /// live in `entities`, as pairs of source and target, source by
+    /// source in index order, then those only their targets still
+    /// list.
+    pub fn dangling<R: Component>(&self, entities: &GenIndexEntitySet<I, G>) -> Vec<(GenIndex<I, G>, GenIndex<I, G>)> {
+        self.kinds.get(&TypeId::of::<R>()).map_or_else(Vec::new, |links| links.dangling(entities))
+    }
+
+    /// Drop every link of every kind from or to an entity no longer
+    /// live in `entities`, and return how many there were.
+    pub fn prune(&mut self, entities: &GenIndexEntitySet<I, G>) -> usize {
+        let mut removed = 0;
+        for links in self.kinds.values_mut() {
+            for (source, target) in links.dangling(entities) {
+                removed += links.remove(source, target) as usize;
+            }
+        }
+        removed
+    }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> Default for Relations<I, G> {
+    fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Targets;
+    struct OwnedBy;
+
+    #[test]
+    fn test_relations() {
+        let mut entities = GenIndexEntitySet::<u32, u32>::new_unsync();
+        let mut relations = Relations::new();
+        let idxs = entities.next_indices(4);
+        let (a, b, c, d) = (idxs[0], idxs[1], idxs[2], idxs[3]);
+        assert_eq!(Ok(true), relations.add::<Targets>(&entities, a, b));
+        assert_eq!(Ok(false), relations.add::<Targets>(&entities, a, b));
+        relations.add::<Targets>(&entities, a, c).unwrap();
+        relations.add::<Targets>(&entities, c, b).unwrap();
+        relations.add::<OwnedBy>(&entities, b, a).unwrap();
+        assert_eq!(3, relations.len::<Targets>());
+        assert!(relations.has::<Targets>(a, c));
+        assert!(!relations.has::<Targets>(c, a));
+        // the kinds are kept apart
+        assert!(!relations.has::<OwnedBy>(a, b));
+        assert_eq!(&[a, c], relations.sources::<Targets>(b));
+        assert_eq!(&[a], relations.targets::<OwnedBy>(b));
+
+        assert!(relations.remove::<Targets>(a, b));
+        assert!(!relations.remove::<Targets>(a, b));
+        assert!(!relations.remove::<OwnedBy>(a, c));
+        assert_eq!(&[c], relations.sources::<Targets>(b));
+        assert_eq!(&[c], relations.targets::<Targets>(a));
+
+        entities.delete_index(d).unwrap();
+        assert_eq!(Err(GenIndexError::AlreadyDeleted), relations.add::<Targets>(&entities, a, d));
+        assert_eq!(2, relations.remove_entity(b) + relations.remove_entity(d));
+        assert_eq!(1, relations.len::<Targets>());
+        assert_eq!(0, relations.len::<OwnedBy>());
+        assert!(!relations.is_empty());
+    }
+
+    #[test]
+    fn test_relations_dangling() {
+        let mut entities = GenIndexEntitySet::<u32, u32>::new_unsync();
+        let mut relations = Relations::new();
+        let idxs = entities.next_indices(3);
+        let (a, b, c) = (idxs[0], idxs[1], idxs[2]);
+        relations.add::<Targets>(&entities, a, b).unwrap();
+        relations.add::<Targets>(&entities, b, c).unwrap();
+        relations.add::<OwnedBy>(&entities, c, b).unwrap();
+        assert!(relations.dangling::<Targets>(&entities).is_empty());
+
+        entities.delete_index(b).unwrap();
+        assert_eq!(vec![(a, b), (b, c)], relations.dangling::<Targets>(&entities));
+        assert_eq!(vec![(c, b)], relations.dangling::<OwnedBy>(&entities));
+        assert_eq!(3, relations.prune(&entities));
+        assert!(relations.is_empty());
+
+        // the recycled slot starts out without links
+        let newcomer = entities.next_index();
+        assert_eq!(b.get_index(), newcomer.get_index());
+        assert!(relations.sources::<Targets>(newcomer).is_empty());
+    }
+
+    #[test]
+    fn test_relations_recycled_slot() {
+        let mut entities = GenIndexEntitySet::<u32, u32>::new_unsync();
+        let mut relations = Relations::new();
+        let idxs = entities.next_indices(3);
+        let (a, b, c) = (idxs[0], idxs[1], idxs[2]);
+        relations.add::<Targets>(&entities, a, c).unwrap();
+        relations.add::<Targets>(&entities, b, c).unwrap();
+        entities.delete_index(b).unwrap();
+
+        // linking the newcomer in b's slot drops b's links, unpruned
+        let newcomer = entities.next_index();
+        assert_eq!(b.get_index(), newcomer.get_index());
+        relations.add::<Targets>(&entities, newcomer, a).unwrap();
+        assert_eq!(&[a], relations.sources::<Targets>(c));
+        assert_eq!(&[newcomer], relations.sources::<Targets>(a));
+        assert_eq!(2, relations.len::<Targets>());
+        assert!(relations.dangling::<Targets>(&entities).is_empty());
+
+        // and as a target
+        entities.delete_index(a).unwrap();
+        let late = entities.next_index();
+        assert_eq!(a.get_index(), late.get_index());
+        relations.add::<Targets>(&entities, c, late).unwrap();
+        assert!(relations.targets::<Targets>(newcomer).is_empty());
+        assert!(relations.targets::<Targets>(a).is_empty());
+        assert_eq!(1, relations.len::<Targets>());
+        assert_eq!(0, relations.prune(&entities));
+    }
+}
@@ -0,0 +1,123 @@
+//! Single values of any number of types, next to the entities.
+
+use std::{any::Any,
+          any::TypeId,
+          collections::HashMap,
+          fmt};
+
+use super::Component;
+
+/// Resources
+///
+/// Holds at most one value of every type, looked up by the type, for
+/// what there is only one of in a world, and belongs to no entity:
+/// the time, the input state, the settings. Any Component type can
+/// be a resource.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// struct Time { elapsed: f32 }
+///
+/// let mut resources = Resources::new();
+/// resources.insert(Time { elapsed: 0.0 });
+/// resources.get_mut::<Time>().unwrap().elapsed += 0.5;
+/// assert_eq!(0.5, resources.get::<Time>().unwrap().elapsed);
+/// assert!(!resources.contains::<String>());
+/// ```
+#[derive(Default)]
+pub struct Resources {
+    values: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl Resources {
+
+    /// Create a new container, without any resources.
+    pub fn new() -> Resources {
+        Resources{values: HashMap::new()}
+    }
+
+    /// Store the resource of type `T`, returning the one it
+    /// replaces, if any.
+    pub fn insert<T: Component>(&mut self, value: T) -> Option<T> {
+        self.values.insert(TypeId::of::<T>(), Box::new(value))
+            // values are only ever stored under their own type's id
+            .map(|old| *old.downcast().unwrap())
+    }
+
+    /// return the resource of type `T`, if there is one.
+    pub fn get<T: Component>(&self) -> Option<&T> {
+        self.values.get(&TypeId::of::<T>())?.downcast_ref()
+    }
+
+    /// return the resource of type `T` for modification, if there
+    /// is one.
+    pub fn get_mut<T: Component>(&mut self) -> Option<&mut T> {
+        self.values.get_mut(&TypeId::of::<T>())?.downcast_mut()
+    }
+
+    /// return the resource of type `T` for modification, storing
+    /// the one made by `f` first if there is none.
+    pub fn get_or_insert_with<T: Component, F: FnOnce() -> T>(&mut self, f: F) -> &mut T {
+        self.values.entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(f()))
+            .downcast_mut()
+            .unwrap()
+    }
+
+    /// Remove the resource of type `T`, and return it.
+    pub fn remove<T: Component>(&mut self) -> Option<T> {
+        self.values.remove(&TypeId::of::<T>()).map(|old| *old.downcast().unwrap())
+    }
+
+    /// Check whether there is a resource of type `T`.
+    pub fn contains<T: Component>(&self) -> bool {
+        self.values.contains_key(&TypeId::of::<T>())
+    }
+
+    /// return the number of resources stored.
+    pub fn len(&self) -> usize { self.values.len() }
+
+    /// return true if there are no resources.
+    pub fn is_empty(&self) -> bool { self.values.is_empty() }
+}
+
+impl fmt::Debug for Resources {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Resources").field("types", &self.values.len()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Time(u32);
+    #[derive(Debug, PartialEq, Default)]
+    struct Score(u32);
+
+    #[test]
+    fn test_resources() {
+        let mut resources = Resources::new();
+        assert!(resources.is_empty());
+        assert_eq!(None, resources.insert(Time(1)));
+        assert_eq!(Some(Time(1)), resources.insert(Time(2)));
+        assert_eq!(Some(&Time(2)), resources.get());
+        assert_eq!(None, resources.get::<Score>());
+
+        resources.get_or_insert_with(Score::default).0 += 5;
+        resources.get_or_insert_with(Score::default).0 += 5;
+        assert_eq!(Some(&Score(10)), resources.get());
+        assert_eq!(2, resources.len());
+
+        assert_eq!(Some(Time(2)), resources.remove());
+        assert_eq!(None, resources.remove::<Time>());
+        assert!(!resources.contains::<Time>());
+        assert!(resources.contains::<Score>());
+    }
+}
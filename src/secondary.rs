@@ -0,0 +1,697 @@
+//! Storing per-entity data for handles allocated elsewhere.
+
+use std::{collections::hash_map::Entry,
+          hash::Hash,
+          marker::PhantomData,
+          ops::AddAssign};
+
+use num::{Num, Bounded, ToPrimitive, zero, one};
+
+use super::{GenIndex, GenIndexError, GenIndexMap};
+
+/// SecondaryMap
+///
+/// Stores a value for handles handed out by some other set or
+/// arena, say one map per component type, all keyed by the same
+/// entity handles.
+///
+/// The map keeps the generation of every handle it stores a value
+/// for. A handle only finds the value stored under that very
+/// handle, never one stored under an older or newer generation of
+/// the same index, and a value stored under an older generation is
+/// simply replaced when a newer one is inserted. Values stored for
+/// entities that have since been deleted stay around until removed
+/// or replaced, however; the map has no way of knowing about the
+/// deletion.
+///
+/// The index and generation types default to `u32`.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// let mut entities = GenIndexEntitySet::<u32, u32>::new_unsync();
+/// let mut positions = SecondaryMap::new();
+///
+/// let player = entities.next_index();
+/// positions.insert(player, (0.0, 0.0)).unwrap();
+/// assert_eq!(Some(&(0.0, 0.0)), positions.get(player));
+///
+/// entities.delete_index(player).unwrap();
+/// let enemy = entities.next_index();
+/// // the same slot, but the player's position is not the enemy's
+/// assert_eq!(None, positions.get(enemy));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SecondaryMap<T,
+                        I: Num + AddAssign + Copy + ToPrimitive + Bounded = u32,
+                        G: Num + AddAssign + Copy + Bounded + PartialOrd = u32> {
+    /// per index, the generation the value was stored under.
+    slots: Vec<Option<(G, T)>>,
+    len: usize,
+    index: PhantomData<fn() -> I>,
+}
+
+impl<T,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> SecondaryMap<T, I, G> {
+
+    /// Create a new, empty map.
+    pub fn new() -> SecondaryMap<T, I, G> {
+        SecondaryMap{slots: Vec::new(), len: 0, index: PhantomData}
+    }
+
+    /// Store a value under the given handle, returning the value
+    /// previously stored under the same handle, if any. A value
+    /// stored under an older generation of the index is dropped.
+    /// Fails with `StaleGeneration`, storing nothing, if a newer
+    /// generation of the index already has a value.
+    pub fn insert(&mut self, gi: GenIndex<I, G>, value: T) -> Result<Option<T>, GenIndexError> {
+        let slot = gi.get_index().to_usize().ok_or(GenIndexError::UnknownIndex)?;
+        if slot >= self.slots.len() {
+            self.slots.resize_with(slot + 1, || None);
+        }
+        let entry = &mut self.slots[slot];
+        match entry {
+            Some((generation, _)) if *generation > gi.get_generation() => {
+                Err(GenIndexError::StaleGeneration)
+            },
+            Some((generation, old)) if *generation == gi.get_generation() => {
+                Ok(Some(std::mem::replace(old, value)))
+            },
+            _ => {
+                if entry.is_none() {
+                    self.len += 1;
+                }
+                *entry = Some((gi.get_generation(), value));
+                Ok(None)
+            },
+        }
+    }
+
+    /// return the value stored under the given handle, if any.
+    pub fn get(&self, gi: GenIndex<I, G>) -> Option<&T> {
+        match self.slots.get(gi.get_index().to_usize()?)? {
+            Some((generation, value)) if *generation == gi.get_generation() => Some(value),
+            _ => None,
+        }
+    }
+
+    /// return the value stored under the given handle for
+    /// modification, if any.
+    pub fn get_mut(&mut self, gi: GenIndex<I, G>) -> Option<&mut T> {
+        match self.slots.get_mut(gi.get_index().to_usize()?)? {
+            Some((generation, value)) if *generation == gi.get_generation() => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Remove the value stored under the given handle, and return
+    /// it. Values stored under other generations are left alone.
+    pub fn remove(&mut self, gi: GenIndex<I, G>) -> Option<T> {
+        let entry = self.slots.get_mut(gi.get_index().to_usize()?)?;
+        match entry {
+            Some((generation, _)) if *generation == gi.get_generation() => {
+                self.len -= 1;
+                entry.take().map(|(_, value)| value)
+            },
+            _ => None,
+        }
+    }
+
+    /// Look up the given handle for inserting or updating its value
+    /// in place. Fails with `StaleGeneration` if a newer generation
+    /// of the index already has a value, as `insert` would.
+    ///
+    /// ```
+    /// # use gen_indices::*;
+    /// let mut hits = SecondaryMap::<u32>::new();
+    /// let gi = GenIndex::new(3, 0);
+    /// *hits.entry(gi).unwrap().or_insert(0) += 1;
+    /// *hits.entry(gi).unwrap().or_insert(0) += 1;
+    /// assert_eq!(Some(&2), hits.get(gi));
+    /// ```
+    pub fn entry(&mut self, gi: GenIndex<I, G>) -> Result<SecondaryEntry<'_, T, I, G>, GenIndexError> {
+        let slot = gi.get_index().to_usize().ok_or(GenIndexError::UnknownIndex)?;
+        if slot >= self.slots.len() {
+            self.slots.resize_with(slot + 1, || None);
+        }
+        let entry = &mut self.slots[slot];
+        let place = Place::Dense{slot: entry, len: &mut self.len};
+        match entry_generation(&place) {
+            Some(generation) if generation > gi.get_generation() => Err(GenIndexError::StaleGeneration),
+            Some(generation) if generation == gi.get_generation() => {
+                Ok(SecondaryEntry::Occupied(OccupiedSecondaryEntry{gi, place}))
+            },
+            _ => Ok(SecondaryEntry::Vacant(VacantSecondaryEntry{gi, place})),
+        }
+    }
+
+    /// Check whether a value is stored under the given handle.
+    pub fn contains_key(&self, gi: GenIndex<I, G>) -> bool { self.get(gi).is_some() }
+
+    /// return the value stored in the slot of the given handle,
+    /// whatever its generation, with the handle it is stored under.
+    pub(crate) fn occupant(&self, gi: GenIndex<I, G>) -> Option<(GenIndex<I, G>, &T)> {
+        match self.slots.get(gi.get_index().to_usize()?)? {
+            Some((generation, value)) => Some((GenIndex::new(gi.get_index(), *generation), value)),
+            None => None,
+        }
+    }
+
+    /// return the number of values stored.
+    pub fn len(&self) -> usize { self.len }
+
+    /// return true if no values are stored.
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    /// iterate over every value, with the handle it is stored
+    /// under, in index order.
+    pub fn iter(&self) -> impl Iterator<Item = (GenIndex<I, G>, &T)> + '_ {
+        let mut index: I = zero();
+        self.slots.iter().filter_map(move |slot| {
+            let gi = index;
+            index += one();
+            slot.as_ref().map(|(generation, value)| (GenIndex::new(gi, *generation), value))
+        })
+    }
+
+    /// iterate over every value for modification, with the handle it
+    /// is stored under, in index order.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (GenIndex<I, G>, &mut T)> + '_ {
+        let mut index: I = zero();
+        self.slots.iter_mut().filter_map(move |slot| {
+            let gi = index;
+            index += one();
+            slot.as_mut().map(|(generation, value)| (GenIndex::new(gi, *generation), value))
+        })
+    }
+
+    /// iterate over the handle of every value, in index order.
+    pub fn keys(&self) -> impl Iterator<Item = GenIndex<I, G>> + '_ {
+        self.iter().map(|(gi, _)| gi)
+    }
+
+    /// iterate over every value, without the handles, in index
+    /// order.
+    pub fn values(&self) -> impl Iterator<Item = &T> + '_ {
+        self.slots.iter().filter_map(|slot| slot.as_ref().map(|(_, value)| value))
+    }
+
+    /// iterate over every value for modification, without the
+    /// handles, in index order.
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut T> + '_ {
+        self.slots.iter_mut().filter_map(|slot| slot.as_mut().map(|(_, value)| value))
+    }
+}
+
+impl<T,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> Default for SecondaryMap<T, I, G> {
+    fn default() -> Self { Self::new() }
+}
+
+/// SparseSecondaryMap
+///
+/// Like SecondaryMap, with the same rules for generations, but
+/// keeping its values in a hash map rather than a vector with a
+/// slot for every index. Meant for data which only a small
+/// fraction of entities ever carries, where most slots of a
+/// SecondaryMap would stand empty.
+///
+/// The index and generation types default to `u32`.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// let mut entities = GenIndexEntitySet::<u32, u32>::new_unsync();
+/// let mut bosses = SparseSecondaryMap::new();
+///
+/// let mooks = entities.next_indices(1000);
+/// let boss = entities.next_index();
+/// bosses.insert(boss, "Big Bad").unwrap();
+/// assert_eq!(None, bosses.get(mooks[0]));
+/// assert_eq!(1, bosses.len());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SparseSecondaryMap<T,
+                              I: Num + AddAssign + Copy + ToPrimitive + Bounded + Hash + Eq = u32,
+                              G: Num + AddAssign + Copy + Bounded + PartialOrd = u32> {
+    /// per index, the generation the value was stored under.
+    slots: GenIndexMap<I, (G, T)>,
+}
+
+impl<T,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded + Hash + Eq,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> SparseSecondaryMap<T, I, G> {
+
+    /// Create a new, empty map.
+    pub fn new() -> SparseSecondaryMap<T, I, G> {
+        SparseSecondaryMap{slots: GenIndexMap::default()}
+    }
+
+    /// Store a value under the given handle, as in
+    /// `SecondaryMap::insert`.
+    pub fn insert(&mut self, gi: GenIndex<I, G>, value: T) -> Result<Option<T>, GenIndexError> {
+        match self.slots.entry(gi.get_index()) {
+            Entry::Vacant(entry) => {
+                entry.insert((gi.get_generation(), value));
+                Ok(None)
+            },
+            Entry::Occupied(mut entry) => {
+                let (generation, old) = entry.get_mut();
+                if *generation > gi.get_generation() {
+                    Err(GenIndexError::StaleGeneration)
+                } else if *generation == gi.get_generation() {
+                    Ok(Some(std::mem::replace(old, value)))
+                } else {
+                    entry.insert((gi.get_generation(), value));
+                    Ok(None)
+                }
+            },
+        }
+    }
+
+    /// return the value stored under the given handle, if any.
+    pub fn get(&self, gi: GenIndex<I, G>) -> Option<&T> {
+        match self.slots.get(&gi.get_index()) {
+            Some((generation, value)) if *generation == gi.get_generation() => Some(value),
+            _ => None,
+        }
+    }
+
+    /// return the value stored under the given handle for
+    /// modification, if any.
+    pub fn get_mut(&mut self, gi: GenIndex<I, G>) -> Option<&mut T> {
+        match self.slots.get_mut(&gi.get_index()) {
+            Some((generation, value)) if *generation == gi.get_generation() => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Remove the value stored under the given handle, and return
+    /// it. Values stored under other generations are left alone.
+    pub fn remove(&mut self, gi: GenIndex<I, G>) -> Option<T> {
+        match self.slots.entry(gi.get_index()) {
+            Entry::Occupied(entry) if entry.get().0 == gi.get_generation() => {
+                Some(entry.remove().1)
+            },
+            _ => None,
+        }
+    }
+
+    /// Look up the given handle for inserting or updating its value
+    /// in place, as `SecondaryMap::entry`.
+    pub fn entry(&mut self, gi: GenIndex<I, G>) -> Result<SecondaryEntry<'_, T, I, G>, GenIndexError> {
+        let place = Place::Sparse(self.slots.entry(gi.get_index()));
+        match entry_generation(&place) {
+            Some(generation) if generation > gi.get_generation() => Err(GenIndexError::StaleGeneration),
+            Some(generation) if generation == gi.get_generation() => {
+                Ok(SecondaryEntry::Occupied(OccupiedSecondaryEntry{gi, place}))
+            },
+            _ => Ok(SecondaryEntry::Vacant(VacantSecondaryEntry{gi, place})),
+        }
+    }
+
+    /// Check whether a value is stored under the given handle.
+    pub fn contains_key(&self, gi: GenIndex<I, G>) -> bool { self.get(gi).is_some() }
+
+    /// return the number of values stored.
+    pub fn len(&self) -> usize { self.slots.len() }
+
+    /// return true if no values are stored.
+    pub fn is_empty(&self) -> bool { self.slots.is_empty() }
+
+    /// iterate over every value, with the handle it is stored
+    /// under, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (GenIndex<I, G>, &T)> + '_ {
+        self.slots.iter().map(|(&index, (generation, value))| (GenIndex::new(index, *generation), value))
+    }
+
+    /// iterate over every value for modification, with the handle it
+    /// is stored under, in no particular order.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (GenIndex<I, G>, &mut T)> + '_ {
+        self.slots.iter_mut().map(|(&index, (generation, value))| (GenIndex::new(index, *generation), value))
+    }
+
+    /// iterate over the handle of every value, in no particular
+    /// order.
+    pub fn keys(&self) -> impl Iterator<Item = GenIndex<I, G>> + '_ {
+        self.iter().map(|(gi, _)| gi)
+    }
+
+    /// iterate over every value, without the handles, in no
+    /// particular order.
+    pub fn values(&self) -> impl Iterator<Item = &T> + '_ {
+        self.slots.values().map(|(_, value)| value)
+    }
+
+    /// iterate over every value for modification, without the
+    /// handles, in no particular order.
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut T> + '_ {
+        self.slots.values_mut().map(|(_, value)| value)
+    }
+}
+
+impl<T,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded + Hash + Eq,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> Default for SparseSecondaryMap<T, I, G> {
+    fn default() -> Self { Self::new() }
+}
+
+/// SecondaryEntry
+///
+/// The value stored under one handle in a SecondaryMap or
+/// SparseSecondaryMap, or the lack of it, as returned by `entry`.
+pub enum SecondaryEntry<'a, T,
+                        I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+                        G: Num + AddAssign + Copy + Bounded + PartialOrd> {
+    /// a value is stored under the handle.
+    Occupied(OccupiedSecondaryEntry<'a, T, I, G>),
+    /// no value is stored under the handle, though one may be
+    /// stored under an older generation of its index.
+    Vacant(VacantSecondaryEntry<'a, T, I, G>),
+}
+
+/// OccupiedSecondaryEntry
+///
+/// A handle in a secondary map, and its value.
+pub struct OccupiedSecondaryEntry<'a, T,
+                                  I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+                                  G: Num + AddAssign + Copy + Bounded + PartialOrd> {
+    gi: GenIndex<I, G>,
+    place: Place<'a, T, I, G>,
+}
+
+/// VacantSecondaryEntry
+///
+/// A handle without a value in a secondary map.
+pub struct VacantSecondaryEntry<'a, T,
+                                I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+                                G: Num + AddAssign + Copy + Bounded + PartialOrd> {
+    gi: GenIndex<I, G>,
+    place: Place<'a, T, I, G>,
+}
+
+/// where an entry's value lives, in either kind of map.
+enum Place<'a, T, I, G> {
+    Dense{slot: &'a mut Option<(G, T)>, len: &'a mut usize},
+    Sparse(Entry<'a, I, (G, T)>),
+}
+
+/// the generation of the value at `place`, if there is one.
+fn entry_generation<T, I, G: Copy>(place: &Place<'_, T, I, G>) -> Option<G> {
+    match place {
+        Place::Dense{slot, ..} => slot.as_ref().map(|(generation, _)| *generation),
+        Place::Sparse(Entry::Occupied(entry)) => Some(entry.get().0),
+        Place::Sparse(Entry::Vacant(_)) => None,
+    }
+}
+
+impl<'a, T, I, G> Place<'a, T, I, G> {
+    /// the value at an occupied place.
+    fn value(&self) -> &T {
+        match self {
+            Place::Dense{slot: Some((_, value)), ..} => value,
+            Place::Sparse(Entry::Occupied(entry)) => &entry.get().1,
+            _ => unreachable!("entry is vacant"),
+        }
+    }
+
+    /// the value at an occupied place, for as long as the map was
+    /// borrowed for.
+    fn into_value(self) -> &'a mut T {
+        match self {
+            Place::Dense{slot: Some((_, value)), ..} => value,
+            Place::Sparse(Entry::Occupied(entry)) => &mut entry.into_mut().1,
+            _ => unreachable!("entry is vacant"),
+        }
+    }
+
+    fn value_mut(&mut self) -> &mut T {
+        match self {
+            Place::Dense{slot: Some((_, value)), ..} => value,
+            Place::Sparse(Entry::Occupied(entry)) => &mut entry.get_mut().1,
+            _ => unreachable!("entry is vacant"),
+        }
+    }
+
+    /// store a value at the place, replacing whatever was there.
+    fn fill(self, generation: G, value: T) -> &'a mut T {
+        match self {
+            Place::Dense{slot, len} => {
+                if slot.is_none() {
+                    *len += 1;
+                }
+                &mut slot.insert((generation, value)).1
+            },
+            Place::Sparse(Entry::Occupied(mut entry)) => {
+                entry.insert((generation, value));
+                &mut entry.into_mut().1
+            },
+            Place::Sparse(Entry::Vacant(entry)) => &mut entry.insert((generation, value)).1,
+        }
+    }
+
+    /// take the value out of an occupied place.
+    fn take(self) -> T {
+        match self {
+            Place::Dense{slot, len} => {
+                *len -= 1;
+                slot.take().map(|(_, value)| value).unwrap()
+            },
+            Place::Sparse(Entry::Occupied(entry)) => entry.remove().1,
+            Place::Sparse(Entry::Vacant(_)) => unreachable!("entry is vacant"),
+        }
+    }
+}
+
+impl<'a, T,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> SecondaryEntry<'a, T, I, G> {
+    /// return the handle of this entry.
+    pub fn key(&self) -> GenIndex<I, G> {
+        match self {
+            SecondaryEntry::Occupied(entry) => entry.key(),
+            SecondaryEntry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// return the value, inserting `value` first if there is none.
+    pub fn or_insert(self, value: T) -> &'a mut T {
+        self.or_insert_with(|| value)
+    }
+
+    /// return the value, inserting the result of `f` first if
+    /// there is none.
+    pub fn or_insert_with<F: FnOnce() -> T>(self, f: F) -> &'a mut T {
+        match self {
+            SecondaryEntry::Occupied(entry) => entry.into_mut(),
+            SecondaryEntry::Vacant(entry) => entry.insert(f()),
+        }
+    }
+
+    /// return the value, inserting the default first if there is
+    /// none.
+    pub fn or_default(self) -> &'a mut T where T: Default {
+        self.or_insert_with(T::default)
+    }
+
+    /// modify the value, if there is one.
+    pub fn and_modify<F: FnOnce(&mut T)>(mut self, f: F) -> Self {
+        if let SecondaryEntry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, T,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> OccupiedSecondaryEntry<'a, T, I, G> {
+    /// return the handle of this entry.
+    pub fn key(&self) -> GenIndex<I, G> { self.gi }
+
+    /// return the value.
+    pub fn get(&self) -> &T { self.place.value() }
+
+    /// return the value for modification.
+    pub fn get_mut(&mut self) -> &mut T { self.place.value_mut() }
+
+    /// return the value for modification, for as long as the map
+    /// was borrowed for.
+    pub fn into_mut(self) -> &'a mut T { self.place.into_value() }
+
+    /// replace the value, returning the old one.
+    pub fn insert(&mut self, value: T) -> T {
+        std::mem::replace(self.get_mut(), value)
+    }
+
+    /// remove the value, returning it.
+    pub fn remove(self) -> T { self.place.take() }
+}
+
+impl<'a, T,
+     I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> VacantSecondaryEntry<'a, T, I, G> {
+    /// return the handle of this entry.
+    pub fn key(&self) -> GenIndex<I, G> { self.gi }
+
+    /// store a value under the handle, dropping any value stored
+    /// under an older generation of its index.
+    pub fn insert(self, value: T) -> &'a mut T {
+        self.place.fill(self.gi.get_generation(), value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::GenIndexArena;
+
+    #[test]
+    fn test_secondary_map() {
+        let mut map = SecondaryMap::<&str>::new();
+        let old = GenIndex::new(3, 1);
+        let new = GenIndex::new(3, 2);
+
+        assert_eq!(Ok(None), map.insert(old, "old"));
+        assert_eq!(Ok(Some("old")), map.insert(old, "older"));
+        assert_eq!(1, map.len());
+
+        // a newer generation takes the slot over
+        assert_eq!(Ok(None), map.insert(new, "new"));
+        assert_eq!(None, map.get(old));
+        assert_eq!(Some(&"new"), map.get(new));
+        assert_eq!(1, map.len());
+
+        // but an older one cannot take it back
+        assert_eq!(Err(GenIndexError::StaleGeneration), map.insert(old, "stale"));
+        assert_eq!(None, map.remove(old));
+        assert_eq!(None, map.get_mut(old));
+
+        *map.get_mut(new).unwrap() = "newer";
+        assert_eq!(Some("newer"), map.remove(new));
+        assert!(map.is_empty());
+        assert!(!map.contains_key(GenIndex::new(99, 0)));
+    }
+
+    #[test]
+    fn test_secondary_map_with_arena() {
+        let mut names = GenIndexArena::<&str>::new();
+        let mut health = SecondaryMap::new();
+        let a = names.insert("a");
+        let b = names.insert("b");
+        health.insert(a, 10).unwrap();
+        health.insert(b, 20).unwrap();
+
+        names.remove(a).unwrap();
+        let c = names.insert("c");
+        assert_eq!(None, health.get(c));
+        health.insert(c, 30).unwrap();
+        assert_eq!(Some(&30), health.get(c));
+        assert_eq!(None, health.get(a));
+        assert_eq!(2, health.len());
+    }
+
+    #[test]
+    fn test_sparse_secondary_map() {
+        let mut map = SparseSecondaryMap::<&str>::new();
+        let old = GenIndex::new(3, 1);
+        let new = GenIndex::new(3, 2);
+
+        assert_eq!(Ok(None), map.insert(old, "old"));
+        assert_eq!(Ok(Some("old")), map.insert(old, "older"));
+        assert_eq!(Ok(None), map.insert(new, "new"));
+        assert_eq!(None, map.get(old));
+        assert_eq!(Some(&"new"), map.get(new));
+        assert_eq!(1, map.len());
+
+        assert_eq!(Err(GenIndexError::StaleGeneration), map.insert(old, "stale"));
+        assert_eq!(None, map.remove(old));
+        assert_eq!(None, map.get_mut(old));
+
+        *map.get_mut(new).unwrap() = "newer";
+        assert_eq!(Some("newer"), map.remove(new));
+        assert!(map.is_empty());
+
+        // far off indices cost no more than near ones
+        map.insert(GenIndex::new(u32::MAX - 1, 0), "far").unwrap();
+        assert!(map.contains_key(GenIndex::new(u32::MAX - 1, 0)));
+    }
+
+    #[test]
+    fn test_secondary_map_iter() {
+        let a = GenIndex::new(4, 2);
+        let b = GenIndex::new(1, 0);
+
+        let mut dense = SecondaryMap::<u32>::new();
+        dense.insert(a, 1).unwrap();
+        dense.insert(b, 2).unwrap();
+        dense.values_mut().for_each(|value| *value *= 10);
+        for (gi, value) in dense.iter_mut() {
+            if gi == a {
+                *value += 1;
+            }
+        }
+        assert_eq!(vec![(b, &20), (a, &11)], dense.iter().collect::<Vec<_>>());
+        assert_eq!(vec![b, a], dense.keys().collect::<Vec<_>>());
+        assert_eq!(vec![&20, &11], dense.values().collect::<Vec<_>>());
+
+        let mut sparse = SparseSecondaryMap::<u32>::new();
+        sparse.insert(a, 1).unwrap();
+        sparse.insert(b, 2).unwrap();
+        sparse.values_mut().for_each(|value| *value *= 10);
+        for (gi, value) in sparse.iter_mut() {
+            if gi == a {
+                *value += 1;
+            }
+        }
+        let mut all: Vec<_> = sparse.iter().map(|(gi, &v)| (gi.get_index(), v)).collect();
+        all.sort();
+        assert_eq!(vec![(1, 20), (4, 11)], all);
+        assert!(sparse.keys().all(|gi| gi == a || gi == b));
+        assert_eq!(31, sparse.values().sum::<u32>());
+    }
+
+    #[test]
+    fn test_secondary_entry() {
+        let old = GenIndex::new(3, 1);
+        let new = GenIndex::new(3, 2);
+
+        let mut dense = SecondaryMap::<Vec<u32>>::new();
+        let mut sparse = SparseSecondaryMap::<Vec<u32>>::new();
+        macro_rules! check {
+            ($map:expr) => {
+                $map.entry(old).unwrap().or_default().push(1);
+                $map.entry(old).unwrap().and_modify(|v| v.push(2)).or_default();
+                assert_eq!(Some(&vec![1, 2]), $map.get(old));
+
+                // a newer generation replaces the old value
+                assert_eq!(&vec![3], $map.entry(new).unwrap().or_insert_with(|| vec![3]));
+                assert_eq!(None, $map.get(old));
+                assert_eq!(1, $map.len());
+                assert_eq!(Err(GenIndexError::StaleGeneration), $map.entry(old).map(|e| e.key()));
+
+                match $map.entry(new).unwrap() {
+                    SecondaryEntry::Occupied(mut entry) => {
+                        assert_eq!(&vec![3], entry.get());
+                        assert_eq!(vec![3], entry.insert(vec![4]));
+                        assert_eq!(vec![4], entry.remove());
+                    },
+                    SecondaryEntry::Vacant(_) => panic!("new has a value"),
+                }
+                assert!($map.is_empty());
+            }
+        }
+        check!(dense);
+        check!(sparse);
+    }
+}
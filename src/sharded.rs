@@ -0,0 +1,353 @@
+//! Splitting the index space across several independently locked sets.
+
+use std::{vec::Vec,
+          ops::AddAssign,
+          hash::Hash,
+          hash::Hasher,
+          collections::hash_map::DefaultHasher,
+          thread,
+          sync::atomic::AtomicUsize,
+          sync::atomic::Ordering};
+
+use num::{Num, Bounded, NumCast, ToPrimitive, cast};
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use super::{GenIndex, GenIndexConfig, GenIndexEntitySet, GenIndexError};
+use super::sync::{Mutex, lock};
+
+/// ShardRouting
+///
+/// Decides which shard of a ShardedGenIndexEntitySet serves an
+/// allocation.
+#[derive(Hash, Debug, PartialEq, Eq, Copy, Clone, Default)]
+pub enum ShardRouting {
+    /// cycle through the shards, one allocation at a time.
+    #[default]
+    RoundRobin,
+    /// always send the same thread to the same shard, so that
+    /// threads only contend when they happen to share a shard.
+    PerThread,
+}
+
+/// ShardedGenIndexEntitySet
+///
+/// Splits the index space across a number of GenIndexEntitySets,
+/// each behind its own Mutex, so that heavily threaded spawning
+/// does not bottleneck on a single lock. The shards are
+/// interleaved: shard `s` of `n` owns the indices `s`, `s + n`,
+/// `s + 2n` and so on, so a handle always finds its way back to
+/// the shard that issued it.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use std::sync::Arc;
+/// use gen_indices::*;
+///
+/// let gi = Arc::new(ShardedGenIndexEntitySet::<u64, u64>::new(4));
+/// let idx = gi.next_index();
+/// assert!(gi.is_live(idx));
+/// gi.delete_index(idx).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct ShardedGenIndexEntitySet<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+                                    G: Num + AddAssign + Copy + Bounded + PartialOrd> {
+    shards: Vec<Mutex<GenIndexEntitySet<I, G>>>,
+    routing: ShardRouting,
+    cursor: AtomicUsize,
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + NumCast,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> ShardedGenIndexEntitySet<I, G> {
+
+    /// Create a new set split into `shards` shards, routing
+    /// allocations round-robin.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    pub fn new(shards: usize) -> ShardedGenIndexEntitySet<I, G> {
+        Self::with_config(shards, ShardRouting::default(), GenIndexConfig::default())
+    }
+
+    /// Create a new set split into `shards` shards, routing
+    /// allocations as given, with every shard using `config`.
+    /// Note that a capacity limit and an index limit apply to each
+    /// shard separately. Each shard's index limit is lowered further
+    /// to its share of the indices representable in `I`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    pub fn with_config(shards: usize,
+                       routing: ShardRouting,
+                       config: GenIndexConfig) -> ShardedGenIndexEntitySet<I, G> {
+        assert!(shards > 0, "a sharded set needs at least one shard");
+        let global = I::max_value().to_usize();
+        ShardedGenIndexEntitySet {
+            shards: (0..shards).map(|s| {
+                // shard `s` owns `s`, `s + n` and so on below the largest
+                // value of `I`, which is never handed out
+                let share = global.map(|max| max.saturating_sub(s).div_ceil(shards));
+                let index_limit = match (config.index_limit, share) {
+                    (Some(limit), Some(share)) => Some(limit.min(share)),
+                    (limit, share) => limit.or(share),
+                };
+                Mutex::new(GenIndexEntitySet::from_config(GenIndexConfig{index_limit, ..config}))
+            }).collect(),
+            routing,
+            cursor: AtomicUsize::new(0),
+        }
+    }
+
+    /// return the number of shards.
+    pub fn shard_count(&self) -> usize { self.shards.len() }
+
+    /// allocate and provide a "new" index from the shard chosen by
+    /// the routing.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as
+    /// `GenIndexEntitySet::next_index`.
+    pub fn next_index(&self) -> GenIndex<I, G> {
+        match self.try_next_index() {
+            Ok(gi) => gi,
+            Err(e) => panic!("next_index: {}", e),
+        }
+    }
+
+    /// allocate and provide a "new" index, reporting an error
+    /// instead of panicking if the chosen shard cannot hand one out.
+    pub fn try_next_index(&self) -> Result<GenIndex<I, G>, GenIndexError> {
+        self.try_next_index_on(self.route())
+    }
+
+    /// allocate and provide a "new" index from the given shard,
+    /// bypassing the routing.
+    pub fn try_next_index_on(&self, shard: usize) -> Result<GenIndex<I, G>, GenIndexError> {
+        let shard = shard % self.shards.len();
+        self.try_next_index_locked(&mut lock(&self.shards[shard]), shard)
+    }
+
+    /// Delete an index, handing it back to the shard that issued it.
+    pub fn delete_index(&self, gi: GenIndex<I, G>) -> Result<(), GenIndexError> {
+        let (shard, local) = self.to_local(gi).ok_or(GenIndexError::UnknownIndex)?;
+        lock(&self.shards[shard]).delete_index(local)
+    }
+
+    /// Check whether the given index is still live.
+    pub fn is_live(&self, gi: GenIndex<I, G>) -> bool {
+        match self.to_local(gi) {
+            Some((shard, local)) => lock(&self.shards[shard]).is_live(local),
+            None => false,
+        }
+    }
+
+    /// return the number of indices currently live across all
+    /// shards. The shards are locked one after another, so under
+    /// concurrent use this is only a snapshot.
+    pub fn live_count(&self) -> usize {
+        self.shards.iter().map(|s| lock(s).live_count()).sum()
+    }
+
+    fn try_next_index_locked(&self,
+                             set: &mut GenIndexEntitySet<I, G>,
+                             shard: usize) -> Result<GenIndex<I, G>, GenIndexError> {
+        let local = set.try_next_index()?;
+        match self.to_global(local, shard) {
+            Some(gi) => Ok(gi),
+            None => {
+                // only when `I` counts past usize, beyond the shard's
+                // index limit: the slots above it are out of range
+                // just as well, so it must not come back off the free list
+                set.retire_index(local)?;
+                Err(GenIndexError::IndexSpaceExhausted)
+            },
+        }
+    }
+
+    fn route(&self) -> usize {
+        match self.routing {
+            ShardRouting::RoundRobin => self.cursor.fetch_add(1, Ordering::Relaxed),
+            ShardRouting::PerThread => {
+                let mut hasher = DefaultHasher::new();
+                thread::current().id().hash(&mut hasher);
+                hasher.finish() as usize
+            },
+        }
+    }
+
+    fn to_global(&self, local: GenIndex<I, G>, shard: usize) -> Option<GenIndex<I, G>> {
+        let index = local.index.to_usize()?
+            .checked_mul(self.shards.len())?
+            .checked_add(shard)?;
+        let index: I = cast(index)?;
+        if index == I::max_value() {
+            return None;
+        }
+        Some(GenIndex::new(index, local.generation))
+    }
+
+    fn to_local(&self, gi: GenIndex<I, G>) -> Option<(usize, GenIndex<I, G>)> {
+        let index = gi.index.to_usize()?;
+        let n = self.shards.len();
+        Some((index % n, GenIndex::new(cast(index / n)?, gi.generation)))
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + NumCast + Send,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send> ShardedGenIndexEntitySet<I, G> {
+
+    /// allocate `n` indices, spread evenly over the shards, with a
+    /// rayon task per shard which takes its shard's lock only once.
+    /// The indices are returned grouped by shard.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `next_index`.
+    pub fn par_next_indices(&self, n: usize) -> Vec<GenIndex<I, G>> {
+        let shards = self.shards.len();
+        let per_shard: Vec<Vec<_>> = self.shards.par_iter().enumerate().map(|(s, shard)| {
+            let count = n / shards + if s < n % shards { 1 } else { 0 };
+            let mut set = lock(shard);
+            (0..count).map(|_| match self.try_next_index_locked(&mut set, s) {
+                Ok(gi) => gi,
+                Err(e) => panic!("next_index: {}", e),
+            }).collect()
+        }).collect();
+        per_shard.into_iter().flatten().collect()
+    }
+
+    /// Delete many indices, with a rayon task per shard which takes
+    /// its shard's lock only once, returning how many were freed.
+    /// Each index is validated as in `delete_index`. Every shard
+    /// stops at its first failure, and the first error met is
+    /// returned once all are done; other indices remain deleted.
+    pub fn par_delete_indices(&self, gis: &[GenIndex<I, G>]) -> Result<usize, GenIndexError> {
+        let mut buckets = vec!{Vec::new(); self.shards.len()};
+        for &gi in gis {
+            let (shard, local) = self.to_local(gi).ok_or(GenIndexError::UnknownIndex)?;
+            buckets[shard].push(local);
+        }
+        let results: Vec<_> = self.shards.par_iter().zip(buckets).map(|(shard, bucket)| {
+            lock(shard).delete_indices(bucket)
+        }).collect();
+        results.into_iter().sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread::*, sync::Arc, collections::HashSet};
+    use super::*;
+
+    const THREADS: usize = 8;
+
+    #[test]
+    fn test_sharded_round_robin() {
+        let gi = ShardedGenIndexEntitySet::<u64, u64>::new(3);
+
+        let idxs: Vec<_> = (0..6).map(|_| gi.next_index()).collect();
+        assert_eq!(vec![0, 1, 2, 3, 4, 5],
+                   idxs.iter().map(|i| i.get_index()).collect::<Vec<_>>());
+        assert_eq!(6, gi.live_count());
+
+        gi.delete_index(idxs[4]).unwrap();
+        assert!(!gi.is_live(idxs[4]));
+        assert_eq!(Err(GenIndexError::AlreadyDeleted), gi.delete_index(idxs[4]));
+
+        // index 4 lives on shard 1, and comes back from there
+        let again = gi.try_next_index_on(1).unwrap();
+        assert_eq!(4, again.get_index());
+        assert_eq!(1, again.get_generation());
+        assert_eq!(Err(GenIndexError::StaleGeneration), gi.delete_index(idxs[4]));
+    }
+
+    #[test]
+    fn test_sharded_index_space() {
+        let gi = ShardedGenIndexEntitySet::<u8, u8>::new(100);
+        let mut count = 0;
+        while gi.try_next_index().is_ok() {
+            count += 1;
+        }
+        // every value but u8::MAX can be handed out
+        assert_eq!(255, count);
+    }
+
+    #[test]
+    fn test_sharded_exhausted_shard() {
+        let config = GenIndexConfig{recycle_threshold: 5, ..GenIndexConfig::default()};
+        let gi = ShardedGenIndexEntitySet::<u8, u8>::with_config(100, ShardRouting::RoundRobin, config);
+        assert_eq!(99, gi.try_next_index_on(99).unwrap().get_index());
+        let second = gi.try_next_index_on(99).unwrap();
+        assert_eq!(199, second.get_index());
+
+        // 299 does not fit, so the shard fails without allocating
+        let (slots, allocated) = {
+            let shard = lock(&gi.shards[99]);
+            (shard.slots.len(), shard.total_allocated())
+        };
+        for _ in 0..10 {
+            assert_eq!(Err(GenIndexError::IndexSpaceExhausted), gi.try_next_index_on(99));
+        }
+        let shard = lock(&gi.shards[99]);
+        assert_eq!(slots, shard.slots.len());
+        assert_eq!(allocated, shard.total_allocated());
+        assert_eq!(0, shard.retired_count());
+        assert_eq!(slots, shard.high_water_mark());
+        drop(shard);
+
+        // the slots in range are recycled, despite the threshold
+        gi.delete_index(second).unwrap();
+        let again = gi.try_next_index_on(99).unwrap();
+        assert_eq!(199, again.get_index());
+        assert_eq!(1, again.get_generation());
+    }
+
+    #[test]
+    fn test_sharded_per_thread() {
+        let gi = Arc::new(ShardedGenIndexEntitySet::<u64, u64>::with_config(
+            4, ShardRouting::PerThread, GenIndexConfig::default()));
+        let threads: Vec<_> = (0..THREADS).map(|_| {
+            let gi = gi.clone();
+            spawn(move || {
+                let idxs: Vec<_> = (0..100).map(|_| gi.next_index()).collect();
+                // one thread always lands on the same shard
+                let shards: HashSet<_> = idxs.iter().map(|i| i.get_index() % 4).collect();
+                assert_eq!(1, shards.len());
+                idxs
+            })
+        }).collect();
+
+        let mut seen = HashSet::new();
+        for t in threads {
+            for idx in t.join().unwrap() {
+                assert!(seen.insert(idx.get_index()));
+            }
+        }
+        assert_eq!(THREADS * 100, gi.live_count());
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn test_sharded_par_bulk() {
+        let gi = ShardedGenIndexEntitySet::<u64, u64>::new(4);
+
+        let idxs = gi.par_next_indices(1001);
+        assert_eq!(1001, idxs.len());
+        assert_eq!(1001, gi.live_count());
+        let unique: HashSet<_> = idxs.iter().map(|i| i.get_index()).collect();
+        assert_eq!(1001, unique.len());
+
+        assert_eq!(Ok(1000), gi.par_delete_indices(&idxs[1..]));
+        assert_eq!(1, gi.live_count());
+        assert_eq!(Err(GenIndexError::AlreadyDeleted), gi.par_delete_indices(&idxs[..2]));
+        assert_eq!(0, gi.live_count());
+    }
+}
@@ -0,0 +1,256 @@
+//! Convenience for working with a SharedGenIndexEntitySet.
+
+use std::ops::AddAssign;
+
+use num::{Num, Bounded, ToPrimitive};
+
+use super::{GenIndex, GenIndexConfig, GenIndexEntitySet, GenIndexError, GenIndexStats,
+            GenIndexStatsReader, OwnedIndex, SharedGenIndexEntitySet};
+use super::sync::{MutexGuard, lock_checked, lock_ignore_poison, lock_recover};
+
+/// SharedGenIndexEntitySetExt
+///
+/// Shortcuts on a SharedGenIndexEntitySet which take the lock for
+/// you, and which never panic, reporting any failure as an
+/// ordinary GenIndexError.
+///
+/// If some thread panicked while holding the lock, the set may have
+/// been left part way through an operation: the hooks run under
+/// the lock, so one panicking leaves those after it uncalled, and
+/// whatever they keep in step with the set out of step. The
+/// shortcuts then fail with `Poisoned`, until the lock is taken
+/// with `recover_set`, which clears the poison, by a caller who
+/// made sure the set and its hooks are in order again, or put them
+/// in order, with `GenIndexEntitySet::restore` say. With the
+/// `parking_lot` feature, the lock is never poisoned.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// let gi = GenIndexEntitySet::<u64, u64>::new();
+/// let idx = gi.allocate().unwrap();
+/// assert!(gi.is_live(idx));
+/// gi.free(idx).unwrap();
+/// ```
+pub trait SharedGenIndexEntitySetExt<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+                                     G: Num + AddAssign + Copy + Bounded + PartialOrd> {
+    /// Take the lock, failing with `Poisoned` if it is poisoned.
+    fn lock_set(&self) -> Result<MutexGuard<'_, GenIndexEntitySet<I, G>>, GenIndexError>;
+
+    /// Take the lock even if it is poisoned, and clear the poison.
+    fn recover_set(&self) -> MutexGuard<'_, GenIndexEntitySet<I, G>>;
+
+    /// allocate a "new" index, see `GenIndexEntitySet::try_next_index`.
+    fn allocate(&self) -> Result<GenIndex<I, G>, GenIndexError> {
+        self.lock_set()?.try_next_index()
+    }
+
+    /// free an index, see `GenIndexEntitySet::delete_index`.
+    fn free(&self, gi: GenIndex<I, G>) -> Result<(), GenIndexError> {
+        self.lock_set()?.delete_index(gi)
+    }
+
+    /// see `GenIndexEntitySet::is_live`; false as well if the lock
+    /// is poisoned.
+    fn is_live(&self, gi: GenIndex<I, G>) -> bool {
+        self.lock_set().is_ok_and(|set| set.is_live(gi))
+    }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> SharedGenIndexEntitySetExt<I, G>
+    for SharedGenIndexEntitySet<I, G> {
+    fn lock_set(&self) -> Result<MutexGuard<'_, GenIndexEntitySet<I, G>>, GenIndexError> {
+        lock_checked(self)
+    }
+
+    fn recover_set(&self) -> MutexGuard<'_, GenIndexEntitySet<I, G>> {
+        lock_recover(self)
+    }
+}
+
+/// SharedGenIndexSet
+///
+/// A GenIndexEntitySet shared between threads, without the Arc and
+/// Mutex showing. Cloning is cheap, and all clones refer to the same
+/// set. As with SharedGenIndexEntitySetExt, a poisoned lock is
+/// reported as `Poisoned` rather than panicking, until recovered.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use std::thread;
+/// use gen_indices::*;
+///
+/// let gi = SharedGenIndexSet::<u64, u64>::new();
+/// let cgi = gi.clone();
+/// let idx = thread::spawn(move || cgi.allocate().unwrap()).join().unwrap();
+/// assert!(gi.is_live(idx));
+/// gi.free(idx).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct SharedGenIndexSet<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+                             G: Num + AddAssign + Copy + Bounded + PartialOrd> {
+    inner: SharedGenIndexEntitySet<I, G>,
+    stats: GenIndexStatsReader,
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> Clone for SharedGenIndexSet<I, G> {
+    fn clone(&self) -> Self {
+        SharedGenIndexSet{inner: self.inner.clone(), stats: self.stats.clone()}
+    }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> SharedGenIndexSet<I, G> {
+
+    /// Create a new, empty set.
+    pub fn new() -> SharedGenIndexSet<I, G> {
+        Self::from_set(GenIndexEntitySet::new_unsync())
+    }
+
+    /// Create a new, empty set with the given settings.
+    pub fn with_config(config: GenIndexConfig) -> SharedGenIndexSet<I, G> {
+        Self::from_set(GenIndexEntitySet::from_config(config))
+    }
+
+    /// Share an existing set.
+    pub fn from_set(set: GenIndexEntitySet<I, G>) -> SharedGenIndexSet<I, G> {
+        Self::from(set.into_shared())
+    }
+
+    /// Take the lock, and give access to the whole set. Fails with
+    /// `Poisoned` if the lock is poisoned.
+    pub fn lock(&self) -> Result<MutexGuard<'_, GenIndexEntitySet<I, G>>, GenIndexError> {
+        lock_checked(&self.inner)
+    }
+
+    /// Take the lock even if it is poisoned, clearing the poison,
+    /// as `SharedGenIndexEntitySetExt::recover_set`.
+    pub fn recover(&self) -> MutexGuard<'_, GenIndexEntitySet<I, G>> {
+        lock_recover(&self.inner)
+    }
+
+    /// return the underlying SharedGenIndexEntitySet, for use with
+    /// the likes of LocalIndexCache.
+    pub fn shared(&self) -> &SharedGenIndexEntitySet<I, G> { &self.inner }
+
+    /// allocate a "new" index, see `GenIndexEntitySet::try_next_index`.
+    pub fn allocate(&self) -> Result<GenIndex<I, G>, GenIndexError> {
+        self.lock()?.try_next_index()
+    }
+
+    /// allocate a "new" index, which is freed again when the
+    /// returned OwnedIndex is dropped.
+    pub fn allocate_owned(&self) -> Result<OwnedIndex<I, G>, GenIndexError> {
+        OwnedIndex::new(self.inner.clone())
+    }
+
+    /// free an index, see `GenIndexEntitySet::delete_index`.
+    pub fn free(&self, gi: GenIndex<I, G>) -> Result<(), GenIndexError> {
+        self.lock()?.delete_index(gi)
+    }
+
+    /// see `GenIndexEntitySet::is_live`; false as well if the lock
+    /// is poisoned.
+    pub fn is_live(&self, gi: GenIndex<I, G>) -> bool {
+        self.lock().is_ok_and(|set| set.is_live(gi))
+    }
+
+    /// return the set's counters without taking the lock, see
+    /// GenIndexStatsReader.
+    pub fn snapshot_stats(&self) -> GenIndexStats {
+        self.stats.snapshot_stats()
+    }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> Default for SharedGenIndexSet<I, G> {
+    fn default() -> Self { Self::new() }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> From<SharedGenIndexEntitySet<I, G>>
+    for SharedGenIndexSet<I, G> {
+    fn from(inner: SharedGenIndexEntitySet<I, G>) -> Self {
+        // the reader is the same, whatever state the set is in
+        let stats = lock_ignore_poison(&inner).stats_reader();
+        SharedGenIndexSet{inner, stats}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allocate_and_free() {
+        let gi = GenIndexEntitySet::<u64, u64>::with_capacity_limit(1);
+
+        let idx = gi.allocate().unwrap();
+        assert!(gi.is_live(idx));
+        assert_eq!(Err(GenIndexError::CapacityExhausted), gi.allocate());
+        gi.free(idx).unwrap();
+        assert_eq!(Err(GenIndexError::AlreadyDeleted), gi.free(idx));
+    }
+
+    #[test]
+    #[cfg(not(feature = "parking_lot"))]
+    fn test_poisoned_lock() {
+        let gi = GenIndexEntitySet::<u64, u64>::new();
+        let idx = gi.allocate().unwrap();
+
+        let cgi = gi.clone();
+        let crashed = std::thread::spawn(move || {
+            let _guard = cgi.lock().unwrap();
+            panic!("crash while holding the lock");
+        }).join();
+        assert!(crashed.is_err());
+        assert!(gi.is_poisoned());
+
+        // nothing goes ahead until the lock is explicitly recovered
+        assert_eq!(Err(GenIndexError::Poisoned), gi.allocate());
+        assert_eq!(Err(GenIndexError::Poisoned), gi.free(idx));
+        assert!(!gi.is_live(idx));
+        assert!(gi.is_poisoned());
+        assert!(gi.recover_set().is_live(idx));
+        assert!(!gi.is_poisoned());
+        gi.free(idx).unwrap();
+        assert_eq!(1, gi.allocate().unwrap().get_generation());
+
+        let shared = SharedGenIndexSet::from(gi.clone());
+        let crashed = std::thread::spawn(move || {
+            let _guard = gi.lock().unwrap();
+            panic!("crash while holding the lock");
+        }).join();
+        assert!(crashed.is_err());
+        assert_eq!(Some(GenIndexError::Poisoned), shared.lock().err());
+        assert_eq!(1, shared.recover().live_count());
+        assert_eq!(1, shared.lock().unwrap().live_count());
+    }
+
+    #[test]
+    fn test_shared_set_newtype() {
+        let gi = SharedGenIndexSet::<u64, u64>::new();
+        let cgi = gi.clone();
+
+        let idx = cgi.allocate().unwrap();
+        assert!(gi.is_live(idx));
+        gi.free(idx).unwrap();
+        assert_eq!(Err(GenIndexError::AlreadyDeleted), cgi.free(idx));
+        assert_eq!(1, gi.allocate().unwrap().get_generation());
+        assert_eq!(1, cgi.lock().unwrap().live_count());
+
+        // the two views of the same set agree
+        let other = SharedGenIndexSet::from(gi.shared().clone());
+        assert_eq!(1, other.allocate().unwrap().get_index());
+        assert_eq!(2, gi.lock().unwrap().live_count());
+    }
+}
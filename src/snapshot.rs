@@ -0,0 +1,106 @@
+//! Saving the state of a set, to go back to later.
+
+use std::{collections::VecDeque,
+          mem,
+          ops::AddAssign};
+
+use num::{Num, Bounded, ToPrimitive};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+use super::{GenIndex, GenIndexError, Slot};
+
+/// GenIndexSnapshot
+///
+/// The state of a GenIndexEntitySet at one instant, as returned by
+/// `GenIndexEntitySet::snapshot`: the next fresh index, the
+/// generation and liveness of every slot, the free list, in order,
+/// and the counters and the clock. Restoring it with
+/// `GenIndexEntitySet::restore` takes the set back to exactly that
+/// state, so that a paused simulation can be resumed with every
+/// handle it saved meaning what it did before, live or stale, and
+/// the set goes on to hand out the very indices it would have.
+///
+/// The settings of the set are not part of the snapshot, nor are
+/// its recording and its hooks.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// let mut entities = GenIndexEntitySet::<u32, u32>::new_unsync();
+/// let keep = entities.next_index();
+/// let gone = entities.next_index();
+/// entities.delete_index(gone).unwrap();
+/// let saved = entities.snapshot();
+/// let next = entities.next_index();
+///
+/// entities.restore(&saved).unwrap();
+/// assert!(entities.is_live(keep));
+/// assert!(!entities.is_live(next));
+/// assert_eq!(next, entities.next_index());
+/// ```
+#[derive(Hash, Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
+pub struct GenIndexSnapshot<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+                            G: Num + AddAssign + Copy + Bounded + PartialOrd> {
+    #[cfg_attr(feature = "serde", serde(rename = "next_index"))]
+    pub(crate) index_note: I,
+    #[cfg_attr(feature = "serde", serde(rename = "free_list"))]
+    pub(crate) deleted: VecDeque<(GenIndex<I, G>, u64)>,
+    pub(crate) slots: Vec<Slot<G>>,
+    pub(crate) retired: Vec<I>,
+    pub(crate) live: usize,
+    pub(crate) high_water: usize,
+    pub(crate) allocations: usize,
+    pub(crate) rng: u64,
+    pub(crate) tick: u64,
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> GenIndexSnapshot<I, G> {
+
+    /// Check whether the handle was live when the snapshot was
+    /// taken.
+    pub fn is_live(&self, gi: GenIndex<I, G>) -> bool {
+        gi.index.to_usize()
+            .and_then(|index| self.slots.get(index))
+            .is_some_and(|slot| slot.live && slot.generation == gi.generation)
+    }
+
+    /// return the number of indices live when the snapshot was
+    /// taken.
+    pub fn live_count(&self) -> usize { self.live }
+
+    /// return the number of deleted indices waiting to be recycled
+    /// when the snapshot was taken.
+    pub fn free_count(&self) -> usize { self.deleted.len() }
+
+    /// Check that the snapshot is of a state a set can be in: the
+    /// next fresh index right after the last slot, every index on
+    /// the free list, or retired, that of a slot neither live nor
+    /// set aside for a ticket, and there only once, and the number
+    /// of live indices that of the live slots.
+    pub(crate) fn check(&self) -> Result<(), GenIndexError> {
+        if self.index_note.to_usize() != Some(self.slots.len()) {
+            return Err(GenIndexError::InvalidSnapshot);
+        }
+        let mut waiting = vec![false; self.slots.len()];
+        for index in self.deleted.iter().map(|(gi, _)| gi.index).chain(self.retired.iter().copied()) {
+            let i = index.to_usize()
+                .filter(|&i| i < self.slots.len())
+                .ok_or(GenIndexError::InvalidSnapshot)?;
+            if self.slots[i].live || self.slots[i].claimed || mem::replace(&mut waiting[i], true) {
+                return Err(GenIndexError::InvalidSnapshot);
+            }
+        }
+        if self.slots.iter().filter(|slot| slot.live).count() != self.live {
+            return Err(GenIndexError::InvalidSnapshot);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,297 @@
+//! Reading a set's counters without taking its lock.
+
+use std::{hash::Hash,
+          hash::Hasher,
+          fmt,
+          ops::Add,
+          sync::Arc,
+          sync::atomic::AtomicUsize,
+          sync::atomic::Ordering,
+          sync::atomic::fence};
+
+/// GenIndexStats
+///
+/// The counters of a GenIndexEntitySet at one instant, as returned
+/// by `snapshot_stats`.
+#[derive(Hash, Debug, PartialEq, Eq, Copy, Clone, Default)]
+pub struct GenIndexStats {
+    /// see `GenIndexEntitySet::live_count`.
+    pub live: usize,
+    /// see `GenIndexEntitySet::free_count`.
+    pub free: usize,
+    /// see `GenIndexEntitySet::retired_count`.
+    pub retired: usize,
+    /// see `GenIndexEntitySet::total_allocated`.
+    pub total_allocated: usize,
+    /// see `GenIndexEntitySet::high_water_mark`.
+    pub high_water_mark: usize,
+}
+
+/// MemoryUsage
+///
+/// The memory held by a set or an arena, in bytes, as returned by
+/// `memory_usage`. Storage is counted by its capacity rather than
+/// by what is in use, since that is what was taken from the
+/// allocator. Memory owned by the stored values themselves, such as
+/// the contents of a `String`, is not counted.
+#[derive(Hash, Debug, PartialEq, Eq, Copy, Clone, Default)]
+pub struct MemoryUsage {
+    /// the per slot storage: generations, and values in an arena.
+    pub slots: usize,
+    /// the deleted indices waiting to be recycled, and the retired
+    /// ones.
+    pub free_list: usize,
+    /// everything else: the structures themselves, the operation
+    /// log, and side tables.
+    pub metadata: usize,
+}
+
+impl MemoryUsage {
+    /// return the sum of all the parts.
+    pub fn total(&self) -> usize { self.slots + self.free_list + self.metadata }
+}
+
+impl Add for MemoryUsage {
+    type Output = MemoryUsage;
+
+    fn add(self, other: MemoryUsage) -> MemoryUsage {
+        MemoryUsage {
+            slots: self.slots + other.slots,
+            free_list: self.free_list + other.free_list,
+            metadata: self.metadata + other.metadata,
+        }
+    }
+}
+
+/// GenIndexStatsReader
+///
+/// Reads the counters of a GenIndexEntitySet while another thread
+/// holds its lock, for monitoring. Obtained once, under the lock,
+/// from `GenIndexEntitySet::stats_reader`, and after that it never
+/// needs the lock again.
+///
+/// The counters are published through a seqlock: the set never
+/// waits for a reader, and a reader always sees a consistent
+/// snapshot, retrying if the set changed while it was reading.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// # #[cfg(not(feature = "parking_lot"))]
+/// # fn main() {
+/// let gi = GenIndexEntitySet::<u64, u64>::new();
+/// let stats = gi.lock().unwrap().stats_reader();
+///
+/// let guard = gi.lock().unwrap();
+/// // no need for the lock
+/// assert_eq!(0, stats.snapshot_stats().live);
+/// # drop(guard);
+/// # }
+/// # #[cfg(feature = "parking_lot")]
+/// # fn main() {}
+/// ```
+#[derive(Debug, Clone)]
+pub struct GenIndexStatsReader {
+    shared: Arc<Published>,
+}
+
+impl GenIndexStatsReader {
+    /// return the counters as last published by the set.
+    pub fn snapshot_stats(&self) -> GenIndexStats {
+        self.shared.read()
+    }
+}
+
+/// The set's side of the seqlock. Every set has its own; cloning a
+/// set gives the clone fresh counters, and since the counters only
+/// ever reflect the rest of the set, they take no part in
+/// comparing or hashing it.
+pub(crate) struct StatsCell {
+    shared: Arc<Published>,
+}
+
+#[derive(Debug, Default)]
+struct Published {
+    seq: AtomicUsize,
+    live: AtomicUsize,
+    free: AtomicUsize,
+    retired: AtomicUsize,
+    total_allocated: AtomicUsize,
+    high_water_mark: AtomicUsize,
+}
+
+impl StatsCell {
+    pub(crate) fn new() -> StatsCell {
+        StatsCell{shared: Arc::new(Published::default())}
+    }
+
+    pub(crate) fn reader(&self) -> GenIndexStatsReader {
+        GenIndexStatsReader{shared: self.shared.clone()}
+    }
+
+    /// called by the owning set, from `&self` methods too, so two
+    /// threads sharing the set may publish at once: a writer first
+    /// takes the sequence from even to odd, and only one can.
+    pub(crate) fn publish(&self, stats: GenIndexStats) {
+        let p = &self.shared;
+        let seq = loop {
+            let seq = p.seq.load(Ordering::Relaxed);
+            if seq % 2 == 1 {
+                std::hint::spin_loop();
+                continue;
+            }
+            if p.seq.compare_exchange_weak(seq, seq.wrapping_add(1), Ordering::Acquire, Ordering::Relaxed).is_ok() {
+                break seq;
+            }
+        };
+        fence(Ordering::Release);
+        p.live.store(stats.live, Ordering::Relaxed);
+        p.free.store(stats.free, Ordering::Relaxed);
+        p.retired.store(stats.retired, Ordering::Relaxed);
+        p.total_allocated.store(stats.total_allocated, Ordering::Relaxed);
+        p.high_water_mark.store(stats.high_water_mark, Ordering::Relaxed);
+        p.seq.store(seq.wrapping_add(2), Ordering::Release);
+    }
+}
+
+impl Published {
+    fn read(&self) -> GenIndexStats {
+        loop {
+            let before = self.seq.load(Ordering::Acquire);
+            if before % 2 == 1 {
+                std::hint::spin_loop();
+                continue;
+            }
+            let stats = GenIndexStats {
+                live: self.live.load(Ordering::Relaxed),
+                free: self.free.load(Ordering::Relaxed),
+                retired: self.retired.load(Ordering::Relaxed),
+                total_allocated: self.total_allocated.load(Ordering::Relaxed),
+                high_water_mark: self.high_water_mark.load(Ordering::Relaxed),
+            };
+            fence(Ordering::Acquire);
+            if self.seq.load(Ordering::Relaxed) == before {
+                return stats;
+            }
+        }
+    }
+}
+
+impl Clone for StatsCell {
+    fn clone(&self) -> Self {
+        let cell = StatsCell::new();
+        cell.publish(self.shared.read());
+        cell
+    }
+}
+
+impl Default for StatsCell {
+    fn default() -> Self { StatsCell::new() }
+}
+
+impl PartialEq for StatsCell {
+    fn eq(&self, _other: &Self) -> bool { true }
+}
+
+impl Hash for StatsCell {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
+impl fmt::Debug for StatsCell {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("StatsCell").field(&self.shared.read()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread::*, sync::atomic::AtomicBool};
+    use super::*;
+    use super::super::{GenIndexEntitySet, SharedGenIndexSet};
+    use super::super::sync::lock;
+
+    #[test]
+    fn test_snapshot_stats() {
+        let gi = GenIndexEntitySet::<u64, u64>::new();
+        let stats = lock(&gi).stats_reader();
+
+        let idxs = lock(&gi).next_indices(3);
+        lock(&gi).delete_index(idxs[0]).unwrap();
+        assert_eq!(GenIndexStats{live: 2, free: 1, retired: 0, total_allocated: 3, high_water_mark: 3},
+                   stats.snapshot_stats());
+        assert_eq!(stats.snapshot_stats(), lock(&gi).snapshot_stats());
+
+        // the reader survives a reset
+        lock(&gi).reset();
+        assert_eq!(GenIndexStats::default(), stats.snapshot_stats());
+
+        // a clone has counters of its own
+        let mut other = lock(&gi).clone();
+        other.next_index();
+        assert_eq!(0, stats.snapshot_stats().live);
+    }
+
+    #[test]
+    fn test_snapshot_stats_while_locked() {
+        let gi = SharedGenIndexSet::<u64, u64>::new();
+        let done = std::sync::Arc::new(AtomicBool::new(false));
+        let worker = {
+            let gi = gi.clone();
+            let done = done.clone();
+            spawn(move || {
+                let mut set = gi.lock().unwrap();
+                for _ in 0..1000 {
+                    let idx = set.next_index();
+                    set.delete_index(idx).unwrap();
+                    set.next_index();
+                }
+                done.store(true, Ordering::Release);
+                // keep holding the lock while the monitor reads
+                while done.load(Ordering::Acquire) {
+                    yield_now();
+                }
+            })
+        };
+
+        let mut last = GenIndexStats::default();
+        while !done.load(Ordering::Acquire) {
+            let s = gi.snapshot_stats();
+            // never torn, nor going back
+            assert!(s.live <= s.high_water_mark && s.high_water_mark <= s.total_allocated);
+            assert!(s.total_allocated >= last.total_allocated && s.high_water_mark >= last.high_water_mark);
+            last = s;
+            yield_now();
+        }
+        let s = gi.snapshot_stats();
+        assert_eq!(1000, s.live);
+        assert_eq!(2000, s.total_allocated);
+        done.store(false, Ordering::Release);
+        worker.join().unwrap();
+    }
+
+    #[test]
+    fn test_publish_from_several_threads() {
+        let mut set = GenIndexEntitySet::<u64, u64>::new_unsync();
+        let idxs = set.next_indices(5);
+        set.delete_index(idxs[0]).unwrap();
+        let expected = set.snapshot_stats();
+        let set = &set;
+        // stats_reader publishes through a shared borrow
+        let readers: Vec<_> = scope(|scope| {
+            let workers: Vec<_> = (0..4).map(|_| scope.spawn(move || {
+                for _ in 0..100 {
+                    set.stats_reader();
+                }
+                set.stats_reader()
+            })).collect();
+            workers.into_iter().map(|worker| worker.join().unwrap()).collect()
+        });
+        for reader in readers {
+            assert_eq!(expected, reader.snapshot_stats());
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! The Mutex used throughout the crate: `std::sync::Mutex`, or
+//! `parking_lot::Mutex` when the `parking_lot` feature is enabled.
+//! The latter is never poisoned, so its `lock()` hands out the
+//! guard itself rather than a `Result`.
+
+#[cfg(not(feature = "parking_lot"))]
+pub use std::sync::{Mutex, MutexGuard};
+#[cfg(feature = "parking_lot")]
+pub use parking_lot::{Mutex, MutexGuard};
+
+use super::GenIndexError;
+
+/// Lock the mutex, panicking if it is poisoned, just as
+/// `lock().unwrap()` would.
+#[cfg(not(feature = "parking_lot"))]
+pub fn lock<T: ?Sized>(m: &Mutex<T>) -> MutexGuard<'_, T> { m.lock().unwrap() }
+
+/// Lock the mutex. parking_lot mutexes are never poisoned.
+#[cfg(feature = "parking_lot")]
+pub fn lock<T: ?Sized>(m: &Mutex<T>) -> MutexGuard<'_, T> { m.lock() }
+
+/// Lock the mutex, failing with `Poisoned` if it is poisoned.
+#[cfg(not(feature = "parking_lot"))]
+pub fn lock_checked<T: ?Sized>(m: &Mutex<T>) -> Result<MutexGuard<'_, T>, GenIndexError> {
+    m.lock().map_err(|_| GenIndexError::Poisoned)
+}
+
+/// Lock the mutex. parking_lot mutexes are never poisoned.
+#[cfg(feature = "parking_lot")]
+pub fn lock_checked<T: ?Sized>(m: &Mutex<T>) -> Result<MutexGuard<'_, T>, GenIndexError> { Ok(m.lock()) }
+
+/// Lock the mutex whether it is poisoned or not, leaving the
+/// poison in place, for state no operation can leave half done.
+#[cfg(not(feature = "parking_lot"))]
+pub fn lock_ignore_poison<T: ?Sized>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Lock the mutex. parking_lot mutexes are never poisoned.
+#[cfg(feature = "parking_lot")]
+pub fn lock_ignore_poison<T: ?Sized>(m: &Mutex<T>) -> MutexGuard<'_, T> { m.lock() }
+
+/// Lock the mutex, recovering the guard if it is poisoned, and
+/// clearing the poison so that later users are not bothered by it.
+#[cfg(not(feature = "parking_lot"))]
+pub fn lock_recover<T: ?Sized>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|poisoned| {
+        m.clear_poison();
+        poisoned.into_inner()
+    })
+}
+
+/// Lock the mutex. parking_lot mutexes are never poisoned.
+#[cfg(feature = "parking_lot")]
+pub fn lock_recover<T: ?Sized>(m: &Mutex<T>) -> MutexGuard<'_, T> { m.lock() }
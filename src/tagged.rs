@@ -0,0 +1,190 @@
+//! Sets whose handles carry a marker type, so that handles from one
+//! set cannot be used with another.
+
+use std::{fmt,
+          ops::AddAssign,
+          marker::PhantomData,
+          sync::Arc};
+
+use num::{Num, Bounded, ToPrimitive};
+
+use super::{GenIndex, GenIndexConfig, GenIndexEntitySet, GenIndexError};
+use super::sync::Mutex;
+
+/// TaggedGenIndexEntitySet
+///
+/// A GenIndexEntitySet which hands out `GenIndex<I, G, Tag>`, and
+/// only accepts handles with the same tag. Give each of your sets
+/// its own tag type, and using a particle handle to look up an
+/// entity becomes a compile error rather than a silent bug.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// struct Entity;
+/// struct Particle;
+///
+/// let mut entities = TaggedGenIndexEntitySet::<u32, u32, Entity>::new_unsync();
+/// let mut particles = TaggedGenIndexEntitySet::<u32, u32, Particle>::new_unsync();
+///
+/// let e: GenIndex<u32, u32, Entity> = entities.next_index();
+/// let p: GenIndex<u32, u32, Particle> = particles.next_index();
+/// assert!(entities.is_live(e));
+/// assert!(particles.is_live(p));
+/// ```
+///
+/// Mixing them up does not compile:
+///
+/// ```compile_fail
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// struct Entity;
+/// struct Particle;
+///
+/// let entities = TaggedGenIndexEntitySet::<u32, u32, Entity>::new_unsync();
+/// let mut particles = TaggedGenIndexEntitySet::<u32, u32, Particle>::new_unsync();
+/// entities.is_live(particles.next_index());
+/// ```
+pub struct TaggedGenIndexEntitySet<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+                                   G: Num + AddAssign + Copy + Bounded + PartialOrd,
+                                   Tag> {
+    set: GenIndexEntitySet<I, G>,
+    tag: PhantomData<fn() -> Tag>,
+}
+
+/// SharedTaggedGenIndexEntitySet
+///
+/// A TaggedGenIndexEntitySet wrapped up to be shared between
+/// threads, as returned by `TaggedGenIndexEntitySet::new()`.
+pub type SharedTaggedGenIndexEntitySet<I, G, Tag> = Arc<Mutex<TaggedGenIndexEntitySet<I, G, Tag>>>;
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd,
+     Tag> TaggedGenIndexEntitySet<I, G, Tag> {
+
+    /// Create a new tagged set, wrapped with a Mutex to allow for
+    /// thread safety.
+    pub fn new() -> SharedTaggedGenIndexEntitySet<I, G, Tag> {
+        Arc::new(Mutex::new(Self::new_unsync()))
+    }
+
+    /// Create a new, unsynchronized tagged set.
+    pub fn new_unsync() -> TaggedGenIndexEntitySet<I, G, Tag> {
+        Self::from_set(GenIndexEntitySet::new_unsync())
+    }
+
+    /// Create a new, unsynchronized tagged set with the given
+    /// settings.
+    pub fn from_config(config: GenIndexConfig) -> TaggedGenIndexEntitySet<I, G, Tag> {
+        Self::from_set(GenIndexEntitySet::from_config(config))
+    }
+
+    /// Tag an existing set. Handles it has already handed out are
+    /// untagged, and can be tagged to match with `GenIndex::retag`.
+    pub fn from_set(set: GenIndexEntitySet<I, G>) -> TaggedGenIndexEntitySet<I, G, Tag> {
+        TaggedGenIndexEntitySet{set, tag: PhantomData}
+    }
+
+    /// return the untagged set underneath, for anything not covered
+    /// here, such as the statistics.
+    pub fn inner(&self) -> &GenIndexEntitySet<I, G> { &self.set }
+
+    /// give up the tagging, and return the set underneath.
+    pub fn into_inner(self) -> GenIndexEntitySet<I, G> { self.set }
+
+    /// See `GenIndexEntitySet::next_index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as
+    /// `GenIndexEntitySet::next_index`.
+    pub fn next_index(&mut self) -> GenIndex<I, G, Tag> {
+        self.set.next_index().retag()
+    }
+
+    /// See `GenIndexEntitySet::try_next_index`.
+    pub fn try_next_index(&mut self) -> Result<GenIndex<I, G, Tag>, GenIndexError> {
+        self.set.try_next_index().map(GenIndex::retag)
+    }
+
+    /// See `GenIndexEntitySet::delete_index`.
+    pub fn delete_index(&mut self, gi: GenIndex<I, G, Tag>) -> Result<(), GenIndexError> {
+        self.set.delete_index(gi.untagged())
+    }
+
+    /// See `GenIndexEntitySet::is_live`.
+    pub fn is_live(&self, gi: GenIndex<I, G, Tag>) -> bool {
+        self.set.is_live(gi.untagged())
+    }
+
+    /// See `GenIndexEntitySet::live_count`.
+    pub fn live_count(&self) -> usize { self.set.live_count() }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd,
+     Tag> Default for TaggedGenIndexEntitySet<I, G, Tag> {
+    fn default() -> Self { Self::new_unsync() }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd,
+     Tag> Clone for TaggedGenIndexEntitySet<I, G, Tag> {
+    fn clone(&self) -> Self {
+        Self::from_set(self.set.clone())
+    }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + fmt::Debug,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + fmt::Debug,
+     Tag> fmt::Debug for TaggedGenIndexEntitySet<I, G, Tag> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TaggedGenIndexEntitySet").field("set", &self.set).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{mem::size_of, thread::*};
+    use super::*;
+    use super::super::sync::lock;
+
+    struct Entity;
+    struct Sound;
+
+    #[test]
+    fn test_tagged_set() {
+        let mut entities = TaggedGenIndexEntitySet::<u64, u64, Entity>::new_unsync();
+        let mut sounds = TaggedGenIndexEntitySet::<u64, u64, Sound>::new_unsync();
+
+        let e = entities.next_index();
+        let s = sounds.next_index();
+        assert_eq!(e.untagged(), s.untagged());
+
+        entities.delete_index(e).unwrap();
+        assert!(!entities.is_live(e));
+        assert!(sounds.is_live(s));
+        assert_eq!(Err(GenIndexError::AlreadyDeleted), entities.delete_index(e));
+        assert_eq!(1, entities.try_next_index().unwrap().get_generation());
+
+        // the tag costs nothing
+        assert_eq!(size_of::<GenIndex<u64, u64>>(), size_of::<GenIndex<u64, u64, Entity>>());
+    }
+
+    #[test]
+    fn test_tagged_shared() {
+        // the tag need not be Send for the handles to be
+        struct NotSend(#[allow(dead_code)] std::rc::Rc<()>);
+
+        let gi = TaggedGenIndexEntitySet::<u32, u32, NotSend>::new();
+        let cgi = gi.clone();
+        let idx = spawn(move || lock(&cgi).next_index()).join().unwrap();
+        assert!(lock(&gi).is_live(idx));
+    }
+}
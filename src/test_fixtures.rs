@@ -0,0 +1,10 @@
+//! Component types shared by the tests of the modules storing
+//! components.
+
+/// a component with a value, for the tests to store and find again.
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct Health(pub(crate) u32);
+
+/// a second component type, for the tests needing two.
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct Armor(pub(crate) u32);
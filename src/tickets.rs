@@ -0,0 +1,208 @@
+//! Two-phase allocation: wait-free tickets, redeemed for handles later.
+
+use std::{ops::AddAssign,
+          sync::atomic::AtomicUsize,
+          sync::atomic::Ordering};
+
+use num::{Num, Bounded, NumCast, ToPrimitive, cast};
+
+use super::{GenIndex, GenIndexError, SharedGenIndexEntitySet};
+use super::sync::lock;
+
+/// GenIndexTicket
+///
+/// The promise of an index, handed out by a TicketDispenser. It
+/// already carries its final index number, but is not live until
+/// redeemed for a GenIndex.
+#[derive(Hash, Debug, PartialEq, Eq, Copy, Clone)]
+pub struct GenIndexTicket<I: Num + AddAssign + Copy> {
+    index: I,
+}
+
+impl<I: Num + AddAssign + Copy> GenIndexTicket<I> {
+    /// return the index number this ticket will redeem to.
+    pub fn get_index(&self) -> I { self.index }
+}
+
+/// TicketDispenser
+///
+/// Sets aside a block of fresh indices in a SharedGenIndexEntitySet
+/// up front, and then hands them out as tickets with a single atomic
+/// increment, never touching the lock. Producers that only need to
+/// know an entity's index number can run flat out, and redeem their
+/// tickets for proper handles later, in a batch, when the lock is
+/// less contended.
+///
+/// Tickets that will never be redeemed should be handed back with
+/// `cancel`, otherwise their slots are lost. Tickets never taken
+/// from the dispenser are handed back to the set when it is dropped.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// let gi = GenIndexEntitySet::<u64, u64>::new();
+/// let tickets = TicketDispenser::new(gi.clone(), 1000).unwrap();
+///
+/// let ticket = tickets.take_ticket().unwrap();
+/// let idx = tickets.redeem(ticket).unwrap();
+/// assert_eq!(ticket.get_index(), idx.get_index());
+/// assert!(gi.is_live(idx));
+/// ```
+#[derive(Debug)]
+pub struct TicketDispenser<I: Num + AddAssign + Copy + ToPrimitive + Bounded + NumCast,
+                           G: Num + AddAssign + Copy + Bounded + PartialOrd> {
+    set: SharedGenIndexEntitySet<I, G>,
+    start: usize,
+    count: usize,
+    taken: AtomicUsize,
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + NumCast,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> TicketDispenser<I, G> {
+
+    /// Create a dispenser of `count` tickets, setting aside that
+    /// many fresh indices in `set`.
+    pub fn new(set: SharedGenIndexEntitySet<I, G>,
+               count: usize) -> Result<TicketDispenser<I, G>, GenIndexError> {
+        let start = lock(&set).claim_fresh(count)?;
+        Ok(TicketDispenser{set, start, count, taken: AtomicUsize::new(0)})
+    }
+
+    /// return the set the tickets are redeemed against.
+    pub fn set(&self) -> &SharedGenIndexEntitySet<I, G> { &self.set }
+
+    /// Take a ticket, wait-free, or None if all have been taken.
+    pub fn take_ticket(&self) -> Option<GenIndexTicket<I>> {
+        let n = self.taken.fetch_add(1, Ordering::Relaxed);
+        if n < self.count {
+            Some(GenIndexTicket{index: cast(self.start + n)?})
+        } else {
+            None
+        }
+    }
+
+    /// return the number of tickets not yet taken.
+    pub fn remaining(&self) -> usize {
+        self.count.saturating_sub(self.taken.load(Ordering::Relaxed))
+    }
+
+    /// Redeem a ticket for a live GenIndex with the ticket's index.
+    /// A ticket can only be redeemed once; if the set's capacity
+    /// limit is reached, the ticket stays valid and can be redeemed
+    /// later.
+    pub fn redeem(&self, ticket: GenIndexTicket<I>) -> Result<GenIndex<I, G>, GenIndexError> {
+        lock(&self.set).redeem_claimed(ticket.index)
+    }
+
+    /// Give up a ticket that will never be redeemed. Its slot goes
+    /// to the set's free list.
+    pub fn cancel(&self, ticket: GenIndexTicket<I>) -> Result<(), GenIndexError> {
+        lock(&self.set).release_claimed(ticket.index)
+    }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + NumCast,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd> Drop for TicketDispenser<I, G> {
+    fn drop(&mut self) {
+        let taken = self.taken.load(Ordering::Relaxed).min(self.count);
+        let mut set = lock(&self.set);
+        for n in taken..self.count {
+            if let Some(index) = cast(self.start + n) {
+                let _ = set.release_claimed(index);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread::*, sync::Arc, collections::HashSet};
+    use super::*;
+    use super::super::{GenIndexConfig, GenIndexEntitySet, GenIndexOp};
+
+    const THREADS: usize = 8;
+
+    #[test]
+    fn test_tickets() {
+        let gi = GenIndexEntitySet::<u64, u64>::new();
+        let before = lock(&gi).next_index();
+        let tickets = TicketDispenser::new(gi.clone(), 3).unwrap();
+
+        // the set does not hand out claimed indices itself
+        assert_eq!(4, lock(&gi).next_index().get_index());
+
+        let t1 = tickets.take_ticket().unwrap();
+        let t2 = tickets.take_ticket().unwrap();
+        assert_eq!(1, t1.get_index());
+        assert_eq!(2, t2.get_index());
+        assert_eq!(1, tickets.remaining());
+
+        let idx = tickets.redeem(t2).unwrap();
+        assert!(lock(&gi).is_live(idx));
+        assert_eq!(Err(GenIndexError::IndexInUse), tickets.redeem(t2));
+        assert!(!lock(&gi).is_live(GenIndex::new(1, 0)));
+
+        // a cancelled ticket is recycled
+        tickets.cancel(t1).unwrap();
+        assert_eq!(Err(GenIndexError::UnknownIndex), tickets.redeem(t1));
+        assert_eq!(1, lock(&gi).next_index().get_index());
+
+        // the untaken ticket is released when the dispenser goes away
+        drop(tickets);
+        assert_eq!(3, lock(&gi).next_index().get_index());
+        assert!(lock(&gi).is_live(before));
+    }
+
+    #[test]
+    fn test_tickets_run_out() {
+        let gi = GenIndexEntitySet::<u64, u64>::new();
+        let tickets = TicketDispenser::new(gi, 2).unwrap();
+        assert!(tickets.take_ticket().is_some());
+        assert!(tickets.take_ticket().is_some());
+        assert!(tickets.take_ticket().is_none());
+        assert_eq!(0, tickets.remaining());
+
+        let small = GenIndexEntitySet::<u8, u8>::new();
+        assert_eq!(Err(GenIndexError::IndexSpaceExhausted),
+                   TicketDispenser::new(small, 300).map(|_| ()));
+    }
+
+    #[test]
+    fn test_tickets_replay() {
+        let config = GenIndexConfig{record_operations: true, ..GenIndexConfig::default()};
+        let gi = GenIndexEntitySet::<u64, u64>::with_config(config);
+        {
+            let tickets = TicketDispenser::new(gi.clone(), 4).unwrap();
+            let t = tickets.take_ticket().unwrap();
+            tickets.take_ticket().unwrap();
+            tickets.redeem(t).unwrap();
+        }
+        let set = lock(&gi);
+        assert_eq!(GenIndexOp::Claim(4), set.recorded_operations()[0]);
+        let replayed = GenIndexEntitySet::from_recording(config, set.recorded_operations()).unwrap();
+        assert_eq!(*set, replayed);
+    }
+
+    #[test]
+    fn test_tickets_threaded() {
+        let gi = GenIndexEntitySet::<u64, u64>::new();
+        let tickets = Arc::new(TicketDispenser::new(gi.clone(), THREADS * 100).unwrap());
+        let threads: Vec<_> = (0..THREADS).map(|_| {
+            let tickets = tickets.clone();
+            spawn(move || (0..100).map(|_| tickets.take_ticket().unwrap()).collect::<Vec<_>>())
+        }).collect();
+
+        let mut seen = HashSet::new();
+        for t in threads {
+            for ticket in t.join().unwrap() {
+                assert!(seen.insert(ticket.get_index()));
+                tickets.redeem(ticket).unwrap();
+            }
+        }
+        assert_eq!(THREADS * 100, lock(&gi).live_count());
+    }
+}
@@ -0,0 +1,187 @@
+//! Stable UUIDs for handles, behind the `uuid` feature.
+
+use std::{collections::HashMap,
+          hash::Hash,
+          ops::AddAssign};
+
+use num::{Num, Bounded, ToPrimitive};
+use uuid::Uuid;
+
+use super::{GenIndex, GenIndexEntitySet, GenIndexError};
+
+/// UuidRegistry
+///
+/// Associates handles with UUIDs, and looks them up in either
+/// direction. A handle is only meaningful to the set that handed it
+/// out, and only for as long as that set lives; a UUID is the same
+/// everywhere and forever. Use the UUIDs whenever an entity has to
+/// be referred to from outside the process, in save files or across
+/// the network, and translate them back to handles on the way in.
+///
+/// The registry does not watch the set. Remove a handle when
+/// deleting it, or sweep out the dead ones now and then with
+/// `retain_live`.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// let mut gi = GenIndexEntitySet::<u64, u64>::new_unsync();
+/// let mut uuids = UuidRegistry::new();
+///
+/// let idx = gi.next_index();
+/// let uuid = uuids.assign(idx);
+/// assert_eq!(Some(idx), uuids.index_of(uuid));
+/// assert_eq!(Some(uuid), uuids.uuid_of(idx));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct UuidRegistry<I: Num + AddAssign + Copy + Hash + Eq,
+                        G: Num + AddAssign + Copy + Hash + Eq> {
+    uuids: HashMap<GenIndex<I, G>, Uuid>,
+    indices: HashMap<Uuid, GenIndex<I, G>>,
+}
+
+impl<I: Num + AddAssign + Copy + Hash + Eq,
+     G: Num + AddAssign + Copy + Hash + Eq> UuidRegistry<I, G> {
+
+    /// Create a new, empty registry.
+    pub fn new() -> UuidRegistry<I, G> {
+        UuidRegistry{uuids: HashMap::new(), indices: HashMap::new()}
+    }
+
+    /// return the UUID of the given handle, giving it a fresh random
+    /// one first if it has none.
+    pub fn assign(&mut self, gi: GenIndex<I, G>) -> Uuid {
+        if let Some(&uuid) = self.uuids.get(&gi) {
+            return uuid;
+        }
+        let uuid = Uuid::new_v4();
+        self.uuids.insert(gi, uuid);
+        self.indices.insert(uuid, gi);
+        uuid
+    }
+
+    /// Associate a handle with a known UUID, as read back from a
+    /// save file. Fails with `IndexInUse` if either of them is
+    /// already associated with something else.
+    pub fn insert(&mut self, gi: GenIndex<I, G>, uuid: Uuid) -> Result<(), GenIndexError> {
+        match (self.uuids.get(&gi), self.indices.get(&uuid)) {
+            (None, None) => {
+                self.uuids.insert(gi, uuid);
+                self.indices.insert(uuid, gi);
+                Ok(())
+            },
+            (Some(&u), Some(&g)) if u == uuid && g == gi => Ok(()),
+            _ => Err(GenIndexError::IndexInUse),
+        }
+    }
+
+    /// return the UUID of the given handle, if it has one.
+    pub fn uuid_of(&self, gi: GenIndex<I, G>) -> Option<Uuid> {
+        self.uuids.get(&gi).copied()
+    }
+
+    /// return the handle with the given UUID, if there is one.
+    pub fn index_of(&self, uuid: Uuid) -> Option<GenIndex<I, G>> {
+        self.indices.get(&uuid).copied()
+    }
+
+    /// Forget the given handle, returning its UUID.
+    pub fn remove(&mut self, gi: GenIndex<I, G>) -> Option<Uuid> {
+        let uuid = self.uuids.remove(&gi)?;
+        self.indices.remove(&uuid);
+        Some(uuid)
+    }
+
+    /// Forget the given UUID, returning its handle.
+    pub fn remove_uuid(&mut self, uuid: Uuid) -> Option<GenIndex<I, G>> {
+        let gi = self.indices.remove(&uuid)?;
+        self.uuids.remove(&gi);
+        Some(gi)
+    }
+
+    /// Forget every handle no longer live in the given set,
+    /// returning how many were removed.
+    pub fn retain_live(&mut self, set: &GenIndexEntitySet<I, G>) -> usize
+    where I: ToPrimitive + Bounded,
+          G: Bounded + PartialOrd {
+        let before = self.uuids.len();
+        let indices = &mut self.indices;
+        self.uuids.retain(|&gi, uuid| {
+            let live = set.is_live(gi);
+            if !live {
+                indices.remove(uuid);
+            }
+            live
+        });
+        before - self.uuids.len()
+    }
+
+    /// return the number of handles with a UUID.
+    pub fn len(&self) -> usize { self.uuids.len() }
+
+    /// return true if no handle has a UUID.
+    pub fn is_empty(&self) -> bool { self.uuids.is_empty() }
+
+    /// iterate over every handle and its UUID, in no particular
+    /// order; for saving them, to be put back with `insert`.
+    pub fn iter(&self) -> impl Iterator<Item = (GenIndex<I, G>, Uuid)> + '_ {
+        self.uuids.iter().map(|(&gi, &uuid)| (gi, uuid))
+    }
+}
+
+impl<I: Num + AddAssign + Copy + Hash + Eq,
+     G: Num + AddAssign + Copy + Hash + Eq> Default for UuidRegistry<I, G> {
+    fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uuid_registry() {
+        let mut gi = GenIndexEntitySet::<u64, u64>::new_unsync();
+        let mut uuids = UuidRegistry::new();
+        let idx1 = gi.next_index();
+        let idx2 = gi.next_index();
+
+        let uuid1 = uuids.assign(idx1);
+        assert_eq!(uuid1, uuids.assign(idx1));
+        let uuid2 = uuids.assign(idx2);
+        assert_ne!(uuid1, uuid2);
+        assert_eq!(2, uuids.len());
+
+        // either side may only be used once
+        let other = Uuid::new_v4();
+        assert_eq!(Ok(()), uuids.insert(idx1, uuid1));
+        assert_eq!(Err(GenIndexError::IndexInUse), uuids.insert(idx1, other));
+        assert_eq!(Err(GenIndexError::IndexInUse), uuids.insert(GenIndex::new(7, 0), uuid1));
+
+        gi.delete_index(idx2).unwrap();
+        assert_eq!(1, uuids.retain_live(&gi));
+        assert_eq!(None, uuids.index_of(uuid2));
+
+        assert_eq!(Some(idx1), uuids.remove_uuid(uuid1));
+        assert_eq!(None, uuids.remove(idx1));
+        assert!(uuids.is_empty());
+    }
+
+    #[test]
+    fn test_uuid_registry_reload() {
+        let mut uuids = UuidRegistry::<u32, u32>::new();
+        for i in 0..10 {
+            uuids.assign(GenIndex::new(i, 0));
+        }
+        let saved: Vec<_> = uuids.iter().collect();
+
+        let mut reloaded = UuidRegistry::new();
+        for (gi, uuid) in saved {
+            reloaded.insert(gi, uuid).unwrap();
+        }
+        assert_eq!(uuids, reloaded);
+    }
+}
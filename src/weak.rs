@@ -0,0 +1,122 @@
+//! Handles that must be checked before use.
+
+use std::{fmt,
+          hash::Hash,
+          hash::Hasher,
+          ops::AddAssign};
+
+use num::{Num, Bounded, ToPrimitive};
+
+use super::{GenIndex, GenIndexEntitySet};
+
+/// WeakGenIndex
+///
+/// A handle which may well refer to an entity that has since been
+/// deleted, and so offers nothing to look anything up with until it
+/// is upgraded back to a GenIndex, which only succeeds while the
+/// entity is live. Obtained from `GenIndex::downgrade`. Keep these
+/// wherever a reference is allowed to dangle, a target that may
+/// have been destroyed, say, and the type says so to everyone
+/// reading the code.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// let mut entities = GenIndexEntitySet::<u32, u32>::new_unsync();
+/// let enemy = entities.next_index();
+/// let target = enemy.downgrade();
+/// assert_eq!(Some(enemy), target.upgrade(&entities));
+///
+/// entities.delete_index(enemy).unwrap();
+/// assert_eq!(None, target.upgrade(&entities));
+/// ```
+pub struct WeakGenIndex<I: Num + AddAssign + Copy,
+                        G: Num + AddAssign + Copy,
+                        Tag = ()> {
+    gi: GenIndex<I, G, Tag>,
+}
+
+impl<I: Num + AddAssign + Copy,
+     G: Num + AddAssign + Copy,
+     Tag> GenIndex<I, G, Tag> {
+    /// return a weak handle to the same entity, which has to be
+    /// upgraded again before it can be used.
+    pub fn downgrade(self) -> WeakGenIndex<I, G, Tag> { WeakGenIndex{gi: self} }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd,
+     Tag> WeakGenIndex<I, G, Tag> {
+    /// return the handle again, if it is still live in `set`, the
+    /// set it was handed out by.
+    pub fn upgrade(&self, set: &GenIndexEntitySet<I, G>) -> Option<GenIndex<I, G, Tag>> {
+        if set.is_live(self.gi.untagged()) {
+            Some(self.gi)
+        } else {
+            None
+        }
+    }
+}
+
+impl<I: Num + AddAssign + Copy,
+     G: Num + AddAssign + Copy,
+     Tag> Clone for WeakGenIndex<I, G, Tag> {
+    fn clone(&self) -> Self { *self }
+}
+
+impl<I: Num + AddAssign + Copy,
+     G: Num + AddAssign + Copy,
+     Tag> Copy for WeakGenIndex<I, G, Tag> {}
+
+impl<I: Num + AddAssign + Copy,
+     G: Num + AddAssign + Copy,
+     Tag> PartialEq for WeakGenIndex<I, G, Tag> {
+    fn eq(&self, other: &Self) -> bool { self.gi == other.gi }
+}
+
+impl<I: Num + AddAssign + Copy + Eq,
+     G: Num + AddAssign + Copy + Eq,
+     Tag> Eq for WeakGenIndex<I, G, Tag> {}
+
+impl<I: Num + AddAssign + Copy + Hash,
+     G: Num + AddAssign + Copy + Hash,
+     Tag> Hash for WeakGenIndex<I, G, Tag> {
+    fn hash<H: Hasher>(&self, state: &mut H) { self.gi.hash(state) }
+}
+
+impl<I: Num + AddAssign + Copy + fmt::Debug,
+     G: Num + AddAssign + Copy + fmt::Debug,
+     Tag> fmt::Debug for WeakGenIndex<I, G, Tag> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("WeakGenIndex").field(&self.gi).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weak_index() {
+        let mut set = GenIndexEntitySet::<u32, u32>::new_unsync();
+        let gi = set.next_index();
+        let weak = gi.downgrade();
+        assert_eq!(weak, weak.clone());
+        assert_eq!(Some(gi), weak.upgrade(&set));
+
+        // a new entity in the same slot does not revive the weak handle
+        set.delete_index(gi).unwrap();
+        let reused = set.next_index();
+        assert_eq!(gi.get_index(), reused.get_index());
+        assert_eq!(None, weak.upgrade(&set));
+
+        // tags survive the round trip
+        struct Enemy;
+        let tagged: GenIndex<u32, u32, Enemy> = reused.retag();
+        assert_eq!(Some(tagged), tagged.downgrade().upgrade(&set));
+    }
+}
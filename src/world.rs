@@ -0,0 +1,242 @@
+//! Entities, their components, and the resources, all in one.
+
+use std::{any::Any,
+          any::TypeId,
+          ops::AddAssign};
+
+use num::{Num, Bounded, NumCast, ToPrimitive};
+
+use super::{Component, EntityBuilder, GenIndex, GenIndexEntitySet, GenIndexError, Query, Registry, Resources,
+            StorageBackend, TagFilter};
+
+/// World
+///
+/// A Registry, handing out the entities and keeping their
+/// components, together with the Resources next to them: all there
+/// is to a small entity component system, ready to use. Callbacks
+/// can be registered to hear of every entity spawned or despawned.
+///
+/// The parts are at hand with `registry` and `resources`, for
+/// what the world does not do itself: tags, change ticks, or
+/// applying a CommandBuffer.
+///
+/// The index and generation types default to `u32`.
+///
+/// Example:
+///
+/// ```
+/// extern crate gen_indices;
+///
+/// use gen_indices::*;
+///
+/// struct Position(f32);
+/// struct Velocity(f32);
+/// struct Time { step: f32 }
+///
+/// let mut world = World::<u32, u32>::new();
+/// world.insert_resource(Time { step: 0.5 });
+/// let ball = world.spawn().with(Position(0.0)).with(Velocity(2.0)).build();
+/// let wall = world.spawn().with(Position(9.0)).build();
+///
+/// let step = world.resource::<Time>().unwrap().step;
+/// for (_, (position, velocity)) in world.query::<(&mut Position, &Velocity)>() {
+///     position.0 += velocity.0 * step;
+/// }
+/// assert_eq!(1.0, world.get::<Position>(ball).unwrap().0);
+///
+/// world.despawn(wall).unwrap();
+/// assert_eq!(1, world.len());
+/// ```
+#[derive(Debug)]
+pub struct World<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static = u32,
+                 G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static = u32> {
+    registry: Registry<I, G>,
+    resources: Resources,
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> World<I, G> {
+
+    /// Create a new world, without entities or resources.
+    pub fn new() -> World<I, G> {
+        Self::with_registry(Registry::new())
+    }
+
+    /// Create a new world, keeping the components as `backend`
+    /// says.
+    pub fn with_backend(backend: StorageBackend) -> World<I, G> {
+        Self::with_registry(Registry::with_backend(GenIndexEntitySet::new_unsync(), backend))
+    }
+
+    /// Create a new world around a registry, which may be
+    /// configured as needed, or already have entities.
+    pub fn with_registry(registry: Registry<I, G>) -> World<I, G> {
+        World{registry, resources: Resources::new()}
+    }
+
+    /// return the registry the entities are kept in.
+    pub fn registry(&self) -> &Registry<I, G> { &self.registry }
+
+    /// return the registry the entities are kept in, for
+    /// modification.
+    pub fn registry_mut(&mut self) -> &mut Registry<I, G> { &mut self.registry }
+
+    /// return the resources.
+    pub fn resources(&self) -> &Resources { &self.resources }
+
+    /// return the resources, for modification.
+    pub fn resources_mut(&mut self) -> &mut Resources { &mut self.resources }
+
+    /// Create a new entity, and return a builder to give it its
+    /// components with.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as `Registry::create`.
+    pub fn spawn(&mut self) -> EntityBuilder<'_, I, G> { self.registry.spawn() }
+
+    /// Destroy an entity, dropping all of its components, and
+    /// return how many there were, as `Registry::destroy`.
+    pub fn despawn(&mut self, gi: GenIndex<I, G>) -> Result<usize, GenIndexError> {
+        self.registry.destroy(gi)
+    }
+
+    /// Check whether the entity is live.
+    pub fn is_live(&self, gi: GenIndex<I, G>) -> bool { self.registry.is_live(gi) }
+
+    /// return the number of live entities.
+    pub fn len(&self) -> usize { self.registry.len() }
+
+    /// return true if there are no live entities.
+    pub fn is_empty(&self) -> bool { self.registry.is_empty() }
+
+    /// Store a component for a live entity, as `Registry::insert`.
+    pub fn insert<T: Component>(&mut self, gi: GenIndex<I, G>, value: T) -> Result<Option<T>, GenIndexError> {
+        self.registry.insert(gi, value)
+    }
+
+    /// Remove the entity's component of type `T`, and return it.
+    pub fn remove<T: Component>(&mut self, gi: GenIndex<I, G>) -> Option<T> { self.registry.remove(gi) }
+
+    /// return the entity's component of type `T`, if it has one.
+    pub fn get<T: Component>(&self, gi: GenIndex<I, G>) -> Option<&T> { self.registry.get(gi) }
+
+    /// return the entity's component of type `T` for modification,
+    /// if it has one.
+    pub fn get_mut<T: Component>(&mut self, gi: GenIndex<I, G>) -> Option<&mut T> { self.registry.get_mut(gi) }
+
+    /// return the entity's component of the type with the given id,
+    /// if it has one, with its type erased, as `Registry::get_dyn`.
+    pub fn get_dyn(&self, gi: GenIndex<I, G>, id: TypeId) -> Option<&dyn Any> { self.registry.get_dyn(gi, id) }
+
+    /// Check whether the entity is live, and has a component of
+    /// type `T`.
+    pub fn has<T: Component>(&self, gi: GenIndex<I, G>) -> bool { self.registry.has::<T>(gi) }
+
+    /// Store a resource, returning the one of the same type there
+    /// was, if any.
+    pub fn insert_resource<T: Component>(&mut self, value: T) -> Option<T> { self.resources.insert(value) }
+
+    /// return the resource of type `T`, if there is one.
+    pub fn resource<T: Component>(&self) -> Option<&T> { self.resources.get() }
+
+    /// return the resource of type `T` for modification, if there
+    /// is one.
+    pub fn resource_mut<T: Component>(&mut self) -> Option<&mut T> { self.resources.get_mut() }
+
+    /// Remove the resource of type `T`, and return it.
+    pub fn remove_resource<T: Component>(&mut self) -> Option<T> { self.resources.remove() }
+
+    /// Register a callback to be called with every entity spawned
+    /// from now on, as `GenIndexEntitySet::on_allocate`; it is
+    /// called before the entity gets its components.
+    pub fn on_spawn<F: Fn(GenIndex<I, G>) + Send + Sync + 'static>(&mut self, hook: F) {
+        self.registry.entities_mut().on_allocate(hook);
+    }
+
+    /// Register a callback to be called with every entity despawned
+    /// from now on, as `GenIndexEntitySet::on_delete`.
+    pub fn on_despawn<F: Fn(GenIndex<I, G>) + Send + Sync + 'static>(&mut self, hook: F) {
+        self.registry.entities_mut().on_delete(hook);
+    }
+
+    /// Drop every callback registered with `on_spawn` and
+    /// `on_despawn`.
+    pub fn clear_hooks(&mut self) { self.registry.entities_mut().clear_hooks() }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + NumCast + 'static,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> World<I, G> {
+
+    /// iterate over every entity having all the components asked
+    /// for, along with them, as `Registry::query`.
+    ///
+    /// # Panics
+    ///
+    /// Panics as `Registry::query` does.
+    pub fn query<'a, Q: Query<'a, I, G> + 'a>(&'a mut self) -> impl Iterator<Item = (GenIndex<I, G>, Q::Item)> + 'a {
+        self.registry.query::<Q>()
+    }
+
+    /// Like `query`, but only finding the entities which pass the
+    /// tag filter `F` as well.
+    ///
+    /// # Panics
+    ///
+    /// Panics as `Registry::query` does.
+    pub fn query_filtered<'a, Q: Query<'a, I, G> + 'a, F: TagFilter>(&'a mut self)
+        -> impl Iterator<Item = (GenIndex<I, G>, Q::Item)> + 'a {
+        self.registry.query_filtered::<Q, F>()
+    }
+}
+
+impl<I: Num + AddAssign + Copy + ToPrimitive + Bounded + 'static,
+     G: Num + AddAssign + Copy + Bounded + PartialOrd + Send + Sync + 'static> Default for World<I, G> {
+    fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use super::super::With;
+    use super::super::test_fixtures::Health;
+
+    #[derive(Debug, PartialEq)]
+    struct Score(u32);
+    struct Dead;
+
+    #[test]
+    fn test_world() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut world = World::<u32, u32>::with_backend(StorageBackend::Archetypes);
+        let spawned = log.clone();
+        world.on_spawn(move |gi| spawned.lock().unwrap().push(("spawn", gi)));
+        let despawned = log.clone();
+        world.on_despawn(move |gi| despawned.lock().unwrap().push(("despawn", gi)));
+
+        let a = world.spawn().with(Health(3)).build();
+        let b = world.spawn().with(Health(0)).build();
+        world.registry_mut().add_tag::<Dead>(b).unwrap();
+        assert_eq!(Ok(Some(Health(3))), world.insert(a, Health(5)));
+        assert_eq!(Some(&Health(5)), world.get(a));
+        assert!(world.has::<Health>(b));
+
+        world.insert_resource(Score(0));
+        let dead: Vec<_> = world.query_filtered::<&Health, With<Dead>>().map(|(gi, _)| gi).collect();
+        assert_eq!(vec![b], dead);
+        for gi in dead {
+            world.despawn(gi).unwrap();
+            world.resource_mut::<Score>().unwrap().0 += 1;
+        }
+        assert_eq!(Some(&Score(1)), world.resource());
+        assert_eq!(Err(GenIndexError::AlreadyDeleted), world.despawn(b));
+        assert_eq!(vec![("spawn", a), ("spawn", b), ("despawn", b)], *log.lock().unwrap());
+
+        world.clear_hooks();
+        world.spawn().build();
+        assert_eq!(3, log.lock().unwrap().len());
+        assert_eq!(Some(Score(1)), world.remove_resource());
+        assert_eq!(2, world.len());
+    }
+}